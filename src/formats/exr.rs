@@ -0,0 +1,375 @@
+use std::io::{Error, Write};
+
+use super::super::math::framebuffer::FrameBuffer;
+use super::zlib::{zlib_compress, Compression};
+
+// OpenEXR scanline encoder with B, G and R channels stored as half or single precision floats.
+// Scanlines are stored one at a time without compression, or in blocks of 16 with ZIP compression.
+
+const MAGIC: [u8; 4] = [0x76, 0x2F, 0x31, 0x01];
+const ZIP_SCANLINES: usize = 16;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ExrCompression
+{
+    None,
+    Zip,
+}
+
+impl ExrCompression
+{
+    pub const ALL: [ExrCompression; 2] = [ExrCompression::None, ExrCompression::Zip];
+
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            ExrCompression::None => "none",
+            ExrCompression::Zip => "zip",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ExrCompression>
+    {
+        ExrCompression::ALL.into_iter().find(|compression| compression.name().eq_ignore_ascii_case(name))
+    }
+
+    fn scanlines_per_block(&self) -> usize
+    {
+        match self
+        {
+            ExrCompression::None => 1,
+            ExrCompression::Zip => ZIP_SCANLINES,
+        }
+    }
+
+    // Value of the compression attribute
+    fn code(&self) -> u8
+    {
+        match self
+        {
+            ExrCompression::None => 0,
+            ExrCompression::Zip => 3,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ExrPixelType
+{
+    Half,
+    Float,
+}
+
+impl ExrPixelType
+{
+    pub const ALL: [ExrPixelType; 2] = [ExrPixelType::Half, ExrPixelType::Float];
+
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            ExrPixelType::Half => "half",
+            ExrPixelType::Float => "float",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ExrPixelType>
+    {
+        ExrPixelType::ALL.into_iter().find(|pixel_type| pixel_type.name().eq_ignore_ascii_case(name))
+    }
+
+    // Value used in the channel list
+    fn code(&self) -> i32
+    {
+        match self
+        {
+            ExrPixelType::Half => 1,
+            ExrPixelType::Float => 2,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ExrOptions
+{
+    pub compression: ExrCompression,
+    pub pixel_type: ExrPixelType,
+}
+
+impl ExrOptions
+{
+    pub fn default() -> Self
+    {
+        ExrOptions
+        {
+            compression: ExrCompression::Zip,
+            pixel_type: ExrPixelType::Half,
+        }
+    }
+}
+
+// Rounds to the nearest half precision float, ties to even. Too large values become infinity.
+pub fn f32_to_half(value: f32) -> u16
+{
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x7FFFFF;
+
+    if exponent == 0xFF
+    {
+        // Infinity stays infinity, NaN stays NaN
+        return sign | 0x7C00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1F
+    {
+        return sign | 0x7C00;
+    }
+
+    // Shift the mantissa (with its implicit leading one) into place, subnormals need a larger shift
+    let (base, mantissa, shift) = if half_exponent <= 0
+    {
+        if half_exponent < -10
+        {
+            return sign;
+        }
+        (0, mantissa | 0x800000, (14 - half_exponent) as u32)
+    }
+    else
+    {
+        ((half_exponent as u32) << 10, mantissa, 13)
+    };
+    let truncated = base | (mantissa >> shift);
+    let remainder = mantissa & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    // A carry out of the mantissa correctly bumps the exponent, up to infinity
+    let rounded = if remainder > halfway || (remainder == halfway && truncated & 1 == 1) { truncated + 1 } else { truncated };
+    sign | rounded as u16
+}
+
+pub fn half_to_f32(half: u16) -> f32
+{
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1F) as i32;
+    let mantissa = (half & 0x3FF) as f32;
+    match exponent
+    {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1F if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1F => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, attribute_type: &str, value: &[u8])
+{
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(attribute_type.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+fn make_header(fb: &FrameBuffer, options: &ExrOptions) -> Vec<u8>
+{
+    let mut header = Vec::new();
+    header.extend_from_slice(&MAGIC);
+    // Version 2, single part scanline image
+    header.extend_from_slice(&2u32.to_le_bytes());
+
+    // Channels are listed (and stored) in alphabetical order
+    let mut channels = Vec::new();
+    for name in ["B", "G", "R"]
+    {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&options.pixel_type.code().to_le_bytes());
+        // pLinear and three reserved bytes, then x and y sampling
+        channels.extend_from_slice(&[0, 0, 0, 0]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+    write_attribute(&mut header, "channels", "chlist", &channels);
+    write_attribute(&mut header, "compression", "compression", &[options.compression.code()]);
+
+    let window: Vec<u8> = [0, 0, fb.width() as i32 - 1, fb.height() as i32 - 1].iter().flat_map(|value| value.to_le_bytes()).collect();
+    write_attribute(&mut header, "dataWindow", "box2i", &window);
+    write_attribute(&mut header, "displayWindow", "box2i", &window);
+    // Increasing y, which is top to bottom
+    write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    write_attribute(&mut header, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    write_attribute(&mut header, "screenWindowCenter", "v2f", &[0.0f32.to_le_bytes(), 0.0f32.to_le_bytes()].concat());
+    write_attribute(&mut header, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    header.push(0);
+    header
+}
+
+// Every scanline stores all of its B values, then G, then R
+fn pack_scanlines(fb: &FrameBuffer, rows: std::ops::Range<usize>, pixel_type: ExrPixelType, output: &mut Vec<u8>)
+{
+    for y in rows
+    {
+        for channel in [2, 1, 0]
+        {
+            for pixel in fb.row(y)
+            {
+                let value = pixel[channel] as f32;
+                match pixel_type
+                {
+                    ExrPixelType::Half => output.extend_from_slice(&f32_to_half(value).to_le_bytes()),
+                    ExrPixelType::Float => output.extend_from_slice(&value.to_le_bytes()),
+                }
+            }
+        }
+    }
+}
+
+// ZIP blocks split the bytes into even and odd halves and store differences between neighbours before deflating
+fn zip_block(raw: &[u8]) -> Vec<u8>
+{
+    let half = raw.len().div_ceil(2);
+    let mut reordered = vec![0u8; raw.len()];
+    for (ind, byte) in raw.iter().enumerate()
+    {
+        reordered[if ind % 2 == 0 { ind / 2 } else { half + ind / 2 }] = *byte;
+    }
+    for ind in (1..reordered.len()).rev()
+    {
+        reordered[ind] = reordered[ind].wrapping_sub(reordered[ind - 1]).wrapping_add(128);
+    }
+    zlib_compress(&reordered, Compression::Deflate)
+}
+
+pub fn write_exr<W: Write>(fb: &FrameBuffer, file: &mut W, options: &ExrOptions) -> Result<(), Error>
+{
+    let header = make_header(fb, options);
+    let scanlines_per_block = options.compression.scanlines_per_block();
+    let block_count = fb.height().div_ceil(scanlines_per_block);
+
+    let mut blocks = Vec::with_capacity(block_count);
+    let mut raw = Vec::new();
+    for block in 0..block_count
+    {
+        let first_row = block * scanlines_per_block;
+        raw.clear();
+        pack_scanlines(fb, first_row..(first_row + scanlines_per_block).min(fb.height()), options.pixel_type, &mut raw);
+        let data = match options.compression
+        {
+            ExrCompression::None => raw.clone(),
+            ExrCompression::Zip =>
+            {
+                // Readers take a block that is as large as the raw data to be uncompressed
+                let compressed = zip_block(&raw);
+                if compressed.len() < raw.len() { compressed } else { raw.clone() }
+            }
+        };
+        blocks.push((first_row as i32, data));
+    }
+
+    // The offset table points at every block from the start of the file
+    let mut offset = (header.len() + 8 * block_count) as u64;
+    file.write_all(&header)?;
+    for (_, data) in &blocks
+    {
+        file.write_all(&offset.to_le_bytes())?;
+        offset += 8 + data.len() as u64;
+    }
+    for (first_row, data) in &blocks
+    {
+        file.write_all(&first_row.to_le_bytes())?;
+        file.write_all(&(data.len() as i32).to_le_bytes())?;
+        file.write_all(data)?;
+    }
+    Ok(())
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::super::utils::color::FColor;
+
+    #[test]
+    fn half_test()
+    {
+        assert_eq!(f32_to_half(0.0), 0x0000);
+        assert_eq!(f32_to_half(-0.0), 0x8000);
+        assert_eq!(f32_to_half(1.0), 0x3C00);
+        assert_eq!(f32_to_half(-2.0), 0xC000);
+        assert_eq!(f32_to_half(65504.0), 0x7BFF);
+        assert_eq!(f32_to_half(1e6), 0x7C00);
+        assert_eq!(f32_to_half(f32::NEG_INFINITY), 0xFC00);
+        assert!(half_to_f32(f32_to_half(f32::NAN)).is_nan());
+        // Smallest subnormal, and a value that rounds up into it
+        assert_eq!(f32_to_half(5.960464e-8), 0x0001);
+        assert_eq!(f32_to_half(4.0e-8), 0x0001);
+        assert_eq!(f32_to_half(2.0e-8), 0x0000);
+        // Ties round to even
+        assert_eq!(f32_to_half(1.0 + 1.0 / 2048.0), 0x3C00);
+        assert_eq!(f32_to_half(1.0 + 3.0 / 2048.0), 0x3C02);
+
+        for half in [0x0001u16, 0x03FF, 0x0400, 0x3555, 0x3C00, 0x7BFF, 0xBC01]
+        {
+            assert_eq!(f32_to_half(half_to_f32(half)), half);
+        }
+    }
+
+    #[test]
+    fn zip_block_test()
+    {
+        // Compresses 1, 2, 3, 4 reordered to 1, 3, 2, 4 with differences 1, 130, 127, 130
+        let stream = zip_block(&[1, 2, 3, 4]);
+        assert_eq!(stream, zlib_compress(&[1, 130, 127, 130], Compression::Deflate));
+    }
+
+    fn read_i32(bytes: &[u8], offset: usize) -> i32
+    {
+        i32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn write_test()
+    {
+        let mut fb = FrameBuffer::new(2, 3);
+        fb.set(1, 0, FColor::new_color(1.0, 2.0, -2.0));
+        let options = ExrOptions{ compression: ExrCompression::None, pixel_type: ExrPixelType::Half };
+        let mut bytes = Vec::new();
+        write_exr(&fb, &mut bytes, &options).unwrap();
+
+        let header = make_header(&fb, &options);
+        assert_eq!(&bytes[..8], &[0x76, 0x2F, 0x31, 0x01, 2, 0, 0, 0]);
+        assert_eq!(&bytes[..header.len()], &header[..]);
+
+        // Three uncompressed scanlines, each a y coordinate, a size and 2 pixels * 3 channels * 2 bytes
+        let offsets: Vec<usize> = (0..3).map(|ind| u64::from_le_bytes(bytes[header.len() + 8 * ind..header.len() + 8 * ind + 8].try_into().unwrap()) as usize).collect();
+        assert_eq!(offsets, vec![header.len() + 24, header.len() + 24 + 20, header.len() + 24 + 40]);
+        assert_eq!(bytes.len(), header.len() + 24 + 60);
+        assert_eq!(read_i32(&bytes, offsets[0]), 0);
+        assert_eq!(read_i32(&bytes, offsets[2]), 2);
+        assert_eq!(read_i32(&bytes, offsets[0] + 4), 12);
+        // B, G and R of both pixels
+        let first_scanline: Vec<u16> = bytes[offsets[0] + 8..offsets[0] + 20].chunks(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+        assert_eq!(first_scanline, vec![0, 0xC000, 0, 0x4000, 0, 0x3C00]);
+
+        // Zip compression with float channels packs all three scanlines into a single block
+        let options = ExrOptions{ compression: ExrCompression::Zip, pixel_type: ExrPixelType::Float };
+        bytes.clear();
+        write_exr(&fb, &mut bytes, &options).unwrap();
+        let header = make_header(&fb, &options);
+        let block = u64::from_le_bytes(bytes[header.len()..header.len() + 8].try_into().unwrap()) as usize;
+        assert_eq!(block, header.len() + 8);
+        assert_eq!(read_i32(&bytes, block), 0);
+        assert_eq!(read_i32(&bytes, block + 4) as usize, bytes.len() - block - 8);
+        assert!(bytes.len() - block - 8 < 3 * 2 * 3 * 4);
+    }
+}
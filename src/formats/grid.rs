@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, Error, ErrorKind, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use super::*;
+use super::super::math::grid::*;
+use super::super::math::medium::GridPlacement;
+use super::super::math::point::Point;
+
+// Voxel grid files: named grids (density, temperature, ...) sharing one placement in the scene.
+//
+// The binary .vgrid format, all numbers little endian:
+//   magic       4 bytes  "VGRD"
+//   version     u32      1
+//   origin      3 x f32  position of voxel index (0, 0, 0)
+//   voxel size  f32      side of a voxel
+//   grid count  u32
+// then for every grid:
+//   name        u32 byte length followed by the UTF-8 name
+//   encoding    u8       0 for dense, 1 for sparse
+//   background  f32      value of the voxels left out
+//   dense:      minimum index 3 x i32, size 3 x u32, then size x * size y * size z f32 values, x varying the fastest then y
+//   sparse:     voxel count u32, then for every voxel its index 3 x i32 and its value f32
+//
+// The .nvdb text format follows the layout of NanoVDB grids, one statement per line, '#' starting a comment:
+//   voxel_size <size>
+//   origin <x> <y> <z>
+//   grid <name> [background]         starts a grid, the statements below add voxels to it
+//   leaf <x> <y> <z>                 a leaf node of 8^3 voxels at a multiple of 8, followed by its 512 values
+//                                    on as many lines as needed, z varying the fastest then y
+//   voxel <x> <y> <z> <value>        a single voxel
+
+pub struct GridSet
+{
+    pub placement: GridPlacement,
+    pub grids: HashMap<String, Arc<dyn VoxelGrid>>,
+}
+
+const VGRID_MAGIC: &[u8; 4] = b"VGRD";
+const VGRID_VERSION: u32 = 1;
+
+fn invalid_data(message: &str) -> Error
+{
+    Error::new(ErrorKind::InvalidData, format!("invalid voxel grid file: {}", message))
+}
+
+// Little endian reads from a byte buffer, failing on truncated data
+struct ByteReader<'a>
+{
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a>
+{
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], Error>
+    {
+        if self.data.len() - self.position < count
+        {
+            return Err(invalid_data("unexpected end of file"));
+        }
+        let bytes = &self.data[self.position..self.position + count];
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error>
+    {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    fn u32(&mut self) -> Result<u32, Error>
+    {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, Error>
+    {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, Error>
+    {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    fn remaining(&self) -> usize
+    {
+        self.data.len() - self.position
+    }
+}
+
+pub fn read_vgrid<R: Read>(file: &mut R) -> Result<GridSet, Error>
+{
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    let mut reader = ByteReader{ data: &data, position: 0 };
+
+    if reader.bytes(4)? != VGRID_MAGIC
+    {
+        return Err(invalid_data("not a VGRD file"));
+    }
+    let version = reader.u32()?;
+    if version != VGRID_VERSION
+    {
+        return Err(invalid_data(&format!("unsupported version {}", version)));
+    }
+    let origin = Point::make_new(reader.f32()? as f64, reader.f32()? as f64, reader.f32()? as f64);
+    let voxel_size = reader.f32()? as f64;
+    if voxel_size.is_nan() || voxel_size <= 0.0
+    {
+        return Err(invalid_data("the voxel size must be positive"));
+    }
+
+    let mut grids: HashMap<String, Arc<dyn VoxelGrid>> = HashMap::new();
+    for _ in 0..reader.u32()?
+    {
+        let name_length = reader.u32()? as usize;
+        let name = String::from_utf8(reader.bytes(name_length)?.to_vec()).map_err(|_| invalid_data("grid name isn't UTF-8"))?;
+        let encoding = reader.array::<1>()?[0];
+        let background = reader.f32()?;
+        let grid: Arc<dyn VoxelGrid> = match encoding
+        {
+            0 =>
+            {
+                let minimum = [reader.i32()? as i64, reader.i32()? as i64, reader.i32()? as i64];
+                let size = [reader.u32()? as usize, reader.u32()? as usize, reader.u32()? as usize];
+                // Checked against the data left before allocating anything
+                let count = size.iter().try_fold(1usize, |count, side| count.checked_mul(*side));
+                if count.is_none_or(|count| count > reader.remaining() / 4)
+                {
+                    return Err(invalid_data("unexpected end of file"));
+                }
+                let values = reader.bytes(count.unwrap_or(0) * 4)?.chunks_exact(4)
+                    .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).collect();
+                Arc::new(DenseGrid::make_new(minimum, size, values, background))
+            }
+            1 =>
+            {
+                let mut grid = SparseGrid::make_new(background);
+                for _ in 0..reader.u32()?
+                {
+                    let (x, y, z) = (reader.i32()? as i64, reader.i32()? as i64, reader.i32()? as i64);
+                    grid.set(x, y, z, reader.f32()?);
+                }
+                Arc::new(grid)
+            }
+            _ => return Err(invalid_data(&format!("unknown encoding {} of grid '{}'", encoding, name))),
+        };
+        grids.insert(name, grid);
+    }
+    Ok(GridSet{ placement: GridPlacement{ origin, voxel_size }, grids })
+}
+
+// Writes every grid densely over its index bounds
+pub fn write_vgrid<W: Write>(placement: &GridPlacement, grids: &[(&str, &dyn VoxelGrid)], file: &mut W) -> Result<(), Error>
+{
+    let mut data = Vec::new();
+    data.extend_from_slice(VGRID_MAGIC);
+    data.extend_from_slice(&VGRID_VERSION.to_le_bytes());
+    for value in [placement.origin.x(), placement.origin.y(), placement.origin.z(), placement.voxel_size]
+    {
+        data.extend_from_slice(&(value as f32).to_le_bytes());
+    }
+    data.extend_from_slice(&(grids.len() as u32).to_le_bytes());
+    for (name, grid) in grids
+    {
+        data.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        data.extend_from_slice(name.as_bytes());
+        data.push(0);
+        // The background is whatever lies outside the bounds
+        let (minimum, maximum) = grid.index_bounds();
+        data.extend_from_slice(&grid.value(maximum[0], maximum[1], maximum[2]).to_le_bytes());
+        for coordinate in minimum
+        {
+            data.extend_from_slice(&(coordinate as i32).to_le_bytes());
+        }
+        for axis in 0..3
+        {
+            data.extend_from_slice(&((maximum[axis] - minimum[axis]) as u32).to_le_bytes());
+        }
+        for z in minimum[2]..maximum[2]
+        {
+            for y in minimum[1]..maximum[1]
+            {
+                for x in minimum[0]..maximum[0]
+                {
+                    data.extend_from_slice(&grid.value(x, y, z).to_le_bytes());
+                }
+            }
+        }
+    }
+    file.write_all(&data)
+}
+
+fn parse_i64(line: usize, token: &Token) -> Result<i64, ParseError>
+{
+    token.text.parse::<i64>().map_err(|_| ParseError::make_new(line, token.column, format!("expected an integer, found '{}'", token.text)))
+}
+
+// The exact number of arguments a statement takes
+fn expect_arguments<'a, 'b>(line: usize, keyword: &Token, arguments: &'b [Token<'a>], count: usize) -> Result<&'b [Token<'a>], ParseError>
+{
+    match arguments.get(count)
+    {
+        Some(extra) => Err(ParseError::make_new(line, extra.column, format!("unexpected argument '{}'", extra.text))),
+        None if arguments.len() < count =>
+        {
+            let column = arguments.last().unwrap_or(keyword);
+            Err(ParseError::make_new(line, column.column + column.text.chars().count(), format!("'{}' needs {} arguments", keyword.text, count)))
+        }
+        None => Ok(arguments),
+    }
+}
+
+// Leaf whose values are still being read
+struct PendingLeaf
+{
+    origin: [i64; 3],
+    values: Vec<f32>,
+    line: usize,
+}
+
+pub fn parse_nvdb(source: &str) -> Result<GridSet, ParseError>
+{
+    let mut placement = GridPlacement{ origin: Point::make_new(0.0, 0.0, 0.0), voxel_size: 1.0 };
+    let mut grids: Vec<(String, SparseGrid)> = Vec::new();
+    let mut leaf: Option<PendingLeaf> = None;
+
+    for (line_index, text) in source.lines().enumerate()
+    {
+        let line = line_index + 1;
+        let tokens = tokenize(text);
+        let Some((keyword, arguments)) = tokens.split_first() else { continue };
+
+        // Lines following a leaf hold its values
+        if let Some(pending) = leaf.as_mut()
+        {
+            for token in &tokens
+            {
+                if pending.values.len() == LEAF_VOXELS
+                {
+                    return Err(ParseError::make_new(line, token.column, format!("the leaf of line {} already has its {} values", pending.line, LEAF_VOXELS)));
+                }
+                pending.values.push(parse_f64(line, token)? as f32);
+            }
+            if pending.values.len() == LEAF_VOXELS
+            {
+                let grid = &mut grids.last_mut().expect("leaves belong to a grid").1;
+                for (offset, value) in pending.values.iter().enumerate()
+                {
+                    let local = [offset as i64 >> (2 * LOG2_LEAF_DIM), (offset as i64 >> LOG2_LEAF_DIM) & (LEAF_DIM - 1), offset as i64 & (LEAF_DIM - 1)];
+                    grid.set(pending.origin[0] + local[0], pending.origin[1] + local[1], pending.origin[2] + local[2], *value);
+                }
+                leaf = None;
+            }
+            continue;
+        }
+
+        match keyword.text
+        {
+            "voxel_size" =>
+            {
+                let arguments = expect_arguments(line, keyword, arguments, 1)?;
+                placement.voxel_size = parse_f64(line, &arguments[0])?;
+                if placement.voxel_size <= 0.0
+                {
+                    return Err(ParseError::new(line, arguments[0].column, "the voxel size must be positive"));
+                }
+            }
+            "origin" =>
+            {
+                let arguments = expect_arguments(line, keyword, arguments, 3)?;
+                placement.origin = Point::make_new(parse_f64(line, &arguments[0])?, parse_f64(line, &arguments[1])?, parse_f64(line, &arguments[2])?);
+            }
+            "grid" =>
+            {
+                let Some(name) = arguments.first()
+                else
+                {
+                    return Err(ParseError::new(line, keyword.column + keyword.text.len(), "'grid' needs a name"));
+                };
+                if grids.iter().any(|(existing, _)| existing == name.text)
+                {
+                    return Err(ParseError::make_new(line, name.column, format!("grid '{}' is already defined", name.text)));
+                }
+                let background = match arguments.get(1)
+                {
+                    Some(token) => parse_f64(line, token)? as f32,
+                    None => 0.0,
+                };
+                if let Some(extra) = arguments.get(2)
+                {
+                    return Err(ParseError::make_new(line, extra.column, format!("unexpected argument '{}'", extra.text)));
+                }
+                grids.push((name.text.to_string(), SparseGrid::make_new(background)));
+            }
+            "leaf" | "voxel" =>
+            {
+                let arguments = expect_arguments(line, keyword, arguments, if keyword.text == "leaf" { 3 } else { 4 })?;
+                let Some((_, grid)) = grids.last_mut()
+                else
+                {
+                    return Err(ParseError::make_new(line, keyword.column, format!("'{}' before any 'grid'", keyword.text)));
+                };
+                let index = [parse_i64(line, &arguments[0])?, parse_i64(line, &arguments[1])?, parse_i64(line, &arguments[2])?];
+                if keyword.text == "voxel"
+                {
+                    grid.set(index[0], index[1], index[2], parse_f64(line, &arguments[3])? as f32);
+                }
+                else if leaf_origin(index[0], index[1], index[2]) != index
+                {
+                    return Err(ParseError::make_new(line, arguments[0].column, format!("leaf origins are multiples of {}", LEAF_DIM)));
+                }
+                else
+                {
+                    leaf = Some(PendingLeaf{ origin: index, values: Vec::with_capacity(LEAF_VOXELS), line });
+                }
+            }
+            _ => return Err(ParseError::make_new(line, keyword.column, format!("unknown statement '{}'", keyword.text))),
+        }
+    }
+
+    if let Some(pending) = leaf
+    {
+        return Err(ParseError::make_new(pending.line, 1, format!("the leaf has {} of its {} values", pending.values.len(), LEAF_VOXELS)));
+    }
+    let grids = grids.into_iter().map(|(name, grid)| (name, Arc::new(grid) as Arc<dyn VoxelGrid>)).collect();
+    Ok(GridSet{ placement, grids })
+}
+
+// Picks the format from the extension, .vgrid or .nvdb
+pub fn load_grids(path: &Path) -> Result<GridSet, LoadError>
+{
+    let io_error = |error: Error| LoadError::Io(path.to_path_buf(), error);
+    match path.extension().and_then(|extension| extension.to_str()).map(|extension| extension.to_ascii_lowercase()).as_deref()
+    {
+        Some("vgrid") =>
+        {
+            let mut file = BufReader::new(File::open(path).map_err(io_error)?);
+            read_vgrid(&mut file).map_err(io_error)
+        }
+        Some("nvdb") =>
+        {
+            let source = fs::read_to_string(path).map_err(io_error)?;
+            parse_nvdb(&source).map_err(|error| LoadError::Parse(path.to_path_buf(), error))
+        }
+        _ => Err(io_error(Error::new(ErrorKind::InvalidInput, "unknown voxel grid format, expected .vgrid or .nvdb"))),
+    }
+}
+
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn vgrid_test()
+    {
+        let values: Vec<f32> = (0..24).map(|value| value as f32 * 0.5).collect();
+        let dense = DenseGrid::make_new([-1, 0, 2], [2, 3, 4], values, 0.0);
+        let mut sparse = SparseGrid::make_new(0.0);
+        sparse.set(10, -3, 4, 2.5);
+        let placement = GridPlacement{ origin: Point::make_new(1.0, -2.0, 0.5), voxel_size: 0.25 };
+
+        let mut data = Vec::new();
+        write_vgrid(&placement, &[("density", &dense), ("temperature", &sparse)], &mut data).unwrap();
+        let set = read_vgrid(&mut data.as_slice()).unwrap();
+        assert_eq!(set.placement, placement);
+        assert_eq!(set.grids.len(), 2);
+        let density = &set.grids["density"];
+        assert_eq!(density.index_bounds(), dense.index_bounds());
+        assert_eq!(density.value(0, 2, 5), 11.5);
+        assert_eq!(density.value(5, 5, 5), 0.0);
+        let temperature = &set.grids["temperature"];
+        assert_eq!(temperature.value(10, -3, 4), 2.5);
+        assert_eq!(temperature.index_bounds(), ([10, -3, 4], [11, -2, 5]));
+
+        // A sparse grid written by hand
+        let mut data = Vec::new();
+        data.extend_from_slice(b"VGRD");
+        for word in [1u32, 0, 0, 0, 1.0f32.to_bits(), 1, 4]
+        {
+            data.extend_from_slice(&word.to_le_bytes());
+        }
+        data.extend_from_slice(b"fire");
+        data.push(1);
+        for word in [0.0f32.to_bits(), 1, (-1i32) as u32, 2, 3, 7.0f32.to_bits()]
+        {
+            data.extend_from_slice(&word.to_le_bytes());
+        }
+        let set = read_vgrid(&mut data.as_slice()).unwrap();
+        assert_eq!(set.grids["fire"].value(-1, 2, 3), 7.0);
+
+        // Truncated, oversized and foreign files are rejected
+        assert!(read_vgrid(&mut &data[..data.len() - 2]).is_err());
+        let mut oversized = Vec::new();
+        write_vgrid(&placement, &[("density", &dense)], &mut oversized).unwrap();
+        let size_at = oversized.len() - 24 * 4 - 12;
+        oversized[size_at..size_at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(read_vgrid(&mut oversized.as_slice()).err().map(|error| error.kind()), Some(ErrorKind::InvalidData));
+        assert!(read_vgrid(&mut &b"PF\n1 1\n"[..]).is_err());
+    }
+
+    #[test]
+    fn nvdb_test()
+    {
+        let mut source = String::from("# smoke\nvoxel_size 0.1\norigin 0 1 0\ngrid density\nleaf 8 0 -8\n");
+        for offset in 0..LEAF_VOXELS
+        {
+            source += &format!("{}{}", offset, if offset % 64 == 63 { "\n" } else { " " });
+        }
+        source += "voxel 100 0 0 2\ngrid temperature 300\nvoxel 0 0 0 1500\n";
+        let set = parse_nvdb(&source).unwrap();
+        assert_eq!(set.placement, GridPlacement{ origin: Point::make_new(0.0, 1.0, 0.0), voxel_size: 0.1 });
+        let density = &set.grids["density"];
+        // z varies the fastest inside a leaf
+        assert_eq!(density.value(8, 0, -8), 0.0);
+        assert_eq!(density.value(8, 0, -7), 1.0);
+        assert_eq!(density.value(8, 1, -8), 8.0);
+        assert_eq!(density.value(9, 0, -8), 64.0);
+        assert_eq!(density.value(15, 7, -1), 511.0);
+        assert_eq!(density.value(100, 0, 0), 2.0);
+        assert_eq!(density.max_value(), 511.0);
+        let temperature = &set.grids["temperature"];
+        assert_eq!(temperature.value(0, 0, 0), 1500.0);
+        assert_eq!(temperature.value(0, 0, 1), 300.0);
+
+        let error = parse_nvdb("grid density\nleaf 4 0 0\n").err().unwrap();
+        assert_eq!((error.line, error.column), (2, 6));
+        assert_eq!(error.message, "leaf origins are multiples of 8");
+
+        let error = parse_nvdb("grid density\nleaf 0 0 0\n1 2 3\n").err().unwrap();
+        assert_eq!((error.line, error.column), (2, 1));
+        assert_eq!(error.message, "the leaf has 3 of its 512 values");
+
+        let error = parse_nvdb("voxel 0 0 0 1\n").err().unwrap();
+        assert_eq!((error.line, error.column), (1, 1));
+
+        let error = parse_nvdb("grid a\ngrid a\n").err().unwrap();
+        assert_eq!((error.line, error.column), (2, 6));
+
+        let error = parse_nvdb("grid a\nvoxel 0 0 0\n").err().unwrap();
+        assert_eq!((error.line, error.column), (2, 12));
+
+        let error = parse_nvdb("grid a\nvoxel 0 0.5 0 1\n").err().unwrap();
+        assert_eq!((error.line, error.column), (2, 9));
+        assert_eq!(error.message, "expected an integer, found '0.5'");
+    }
+}
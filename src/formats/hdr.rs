@@ -0,0 +1,385 @@
+use std::io::{Error, ErrorKind, Read, Write};
+
+use super::super::math::framebuffer::FrameBuffer;
+use super::super::utils::color::FColor;
+
+// Radiance RGBE (.hdr) encoder and decoder.
+// Every pixel shares one 8-bit exponent between its three mantissas, and scanlines are run length encoded
+// one component at a time when the width allows it.
+
+const MIN_RUN_LENGTH: usize = 4;
+const MAX_RUN_LENGTH: usize = 127;
+const MAX_DUMP_LENGTH: usize = 128;
+
+pub fn to_rgbe(color: &FColor) -> [u8; 4]
+{
+    let (r, g, b) = (color.r().max(0.0), color.g().max(0.0), color.b().max(0.0));
+    let value = r.max(g).max(b);
+    if value < 1e-32 || !value.is_finite()
+    {
+        return [0, 0, 0, 0];
+    }
+    // value = mantissa * 2^exponent with mantissa in [0.5, 1)
+    let mut exponent = value.log2().floor() as i32 + 1;
+    if value / 2f64.powi(exponent) >= 1.0
+    {
+        exponent += 1;
+    }
+    else if value / 2f64.powi(exponent) < 0.5
+    {
+        exponent -= 1;
+    }
+    let exponent = exponent.clamp(-128, 127);
+    let scale = 256.0 / 2f64.powi(exponent);
+    [(r * scale).min(255.0) as u8, (g * scale).min(255.0) as u8, (b * scale).min(255.0) as u8, (exponent + 128) as u8]
+}
+
+pub fn from_rgbe(rgbe: [u8; 4]) -> FColor
+{
+    if rgbe[3] == 0
+    {
+        return FColor::new_color(0.0, 0.0, 0.0);
+    }
+    let scale = 2f64.powi(rgbe[3] as i32 - 128 - 8);
+    // Decoding at the middle of the quantization step
+    FColor::new_color((rgbe[0] as f64 + 0.5) * scale, (rgbe[1] as f64 + 0.5) * scale, (rgbe[2] as f64 + 0.5) * scale)
+}
+
+// Runs of at least MIN_RUN_LENGTH equal bytes become (128 + count, byte), everything else is dumped as (count, bytes...)
+fn encode_component(data: &[u8], output: &mut Vec<u8>)
+{
+    let mut current = 0;
+    while current < data.len()
+    {
+        // Find the next run that is long enough
+        let mut run_start = current;
+        let mut run_length = 0;
+        while run_start < data.len()
+        {
+            run_length = data[run_start..].iter().take(MAX_RUN_LENGTH).take_while(|byte| **byte == data[run_start]).count();
+            if run_length >= MIN_RUN_LENGTH
+            {
+                break;
+            }
+            run_start += run_length;
+        }
+        if run_start >= data.len()
+        {
+            run_length = 0;
+        }
+
+        while current < run_start
+        {
+            let dump_length = MAX_DUMP_LENGTH.min(run_start - current);
+            output.push(dump_length as u8);
+            output.extend_from_slice(&data[current..current + dump_length]);
+            current += dump_length;
+        }
+        if run_length >= MIN_RUN_LENGTH
+        {
+            output.push((128 + run_length) as u8);
+            output.push(data[run_start]);
+            current += run_length;
+        }
+    }
+}
+
+pub fn write_hdr<W: Write>(fb: &FrameBuffer, file: &mut W) -> Result<(), Error>
+{
+    file.write_all(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n")?;
+    file.write_all(format!("-Y {} +X {}\n", fb.height(), fb.width()).as_bytes())?;
+
+    // The run length encoding can only describe scanlines between 8 and 32767 pixels wide
+    let encode = (8..32768).contains(&fb.width());
+    let mut components: Vec<Vec<u8>> = (0..4).map(|_| Vec::with_capacity(fb.width())).collect();
+    let mut scanline = Vec::with_capacity(fb.width() * 4);
+    for y in 0..fb.height()
+    {
+        scanline.clear();
+        if encode
+        {
+            for component in &mut components
+            {
+                component.clear();
+            }
+            for pixel in fb.row(y)
+            {
+                for (component, byte) in components.iter_mut().zip(to_rgbe(pixel))
+                {
+                    component.push(byte);
+                }
+            }
+            scanline.extend_from_slice(&[2, 2, (fb.width() >> 8) as u8, (fb.width() & 0xFF) as u8]);
+            for component in &components
+            {
+                encode_component(component, &mut scanline);
+            }
+        }
+        else
+        {
+            scanline.extend(fb.row(y).iter().flat_map(to_rgbe));
+        }
+        file.write_all(&scanline)?;
+    }
+    Ok(())
+}
+
+fn invalid_data(message: &str) -> Error
+{
+    Error::new(ErrorKind::InvalidData, format!("invalid Radiance HDR file: {}", message))
+}
+
+// Reads one line of the text header, without the newline
+fn read_line<'a>(data: &'a [u8], position: &mut usize) -> Result<&'a str, Error>
+{
+    let rest = &data[*position..];
+    let length = rest.iter().position(|byte| *byte == b'\n').ok_or_else(|| invalid_data("truncated header"))?;
+    *position += length + 1;
+    std::str::from_utf8(&rest[..length]).map_err(|_| invalid_data("the header isn't text"))
+}
+
+// Decodes one scanline of width pixels starting at position, in any of the three layouts
+fn read_scanline(data: &[u8], position: &mut usize, width: usize, scanline: &mut Vec<[u8; 4]>) -> Result<(), Error>
+{
+    let truncated = || invalid_data("truncated pixel data");
+    scanline.clear();
+    let start = data.get(*position..*position + 4).ok_or_else(truncated)?;
+    if (8..32768).contains(&width) && start[0] == 2 && start[1] == 2 && start[2] & 0x80 == 0
+    {
+        if ((start[2] as usize) << 8 | start[3] as usize) != width
+        {
+            return Err(invalid_data("scanline width mismatch"));
+        }
+        *position += 4;
+        scanline.resize(width, [0; 4]);
+        // Every component is run length encoded on its own
+        for component in 0..4
+        {
+            let mut x = 0;
+            while x < width
+            {
+                let count = *data.get(*position).ok_or_else(truncated)? as usize;
+                *position += 1;
+                if count > 128
+                {
+                    let count = count - 128;
+                    let value = *data.get(*position).ok_or_else(truncated)?;
+                    *position += 1;
+                    if x + count > width
+                    {
+                        return Err(invalid_data("run past the end of a scanline"));
+                    }
+                    scanline[x..x + count].iter_mut().for_each(|pixel| pixel[component] = value);
+                    x += count;
+                }
+                else
+                {
+                    if count == 0 || x + count > width
+                    {
+                        return Err(invalid_data("bad run length"));
+                    }
+                    let values = data.get(*position..*position + count).ok_or_else(truncated)?;
+                    *position += count;
+                    scanline[x..x + count].iter_mut().zip(values).for_each(|(pixel, value)| pixel[component] = *value);
+                    x += count;
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    // Flat pixels, where (1, 1, 1, n) repeats the previous pixel in the original run length encoding
+    let mut shift = 0;
+    while scanline.len() < width
+    {
+        let bytes = data.get(*position..*position + 4).ok_or_else(truncated)?;
+        *position += 4;
+        let pixel = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        match scanline.last().copied()
+        {
+            Some(previous) if pixel[0] == 1 && pixel[1] == 1 && pixel[2] == 1 =>
+            {
+                // Consecutive runs add the next byte of the count, which has at most four of them
+                if shift > 24
+                {
+                    return Err(invalid_data("run length too long"));
+                }
+                let count = (pixel[3] as usize) << shift;
+                if scanline.len() + count > width
+                {
+                    return Err(invalid_data("run past the end of a scanline"));
+                }
+                scanline.extend(std::iter::repeat_n(previous, count));
+                shift += 8;
+            }
+            _ =>
+            {
+                scanline.push(pixel);
+                shift = 0;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn read_hdr<R: Read>(file: &mut R) -> Result<FrameBuffer, Error>
+{
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    let mut position = 0;
+
+    let magic = read_line(&data, &mut position)?;
+    if !magic.starts_with("#?")
+    {
+        return Err(invalid_data("missing the #? signature"));
+    }
+    loop
+    {
+        let line = read_line(&data, &mut position)?;
+        if line.is_empty()
+        {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=")
+        {
+            if format.trim() != "32-bit_rle_rgbe"
+            {
+                return Err(invalid_data(&format!("unsupported pixel format {}", format)));
+            }
+        }
+    }
+
+    // Only the usual orientations, rows from the top (-Y) or the bottom (+Y) and columns from the left
+    let resolution: Vec<&str> = read_line(&data, &mut position)?.split_whitespace().collect();
+    let (top_down, height, width) = match resolution[..]
+    {
+        [y_sign, height, "+X", width] if y_sign == "-Y" || y_sign == "+Y" =>
+        {
+            let height = height.parse::<usize>().map_err(|_| invalid_data("bad height"))?;
+            let width = width.parse::<usize>().map_err(|_| invalid_data("bad width"))?;
+            (y_sign == "-Y", height, width)
+        }
+        _ => return Err(invalid_data("unsupported resolution line")),
+    };
+    if width == 0 || height == 0
+    {
+        return Err(invalid_data("empty image"));
+    }
+
+    let mut fb = FrameBuffer::new(width, height);
+    let mut scanline = Vec::with_capacity(width);
+    for row in 0..height
+    {
+        read_scanline(&data, &mut position, width, &mut scanline)?;
+        let y = if top_down { row } else { height - 1 - row };
+        for (x, rgbe) in scanline.iter().enumerate()
+        {
+            fb.set(x, y, from_rgbe(*rgbe));
+        }
+    }
+    Ok(fb)
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn rgbe_test()
+    {
+        assert_eq!(to_rgbe(&FColor::new_color(0.0, 0.0, 0.0)), [0, 0, 0, 0]);
+        assert_eq!(to_rgbe(&FColor::new_color(1.0, 0.5, 0.25)), [128, 64, 32, 129]);
+        assert_eq!(to_rgbe(&FColor::new_color(-1.0, 0.5, 0.0)), [0, 128, 0, 128]);
+
+        for color in [FColor::new_color(1000.0, 3.5, 0.01), FColor::new_color(0.001, 0.002, 0.003), FColor::new_color(0.75, 0.75, 0.75)]
+        {
+            let decoded = from_rgbe(to_rgbe(&color));
+            let largest = color.r().max(color.g()).max(color.b());
+            // One quantization step of the largest channel
+            assert!((decoded - color).length() <= largest / 128.0);
+        }
+    }
+
+    #[test]
+    fn run_length_test()
+    {
+        let mut output = Vec::new();
+        encode_component(&[1, 2, 3, 3, 3, 3, 3, 4, 4], &mut output);
+        assert_eq!(output, vec![2, 1, 2, 133, 3, 2, 4, 4]);
+
+        output.clear();
+        let long_run = vec![9; 300];
+        encode_component(&long_run, &mut output);
+        assert_eq!(output, vec![255, 9, 255, 9, 128 + 46, 9]);
+    }
+
+    #[test]
+    fn write_test()
+    {
+        let mut fb = FrameBuffer::new(10, 1);
+        fb.set(9, 0, FColor::new_color(2.0, 2.0, 2.0));
+        let mut bytes = Vec::new();
+        write_hdr(&fb, &mut bytes).unwrap();
+
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 10\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let mut expected = vec![2, 2, 0, 10];
+        for component in [128, 128, 128, 130]
+        {
+            // Nine zeros then the one bright pixel
+            expected.extend_from_slice(&[128 + 9, 0, 1, component]);
+        }
+        assert_eq!(&bytes[header.len()..], &expected[..]);
+    }
+
+    #[test]
+    fn read_test()
+    {
+        // Wide enough for run length encoding, and too narrow for it
+        for width in [20, 3]
+        {
+            let mut fb = FrameBuffer::new(width, 4);
+            for y in 0..4
+            {
+                for x in 0..width
+                {
+                    fb.set(x, y, FColor::new_color(x as f64 * 0.5, y as f64 * 10.0, if x == y { 1000.0 } else { 0.0 }));
+                }
+            }
+            let mut bytes = Vec::new();
+            write_hdr(&fb, &mut bytes).unwrap();
+            let decoded = read_hdr(&mut bytes.as_slice()).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (width, 4));
+            for (expected, pixel) in fb.pixels().iter().zip(decoded.pixels())
+            {
+                assert_eq!(from_rgbe(to_rgbe(expected)), *pixel);
+            }
+        }
+
+        // Old style runs repeat the previous pixel
+        let mut bytes = b"#?RGBE\n\n+Y 1 +X 5\n".to_vec();
+        bytes.extend_from_slice(&[128, 64, 0, 129, 1, 1, 1, 3, 0, 0, 0, 0]);
+        let decoded = read_hdr(&mut bytes.as_slice()).unwrap();
+        assert_eq!(*decoded.get(3, 0), FColor::new_color(1.00390625, 0.50390625, 0.00390625));
+        assert!(decoded.get(4, 0).is_zero());
+
+        // Endless chains of empty runs are rejected instead of overflowing the count
+        let mut bytes = b"#?RGBE\n\n+Y 1 +X 5\n".to_vec();
+        bytes.extend_from_slice(&[128, 64, 0, 129]);
+        for _ in 0..9
+        {
+            bytes.extend_from_slice(&[1, 1, 1, 0]);
+        }
+        assert!(read_hdr(&mut bytes.as_slice()).is_err());
+
+        assert!(read_hdr(&mut b"P6\n".as_slice()).is_err());
+        assert!(read_hdr(&mut b"#?RADIANCE\n\n-Y 2 +X 2\n\x10\x10".as_slice()).is_err());
+    }
+}
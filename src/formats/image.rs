@@ -0,0 +1,96 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+
+use super::super::math::framebuffer::FrameBuffer;
+use super::png::{read_png, write_png};
+use super::ppm::read_ppm;
+use super::pfm::{read_pfm, write_pfm};
+use super::hdr::{read_hdr, write_hdr};
+use super::exr::{write_exr, ExrOptions};
+use super::zlib::Compression;
+
+// Output image formats, picked by name on the command line or from the output file extension.
+// PPM and PNG are gamma corrected 8-bit images, PFM, HDR and EXR keep the linear radiance of the frame buffer.
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ImageFormat
+{
+    Ppm,
+    Png,
+    Pfm,
+    Hdr,
+    Exr,
+}
+
+impl ImageFormat
+{
+    pub const ALL: [ImageFormat; 5] = [ImageFormat::Ppm, ImageFormat::Png, ImageFormat::Pfm, ImageFormat::Hdr, ImageFormat::Exr];
+
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            ImageFormat::Ppm => "ppm",
+            ImageFormat::Png => "png",
+            ImageFormat::Pfm => "pfm",
+            ImageFormat::Hdr => "hdr",
+            ImageFormat::Exr => "exr",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str
+    {
+        self.name()
+    }
+
+    pub fn from_name(name: &str) -> Option<ImageFormat>
+    {
+        ImageFormat::ALL.into_iter().find(|format| format.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn from_path(path: &Path) -> Option<ImageFormat>
+    {
+        let extension = path.extension()?.to_str()?;
+        ImageFormat::ALL.into_iter().find(|format| format.extension().eq_ignore_ascii_case(extension))
+    }
+}
+
+pub fn write_image(fb: &FrameBuffer, path: &Path, format: ImageFormat, exr_options: &ExrOptions) -> Result<(), Error>
+{
+    let mut file = BufWriter::new(File::create(path)?);
+    match format
+    {
+        ImageFormat::Ppm =>
+        {
+            file.write_all(b"P3\n")?;
+            file.write_all(format!("{} {}\n", fb.width(), fb.height()).as_bytes())?;
+            file.write_all(b"255\n")?;
+            fb.write_to_file(&mut file)?;
+        }
+        ImageFormat::Png => write_png(fb, &mut file, Compression::Deflate)?,
+        ImageFormat::Pfm => write_pfm(fb, &mut file)?,
+        ImageFormat::Hdr => write_hdr(fb, &mut file)?,
+        ImageFormat::Exr => write_exr(fb, &mut file, exr_options)?,
+    }
+    file.flush()
+}
+
+// Loads any of the formats except OpenEXR, e.g. for textures and environment maps.
+// 8-bit formats are converted back to linear radiance.
+pub fn read_image(path: &Path) -> Result<FrameBuffer, Error>
+{
+    let format = ImageFormat::from_path(path);
+    if matches!(format, None | Some(ImageFormat::Exr))
+    {
+        return Err(Error::new(ErrorKind::Unsupported, "only .ppm, .png, .hdr and .pfm images can be loaded"));
+    }
+    let mut file = BufReader::new(File::open(path)?);
+    match format
+    {
+        Some(ImageFormat::Ppm) => read_ppm(&mut file),
+        Some(ImageFormat::Png) => read_png(&mut file),
+        Some(ImageFormat::Hdr) => read_hdr(&mut file),
+        _ => read_pfm(&mut file),
+    }
+}
@@ -0,0 +1,166 @@
+use std::fmt;
+use std::path::PathBuf;
+
+pub mod obj;
+pub mod scene;
+pub mod image;
+pub mod zlib;
+pub mod png;
+pub mod ppm;
+pub mod pfm;
+pub mod hdr;
+pub mod exr;
+pub mod grid;
+
+// A syntax or semantic error in a text file, pointing at the offending token
+#[derive(Debug, PartialEq)]
+pub struct ParseError
+{
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError
+{
+    pub fn new(line: usize, column: usize, message: &str) -> Self
+    {
+        ParseError
+        {
+            line,
+            column,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn make_new(line: usize, column: usize, message: String) -> Self
+    {
+        ParseError
+        {
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ParseError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug)]
+pub enum LoadError
+{
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, ParseError),
+}
+
+impl fmt::Display for LoadError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            LoadError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            LoadError::Parse(path, error) => write!(f, "{}:{}:{}: {}", path.display(), error.line, error.column, error.message),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+// A whitespace separated word of a line, with its 1-based column for error reporting
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Token<'a>
+{
+    pub text: &'a str,
+    pub column: usize,
+}
+
+// Splits a line into tokens, dropping everything after a '#' comment marker
+pub fn tokenize(line: &str) -> Vec<Token<'_>>
+{
+    let line = match line.find('#')
+    {
+        Some(comment_start) => &line[..comment_start],
+        None => line,
+    };
+
+    let mut tokens = Vec::new();
+    let mut start: Option<usize> = None;
+    for (ind, c) in line.char_indices()
+    {
+        if c.is_whitespace()
+        {
+            if let Some(token_start) = start.take()
+            {
+                tokens.push(Token{ text: &line[token_start..ind], column: line[..token_start].chars().count() + 1 });
+            }
+        }
+        else if start.is_none()
+        {
+            start = Some(ind);
+        }
+    }
+    if let Some(token_start) = start
+    {
+        tokens.push(Token{ text: &line[token_start..], column: line[..token_start].chars().count() + 1 });
+    }
+    tokens
+}
+
+// Infinities and NaNs are rejected, no value in the file formats can take them
+pub fn parse_f64(line: usize, token: &Token) -> Result<f64, ParseError>
+{
+    match token.text.parse::<f64>()
+    {
+        Ok(value) if value.is_finite() => Ok(value),
+        _ => Err(ParseError::make_new(line, token.column, format!("expected a number, found '{}'", token.text))),
+    }
+}
+
+pub fn parse_usize(line: usize, token: &Token) -> Result<usize, ParseError>
+{
+    token.text.parse::<usize>().map_err(|_| ParseError::make_new(line, token.column, format!("expected a non-negative integer, found '{}'", token.text)))
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn tokenize_test()
+    {
+        let tokens = tokenize("  v 1.0\t-2  3 # a comment");
+        assert_eq!(tokens, vec![Token{ text: "v", column: 3 }, Token{ text: "1.0", column: 5 },
+                                Token{ text: "-2", column: 9 }, Token{ text: "3", column: 13 }]);
+        assert!(tokenize("# only a comment").is_empty());
+        assert!(tokenize("").is_empty());
+    }
+
+    #[test]
+    fn parse_f64_test()
+    {
+        assert_eq!(parse_f64(1, &Token{ text: "2.5", column: 1 }), Ok(2.5));
+        assert_eq!(parse_f64(4, &Token{ text: "x", column: 7 }),
+                   Err(ParseError::new(4, 7, "expected a number, found 'x'")));
+        assert_eq!(parse_f64(2, &Token{ text: "nan", column: 3 }),
+                   Err(ParseError::new(2, 3, "expected a number, found 'nan'")));
+        assert_eq!(parse_f64(2, &Token{ text: "-inf", column: 3 }),
+                   Err(ParseError::new(2, 3, "expected a number, found '-inf'")));
+        assert!(parse_f64(2, &Token{ text: "1e400", column: 3 }).is_err());
+    }
+}
@@ -0,0 +1,605 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::Arc;
+
+use super::*;
+use super::super::math::hittable::HittableList;
+use super::super::math::material::*;
+use super::super::math::point::Point;
+use super::super::math::principled::*;
+use super::super::math::texture::SolidColor;
+use super::super::math::triangle::*;
+use super::super::math::vect::Vect;
+use super::super::utils::color::FColor;
+
+// Wavefront OBJ and MTL loading.
+// Supported OBJ statements: v, vn, vt, f (any polygon, fan triangulated, negative indices), mtllib and usemtl.
+// Every material used by the file becomes its own TriangleMesh. Other statements (g, o, s, l, ...) are ignored.
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MtlMaterial
+{
+    pub name: String,
+    pub diffuse: FColor,
+    pub specular: FColor,
+    // Radiance given off by the surface (Ke), black for materials that don't glow
+    pub emission: FColor,
+    pub shininess: f64,
+    pub index_of_refraction: f64,
+    pub dissolve: f64,
+    pub illumination_model: u32,
+    // Parameters of the PBR extension (Pr, Pm, Ps, Pc, Pcr, aniso), set when any of them appears
+    pub physically_based: bool,
+    pub roughness: f64,
+    pub metallic: f64,
+    pub sheen: f64,
+    pub clearcoat: f64,
+    pub clearcoat_roughness: f64,
+    pub anisotropy: f64,
+}
+
+impl MtlMaterial
+{
+    pub fn new(name: &str) -> Self
+    {
+        MtlMaterial
+        {
+            name: name.to_string(),
+            diffuse: FColor::new_color(0.8, 0.8, 0.8),
+            specular: FColor::new_color(0.0, 0.0, 0.0),
+            emission: FColor::new_color(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            index_of_refraction: 1.5,
+            dissolve: 1.0,
+            illumination_model: 2,
+            physically_based: false,
+            roughness: 0.5,
+            metallic: 0.0,
+            sheen: 0.0,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.0,
+            anisotropy: 0.0,
+        }
+    }
+
+    // Maps the MTL parameters onto the closest of the renderer's materials: glowing ones become DiffuseLight,
+    // PBR materials Principled, transparent or refracting models Dielectric, reflective ones Metal, everything else Lambertian.
+    pub fn to_material(&self) -> Arc<dyn Material>
+    {
+        if !self.emission.is_zero()
+        {
+            return Arc::new(DiffuseLight::make_new(self.emission));
+        }
+        if self.physically_based
+        {
+            let mut parameters = PrincipledParameters::default();
+            parameters.base_color = Arc::new(SolidColor::make_new(self.diffuse));
+            parameters.index_of_refraction = self.index_of_refraction;
+            for (name, value) in [("roughness", self.roughness), ("metallic", self.metallic), ("sheen", self.sheen), ("clearcoat", self.clearcoat),
+                                  ("clearcoat_gloss", 1.0 - self.clearcoat_roughness), ("anisotropic", self.anisotropy), ("transmission", 1.0 - self.dissolve)]
+            {
+                parameters.set_constant(name, value);
+            }
+            return Arc::new(Principled::make_new(parameters));
+        }
+        let max_component = |c: &FColor| c.r().max(c.g()).max(c.b());
+
+        if self.dissolve < 1.0 || matches!(self.illumination_model, 4 | 6 | 7 | 9)
+        {
+            return Arc::new(Dielectric::make_new(self.index_of_refraction));
+        }
+        if matches!(self.illumination_model, 3 | 5 | 8) || max_component(&self.specular) > max_component(&self.diffuse)
+        {
+            let albedo = if max_component(&self.specular) > 0.0 { self.specular } else { self.diffuse };
+            // Phong exponent to the matching GGX width sqrt(2 / (n + 2)), an exponent of 0 is as rough as it gets
+            let roughness = (2.0 / (self.shininess.max(0.0) + 2.0)).powf(0.25);
+            return Arc::new(Metal::make_new(albedo, roughness));
+        }
+        Arc::new(Lambertian::make_new(self.diffuse))
+    }
+}
+
+fn parse_color(line: usize, keyword: &Token, arguments: &[Token]) -> Result<FColor, ParseError>
+{
+    match arguments.len()
+    {
+        // A single value is a grey level
+        1 =>
+        {
+            let value = parse_f64(line, &arguments[0])?;
+            Ok(FColor::new_color(value, value, value))
+        }
+        3 => Ok(FColor::new_color(parse_f64(line, &arguments[0])?, parse_f64(line, &arguments[1])?, parse_f64(line, &arguments[2])?)),
+        _ => Err(ParseError::make_new(line, keyword.column, format!("'{}' expects 1 or 3 values", keyword.text))),
+    }
+}
+
+// Material names may contain spaces, so they are made of every argument
+fn parse_name(arguments: &[Token]) -> String
+{
+    arguments.iter().map(|argument| argument.text).collect::<Vec<_>>().join(" ")
+}
+
+fn parse_single(line: usize, keyword: &Token, arguments: &[Token]) -> Result<f64, ParseError>
+{
+    if arguments.len() != 1
+    {
+        return Err(ParseError::make_new(line, keyword.column, format!("'{}' expects 1 value", keyword.text)));
+    }
+    parse_f64(line, &arguments[0])
+}
+
+pub fn parse_mtl(source: &str) -> Result<HashMap<String, MtlMaterial>, ParseError>
+{
+    let mut materials = HashMap::new();
+    let mut current: Option<MtlMaterial> = None;
+
+    for (line_index, line) in source.lines().enumerate()
+    {
+        let line_number = line_index + 1;
+        let tokens = tokenize(line);
+        let Some((keyword, arguments)) = tokens.split_first() else { continue };
+
+        if keyword.text == "newmtl"
+        {
+            if arguments.is_empty()
+            {
+                return Err(ParseError::new(line_number, keyword.column, "'newmtl' expects a material name"));
+            }
+            if let Some(material) = current.take()
+            {
+                materials.insert(material.name.clone(), material);
+            }
+            current = Some(MtlMaterial::new(&parse_name(arguments)));
+            continue;
+        }
+
+        let Some(material) = current.as_mut()
+        else
+        {
+            return Err(ParseError::make_new(line_number, keyword.column, format!("'{}' before any 'newmtl'", keyword.text)));
+        };
+
+        match keyword.text
+        {
+            "Kd" => material.diffuse = parse_color(line_number, keyword, arguments)?,
+            "Ks" => material.specular = parse_color(line_number, keyword, arguments)?,
+            "Ke" => material.emission = parse_color(line_number, keyword, arguments)?,
+            "Ns" => material.shininess = parse_single(line_number, keyword, arguments)?,
+            "Ni" => material.index_of_refraction = parse_single(line_number, keyword, arguments)?,
+            "d" => material.dissolve = parse_single(line_number, keyword, arguments)?,
+            "Tr" => material.dissolve = 1.0 - parse_single(line_number, keyword, arguments)?,
+            "illum" =>
+            {
+                let value = parse_single(line_number, keyword, arguments)?;
+                if value < 0.0 || value.fract() != 0.0
+                {
+                    return Err(ParseError::new(line_number, arguments[0].column, "'illum' expects a non-negative integer"));
+                }
+                material.illumination_model = value as u32;
+            }
+            "Pr" | "Pm" | "Ps" | "Pc" | "Pcr" | "aniso" =>
+            {
+                let value = parse_single(line_number, keyword, arguments)?;
+                material.physically_based = true;
+                match keyword.text
+                {
+                    "Pr" => material.roughness = value,
+                    "Pm" => material.metallic = value,
+                    "Ps" => material.sheen = value,
+                    "Pc" => material.clearcoat = value,
+                    "Pcr" => material.clearcoat_roughness = value,
+                    _ => material.anisotropy = value,
+                }
+            }
+            // Texture maps and other parameters have no equivalent in the renderer
+            _ => {}
+        }
+    }
+
+    if let Some(material) = current.take()
+    {
+        materials.insert(material.name.clone(), material);
+    }
+    Ok(materials)
+}
+
+pub fn load_mtl(path: &Path) -> Result<HashMap<String, MtlMaterial>, LoadError>
+{
+    let source = fs::read_to_string(path).map_err(|error| LoadError::Io(path.to_path_buf(), error))?;
+    parse_mtl(&source).map_err(|error| LoadError::Parse(path.to_path_buf(), error))
+}
+
+// Faces that share a material, with indices into the file-wide vertex buffers
+struct FaceGroup
+{
+    material: Option<String>,
+    faces: Vec<MeshFace>,
+}
+
+struct ObjParser<'a>
+{
+    path: &'a Path,
+    positions: Vec<Point>,
+    normals: Vec<Vect>,
+    uvs: Vec<Vect<2>>,
+    groups: Vec<FaceGroup>,
+    current_group: Option<usize>,
+    current_material: Option<String>,
+    materials: HashMap<String, MtlMaterial>,
+}
+
+impl<'a> ObjParser<'a>
+{
+    fn error(&self, line: usize, column: usize, message: String) -> LoadError
+    {
+        LoadError::Parse(self.path.to_path_buf(), ParseError::make_new(line, column, message))
+    }
+
+    fn parse_vector<const COUNT: usize>(&self, line: usize, keyword: &Token, arguments: &[Token], required: usize) -> Result<Vect<COUNT>, LoadError>
+    {
+        if arguments.len() < required || arguments.len() > COUNT + 1
+        {
+            return Err(self.error(line, keyword.column, format!("'{}' expects {} to {} values", keyword.text, required, COUNT + 1)));
+        }
+        let mut result = Vect::<COUNT>::new();
+        // Trailing homogeneous (w) coordinates are accepted but ignored
+        for (ind, argument) in arguments.iter().enumerate().take(COUNT)
+        {
+            result[ind] = parse_f64(line, argument).map_err(|error| LoadError::Parse(self.path.to_path_buf(), error))?;
+        }
+        Ok(result)
+    }
+
+    // Resolves a 1-based or negative (relative to the end) OBJ index to a 0-based one
+    fn resolve_index(&self, line: usize, column: usize, text: &str, count: usize, kind: &str) -> Result<usize, LoadError>
+    {
+        let index = text.parse::<i64>().map_err(|_| self.error(line, column, format!("invalid {} index '{}'", kind, text)))?;
+        let resolved = if index > 0 { index - 1 } else { count as i64 + index };
+        if index == 0 || resolved < 0 || resolved >= count as i64
+        {
+            return Err(self.error(line, column, format!("{} index {} is out of range", kind, index)));
+        }
+        Ok(resolved as usize)
+    }
+
+    fn parse_face(&mut self, line: usize, keyword: &Token, arguments: &[Token]) -> Result<(), LoadError>
+    {
+        if arguments.len() < 3
+        {
+            return Err(self.error(line, keyword.column, "a face needs at least 3 vertices".to_string()));
+        }
+
+        let mut corners = Vec::with_capacity(arguments.len());
+        for argument in arguments
+        {
+            let mut parts = argument.text.split('/');
+            let position = self.resolve_index(line, argument.column, parts.next().unwrap_or(""), self.positions.len(), "vertex")?;
+            let uv = match parts.next()
+            {
+                Some(text) if !text.is_empty() => Some(self.resolve_index(line, argument.column, text, self.uvs.len(), "texture coordinate")?),
+                _ => None,
+            };
+            let normal = match parts.next()
+            {
+                Some(text) if !text.is_empty() => Some(self.resolve_index(line, argument.column, text, self.normals.len(), "normal")?),
+                _ => None,
+            };
+            if parts.next().is_some()
+            {
+                return Err(self.error(line, argument.column, format!("malformed face vertex '{}'", argument.text)));
+            }
+            corners.push((position, uv, normal));
+        }
+
+        let group = match self.current_group
+        {
+            Some(group) => group,
+            None =>
+            {
+                let material = self.current_material.clone();
+                let group = match self.groups.iter().position(|group| group.material == material)
+                {
+                    Some(group) => group,
+                    None =>
+                    {
+                        self.groups.push(FaceGroup{ material, faces: Vec::new() });
+                        self.groups.len() - 1
+                    }
+                };
+                self.current_group = Some(group);
+                group
+            }
+        };
+
+        // Fan triangulation around the first corner
+        for ind in 1..corners.len() - 1
+        {
+            let triangle = [corners[0], corners[ind], corners[ind + 1]];
+            let uvs = triangle.iter().map(|corner| corner.1).collect::<Option<Vec<usize>>>();
+            let normals = triangle.iter().map(|corner| corner.2).collect::<Option<Vec<usize>>>();
+            self.groups[group].faces.push(MeshFace::make_new(
+                [triangle[0].0, triangle[1].0, triangle[2].0],
+                normals.map(|n| [n[0], n[1], n[2]]),
+                uvs.map(|uv| [uv[0], uv[1], uv[2]]),
+            ));
+        }
+        Ok(())
+    }
+
+    fn parse(&mut self, source: &str) -> Result<(), LoadError>
+    {
+        for (line_index, line) in source.lines().enumerate()
+        {
+            let line_number = line_index + 1;
+            let tokens = tokenize(line);
+            let Some((keyword, arguments)) = tokens.split_first() else { continue };
+
+            match keyword.text
+            {
+                "v" =>
+                {
+                    let position = self.parse_vector::<3>(line_number, keyword, arguments, 3)?;
+                    self.positions.push(position);
+                }
+                "vn" =>
+                {
+                    let normal = self.parse_vector::<3>(line_number, keyword, arguments, 3)?;
+                    self.normals.push(normal);
+                }
+                "vt" =>
+                {
+                    let uv = self.parse_vector::<2>(line_number, keyword, arguments, 1)?;
+                    self.uvs.push(uv);
+                }
+                "f" => self.parse_face(line_number, keyword, arguments)?,
+                "mtllib" =>
+                {
+                    if arguments.is_empty()
+                    {
+                        return Err(self.error(line_number, keyword.column, "'mtllib' expects a file name".to_string()));
+                    }
+                    let directory = self.path.parent().unwrap_or(Path::new(""));
+                    for argument in arguments
+                    {
+                        self.materials.extend(load_mtl(&directory.join(argument.text))?);
+                    }
+                }
+                "usemtl" =>
+                {
+                    let name = parse_name(arguments);
+                    if !self.materials.contains_key(&name)
+                    {
+                        return Err(self.error(line_number, keyword.column, format!("undefined material '{}'", name)));
+                    }
+                    self.current_material = Some(name);
+                    self.current_group = None;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Builds one mesh per material group, each with compact buffers holding only the vertices it uses
+    fn build(self) -> Result<HittableList, LoadError>
+    {
+        let mut world = HittableList::default();
+        let default_material: Arc<dyn Material> = Arc::new(Lambertian::make_new(MtlMaterial::new("").diffuse));
+        let mut converted: HashMap<&str, Arc<dyn Material>> = HashMap::new();
+
+        for group in &self.groups
+        {
+            let material = match &group.material
+            {
+                Some(name) => converted.entry(name.as_str()).or_insert_with(|| self.materials[name].to_material()).clone(),
+                None => default_material.clone(),
+            };
+
+            let mut position_map = HashMap::new();
+            let mut normal_map = HashMap::new();
+            let mut uv_map = HashMap::new();
+            let mut positions = Vec::new();
+            let mut normals = Vec::new();
+            let mut uvs = Vec::new();
+
+            fn remap<T: Copy>(index: usize, source: &[T], map: &mut HashMap<usize, usize>, target: &mut Vec<T>) -> usize
+            {
+                *map.entry(index).or_insert_with(||
+                    {
+                        target.push(source[index]);
+                        target.len() - 1
+                    })
+            }
+
+            let faces = group.faces.iter().map(|face|
+                {
+                    MeshFace::make_new(
+                        face.positions.map(|ind| remap(ind, &self.positions, &mut position_map, &mut positions)),
+                        face.normals.map(|indices| indices.map(|ind| remap(ind, &self.normals, &mut normal_map, &mut normals))),
+                        face.uvs.map(|indices| indices.map(|ind| remap(ind, &self.uvs, &mut uv_map, &mut uvs))),
+                    )
+                }).collect();
+
+            let mesh = TriangleMesh::make_new(positions, normals, uvs, faces, material)
+                .map_err(|message| LoadError::Io(self.path.to_path_buf(), Error::new(ErrorKind::InvalidData, message)))?;
+            world.add(Arc::new(mesh));
+        }
+        Ok(world)
+    }
+}
+
+// Parses OBJ source text. `path` is used for error messages and to resolve mtllib statements.
+pub fn parse_obj(source: &str, path: &Path) -> Result<HittableList, LoadError>
+{
+    let mut parser = ObjParser
+    {
+        path,
+        positions: Vec::new(),
+        normals: Vec::new(),
+        uvs: Vec::new(),
+        groups: Vec::new(),
+        current_group: None,
+        current_material: None,
+        materials: HashMap::new(),
+    };
+    parser.parse(source)?;
+    parser.build()
+}
+
+pub fn load_obj(path: &Path) -> Result<HittableList, LoadError>
+{
+    let source = fs::read_to_string(path).map_err(|error| LoadError::Io(path.to_path_buf(), error))?;
+    parse_obj(&source, path)
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::super::math::hittable::Hittable;
+    use super::super::super::math::ray::Ray;
+
+    fn parse_error(result: Result<HittableList, LoadError>) -> ParseError
+    {
+        match result
+        {
+            Err(LoadError::Parse(_, error)) => error,
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn quad_test()
+    {
+        let source = "\
+# a unit quad written as a single polygon
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vn 0 0 1
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+f 1/1/1 2/2/1 3/3/1 4/4/1
+";
+        let world = parse_obj(source, Path::new("quad.obj")).unwrap();
+        assert_eq!(world.len(), 1);
+
+        let r = Ray::make_new(Point::make_new(0.25, 0.75, 1.0), Vect::make_new(0.0, 0.0, -1.0));
+        let hit_result = world.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(hit_result.t(), 1.0);
+        assert_eq!(*hit_result.shading_normal(), Vect::make_new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn negative_index_test()
+    {
+        let source = "\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+f -3 -2 -1
+v 5 5 5
+f 1//  2 -2
+";
+        let world = parse_obj(source, Path::new("negative.obj")).unwrap();
+        assert_eq!(world.len(), 1);
+        let r = Ray::make_new(Point::make_new(0.1, 0.1, 1.0), Vect::make_new(0.0, 0.0, -1.0));
+        assert!(world.hit(&r, 0.001, f64::INFINITY).is_some());
+    }
+
+    #[test]
+    fn error_test()
+    {
+        let error = parse_error(parse_obj("v 0 0 0\nv 1 0 0\nf 1 2 3\n", Path::new("bad.obj")));
+        assert_eq!((error.line, error.column), (3, 7));
+
+        let error = parse_error(parse_obj("v 0 0\n", Path::new("bad.obj")));
+        assert_eq!((error.line, error.column), (1, 1));
+
+        let error = parse_error(parse_obj("\nv 0 zero 0\n", Path::new("bad.obj")));
+        assert_eq!((error.line, error.column), (2, 5));
+
+        let error = parse_error(parse_obj("v 0 0 0\nf 1 1\n", Path::new("bad.obj")));
+        assert_eq!(error.line, 2);
+
+        let error = parse_error(parse_obj("v 0 0 0\nusemtl missing # comment\n", Path::new("bad.obj")));
+        assert_eq!(error.line, 2);
+        assert_eq!(error.message, "undefined material 'missing'");
+    }
+
+    #[test]
+    fn usemtl_test()
+    {
+        let path = std::env::temp_dir().join(format!("usemtl_test_{}.mtl", std::process::id()));
+        fs::write(&path, "newmtl red paint # walls\nKd 0.8 0.1 0.1\nnewmtl lamp\nKe 4 4 4\n").unwrap();
+        let source = format!("mtllib {}\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red paint # walls\nf 1 2 3\nusemtl lamp\nf 3 2 1\n", path.display());
+        let world = parse_obj(&source, Path::new("walls.obj"));
+        fs::remove_file(&path).unwrap();
+        let world = world.unwrap();
+        assert_eq!(world.len(), 2);
+        // Meshes made of a glowing material are lights
+        assert_eq!(world.hittables.iter().filter(|mesh| mesh.is_emissive()).count(), 1);
+    }
+
+    #[test]
+    fn mtl_test()
+    {
+        let source = "\
+newmtl red  paint # for the walls
+Kd 0.8 0.1 0.1
+Ks 0.04
+Ns 10
+illum 2
+
+newmtl chrome
+Kd 0 0 0
+Ks 0.9 0.9 0.9
+Ns 1000
+illum 3
+
+newmtl glass
+Ni 1.45
+d 0.1
+
+newmtl brushed
+Kd 0.9 0.9 0.9
+Pm 1
+Pr 0.3
+aniso 0.6
+
+newmtl lamp
+Kd 0 0 0
+Ke 5 5 4
+";
+        let materials = parse_mtl(source).unwrap();
+        assert_eq!(materials.len(), 5);
+        assert_eq!(materials["red paint"].diffuse, FColor::new_color(0.8, 0.1, 0.1));
+        assert_eq!(materials["red paint"].specular, FColor::new_color(0.04, 0.04, 0.04));
+        assert_eq!(materials["chrome"].illumination_model, 3);
+        assert_eq!(materials["glass"].index_of_refraction, 1.45);
+        assert_eq!(materials["glass"].dissolve, 0.1);
+        assert!(!materials["glass"].physically_based);
+        assert!(materials["brushed"].physically_based);
+        assert_eq!((materials["brushed"].metallic, materials["brushed"].roughness, materials["brushed"].anisotropy), (1.0, 0.3, 0.6));
+        assert_eq!(materials["lamp"].emission, FColor::new_color(5.0, 5.0, 4.0));
+        assert!(materials["lamp"].to_material().is_emissive());
+        assert!(!materials["red paint"].to_material().is_emissive());
+
+        let error = parse_mtl("Kd 1 1 1\n").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 1));
+        let error = parse_mtl("newmtl a\nKd 1 1\n").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 1));
+    }
+}
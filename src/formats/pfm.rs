@@ -0,0 +1,147 @@
+use std::io::{Error, ErrorKind, Read, Write};
+
+use super::super::math::framebuffer::FrameBuffer;
+use super::super::utils::color::FColor;
+
+// Portable float map: a tiny text header followed by raw 32-bit floats.
+// A negative scale marks little endian data, and scanlines run from the bottom of the image to the top.
+
+pub fn write_pfm<W: Write>(fb: &FrameBuffer, file: &mut W) -> Result<(), Error>
+{
+    file.write_all(format!("PF\n{} {}\n-1.0\n", fb.width(), fb.height()).as_bytes())?;
+
+    let mut scanline = Vec::with_capacity(fb.width() * 12);
+    for y in (0..fb.height()).rev()
+    {
+        scanline.clear();
+        for pixel in fb.row(y)
+        {
+            for channel in [pixel.r(), pixel.g(), pixel.b()]
+            {
+                scanline.extend_from_slice(&(channel as f32).to_le_bytes());
+            }
+        }
+        file.write_all(&scanline)?;
+    }
+    Ok(())
+}
+
+fn invalid_data(message: &str) -> Error
+{
+    Error::new(ErrorKind::InvalidData, format!("invalid PFM file: {}", message))
+}
+
+// Reads both the color (PF) and the grayscale (Pf) variants
+pub fn read_pfm<R: Read>(file: &mut R) -> Result<FrameBuffer, Error>
+{
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+
+    // Three whitespace separated header fields: the type, the size and the scale, then a single whitespace byte
+    let mut fields = Vec::new();
+    let mut position = 0;
+    while fields.len() < 4
+    {
+        while data.get(position).is_some_and(|byte| byte.is_ascii_whitespace())
+        {
+            position += 1;
+        }
+        let start = position;
+        while data.get(position).is_some_and(|byte| !byte.is_ascii_whitespace())
+        {
+            position += 1;
+        }
+        if start == position
+        {
+            return Err(invalid_data("truncated header"));
+        }
+        fields.push(String::from_utf8_lossy(&data[start..position]).to_string());
+    }
+    position += 1;
+
+    let channels = match fields[0].as_str()
+    {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid_data("missing the PF signature")),
+    };
+    let width = fields[1].parse::<usize>().map_err(|_| invalid_data("bad width"))?;
+    let height = fields[2].parse::<usize>().map_err(|_| invalid_data("bad height"))?;
+    let scale = fields[3].parse::<f64>().map_err(|_| invalid_data("bad scale"))?;
+    if width == 0 || height == 0 || scale == 0.0
+    {
+        return Err(invalid_data("empty image"));
+    }
+    let little_endian = scale < 0.0;
+
+    let row_size = width * channels * 4;
+    let pixels = data.get(position..position + row_size * height).ok_or_else(|| invalid_data("truncated pixel data"))?;
+    let mut fb = FrameBuffer::new(width, height);
+    for (row, scanline) in pixels.chunks(row_size).enumerate()
+    {
+        // Scanlines run from the bottom of the image to the top
+        let y = height - 1 - row;
+        for (x, pixel) in scanline.chunks(channels * 4).enumerate()
+        {
+            let values: Vec<f64> = pixel.chunks(4).map(|bytes|
+                {
+                    let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+                    (if little_endian { f32::from_le_bytes(bytes) } else { f32::from_be_bytes(bytes) }) as f64
+                }).collect();
+            let color = if channels == 3 { FColor::new_color(values[0], values[1], values[2]) } else { FColor::new_color(values[0], values[0], values[0]) };
+            fb.set(x, y, color);
+        }
+    }
+    Ok(fb)
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn write_test()
+    {
+        let mut fb = FrameBuffer::new(2, 2);
+        fb.set(0, 0, FColor::new_color(1.5, 2.0, 100.0));
+        let mut bytes = Vec::new();
+        write_pfm(&fb, &mut bytes).unwrap();
+
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(bytes.len(), header.len() + 4 * 12);
+        // The top left pixel is the first one of the last scanline
+        let top_left = header.len() + 2 * 12;
+        assert_eq!(&bytes[top_left..top_left + 12], [1.5f32.to_le_bytes(), 2.0f32.to_le_bytes(), 100.0f32.to_le_bytes()].concat());
+        assert!(bytes[header.len()..top_left].iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn read_test()
+    {
+        let mut fb = FrameBuffer::new(3, 2);
+        fb.set(0, 0, FColor::new_color(1.5, 2.0, 100.0));
+        fb.set(2, 1, FColor::new_color(0.25, 0.0, 7.0));
+        let mut bytes = Vec::new();
+        write_pfm(&fb, &mut bytes).unwrap();
+        assert_eq!(read_pfm(&mut bytes.as_slice()).unwrap(), fb);
+
+        // Big endian grayscale
+        let mut bytes = b"Pf\n1 2\n1.0\n".to_vec();
+        bytes.extend_from_slice(&2.0f32.to_be_bytes());
+        bytes.extend_from_slice(&0.5f32.to_be_bytes());
+        let gray = read_pfm(&mut bytes.as_slice()).unwrap();
+        assert_eq!(*gray.get(0, 0), FColor::new_color(0.5, 0.5, 0.5));
+        assert_eq!(*gray.get(0, 1), FColor::new_color(2.0, 2.0, 2.0));
+
+        assert!(read_pfm(&mut b"PF\n2 2\n-1.0\n".as_slice()).is_err());
+        assert!(read_pfm(&mut b"P6\n2 2\n255\n".as_slice()).is_err());
+    }
+}
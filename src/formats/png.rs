@@ -0,0 +1,405 @@
+use std::io::{Error, ErrorKind, Read, Write};
+
+use super::super::math::framebuffer::FrameBuffer;
+use super::super::utils::color::FColor;
+use super::zlib::*;
+
+// PNG encoder for 8-bit RGB images.
+// Every scanline picks the filter with the smallest sum of absolute differences before the image data is compressed.
+// The decoder reads every color type and bit depth without interlacing, ignores alpha and
+// undoes the gamma applied by write_png.
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const BYTES_PER_PIXEL: usize = 3;
+
+fn write_chunk<W: Write>(file: &mut W, chunk_type: &[u8; 4], data: &[u8]) -> Result<(), Error>
+{
+    file.write_all(&(data.len() as u32).to_be_bytes())?;
+    file.write_all(chunk_type)?;
+    file.write_all(data)?;
+    file.write_all(&crc32_update(crc32(chunk_type), data).to_be_bytes())
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8
+{
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance_left = (estimate - left as i16).abs();
+    let distance_up = (estimate - up as i16).abs();
+    let distance_up_left = (estimate - up_left as i16).abs();
+    if distance_left <= distance_up && distance_left <= distance_up_left
+    {
+        left
+    }
+    else if distance_up <= distance_up_left
+    {
+        up
+    }
+    else
+    {
+        up_left
+    }
+}
+
+// Applies filter type 0 to 4 to a scanline given the unfiltered previous one
+fn filter_row(filter: u8, row: &[u8], previous: &[u8], output: &mut Vec<u8>)
+{
+    output.push(filter);
+    for ind in 0..row.len()
+    {
+        let left = if ind >= BYTES_PER_PIXEL { row[ind - BYTES_PER_PIXEL] } else { 0 };
+        let up = previous[ind];
+        let up_left = if ind >= BYTES_PER_PIXEL { previous[ind - BYTES_PER_PIXEL] } else { 0 };
+        let prediction = match filter
+        {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            _ => paeth(left, up, up_left),
+        };
+        output.push(row[ind].wrapping_sub(prediction));
+    }
+}
+
+// Raw image data with the best filter byte in front of every scanline
+fn filter_image(fb: &FrameBuffer) -> Vec<u8>
+{
+    let row_size = fb.width() * BYTES_PER_PIXEL;
+    let mut filtered = Vec::with_capacity((row_size + 1) * fb.height());
+    let mut previous = vec![0u8; row_size];
+    let mut row = Vec::with_capacity(row_size);
+    let mut candidate = Vec::with_capacity(row_size + 1);
+    let mut best = Vec::with_capacity(row_size + 1);
+
+    for y in 0..fb.height()
+    {
+        row.clear();
+        row.extend(fb.row(y).iter().flat_map(|pixel| pixel.gamma_corrected().rgb8()));
+
+        let mut best_cost = u64::MAX;
+        for filter in 0..5
+        {
+            candidate.clear();
+            filter_row(filter, &row, &previous, &mut candidate);
+            // Filtered bytes read as signed, values near zero compress best
+            let cost: u64 = candidate[1..].iter().map(|byte| (*byte as i8).unsigned_abs() as u64).sum();
+            if cost < best_cost
+            {
+                best_cost = cost;
+                std::mem::swap(&mut best, &mut candidate);
+            }
+        }
+        filtered.extend_from_slice(&best);
+        std::mem::swap(&mut previous, &mut row);
+    }
+    filtered
+}
+
+pub fn write_png<W: Write>(fb: &FrameBuffer, file: &mut W, compression: Compression) -> Result<(), Error>
+{
+    file.write_all(&SIGNATURE)?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(fb.width() as u32).to_be_bytes());
+    header.extend_from_slice(&(fb.height() as u32).to_be_bytes());
+    // 8 bits per channel, truecolor, deflate, adaptive filtering, no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(file, b"IHDR", &header)?;
+
+    write_chunk(file, b"IDAT", &zlib_compress(&filter_image(fb), compression))?;
+    write_chunk(file, b"IEND", &[])
+}
+
+fn invalid_data(message: &str) -> Error
+{
+    Error::new(ErrorKind::InvalidData, format!("invalid PNG file: {}", message))
+}
+
+// Reverses filter_row in place, bytes_per_pixel is at least one even for packed pixels
+fn unfilter_row(filter: u8, row: &mut [u8], previous: &[u8], bytes_per_pixel: usize) -> Result<(), Error>
+{
+    for ind in 0..row.len()
+    {
+        let left = if ind >= bytes_per_pixel { row[ind - bytes_per_pixel] } else { 0 };
+        let up = previous[ind];
+        let up_left = if ind >= bytes_per_pixel { previous[ind - bytes_per_pixel] } else { 0 };
+        let prediction = match filter
+        {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            4 => paeth(left, up, up_left),
+            _ => return Err(invalid_data("unknown filter type")),
+        };
+        row[ind] = row[ind].wrapping_add(prediction);
+    }
+    Ok(())
+}
+
+struct Header
+{
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+}
+
+impl Header
+{
+    fn channels(&self) -> usize
+    {
+        match self.color_type
+        {
+            0 | 3 => 1,
+            4 => 2,
+            2 => 3,
+            _ => 4,
+        }
+    }
+
+    fn row_size(&self) -> usize
+    {
+        (self.width * self.channels() * self.bit_depth as usize).div_ceil(8)
+    }
+}
+
+// Sample number ind of a scanline scaled to [0, 1], or the raw value for palette indices
+fn read_sample(row: &[u8], ind: usize, bit_depth: u8) -> u16
+{
+    match bit_depth
+    {
+        16 => u16::from_be_bytes([row[2 * ind], row[2 * ind + 1]]),
+        8 => row[ind] as u16,
+        _ =>
+        {
+            let per_byte = 8 / bit_depth as usize;
+            let shift = 8 - bit_depth as usize * (ind % per_byte + 1);
+            ((row[ind / per_byte] >> shift) & ((1 << bit_depth) - 1)) as u16
+        }
+    }
+}
+
+pub fn read_png<R: Read>(file: &mut R) -> Result<FrameBuffer, Error>
+{
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    if data.len() < SIGNATURE.len() || data[..SIGNATURE.len()] != SIGNATURE
+    {
+        return Err(invalid_data("missing the PNG signature"));
+    }
+
+    let mut position = SIGNATURE.len();
+    let mut header = None;
+    let mut palette: Vec<[u8; 3]> = Vec::new();
+    let mut compressed = Vec::new();
+    loop
+    {
+        let chunk_header = data.get(position..position + 8).ok_or_else(|| invalid_data("truncated chunk"))?;
+        let length = u32::from_be_bytes(chunk_header[..4].try_into().unwrap()) as usize;
+        let chunk_type: [u8; 4] = chunk_header[4..].try_into().unwrap();
+        let chunk = data.get(position + 8..position + 8 + length).ok_or_else(|| invalid_data("truncated chunk"))?;
+        let crc = data.get(position + 8 + length..position + 12 + length).ok_or_else(|| invalid_data("truncated chunk"))?;
+        if crc32_update(crc32(&chunk_type), chunk).to_be_bytes() != crc
+        {
+            return Err(invalid_data("chunk CRC mismatch"));
+        }
+        position += 12 + length;
+
+        match &chunk_type
+        {
+            b"IHDR" =>
+            {
+                if length != 13
+                {
+                    return Err(invalid_data("IHDR has the wrong size"));
+                }
+                let width = u32::from_be_bytes(chunk[0..4].try_into().unwrap()) as usize;
+                let height = u32::from_be_bytes(chunk[4..8].try_into().unwrap()) as usize;
+                let (bit_depth, color_type) = (chunk[8], chunk[9]);
+                let valid_depth = match color_type
+                {
+                    0 => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+                    3 => matches!(bit_depth, 1 | 2 | 4 | 8),
+                    2 | 4 | 6 => matches!(bit_depth, 8 | 16),
+                    _ => false,
+                };
+                if !valid_depth || width == 0 || height == 0
+                {
+                    return Err(invalid_data("unsupported image size, color type or bit depth"));
+                }
+                if chunk[12] != 0
+                {
+                    return Err(Error::new(ErrorKind::Unsupported, "interlaced PNG images aren't supported"));
+                }
+                header = Some(Header{ width, height, bit_depth, color_type });
+            }
+            b"PLTE" => palette = chunk.chunks_exact(3).map(|entry| [entry[0], entry[1], entry[2]]).collect(),
+            b"IDAT" => compressed.extend_from_slice(chunk),
+            b"IEND" => break,
+            // Ancillary chunks (lowercase first letter) can be skipped
+            _ if chunk_type[0].is_ascii_lowercase() => {}
+            _ => return Err(invalid_data("unknown critical chunk")),
+        }
+    }
+
+    let header = header.ok_or_else(|| invalid_data("missing IHDR"))?;
+    if header.color_type == 3 && palette.is_empty()
+    {
+        return Err(invalid_data("missing palette"));
+    }
+    let mut raw = zlib_decompress(&compressed)?;
+    let row_size = header.row_size();
+    if raw.len() < (row_size + 1) * header.height
+    {
+        return Err(invalid_data("not enough image data"));
+    }
+
+    let bytes_per_pixel = (header.channels() * header.bit_depth as usize).div_ceil(8);
+    let max_value = ((1u32 << header.bit_depth) - 1) as f64;
+    let mut fb = FrameBuffer::new(header.width, header.height);
+    let mut previous = vec![0u8; row_size];
+    for (y, line) in raw.chunks_exact_mut(row_size + 1).take(header.height).enumerate()
+    {
+        let (filter, row) = line.split_first_mut().unwrap();
+        unfilter_row(*filter, row, &previous, bytes_per_pixel)?;
+
+        for x in 0..header.width
+        {
+            let sample = |channel: usize| read_sample(row, x * header.channels() + channel, header.bit_depth) as f64 / max_value;
+            let color = match header.color_type
+            {
+                0 | 4 => FColor::new_color(sample(0), sample(0), sample(0)),
+                3 =>
+                {
+                    let index = read_sample(row, x, header.bit_depth) as usize;
+                    let entry = palette.get(index).ok_or_else(|| invalid_data("palette index out of range"))?;
+                    FColor::new_color(entry[0] as f64 / 255.0, entry[1] as f64 / 255.0, entry[2] as f64 / 255.0)
+                }
+                _ => FColor::new_color(sample(0), sample(1), sample(2)),
+            };
+            fb.set(x, y, color.gamma_decoded());
+        }
+        previous.copy_from_slice(row);
+    }
+    Ok(fb)
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::super::utils::color::FColor;
+
+    #[test]
+    fn filter_test()
+    {
+        let row = [10, 20, 30, 15, 25, 35];
+        let previous = [5, 5, 5, 40, 40, 40];
+        let mut output = Vec::new();
+        filter_row(1, &row, &previous, &mut output);
+        assert_eq!(output, vec![1, 10, 20, 30, 5, 5, 5]);
+        output.clear();
+        filter_row(2, &row, &previous, &mut output);
+        assert_eq!(output, vec![2, 5, 15, 25, 231, 241, 251]);
+        output.clear();
+        filter_row(3, &row, &previous, &mut output);
+        assert_eq!(output, vec![3, 8, 18, 28, 246, 251, 0]);
+        assert_eq!(paeth(10, 20, 15), 15);
+        assert_eq!(paeth(10, 20, 10), 20);
+    }
+
+    #[test]
+    fn write_test()
+    {
+        let mut fb = FrameBuffer::new(3, 2);
+        // Gamma corrected to 1.0, 0.5 and 0.0
+        fb.set(1, 0, FColor::new_color(1.0, 0.25, 0.0));
+        let mut bytes = Vec::new();
+        write_png(&fb, &mut bytes, Compression::Stored).unwrap();
+
+        assert_eq!(&bytes[..8], &SIGNATURE);
+        // IHDR
+        assert_eq!(&bytes[8..16], &[0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+        assert_eq!(&bytes[16..29], &[0, 0, 0, 3, 0, 0, 0, 2, 8, 2, 0, 0, 0]);
+        assert_eq!(&bytes[29..33], &crc32(&bytes[12..29]).to_be_bytes());
+        // IDAT holds a zlib stream wrapping the two filtered scanlines
+        let idat_length = u32::from_be_bytes(bytes[33..37].try_into().unwrap()) as usize;
+        assert_eq!(&bytes[37..41], b"IDAT");
+        let stream = &bytes[41..41 + idat_length];
+        assert_eq!(stream.len(), 2 + 5 + 2 * 10 + 4);
+        assert_eq!(&stream[7..17], &[0, 0, 0, 0, 255, 128, 0, 0, 0, 0]);
+        // The second row is all zero, which the unfiltered scanline already is
+        assert_eq!(&stream[17..27], &[0; 10]);
+        // IEND
+        assert_eq!(&bytes[bytes.len() - 12..], &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]);
+    }
+
+    // PNG file around already filtered scanlines
+    fn make_png(width: u32, height: u32, bit_depth: u8, color_type: u8, palette: &[u8], scanlines: &[u8]) -> Vec<u8>
+    {
+        let mut bytes = SIGNATURE.to_vec();
+        let mut header = Vec::new();
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+        write_chunk(&mut bytes, b"IHDR", &header).unwrap();
+        if !palette.is_empty()
+        {
+            write_chunk(&mut bytes, b"PLTE", palette).unwrap();
+        }
+        write_chunk(&mut bytes, b"tEXt", b"Comment\0skipped").unwrap();
+        write_chunk(&mut bytes, b"IDAT", &zlib_compress(scanlines, Compression::Deflate)).unwrap();
+        write_chunk(&mut bytes, b"IEND", &[]).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn read_test()
+    {
+        // What write_png produces comes back within the 8-bit quantization
+        let mut fb = FrameBuffer::new(5, 3);
+        for (x, y, pixel) in fb.rows_mut(0..3).iter_mut()
+        {
+            *pixel = FColor::new_color(x as f64 / 4.0, y as f64 / 2.0, 1.0);
+        }
+        let mut bytes = Vec::new();
+        write_png(&fb, &mut bytes, Compression::Deflate).unwrap();
+        let read = read_png(&mut bytes.as_slice()).unwrap();
+        assert_eq!((read.width(), read.height()), (5, 3));
+        for (expected, pixel) in fb.pixels().iter().zip(read.pixels())
+        {
+            assert!((*expected - *pixel).length() < 0.01);
+        }
+
+        // 2-bit palette indices, red green blue then blue green red
+        let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let bytes = make_png(3, 2, 2, 3, &palette, &[0, 0b00011000, 0, 0b10010000]);
+        let read = read_png(&mut bytes.as_slice()).unwrap();
+        assert_eq!(*read.get(0, 0), FColor::new_color(1.0, 0.0, 0.0));
+        assert_eq!(*read.get(2, 0), FColor::new_color(0.0, 0.0, 1.0));
+        assert_eq!(*read.get(0, 1), FColor::new_color(0.0, 0.0, 1.0));
+        assert_eq!(*read.get(1, 1), FColor::new_color(0.0, 1.0, 0.0));
+
+        // 16-bit gray and alpha with the sub filter: white then black
+        let bytes = make_png(2, 1, 16, 4, &[], &[1, 255, 255, 0, 0, 1, 1, 0, 0]);
+        let read = read_png(&mut bytes.as_slice()).unwrap();
+        assert_eq!(*read.get(0, 0), FColor::new_color(1.0, 1.0, 1.0));
+        assert_eq!(*read.get(1, 0), FColor::new_color(0.0, 0.0, 0.0));
+
+        // Corruption is detected
+        let mut bytes = make_png(2, 1, 8, 0, &[], &[0, 10, 20]);
+        assert!(read_png(&mut bytes.as_slice()).is_ok());
+        bytes[20] ^= 1;
+        assert_eq!(read_png(&mut bytes.as_slice()).unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(read_png(&mut make_png(2, 1, 8, 0, &[], &[0, 10]).as_slice()).is_err());
+        assert!(read_png(&mut make_png(2, 1, 8, 0, &[], &[7, 10, 20]).as_slice()).is_err());
+        assert!(read_png(&mut &b"GIF89a"[..]).is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use std::io::{Error, ErrorKind, Read};
+
+use super::super::math::framebuffer::FrameBuffer;
+use super::super::utils::color::FColor;
+
+// Netpbm color image decoder, plain text (P3) and binary (P6), with up to 16 bits per channel.
+// Values are gamma encoded like the PPM files this program writes.
+
+fn invalid_data(message: &str) -> Error
+{
+    Error::new(ErrorKind::InvalidData, format!("invalid PPM file: {}", message))
+}
+
+// Next whitespace separated word, skipping '#' comments
+fn next_word<'a>(data: &'a [u8], position: &mut usize) -> Option<&'a [u8]>
+{
+    loop
+    {
+        while *position < data.len() && data[*position].is_ascii_whitespace()
+        {
+            *position += 1;
+        }
+        if *position < data.len() && data[*position] == b'#'
+        {
+            while *position < data.len() && data[*position] != b'\n'
+            {
+                *position += 1;
+            }
+            continue;
+        }
+        break;
+    }
+    let start = *position;
+    while *position < data.len() && !data[*position].is_ascii_whitespace()
+    {
+        *position += 1;
+    }
+    if start == *position { None } else { Some(&data[start..*position]) }
+}
+
+fn next_number(data: &[u8], position: &mut usize, name: &str) -> Result<usize, Error>
+{
+    let word = next_word(data, position).ok_or_else(|| invalid_data(&format!("missing {}", name)))?;
+    std::str::from_utf8(word).ok().and_then(|text| text.parse().ok()).ok_or_else(|| invalid_data(&format!("invalid {}", name)))
+}
+
+pub fn read_ppm<R: Read>(file: &mut R) -> Result<FrameBuffer, Error>
+{
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    let mut position = 0;
+
+    let binary = match next_word(&data, &mut position)
+    {
+        Some(b"P3") => false,
+        Some(b"P6") => true,
+        _ => return Err(invalid_data("missing the P3 or P6 signature")),
+    };
+    let width = next_number(&data, &mut position, "width")?;
+    let height = next_number(&data, &mut position, "height")?;
+    let max_value = next_number(&data, &mut position, "maximum value")?;
+    if width == 0 || height == 0 || max_value == 0 || max_value > 65535
+    {
+        return Err(invalid_data("the size and maximum value must be positive and the maximum at most 65535"));
+    }
+
+    let count = width * height * 3;
+    let mut values = Vec::with_capacity(count);
+    if binary
+    {
+        // A single whitespace character separates the header from the pixels
+        position += 1;
+        let bytes_per_value = if max_value > 255 { 2 } else { 1 };
+        let pixels = data.get(position..position + count * bytes_per_value).ok_or_else(|| invalid_data("truncated pixel data"))?;
+        if bytes_per_value == 2
+        {
+            values.extend(pixels.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]]) as usize));
+        }
+        else
+        {
+            values.extend(pixels.iter().map(|byte| *byte as usize));
+        }
+    }
+    else
+    {
+        for _ in 0..count
+        {
+            values.push(next_number(&data, &mut position, "pixel value")?);
+        }
+    }
+
+    let mut fb = FrameBuffer::new(width, height);
+    for (ind, rgb) in values.chunks_exact(3).enumerate()
+    {
+        let [r, g, b] = [rgb[0], rgb[1], rgb[2]].map(|value| value.min(max_value) as f64 / max_value as f64);
+        fb.set(ind % width, ind / width, FColor::new_color(r, g, b).gamma_decoded());
+    }
+    Ok(fb)
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn read_test()
+    {
+        let text = b"P3\n# comment\n2 1\n255\n255 0 0\n0 0 255 # blue\n";
+        let fb = read_ppm(&mut &text[..]).unwrap();
+        assert_eq!((fb.width(), fb.height()), (2, 1));
+        assert_eq!(*fb.get(0, 0), FColor::new_color(1.0, 0.0, 0.0));
+        assert_eq!(*fb.get(1, 0), FColor::new_color(0.0, 0.0, 1.0));
+
+        // Binary with 16-bit values, half of the maximum decodes to a quarter of the radiance
+        let mut binary = b"P6 1 2 1000\n".to_vec();
+        binary.extend_from_slice(&[0, 0, 3, 232, 1, 244, 1, 244, 1, 244, 0, 0]);
+        let fb = read_ppm(&mut binary.as_slice()).unwrap();
+        assert_eq!(*fb.get(0, 0), FColor::new_color(0.0, 1.0, 0.25));
+        assert_eq!(*fb.get(0, 1), FColor::new_color(0.25, 0.25, 0.0));
+
+        assert!(read_ppm(&mut &b"P6 1 1 255\n\x01\x02"[..]).is_err());
+        assert!(read_ppm(&mut &b"P3 1 1 255 1 x 3"[..]).is_err());
+        assert!(read_ppm(&mut &b"P5 1 1 255 1"[..]).is_err());
+    }
+}
@@ -0,0 +1,1386 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::*;
+use super::grid::load_grids;
+use super::image::read_image;
+use super::obj::load_obj;
+use super::super::math::camera::Camera;
+use super::super::math::core::INFINITY;
+use super::super::math::hittable::*;
+use super::super::math::light::*;
+use super::super::math::material::*;
+use super::super::math::matrix::Matrix;
+use super::super::math::medium::*;
+use super::super::math::microfacet::conductor_preset;
+use super::super::math::point::Point;
+use super::super::math::principled::*;
+use super::super::math::sphere::Sphere;
+use super::super::math::texture::*;
+use super::super::math::transform::Transform;
+use super::super::math::triangle::Triangle;
+use super::super::math::vect::Vect;
+use super::super::render::environment::*;
+use super::super::render::scene::*;
+use super::super::render::settings::RenderSettings;
+use super::super::render::sky::*;
+use super::super::utils::color::FColor;
+
+// Text scene description, see the "Scene files" section of the README for the full format.
+// Every non-empty line is a statement made of a keyword followed by its arguments, '#' starts a comment.
+// Relative paths (meshes and the output image) are resolved against the directory of the scene file.
+
+struct CameraDescription
+{
+    position: Point,
+    look_at: Point,
+    up: Vect,
+    vfov: f64,
+    aperture: f64,
+    focus_distance: Option<f64>,
+    // Shutter open and close times
+    shutter: (f64, f64),
+    // Last line that moved or oriented the camera, for reporting an invalid orientation
+    line: usize,
+}
+
+// Cursor over the arguments of one statement
+struct Arguments<'a, 'b>
+{
+    line: usize,
+    keyword: &'b Token<'a>,
+    tokens: &'b [Token<'a>],
+    next: usize,
+    end_column: usize,
+}
+
+impl<'a, 'b> Arguments<'a, 'b>
+{
+    fn token(&mut self, expected: &str) -> Result<&'b Token<'a>, ParseError>
+    {
+        let token = self.tokens.get(self.next).ok_or_else(||
+            ParseError::make_new(self.line, self.end_column, format!("'{}' is missing {}", self.keyword.text, expected)))?;
+        self.next += 1;
+        Ok(token)
+    }
+
+    fn f64(&mut self, expected: &str) -> Result<f64, ParseError>
+    {
+        let token = self.token(expected)?;
+        parse_f64(self.line, token)
+    }
+
+    fn positive_f64(&mut self, expected: &str) -> Result<f64, ParseError>
+    {
+        let token = self.token(expected)?;
+        let value = parse_f64(self.line, token)?;
+        if value <= 0.0
+        {
+            return Err(ParseError::make_new(self.line, token.column, format!("{} must be positive", expected)));
+        }
+        Ok(value)
+    }
+
+    fn non_negative_f64(&mut self, expected: &str) -> Result<f64, ParseError>
+    {
+        let token = self.token(expected)?;
+        let value = parse_f64(self.line, token)?;
+        if value < 0.0
+        {
+            return Err(ParseError::make_new(self.line, token.column, format!("{} can't be negative", expected)));
+        }
+        Ok(value)
+    }
+
+    fn unit_f64(&mut self, expected: &str) -> Result<f64, ParseError>
+    {
+        let token = self.token(expected)?;
+        let value = parse_f64(self.line, token)?;
+        if !(0.0..=1.0).contains(&value)
+        {
+            return Err(ParseError::make_new(self.line, token.column, format!("{} must be between 0 and 1", expected)));
+        }
+        Ok(value)
+    }
+
+    // Negative radii are allowed, they turn spheres inside out
+    fn radius(&mut self) -> Result<f64, ParseError>
+    {
+        let token = self.token("a radius")?;
+        let value = parse_f64(self.line, token)?;
+        if value == 0.0
+        {
+            return Err(ParseError::new(self.line, token.column, "a radius can't be zero"));
+        }
+        Ok(value)
+    }
+
+    fn positive_usize(&mut self, expected: &str) -> Result<usize, ParseError>
+    {
+        self.bounded_usize(expected, usize::MAX)
+    }
+
+    fn bounded_usize(&mut self, expected: &str, max: usize) -> Result<usize, ParseError>
+    {
+        let token = self.token(expected)?;
+        let value = parse_usize(self.line, token)?;
+        if value == 0
+        {
+            return Err(ParseError::make_new(self.line, token.column, format!("{} must be positive", expected)));
+        }
+        if value > max
+        {
+            return Err(ParseError::make_new(self.line, token.column, format!("{} can't be above {}", expected, max)));
+        }
+        Ok(value)
+    }
+
+    fn vect(&mut self, expected: &str) -> Result<Vect, ParseError>
+    {
+        Ok(Vect::make_new(self.f64(expected)?, self.f64(expected)?, self.f64(expected)?))
+    }
+
+    fn color(&mut self) -> Result<FColor, ParseError>
+    {
+        let token = self.tokens.get(self.next).copied();
+        let color = self.vect("a color")?;
+        if color.r() < 0.0 || color.g() < 0.0 || color.b() < 0.0
+        {
+            let column = token.map_or(self.end_column, |token| token.column);
+            return Err(ParseError::new(self.line, column, "color components can't be negative"));
+        }
+        Ok(color)
+    }
+
+    fn finish(&self) -> Result<(), ParseError>
+    {
+        match self.tokens.get(self.next)
+        {
+            Some(token) => Err(ParseError::make_new(self.line, token.column, format!("unexpected argument '{}'", token.text))),
+            None => Ok(()),
+        }
+    }
+}
+
+// A number, a named glass, or the coefficients of a dispersion formula
+fn refractive_index(arguments: &mut Arguments) -> Result<RefractiveIndex, ParseError>
+{
+    match arguments.tokens.get(arguments.next)
+    {
+        Some(token) if token.text.parse::<f64>().is_err() => arguments.next += 1,
+        _ => return Ok(RefractiveIndex::Constant(arguments.positive_f64("an index of refraction")?)),
+    }
+    let token = &arguments.tokens[arguments.next - 1];
+    let positive = |arguments: &mut Arguments| arguments.positive_f64("a dispersion coefficient");
+    match token.text
+    {
+        "cauchy" => Ok(RefractiveIndex::Cauchy{ a: positive(arguments)?, b: arguments.f64("a dispersion coefficient")? }),
+        "sellmeier" =>
+        {
+            let b = [positive(arguments)?, positive(arguments)?, positive(arguments)?];
+            let c = [positive(arguments)?, positive(arguments)?, positive(arguments)?];
+            Ok(RefractiveIndex::Sellmeier{ b, c })
+        }
+        _ => RefractiveIndex::from_preset(token.text).ok_or_else(||
+            ParseError::make_new(arguments.line, token.column, format!("unknown glass '{}'", token.text))),
+    }
+}
+
+struct SceneParser<'a>
+{
+    path: &'a Path,
+    directory: PathBuf,
+    settings: RenderSettings,
+    output: Option<PathBuf>,
+    camera: CameraDescription,
+    materials: HashMap<String, Arc<dyn Material>>,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    // Shapes kept out of the world until instanced
+    objects: HashMap<String, Arc<dyn Hittable>>,
+    world: HittableList,
+    lights: Vec<Arc<dyn Light>>,
+    environment: Arc<dyn Environment>,
+    // Sun matching a sky statement, replaced along with the environment
+    sun: Option<Arc<dyn Light>>,
+    fog: Option<Fog>,
+}
+
+impl<'a> SceneParser<'a>
+{
+    fn new(path: &'a Path) -> Self
+    {
+        SceneParser
+        {
+            path,
+            directory: path.parent().unwrap_or(Path::new("")).to_path_buf(),
+            settings: RenderSettings::default(),
+            output: None,
+            camera: CameraDescription
+            {
+                position: Point::make_new(0.0, 0.0, 0.0),
+                look_at: Point::make_new(0.0, 0.0, -1.0),
+                up: Vect::make_new(0.0, 1.0, 0.0),
+                vfov: 90.0,
+                aperture: 0.0,
+                focus_distance: None,
+                shutter: (0.0, 0.0),
+                line: 1,
+            },
+            materials: HashMap::new(),
+            textures: HashMap::new(),
+            objects: HashMap::new(),
+            world: HittableList::default(),
+            lights: Vec::new(),
+            environment: Arc::new(SkyGradient),
+            sun: None,
+            fog: None,
+        }
+    }
+
+    fn material(&self, arguments: &mut Arguments) -> Result<Arc<dyn Material>, ParseError>
+    {
+        let token = arguments.token("a material name")?;
+        self.materials.get(token.text).cloned().ok_or_else(||
+            ParseError::make_new(arguments.line, token.column, format!("undefined material '{}'", token.text)))
+    }
+
+    // Material that media scatter with
+    fn phase_function(&self, arguments: &mut Arguments) -> Result<Arc<dyn Material>, ParseError>
+    {
+        let column = arguments.tokens.get(arguments.next).map_or(arguments.end_column, |token| token.column);
+        let material = self.material(arguments)?;
+        if !material.is_volumetric()
+        {
+            return Err(ParseError::new(arguments.line, column, "media need an isotropic or henyey_greenstein material"));
+        }
+        Ok(material)
+    }
+
+    // Either a color or the name of a texture
+    fn texture(&self, arguments: &mut Arguments) -> Result<Arc<dyn Texture>, ParseError>
+    {
+        match arguments.tokens.get(arguments.next)
+        {
+            Some(token) if token.text.parse::<f64>().is_err() =>
+            {
+                arguments.next += 1;
+                self.textures.get(token.text).cloned().ok_or_else(||
+                    ParseError::make_new(arguments.line, token.column, format!("undefined texture '{}'", token.text)))
+            }
+            _ => Ok(Arc::new(SolidColor::make_new(arguments.color()?))),
+        }
+    }
+
+    fn parse_texture(&mut self, arguments: &mut Arguments) -> Result<(), LoadError>
+    {
+        let parse_error = |error: ParseError| LoadError::Parse(self.path.to_path_buf(), error);
+        let name = arguments.token("a texture name").map_err(parse_error)?;
+        if self.textures.contains_key(name.text)
+        {
+            return Err(parse_error(ParseError::make_new(arguments.line, name.column, format!("texture '{}' is already defined", name.text))));
+        }
+
+        let kind = arguments.token("a texture type").map_err(parse_error)?;
+        let texture: Arc<dyn Texture> = match kind.text
+        {
+            "solid" => Arc::new(SolidColor::make_new(arguments.color().map_err(parse_error)?)),
+            "checker" =>
+            {
+                let even = self.texture(arguments).map_err(parse_error)?;
+                let odd = self.texture(arguments).map_err(parse_error)?;
+                Arc::new(CheckerTexture::make_new(even, odd, arguments.positive_f64("a scale").map_err(parse_error)?))
+            }
+            "noise" =>
+            {
+                let color = arguments.color().map_err(parse_error)?;
+                Arc::new(NoiseTexture::make_new(color, arguments.positive_f64("a scale").map_err(parse_error)?))
+            }
+            "turbulence" =>
+            {
+                let color = arguments.color().map_err(parse_error)?;
+                let scale = arguments.positive_f64("a scale").map_err(parse_error)?;
+                // The number of octaves is optional
+                let octaves = if arguments.tokens.get(arguments.next).is_some() { arguments.positive_usize("an octave count").map_err(parse_error)? } else { 7 };
+                Arc::new(TurbulenceTexture::make_new(color, scale, octaves))
+            }
+            "marble" | "wood" | "voronoi" =>
+            {
+                let first = arguments.color().map_err(parse_error)?;
+                let second = arguments.color().map_err(parse_error)?;
+                let scale = arguments.positive_f64("a scale").map_err(parse_error)?;
+                match kind.text
+                {
+                    "marble" => Arc::new(MarbleTexture::make_new(first, second, scale)),
+                    "wood" => Arc::new(WoodTexture::make_new(first, second, scale)),
+                    _ => Arc::new(VoronoiTexture::make_new(first, second, scale)),
+                }
+            }
+            "image" =>
+            {
+                let path = arguments.token("an image path").map_err(parse_error)?;
+                let path = self.directory.join(path.text);
+                let wrap = match arguments.tokens.get(arguments.next)
+                {
+                    Some(token) =>
+                    {
+                        arguments.next += 1;
+                        WrapMode::from_name(token.text).ok_or_else(||
+                            parse_error(ParseError::make_new(arguments.line, token.column, format!("unknown wrap mode '{}', expected repeat, clamp or mirror", token.text))))?
+                    }
+                    None => WrapMode::Repeat,
+                };
+                arguments.finish().map_err(parse_error)?;
+                let image = read_image(&path).map_err(|error| LoadError::Io(path.clone(), error))?;
+                Arc::new(ImageTexture::make_new(image, wrap))
+            }
+            _ => return Err(parse_error(ParseError::make_new(arguments.line, kind.column, format!("unknown texture type '{}'", kind.text)))),
+        };
+        self.textures.insert(name.text.to_string(), texture);
+        Ok(())
+    }
+
+    fn parse_camera(&mut self, arguments: &mut Arguments) -> Result<(), ParseError>
+    {
+        let property = arguments.token("a camera property")?;
+        if matches!(property.text, "position" | "look_at" | "up")
+        {
+            self.camera.line = arguments.line;
+        }
+        match property.text
+        {
+            "position" => self.camera.position = arguments.vect("a position")?,
+            "look_at" => self.camera.look_at = arguments.vect("a target point")?,
+            "up" => self.camera.up = arguments.vect("an up vector")?,
+            "vfov" =>
+            {
+                let token = arguments.token("a field of view")?;
+                let vfov = parse_f64(arguments.line, token)?;
+                if vfov <= 0.0 || vfov >= 180.0
+                {
+                    return Err(ParseError::new(arguments.line, token.column, "the field of view must be between 0 and 180 degrees"));
+                }
+                self.camera.vfov = vfov;
+            }
+            "aperture" => self.camera.aperture = arguments.non_negative_f64("an aperture")?,
+            "focus_distance" => self.camera.focus_distance = Some(arguments.positive_f64("a focus distance")?),
+            "shutter" =>
+            {
+                let open = arguments.f64("an opening time")?;
+                let column = arguments.tokens.get(arguments.next).map_or(arguments.end_column, |token| token.column);
+                let close = arguments.f64("a closing time")?;
+                if close < open
+                {
+                    return Err(ParseError::new(arguments.line, column, "the shutter can't close before it opens"));
+                }
+                self.camera.shutter = (open, close);
+            }
+            _ => return Err(ParseError::make_new(arguments.line, property.column, format!("unknown camera property '{}'", property.text))),
+        }
+        Ok(())
+    }
+
+    fn parse_material(&mut self, arguments: &mut Arguments) -> Result<(), ParseError>
+    {
+        let name = arguments.token("a material name")?;
+        if self.materials.contains_key(name.text)
+        {
+            return Err(ParseError::make_new(arguments.line, name.column, format!("material '{}' is already defined", name.text)));
+        }
+
+        let kind = arguments.token("a material type")?;
+        let material: Arc<dyn Material> = match kind.text
+        {
+            "lambertian" => Arc::new(Lambertian::make_textured(self.texture(arguments)?)),
+            "metal" =>
+            {
+                let albedo = self.texture(arguments)?;
+                Arc::new(Metal::make_textured(albedo, arguments.unit_f64("a roughness")?))
+            }
+            "conductor" =>
+            {
+                // Either a named metal or its complex index of refraction
+                let (eta, k) = match arguments.tokens.get(arguments.next)
+                {
+                    Some(token) if token.text.parse::<f64>().is_err() =>
+                    {
+                        arguments.next += 1;
+                        conductor_preset(token.text).ok_or_else(||
+                            ParseError::make_new(arguments.line, token.column, format!("unknown conductor '{}'", token.text)))?
+                    }
+                    _ => (arguments.color()?, arguments.color()?),
+                };
+                Arc::new(Conductor::make_new(eta, k, arguments.unit_f64("a roughness")?))
+            }
+            "dielectric" =>
+            {
+                let index_of_refraction = refractive_index(arguments)?;
+                // Smooth clear glass unless a roughness or an absorption is given
+                let mut roughness = 0.0;
+                if let Some(token) = arguments.tokens.get(arguments.next).filter(|token| token.text.parse::<f64>().is_ok())
+                {
+                    roughness = arguments.unit_f64("a roughness")?;
+                    // Rough glass doesn't disperse, its index has to be a number
+                    if roughness > 0.0 && index_of_refraction.is_dispersive()
+                    {
+                        return Err(ParseError::new(arguments.line, token.column, "rough glass needs a constant index of refraction"));
+                    }
+                }
+                let mut absorption = FColor::new_color(0.0, 0.0, 0.0);
+                if let Some(token) = arguments.tokens.get(arguments.next)
+                {
+                    if token.text != "absorption"
+                    {
+                        return Err(ParseError::make_new(arguments.line, token.column, format!("unexpected argument '{}'", token.text)));
+                    }
+                    arguments.next += 1;
+                    absorption = arguments.color()?;
+                }
+                if roughness > 0.0
+                {
+                    Arc::new(RoughDielectric::make_absorbing(index_of_refraction.at(REFERENCE_WAVELENGTH), roughness, absorption))
+                }
+                else
+                {
+                    Arc::new(Dielectric::make_absorbing(index_of_refraction, absorption))
+                }
+            }
+            "principled" => Arc::new(Principled::make_new(self.principled_parameters(arguments)?)),
+            "diffuse_light" => Arc::new(DiffuseLight::make_new(arguments.color()?)),
+            "isotropic" => Arc::new(Isotropic::make_textured(self.texture(arguments)?)),
+            "henyey_greenstein" =>
+            {
+                let albedo = self.texture(arguments)?;
+                // The distribution turns into a single direction at -1 and 1
+                let column = arguments.tokens.get(arguments.next).map_or(arguments.end_column, |token| token.column);
+                let g = arguments.f64("an anisotropy")?;
+                if g.abs() >= 1.0
+                {
+                    return Err(ParseError::new(arguments.line, column, "the anisotropy must be strictly between -1 and 1"));
+                }
+                Arc::new(HenyeyGreenstein::make_textured(albedo, g))
+            }
+            _ => return Err(ParseError::make_new(arguments.line, kind.column, format!("unknown material type '{}'", kind.text))),
+        };
+        self.materials.insert(name.text.to_string(), material);
+        Ok(())
+    }
+
+    // Pairs of parameter names and values, the parameters left out keep their defaults
+    fn principled_parameters(&self, arguments: &mut Arguments) -> Result<PrincipledParameters, ParseError>
+    {
+        let mut parameters = PrincipledParameters::default();
+        while let Some(name) = arguments.tokens.get(arguments.next)
+        {
+            arguments.next += 1;
+            match name.text
+            {
+                "base_color" => parameters.base_color = self.texture(arguments)?,
+                "ior" => parameters.index_of_refraction = arguments.positive_f64("an index of refraction")?,
+                _ =>
+                {
+                    let Some(slot) = parameters.scalar_mut(name.text) else
+                    {
+                        return Err(ParseError::make_new(arguments.line, name.column, format!("unknown principled parameter '{}'", name.text)));
+                    };
+                    // A single number or the name of a texture
+                    *slot = match arguments.tokens.get(arguments.next)
+                    {
+                        Some(token) if token.text.parse::<f64>().is_err() => self.texture(arguments)?,
+                        _ =>
+                        {
+                            let value = arguments.unit_f64(&format!("a value for '{}'", name.text))?;
+                            Arc::new(SolidColor::make_new(FColor::new_color(value, value, value)))
+                        }
+                    };
+                }
+            }
+        }
+        Ok(parameters)
+    }
+
+    fn parse_light(&mut self, arguments: &mut Arguments) -> Result<(), ParseError>
+    {
+        let light: Arc<dyn Light> = match arguments.keyword.text
+        {
+            "point_light" =>
+            {
+                let position = arguments.vect("a position")?;
+                Arc::new(PointLight::make_new(position, arguments.color()?))
+            }
+            "spot_light" =>
+            {
+                let position = arguments.vect("a position")?;
+                let target_column = arguments.tokens.get(arguments.next).map_or(arguments.end_column, |token| token.column);
+                let target = arguments.vect("a target point")?;
+                if (target - position).is_zero()
+                {
+                    return Err(ParseError::new(arguments.line, target_column, "the spot light must point at a target away from its position"));
+                }
+                let intensity = arguments.color()?;
+                let inner_angle = arguments.f64("an inner cone angle")?;
+                let outer_column = arguments.tokens.get(arguments.next).map_or(arguments.end_column, |token| token.column);
+                let outer_angle = arguments.f64("an outer cone angle")?;
+                if inner_angle < 0.0 || inner_angle > outer_angle || outer_angle > 180.0
+                {
+                    return Err(ParseError::new(arguments.line, outer_column, "the cone angles must satisfy 0 <= inner <= outer <= 180"));
+                }
+                Arc::new(SpotLight::make_new(position, target, intensity, inner_angle, outer_angle))
+            }
+            _ =>
+            {
+                let direction_column = arguments.tokens.get(arguments.next).map_or(arguments.end_column, |token| token.column);
+                let direction = arguments.vect("a direction")?;
+                if direction.is_zero()
+                {
+                    return Err(ParseError::new(arguments.line, direction_column, "the light direction can't be zero"));
+                }
+                let irradiance = arguments.color()?;
+                // The angular diameter is optional, a sharp light by default
+                let angular_diameter = if arguments.tokens.get(arguments.next).is_some() { arguments.non_negative_f64("an angular diameter")? } else { 0.0 };
+                Arc::new(DirectionalLight::make_new(direction, irradiance, angular_diameter))
+            }
+        };
+        self.lights.push(light);
+        Ok(())
+    }
+
+    // A medium filling a sphere or a closed mesh
+    fn parse_medium(&mut self, arguments: &mut Arguments) -> Result<(), LoadError>
+    {
+        let parse_error = |error: ParseError| LoadError::Parse(self.path.to_path_buf(), error);
+        let density = arguments.positive_f64("a density").map_err(parse_error)?;
+        let phase_function = self.phase_function(arguments).map_err(parse_error)?;
+        let shape = arguments.token("'sphere' or 'mesh'").map_err(parse_error)?;
+        let boundary: Arc<dyn Hittable> = match shape.text
+        {
+            "sphere" =>
+            {
+                let center = arguments.vect("a center").map_err(parse_error)?;
+                let radius = arguments.radius().map_err(parse_error)?;
+                Arc::new(Sphere::make_new(center, radius, phase_function.clone()))
+            }
+            "mesh" =>
+            {
+                let path = arguments.token("a file path").map_err(parse_error)?;
+                Arc::new(load_obj(&self.directory.join(path.text))?)
+            }
+            _ => return Err(parse_error(ParseError::make_new(arguments.line, shape.column, format!("expected 'sphere' or 'mesh', found '{}'", shape.text)))),
+        };
+        self.world.add(Arc::new(ConstantMedium::make_new(boundary, density, phase_function)));
+        Ok(())
+    }
+
+    // A medium whose density, and optionally temperature and emission, come from the grids of a file
+    fn parse_volume(&mut self, arguments: &mut Arguments) -> Result<(), LoadError>
+    {
+        let parse_error = |error: ParseError| LoadError::Parse(self.path.to_path_buf(), error);
+        let path = arguments.token("a grid file path").map_err(parse_error)?;
+        let path_column = path.column;
+        let mut grids = load_grids(&self.directory.join(path.text))?;
+        let Some(density) = grids.grids.remove("density")
+        else
+        {
+            return Err(parse_error(ParseError::make_new(arguments.line, path_column, format!("'{}' has no density grid", path.text))));
+        };
+
+        let mut parameters = GridParameters::default();
+        parameters.placement = grids.placement;
+        parameters.phase_function = self.phase_function(arguments).map_err(parse_error)?;
+        parameters.temperature = grids.grids.remove("temperature");
+        parameters.emission = grids.grids.remove("emission");
+        while let Some(option) = arguments.tokens.get(arguments.next)
+        {
+            arguments.next += 1;
+            match option.text
+            {
+                "density" => parameters.density_scale = arguments.non_negative_f64("a density scale").map_err(parse_error)?,
+                "temperature" =>
+                {
+                    parameters.temperature_scale = arguments.f64("a temperature scale").map_err(parse_error)?;
+                    parameters.blackbody_intensity = arguments.non_negative_f64("a blackbody intensity").map_err(parse_error)?;
+                }
+                "emission" => parameters.emission_color = arguments.color().map_err(parse_error)?,
+                "origin" => parameters.placement.origin = arguments.vect("an origin").map_err(parse_error)?,
+                "voxel_size" => parameters.placement.voxel_size = arguments.positive_f64("a voxel size").map_err(parse_error)?,
+                _ => return Err(parse_error(ParseError::make_new(arguments.line, option.column, format!("unknown volume option '{}'", option.text)))),
+            }
+        }
+        self.world.add(Arc::new(GridMedium::make_new(density, parameters)));
+        Ok(())
+    }
+
+    // Names a sphere or a mesh without adding it to the world
+    fn parse_object(&mut self, arguments: &mut Arguments) -> Result<(), LoadError>
+    {
+        let parse_error = |error: ParseError| LoadError::Parse(self.path.to_path_buf(), error);
+        let name = arguments.token("an object name").map_err(parse_error)?;
+        if self.objects.contains_key(name.text)
+        {
+            return Err(parse_error(ParseError::make_new(arguments.line, name.column, format!("object '{}' is already defined", name.text))));
+        }
+        let shape = arguments.token("'sphere' or 'mesh'").map_err(parse_error)?;
+        let object: Arc<dyn Hittable> = match shape.text
+        {
+            "sphere" =>
+            {
+                let center = arguments.vect("a center").map_err(parse_error)?;
+                let radius = arguments.radius().map_err(parse_error)?;
+                let material = self.material(arguments).map_err(parse_error)?;
+                Arc::new(Sphere::make_new(center, radius, material))
+            }
+            "mesh" =>
+            {
+                let path = arguments.token("a file path").map_err(parse_error)?;
+                Arc::new(load_obj(&self.directory.join(path.text))?)
+            }
+            _ => return Err(parse_error(ParseError::make_new(arguments.line, shape.column, format!("expected 'sphere' or 'mesh', found '{}'", shape.text)))),
+        };
+        self.objects.insert(name.text.to_string(), object);
+        Ok(())
+    }
+
+    // Places a named object in the world, applying the transforms in the order they are written.
+    // Each 'at <time>' starts the transforms of a new keyframe, the ones before the first apply at time 0.
+    fn parse_instance(&mut self, arguments: &mut Arguments) -> Result<(), ParseError>
+    {
+        let name = arguments.token("an object name")?;
+        let object = self.objects.get(name.text).cloned().ok_or_else(||
+            ParseError::make_new(arguments.line, name.column, format!("undefined object '{}'", name.text)))?;
+
+        let mut keyframes = vec![(0.0, Matrix::identity())];
+        let mut leading = true;
+        while let Some(operation) = arguments.tokens.get(arguments.next)
+        {
+            arguments.next += 1;
+            if operation.text == "at"
+            {
+                let column = arguments.tokens.get(arguments.next).map_or(arguments.end_column, |token| token.column);
+                let time = arguments.f64("a time")?;
+                if leading
+                {
+                    // Starting with 'at' times the first keyframe
+                    keyframes[0].0 = time;
+                }
+                else if keyframes.last().is_some_and(|(previous, _)| time <= *previous)
+                {
+                    return Err(ParseError::new(arguments.line, column, "keyframe times must increase"));
+                }
+                else
+                {
+                    keyframes.push((time, Matrix::identity()));
+                }
+                leading = false;
+                continue;
+            }
+            leading = false;
+            let step = match operation.text
+            {
+                "translate" => Matrix::translation(&arguments.vect("an offset")?),
+                "rotate" =>
+                {
+                    let column = arguments.tokens.get(arguments.next).map_or(arguments.end_column, |token| token.column);
+                    let axis = arguments.vect("an axis")?;
+                    if axis.is_zero()
+                    {
+                        return Err(ParseError::new(arguments.line, column, "the rotation axis can't be zero"));
+                    }
+                    Matrix::rotation(&axis, arguments.f64("an angle")?)
+                }
+                "scale" => Matrix::scaling(&arguments.vect("3 scale factors")?),
+                _ => return Err(ParseError::make_new(arguments.line, operation.column, format!("unknown transform '{}'", operation.text))),
+            };
+            if let Some((_, matrix)) = keyframes.last_mut()
+            {
+                *matrix = step * *matrix;
+            }
+        }
+
+        let instance = Transform::make_animated(object, keyframes).ok_or_else(||
+            ParseError::new(arguments.line, name.column, "the transform can't be inverted"))?;
+        self.world.add(Arc::new(instance));
+        Ok(())
+    }
+
+    fn parse_sky(&mut self, arguments: &mut Arguments) -> Result<(), ParseError>
+    {
+        let turbidity = arguments.positive_f64("a turbidity")?;
+        let mode = arguments.token("'sun' or 'location'")?;
+        let direction = match mode.text
+        {
+            "sun" => sun_direction(arguments.f64("an elevation")?, arguments.f64("an azimuth")?),
+            "location" =>
+            {
+                let latitude = arguments.f64("a latitude")?;
+                let longitude = arguments.f64("a longitude")?;
+                let utc_offset = arguments.f64("a UTC offset")?;
+                let day = arguments.f64("a day of the year")?;
+                solar_direction(latitude, longitude, utc_offset, day, arguments.f64("a time of day")?)
+            }
+            _ => return Err(ParseError::make_new(arguments.line, mode.column, format!("expected 'sun' or 'location', found '{}'", mode.text))),
+        };
+        let intensity = if arguments.tokens.get(arguments.next).is_some() { arguments.non_negative_f64("an intensity")? } else { 1.0 };
+
+        let sky = PreethamSky::make_new(direction, turbidity, intensity);
+        self.sun = sky.sun_light().map(|sun| Arc::new(sun) as Arc<dyn Light>);
+        self.environment = Arc::new(sky);
+        Ok(())
+    }
+
+    fn parse_statement(&mut self, arguments: &mut Arguments) -> Result<(), LoadError>
+    {
+        let parse_error = |error: ParseError| LoadError::Parse(self.path.to_path_buf(), error);
+
+        match arguments.keyword.text
+        {
+            "output" =>
+            {
+                let path = arguments.token("a file path").map_err(parse_error)?;
+                self.output = Some(self.directory.join(path.text));
+            }
+            "resolution" =>
+            {
+                self.settings.width = arguments.bounded_usize("a width", RenderSettings::MAX_RESOLUTION).map_err(parse_error)?;
+                self.settings.height = arguments.bounded_usize("a height", RenderSettings::MAX_RESOLUTION).map_err(parse_error)?;
+            }
+            "samples" => self.settings.samples = arguments.positive_usize("a sample count").map_err(parse_error)?,
+            "max_depth" => self.settings.max_depth = arguments.bounded_usize("a depth", u32::MAX as usize).map_err(parse_error)? as u32,
+            "spectral" =>
+            {
+                let switch = arguments.token("on or off").map_err(parse_error)?;
+                self.settings.spectral = match switch.text
+                {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(parse_error(ParseError::make_new(arguments.line, switch.column, format!("expected on or off, found '{}'", switch.text)))),
+                };
+            }
+            "camera" => self.parse_camera(arguments).map_err(parse_error)?,
+            "background" =>
+            {
+                let sky = arguments.tokens.get(arguments.next).is_some_and(|token| token.text == "sky");
+                self.sun = None;
+                self.environment = if sky
+                {
+                    arguments.next += 1;
+                    Arc::new(SkyGradient)
+                }
+                else
+                {
+                    Arc::new(ConstantEnvironment::make_new(arguments.color().map_err(parse_error)?))
+                };
+            }
+            "environment" =>
+            {
+                let path = arguments.token("an image path").map_err(parse_error)?;
+                let path = self.directory.join(path.text);
+                // Optional intensity and rotation about the up axis in degrees
+                let intensity = if arguments.tokens.get(arguments.next).is_some() { arguments.non_negative_f64("an intensity").map_err(parse_error)? } else { 1.0 };
+                let rotation = if arguments.tokens.get(arguments.next).is_some() { arguments.f64("a rotation").map_err(parse_error)? } else { 0.0 };
+                arguments.finish().map_err(parse_error)?;
+                let image = read_image(&path).map_err(|error| LoadError::Io(path.clone(), error))?;
+                self.sun = None;
+                self.environment = Arc::new(ImageEnvironment::make_new(image, intensity, rotation));
+            }
+            "sky" => self.parse_sky(arguments).map_err(parse_error)?,
+            "material" => self.parse_material(arguments).map_err(parse_error)?,
+            "texture" => self.parse_texture(arguments)?,
+            "point_light" | "spot_light" | "directional_light" => self.parse_light(arguments).map_err(parse_error)?,
+            "sphere" =>
+            {
+                let center = arguments.vect("a center").map_err(parse_error)?;
+                let radius = arguments.radius().map_err(parse_error)?;
+                let material = self.material(arguments).map_err(parse_error)?;
+                let sphere = match arguments.tokens.get(arguments.next)
+                {
+                    Some(token) if token.text == "moving" =>
+                    {
+                        arguments.next += 1;
+                        Sphere::make_moving(center, arguments.vect("a center at time 1").map_err(parse_error)?, radius, material)
+                    }
+                    _ => Sphere::make_new(center, radius, material),
+                };
+                self.world.add(Arc::new(sphere));
+            }
+            "triangle" =>
+            {
+                let v0 = arguments.vect("3 vertices").map_err(parse_error)?;
+                let v1 = arguments.vect("3 vertices").map_err(parse_error)?;
+                let v2 = arguments.vect("3 vertices").map_err(parse_error)?;
+                let material = self.material(arguments).map_err(parse_error)?;
+                self.world.add(Arc::new(Triangle::make_new(v0, v1, v2, material)));
+            }
+            "mesh" =>
+            {
+                let path = arguments.token("a file path").map_err(parse_error)?;
+                let meshes = load_obj(&self.directory.join(path.text))?;
+                for mesh in meshes.hittables
+                {
+                    self.world.add(mesh);
+                }
+            }
+            "object" => self.parse_object(arguments)?,
+            "instance" => self.parse_instance(arguments).map_err(parse_error)?,
+            "medium" => self.parse_medium(arguments)?,
+            "volume" => self.parse_volume(arguments)?,
+            "fog" =>
+            {
+                let density = arguments.positive_f64("a density").map_err(parse_error)?;
+                let phase_function = self.phase_function(arguments).map_err(parse_error)?;
+                // Up to an optional height, everywhere by default
+                let height = if arguments.tokens.get(arguments.next).is_some() { arguments.f64("a height").map_err(parse_error)? } else { INFINITY };
+                self.fog = Some(Fog::make_new(density, phase_function, height));
+            }
+            _ =>
+            {
+                let keyword = arguments.keyword;
+                return Err(parse_error(ParseError::make_new(arguments.line, keyword.column, format!("unknown statement '{}'", keyword.text))));
+            }
+        }
+        arguments.finish().map_err(parse_error)
+    }
+
+    fn parse(&mut self, source: &str) -> Result<(), LoadError>
+    {
+        for (line_index, line) in source.lines().enumerate()
+        {
+            let tokens = tokenize(line);
+            let Some((keyword, rest)) = tokens.split_first() else { continue };
+            let last = tokens.last().unwrap_or(keyword);
+            let mut arguments = Arguments
+            {
+                line: line_index + 1,
+                keyword,
+                tokens: rest,
+                next: 0,
+                end_column: last.column + last.text.chars().count(),
+            };
+            self.parse_statement(&mut arguments)?;
+        }
+        Ok(())
+    }
+
+    fn build(mut self) -> Scene
+    {
+        self.lights.extend(self.sun.take());
+        let camera = &self.camera;
+        let focus_distance = camera.focus_distance.unwrap_or_else(|| (camera.position - camera.look_at).length());
+        let output = self.output.unwrap_or_else(||
+            {
+                let stem = self.path.file_stem().map_or("output".into(), |stem| stem.to_string_lossy());
+                self.directory.join(format!("{}.ppm", stem))
+            });
+
+        let mut scene_camera = Camera::new(camera.position, camera.look_at, camera.up, camera.vfov,
+                                           self.settings.aspect_ratio(), camera.aperture, focus_distance);
+        scene_camera.set_shutter(camera.shutter.0, camera.shutter.1);
+
+        Scene
+        {
+            camera: scene_camera,
+            world: self.world,
+            lights: self.lights,
+            environment: self.environment,
+            fog: self.fog,
+            settings: self.settings,
+            output,
+        }
+    }
+}
+
+// Parses scene source text. `path` is used for error messages and to resolve relative paths.
+pub fn parse_scene(source: &str, path: &Path) -> Result<Scene, LoadError>
+{
+    let mut parser = SceneParser::new(path);
+    parser.parse(source)?;
+
+    let camera = &parser.camera;
+    if (camera.position - camera.look_at).is_zero() || Vect::cross(&(camera.look_at - camera.position), &camera.up).is_zero()
+    {
+        return Err(LoadError::Parse(path.to_path_buf(),
+            ParseError::new(camera.line, 1, "the camera must look at a point away from its position and not along its up vector")));
+    }
+    Ok(parser.build())
+}
+
+pub fn load_scene(path: &Path) -> Result<Scene, LoadError>
+{
+    let source = fs::read_to_string(path).map_err(|error| LoadError::Io(path.to_path_buf(), error))?;
+    parse_scene(&source, path)
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn parse_error(source: &str) -> ParseError
+    {
+        match parse_scene(source, Path::new("scenes/test.scene"))
+        {
+            Err(LoadError::Parse(_, error)) => error,
+            Err(error) => panic!("expected a parse error, got {}", error),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn full_scene_test()
+    {
+        let source = "\
+# Three balls on a big one
+output renders/balls.ppm
+resolution 320 180
+samples 16
+max_depth 8
+
+camera position 0 1 3
+camera look_at 0 0 -1
+camera up 0 1 0
+camera vfov 40
+camera aperture 0.1
+
+material ground lambertian 0.8 0.8 0.0
+material gold   metal 0.8 0.6 0.2 0.3
+material glass  dielectric 1.5
+material lamp   diffuse_light 4 4 4
+
+sphere 0 -100.5 -1 100 ground
+sphere -1 0 -1 0.5 glass
+sphere  1 0 -1 0.5 gold   # trailing comment
+triangle 0 0 0  1 0 0  0 1 0 gold
+sphere 0 5 0 1 lamp
+background 0 0 0.1
+point_light 0 4 0  10 10 10
+spot_light 0 4 0  0 0 -1  20 20 20  15 30
+directional_light 1 1 0  2 2 2  0.53
+directional_light 0 1 0  1 1 1
+";
+        let scene = parse_scene(source, Path::new("scenes/test.scene")).unwrap();
+        assert_eq!(scene.settings, RenderSettings{ width: 320, height: 180, samples: 16, max_depth: 8, ..RenderSettings::default() });
+        assert_eq!(scene.output, PathBuf::from("scenes/renders/balls.ppm"));
+        assert_eq!(scene.world.len(), 5);
+        assert_eq!(scene.lights.len(), 4);
+        assert_eq!(scene.environment.radiance(&Vect::make_new(0.0, 1.0, 0.0)), FColor::new_color(0.0, 0.0, 0.1));
+        assert_eq!(scene.camera.origin, Point::make_new(0.0, 1.0, 3.0));
+        assert_eq!(scene.camera.lens_radius, 0.05);
+    }
+
+    #[test]
+    fn defaults_test()
+    {
+        let scene = parse_scene("camera look_at 0 0 -1\n", Path::new("scenes/test.scene")).unwrap();
+        assert_eq!(scene.settings, RenderSettings::default());
+        assert_eq!(scene.output, PathBuf::from("scenes/test.ppm"));
+        assert!(scene.world.is_empty());
+        assert!(scene.lights.is_empty());
+        let up = Vect::make_new(0.0, 1.0, 0.0);
+        assert_eq!(scene.environment.radiance(&up), FColor::new_color(0.5, 0.7, 1.0));
+        assert_eq!(parse_scene("background 1 1 1\nbackground sky\n", Path::new("a.scene")).unwrap().environment.radiance(&up), FColor::new_color(0.5, 0.7, 1.0));
+    }
+
+    #[test]
+    fn texture_test()
+    {
+        let source = "\
+texture white solid 1 1 1
+texture checks checker white 0 0 0 0.5
+texture clouds turbulence 1 1 1 4
+texture stone marble 0.9 0.9 0.9  0.2 0.2 0.3  5
+material ground lambertian checks
+material shiny metal stone 0.2
+material plain lambertian 0.5 0.5 0.5
+sphere 0 0 0 1 ground
+";
+        let scene = parse_scene(source, Path::new("a.scene")).unwrap();
+        assert_eq!(scene.world.len(), 1);
+
+        let error = parse_error("material a lambertian checks\n");
+        assert_eq!((error.line, error.column), (1, 23));
+        assert_eq!(error.message, "undefined texture 'checks'");
+
+        let error = parse_error("texture a solid 1 1 1\ntexture a noise 1 1 1 2\n");
+        assert_eq!((error.line, error.column), (2, 9));
+
+        let error = parse_error("texture a plaid 1 1 1\n");
+        assert_eq!((error.line, error.column), (1, 11));
+
+        let error = parse_error("texture a image wall.png tile\n");
+        assert_eq!((error.line, error.column), (1, 26));
+
+        let error = parse_error("texture a wood 1 1 1 0 0 0 -2\n");
+        assert_eq!((error.line, error.column), (1, 28));
+
+        assert!(matches!(parse_scene("texture a image missing.png clamp\n", Path::new("a.scene")), Err(LoadError::Io(..))));
+    }
+
+    #[test]
+    fn microfacet_test()
+    {
+        let source = "\
+material gold conductor gold 0.2
+material custom conductor 0.2 0.9 1.1  3.9 2.4 2.2  0
+material frosted dielectric 1.5 0.3
+material glass dielectric 1.5 0
+sphere 0 0 0 1 gold
+sphere 2 0 0 1 frosted
+";
+        let scene = parse_scene(source, Path::new("a.scene")).unwrap();
+        assert_eq!(scene.world.len(), 2);
+
+        let error = parse_error("material a conductor brass 0.2\n");
+        assert_eq!((error.line, error.column), (1, 22));
+        assert_eq!(error.message, "unknown conductor 'brass'");
+
+        let error = parse_error("material a conductor 0.2 0.9 1.1  3.9 2.4\n");
+        assert_eq!((error.line, error.column), (1, 42));
+
+        let error = parse_error("material a conductor gold\n");
+        assert_eq!((error.line, error.column), (1, 26));
+
+        let error = parse_error("material a dielectric 1.5 rough\n");
+        assert_eq!((error.line, error.column), (1, 27));
+    }
+
+    #[test]
+    fn dispersion_test()
+    {
+        let source = "\
+material crown dielectric bk7
+material gem dielectric diamond absorption 0.1 0.2 0
+material flint dielectric cauchy 1.6 0.01
+material custom dielectric sellmeier 1.03 0.23 1.01 0.006 0.02 103.5 absorption 0 0 1
+material tinted dielectric 1.5 0.1 absorption 0.5 0.5 0
+sphere 0 0 0 1 gem
+spectral on
+";
+        let scene = parse_scene(source, Path::new("a.scene")).unwrap();
+        assert_eq!(scene.world.len(), 1);
+        assert!(scene.settings.spectral);
+        assert!(!parse_scene("spectral off\n", Path::new("a.scene")).unwrap().settings.spectral);
+
+        let error = parse_error("spectral yes\n");
+        assert_eq!((error.line, error.column), (1, 10));
+        assert_eq!(error.message, "expected on or off, found 'yes'");
+
+        let error = parse_error("material a dielectric obsidian\n");
+        assert_eq!((error.line, error.column), (1, 23));
+        assert_eq!(error.message, "unknown glass 'obsidian'");
+
+        let error = parse_error("material a dielectric sellmeier 1 1 1 0 1 1\n");
+        assert_eq!((error.line, error.column), (1, 39));
+
+        let error = parse_error("material a dielectric 1.5 absorption 1 1\n");
+        assert_eq!((error.line, error.column), (1, 41));
+
+        let error = parse_error("material a dielectric bk7 0.3\n");
+        assert_eq!((error.line, error.column), (1, 27));
+        assert_eq!(error.message, "rough glass needs a constant index of refraction");
+        let error = parse_error("material a dielectric cauchy 1.5 0.004 0.2 absorption 0 0 1\n");
+        assert_eq!((error.line, error.column), (1, 40));
+        // Smooth dispersive glass is fine
+        assert!(parse_scene("material a dielectric bk7 0\n", Path::new("a.scene")).is_ok());
+    }
+
+    #[test]
+    fn medium_test()
+    {
+        let source = "\
+material smoke isotropic 0.8 0.8 0.8
+material haze henyey_greenstein 1 1 1 0.6
+medium 0.5 smoke sphere 0 1 0 1
+fog 0.05 haze 2
+";
+        let scene = parse_scene(source, Path::new("a.scene")).unwrap();
+        assert_eq!(scene.world.len(), 1);
+        assert!(scene.fog.is_some());
+        assert!(parse_scene("", Path::new("a.scene")).unwrap().fog.is_none());
+
+        let error = parse_error("material a lambertian 1 1 1\nfog 0.1 a\n");
+        assert_eq!((error.line, error.column), (2, 9));
+        assert_eq!(error.message, "media need an isotropic or henyey_greenstein material");
+
+        let error = parse_error("material a isotropic 1 1 1\nmedium 0.1 a box 0 0 0 1\n");
+        assert_eq!((error.line, error.column), (2, 14));
+        assert_eq!(error.message, "expected 'sphere' or 'mesh', found 'box'");
+
+        let error = parse_error("material a isotropic 1 1 1\nmedium 0 a sphere 0 0 0 1\n");
+        assert_eq!((error.line, error.column), (2, 8));
+
+        let error = parse_error("material a henyey_greenstein 1 1 1\n");
+        assert_eq!((error.line, error.column), (1, 35));
+    }
+
+    #[test]
+    fn volume_test()
+    {
+        let path = std::env::temp_dir().join(format!("volume_test_{}.nvdb", std::process::id()));
+        fs::write(&path, "voxel_size 0.5\ngrid density\nvoxel 0 0 0 1\ngrid temperature\nvoxel 0 0 0 1500\n").unwrap();
+        let source = format!("material smoke isotropic 0.5 0.5 0.5\nvolume {} smoke density 2 temperature 1 0.5 origin 0 1 0\n", path.display());
+        let scene = parse_scene(&source, Path::new("a.scene"));
+        let error = parse_error(&format!("material smoke isotropic 1 1 1\nvolume {} smoke scale 2\n", path.display()));
+        let density_error = parse_error(&format!("material smoke isotropic 1 1 1\nvolume {} smoke density -2\n", path.display()));
+        let intensity_error = parse_error(&format!("material smoke isotropic 1 1 1\nvolume {} smoke temperature 1 -1\n", path.display()));
+        fs::remove_file(&path).unwrap();
+        let scene = scene.unwrap();
+        assert_eq!(scene.world.len(), 1);
+        let bounds = scene.world.bounding_box().unwrap();
+        assert_eq!((*bounds.minimum(), *bounds.maximum()), (Point::make_new(0.0, 1.0, 0.0), Point::make_new(0.5, 1.5, 0.5)));
+        assert_eq!(error.message, "unknown volume option 'scale'");
+        assert_eq!(density_error.message, "a density scale can't be negative");
+        assert_eq!(intensity_error.message, "a blackbody intensity can't be negative");
+
+        assert!(matches!(parse_scene("material a isotropic 1 1 1\nvolume missing.vgrid a\n", Path::new("a.scene")), Err(LoadError::Io(..))));
+        assert!(matches!(parse_scene("material a isotropic 1 1 1\nvolume smoke.vdb a\n", Path::new("a.scene")), Err(LoadError::Io(..))));
+    }
+
+    #[test]
+    fn instance_test()
+    {
+        let source = "\
+material red lambertian 0.8 0.1 0.1
+object ball sphere 0 0 0 1 red
+instance ball translate 1 0 0
+instance ball scale 2 2 2 translate 0 3 0
+";
+        let scene = parse_scene(source, Path::new("a.scene")).unwrap();
+        assert_eq!(scene.world.len(), 2);
+        let bounds = scene.world.bounding_box().unwrap();
+        assert_eq!((*bounds.minimum(), *bounds.maximum()), (Point::make_new(-2.0, -1.0, -2.0), Point::make_new(2.0, 5.0, 2.0)));
+
+        let error = parse_error("instance ball\n");
+        assert_eq!((error.line, error.column), (1, 10));
+        assert_eq!(error.message, "undefined object 'ball'");
+
+        let error = parse_error("material a lambertian 1 1 1\nobject b sphere 0 0 0 1 a\nobject b sphere 0 0 0 2 a\n");
+        assert_eq!((error.line, error.column), (3, 8));
+
+        let error = parse_error("material a lambertian 1 1 1\nobject b sphere 0 0 0 1 a\ninstance b shear 1 0 0\n");
+        assert_eq!((error.line, error.column), (3, 12));
+        assert_eq!(error.message, "unknown transform 'shear'");
+
+        let error = parse_error("material a lambertian 1 1 1\nobject b sphere 0 0 0 1 a\ninstance b rotate 0 0 0 90\n");
+        assert_eq!((error.line, error.column), (3, 19));
+
+        let error = parse_error("material a lambertian 1 1 1\nobject b sphere 0 0 0 1 a\ninstance b scale 1 0 1\n");
+        assert_eq!((error.line, error.column), (3, 10));
+        assert_eq!(error.message, "the transform can't be inverted");
+    }
+
+    #[test]
+    fn motion_test()
+    {
+        let source = "\
+camera shutter 0 1
+material red lambertian 0.8 0.1 0.1
+sphere 0 0 0 1 red moving 2 0 0
+object ball sphere 0 0 0 1 red
+instance ball translate 0 3 0 at 1 translate 4 3 0
+instance ball at 0.5 at 1 scale 2 2 2
+";
+        let scene = parse_scene(source, Path::new("a.scene")).unwrap();
+        assert_eq!((scene.camera.shutter_open, scene.camera.shutter_close), (0.0, 1.0));
+        assert_eq!(scene.world.len(), 3);
+        let bounds = scene.world.hittables[0].bounding_box().unwrap();
+        assert_eq!((*bounds.minimum(), *bounds.maximum()), (Point::make_new(-1.0, -1.0, -1.0), Point::make_new(3.0, 1.0, 1.0)));
+        let bounds = scene.world.hittables[1].bounding_box().unwrap();
+        assert!(bounds.minimum().x() <= -1.0 && bounds.maximum().x() >= 5.0);
+        assert!(bounds.maximum().x() < 5.01);
+        let bounds = scene.world.hittables[2].bounding_box().unwrap();
+        assert!(bounds.maximum().x() >= 2.0);
+        assert_eq!(parse_scene("", Path::new("a.scene")).unwrap().camera.shutter_close, 0.0);
+
+        let error = parse_error("camera shutter 1 0.5\n");
+        assert_eq!((error.line, error.column), (1, 18));
+        assert_eq!(error.message, "the shutter can't close before it opens");
+
+        let error = parse_error("material a lambertian 1 1 1\nobject b sphere 0 0 0 1 a\ninstance b at 1 translate 1 0 0 at 1\n");
+        assert_eq!((error.line, error.column), (3, 36));
+        assert_eq!(error.message, "keyframe times must increase");
+
+        let error = parse_error("material a lambertian 1 1 1\nsphere 0 0 0 1 a moving 1 0\n");
+        assert_eq!(error.line, 2);
+    }
+
+    #[test]
+    fn principled_test()
+    {
+        let source = "\
+texture rust voronoi 0.6 0.3 0.1  0.3 0.1 0.05  4
+material plastic principled base_color 0.8 0.1 0.1 roughness 0.3 clearcoat 1
+material glass principled transmission 1 roughness 0.1 ior 1.45
+material worn principled base_color rust metallic rust anisotropic 0.5
+material plain principled
+sphere 0 0 0 1 worn
+";
+        let scene = parse_scene(source, Path::new("a.scene")).unwrap();
+        assert_eq!(scene.world.len(), 1);
+
+        let error = parse_error("material a principled shininess 3\n");
+        assert_eq!((error.line, error.column), (1, 23));
+        assert_eq!(error.message, "unknown principled parameter 'shininess'");
+
+        let error = parse_error("material a principled roughness\n");
+        assert_eq!((error.line, error.column), (1, 32));
+        assert_eq!(error.message, "'material' is missing a value for 'roughness'");
+
+        let error = parse_error("material a principled metallic chrome\n");
+        assert_eq!((error.line, error.column), (1, 32));
+
+        let error = parse_error("material a principled ior -1\n");
+        assert_eq!((error.line, error.column), (1, 27));
+    }
+
+    #[test]
+    fn sky_test()
+    {
+        let up = Vect::make_new(0.0, 1.0, 0.0);
+        let scene = parse_scene("sky 3 sun 30 180\npoint_light 0 1 0  1 1 1\n", Path::new("a.scene")).unwrap();
+        assert_eq!(scene.lights.len(), 2);
+        assert_eq!(scene.environment.radiance(&up), PreethamSky::make_new(sun_direction(30.0, 180.0), 3.0, 1.0).radiance(&up));
+
+        let scene = parse_scene("sky 2.5 location 40 -75 -5 172 12.5 2\n", Path::new("a.scene")).unwrap();
+        let sky = PreethamSky::make_new(solar_direction(40.0, -75.0, -5.0, 172.0, 12.5), 2.5, 2.0);
+        assert_eq!(scene.environment.radiance(&up), sky.radiance(&up));
+        assert_eq!(scene.lights.len(), 1);
+
+        // The sun goes away with its sky, and doesn't light the scene at night
+        assert!(parse_scene("sky 3 sun 30 180\nbackground sky\n", Path::new("a.scene")).unwrap().lights.is_empty());
+        assert!(parse_scene("sky 3 sun -10 180\n", Path::new("a.scene")).unwrap().lights.is_empty());
+    }
+
+    #[test]
+    fn error_test()
+    {
+        let error = parse_error("samples 10\nsphere 0 0 0 1 missing\n");
+        assert_eq!((error.line, error.column), (2, 16));
+        assert_eq!(error.message, "undefined material 'missing'");
+
+        let error = parse_error("resolution 640\n");
+        assert_eq!((error.line, error.column), (1, 15));
+
+        let error = parse_error("\n  resolution 640 0\n");
+        assert_eq!((error.line, error.column), (2, 18));
+
+        let error = parse_error("max_depth 5000000000\n");
+        assert_eq!((error.line, error.column), (1, 11));
+        assert_eq!(error.message, "a depth can't be above 4294967295");
+
+        let error = parse_error("resolution 100000 100000\n");
+        assert_eq!((error.line, error.column), (1, 12));
+        assert_eq!(error.message, "a width can't be above 65536");
+
+        let error = parse_error("material a lambertian 1 x 1\n");
+        assert_eq!((error.line, error.column), (1, 25));
+
+        let error = parse_error("material a lambertian 1 1 1\nmaterial a dielectric 1.5\n");
+        assert_eq!((error.line, error.column), (2, 10));
+
+        let error = parse_error("samples 4 5\n");
+        assert_eq!((error.line, error.column), (1, 11));
+
+        let error = parse_error("camera fov 30\n");
+        assert_eq!((error.line, error.column), (1, 8));
+
+        let error = parse_error("background sky 1\n");
+        assert_eq!((error.line, error.column), (1, 16));
+
+        let error = parse_error("background blue\n");
+        assert_eq!((error.line, error.column), (1, 12));
+
+        let error = parse_error("spot_light 0 1 0  0 1 0  1 1 1  10 20\n");
+        assert_eq!((error.line, error.column), (1, 19));
+
+        let error = parse_error("spot_light 0 1 0  0 0 0  1 1 1  30 20\n");
+        assert_eq!((error.line, error.column), (1, 36));
+
+        let error = parse_error("directional_light 0 0 0  1 1 1\n");
+        assert_eq!((error.line, error.column), (1, 19));
+
+        let error = parse_error("point_light 0 1 0  1 1\n");
+        assert_eq!((error.line, error.column), (1, 23));
+
+        let error = parse_error("environment sky.hdr 1 90 2\n");
+        assert_eq!((error.line, error.column), (1, 26));
+        assert!(matches!(parse_scene("environment missing.hdr\n", Path::new("a.scene")), Err(LoadError::Io(..))));
+
+        let error = parse_error("sky 3 moon 30 180\n");
+        assert_eq!((error.line, error.column), (1, 7));
+
+        let error = parse_error("sky 0 sun 30 180\n");
+        assert_eq!((error.line, error.column), (1, 5));
+
+        let error = parse_error("sky 3 location 40 -75 -5 172\n");
+        assert_eq!((error.line, error.column), (1, 29));
+
+        let error = parse_error("cube 0 0 0\n");
+        assert_eq!((error.line, error.column), (1, 1));
+
+        let error = parse_error("samples 1\ncamera position 0 0 -1\n");
+        assert_eq!((error.line, error.column), (2, 1));
+    }
+
+    #[test]
+    fn value_range_test()
+    {
+        let error = parse_error("camera aperture -0.1\n");
+        assert_eq!((error.line, error.column), (1, 17));
+        assert_eq!(error.message, "an aperture can't be negative");
+
+        let error = parse_error("directional_light 0 -1 0  1 1 1  -2\n");
+        assert_eq!((error.line, error.column), (1, 34));
+        assert_eq!(error.message, "an angular diameter can't be negative");
+
+        let error = parse_error("sky 3 sun 30 180 -1\n");
+        assert_eq!((error.line, error.column), (1, 18));
+        assert_eq!(error.message, "an intensity can't be negative");
+
+        let error = parse_error("environment sky.hdr -1\n");
+        assert_eq!((error.line, error.column), (1, 21));
+        assert_eq!(error.message, "an intensity can't be negative");
+
+        let error = parse_error("material a lambertian 1 1 1\nsphere 0 0 0 0 a\n");
+        assert_eq!((error.line, error.column), (2, 14));
+        assert_eq!(error.message, "a radius can't be zero");
+
+        let error = parse_error("material m isotropic 1 1 1\nmedium 1 m sphere 0 0 0 0\n");
+        assert_eq!((error.line, error.column), (2, 25));
+
+        let error = parse_error("material a lambertian 1 1 1\nobject ball sphere 0 0 0 0 a\n");
+        assert_eq!((error.line, error.column), (2, 26));
+
+        // Hollow glass still works
+        assert!(parse_scene("material a dielectric 1.5\nsphere 0 0 0 -1 a\n", Path::new("a.scene")).is_ok());
+
+        let error = parse_error("camera vfov nan\n");
+        assert_eq!((error.line, error.column), (1, 13));
+        assert_eq!(error.message, "expected a number, found 'nan'");
+
+        let error = parse_error("material a lambertian 1 1 1\nsphere 0 inf 0 1 a\n");
+        assert_eq!((error.line, error.column), (2, 10));
+
+        let error = parse_error("material h henyey_greenstein 1 1 1 7\n");
+        assert_eq!((error.line, error.column), (1, 36));
+        assert_eq!(error.message, "the anisotropy must be strictly between -1 and 1");
+        assert_eq!(parse_error("material h henyey_greenstein 1 1 1 -1\n").column, 36);
+
+        let error = parse_error("material m metal 1 1 1 1.5\n");
+        assert_eq!((error.line, error.column), (1, 24));
+        assert_eq!(error.message, "a roughness must be between 0 and 1");
+        let error = parse_error("material g dielectric 1.5 -0.2\n");
+        assert_eq!((error.line, error.column), (1, 27));
+        let error = parse_error("material c conductor gold 2\n");
+        assert_eq!((error.line, error.column), (1, 27));
+
+        let error = parse_error("material p principled metallic 2\n");
+        assert_eq!((error.line, error.column), (1, 32));
+        assert_eq!(error.message, "a value for 'metallic' must be between 0 and 1");
+    }
+}
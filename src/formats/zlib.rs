@@ -0,0 +1,648 @@
+use std::io::{Error, ErrorKind};
+
+// zlib (RFC 1950) and deflate (RFC 1951) compression, plus the CRC32 and Adler32 checksums they rely on.
+// Deflate output uses the fixed Huffman codes with greedy LZ77 matching over hash chains,
+// and falls back to stored blocks whenever that would be smaller.
+// Decompression handles all three block types (stored, fixed and dynamic Huffman codes).
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Compression
+{
+    // Data is copied as is into stored blocks
+    Stored,
+    Deflate,
+}
+
+const CRC32_TABLE: [u32; 256] = make_crc32_table();
+
+const fn make_crc32_table() -> [u32; 256]
+{
+    let mut table = [0u32; 256];
+    let mut ind = 0;
+    while ind < 256
+    {
+        let mut crc = ind as u32;
+        let mut bit = 0;
+        while bit < 8
+        {
+            crc = if crc & 1 != 0 { 0xEDB88320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[ind] = crc;
+        ind += 1;
+    }
+    table
+}
+
+// Continues a CRC32 over more data, start with crc = 0
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32
+{
+    let mut crc = !crc;
+    for byte in data
+    {
+        crc = CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+pub fn crc32(data: &[u8]) -> u32
+{
+    crc32_update(0, data)
+}
+
+pub fn adler32(data: &[u8]) -> u32
+{
+    const MODULUS: u32 = 65521;
+    // 5552 is the longest run that can't overflow the sums before taking the modulus
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552)
+    {
+        for byte in chunk
+        {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= MODULUS;
+        b %= MODULUS;
+    }
+    (b << 16) | a
+}
+
+// Writes bits least significant first, as deflate expects
+struct BitWriter
+{
+    bytes: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter
+{
+    fn new() -> Self
+    {
+        BitWriter{ bytes: Vec::new(), buffer: 0, count: 0 }
+    }
+
+    fn write_bits(&mut self, value: u32, count: u32)
+    {
+        self.buffer |= (value as u64) << self.count;
+        self.count += count;
+        while self.count >= 8
+        {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes are defined most significant bit first
+    fn write_code(&mut self, code: u32, length: u32)
+    {
+        self.write_bits(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn align_to_byte(&mut self)
+    {
+        if self.count > 0
+        {
+            self.write_bits(0, 8 - self.count);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8>
+    {
+        self.align_to_byte();
+        self.bytes
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073,
+                                   4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 128;
+const HASH_BITS: u32 = 15;
+
+// Fixed literal/length code from section 3.2.6 of RFC 1951
+fn write_literal_length(writer: &mut BitWriter, symbol: u32)
+{
+    match symbol
+    {
+        0..=143 => writer.write_code(0x30 + symbol, 8),
+        144..=255 => writer.write_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.write_code(symbol - 256, 7),
+        _ => writer.write_code(0xC0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize)
+{
+    let length_code = LENGTH_BASE.iter().rposition(|base| *base as usize <= length).unwrap();
+    write_literal_length(writer, 257 + length_code as u32);
+    writer.write_bits((length - LENGTH_BASE[length_code] as usize) as u32, LENGTH_EXTRA[length_code] as u32);
+
+    let distance_code = DISTANCE_BASE.iter().rposition(|base| *base as usize <= distance).unwrap();
+    writer.write_code(distance_code as u32, 5);
+    writer.write_bits((distance - DISTANCE_BASE[distance_code] as usize) as u32, DISTANCE_EXTRA[distance_code] as u32);
+}
+
+fn hash(data: &[u8], pos: usize) -> usize
+{
+    let value = (data[pos] as u32) << 16 | (data[pos + 1] as u32) << 8 | data[pos + 2] as u32;
+    (value.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+fn deflate_fixed(data: &[u8]) -> Vec<u8>
+{
+    let mut writer = BitWriter::new();
+    // Final block, fixed Huffman codes
+    writer.write_bits(1, 1);
+    writer.write_bits(1, 2);
+
+    // head holds the latest position of every hash, prev links each position to the previous one with the same hash
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+    let insert = |head: &mut Vec<usize>, prev: &mut Vec<usize>, pos: usize|
+        {
+            if pos + MIN_MATCH <= data.len()
+            {
+                let key = hash(data, pos);
+                prev[pos % WINDOW_SIZE] = head[key];
+                head[key] = pos;
+            }
+        };
+
+    let mut pos = 0;
+    while pos < data.len()
+    {
+        let mut best_length = 0;
+        let mut best_distance = 0;
+        if pos + MIN_MATCH <= data.len()
+        {
+            let max_length = MAX_MATCH.min(data.len() - pos);
+            let mut candidate = head[hash(data, pos)];
+            let mut chain = 0;
+            while candidate != usize::MAX && pos - candidate <= WINDOW_SIZE && chain < MAX_CHAIN
+            {
+                let length = data[candidate..].iter().zip(&data[pos..pos + max_length]).take_while(|(a, b)| a == b).count();
+                if length > best_length
+                {
+                    best_length = length;
+                    best_distance = pos - candidate;
+                    if length == max_length
+                    {
+                        break;
+                    }
+                }
+                let next = prev[candidate % WINDOW_SIZE];
+                // Slots get reused once the window wraps around, stop when the chain stops going backwards
+                if next == usize::MAX || next >= candidate
+                {
+                    break;
+                }
+                candidate = next;
+                chain += 1;
+            }
+        }
+
+        if best_length >= MIN_MATCH
+        {
+            write_match(&mut writer, best_length, best_distance);
+            for ind in pos..pos + best_length
+            {
+                insert(&mut head, &mut prev, ind);
+            }
+            pos += best_length;
+        }
+        else
+        {
+            write_literal_length(&mut writer, data[pos] as u32);
+            insert(&mut head, &mut prev, pos);
+            pos += 1;
+        }
+    }
+    write_literal_length(&mut writer, 256);
+    writer.finish()
+}
+
+fn deflate_stored(data: &[u8]) -> Vec<u8>
+{
+    let mut output = Vec::with_capacity(data.len() + 5 * (data.len() / 65535 + 1));
+    let mut chunks = data.chunks(65535).peekable();
+    if chunks.peek().is_none()
+    {
+        // Even empty input needs one final block
+        output.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next()
+    {
+        output.push(if chunks.peek().is_none() { 1 } else { 0 });
+        let length = chunk.len() as u16;
+        output.extend_from_slice(&length.to_le_bytes());
+        output.extend_from_slice(&(!length).to_le_bytes());
+        output.extend_from_slice(chunk);
+    }
+    output
+}
+
+// Raw deflate stream
+pub fn deflate(data: &[u8], compression: Compression) -> Vec<u8>
+{
+    match compression
+    {
+        Compression::Stored => deflate_stored(data),
+        Compression::Deflate =>
+        {
+            let compressed = deflate_fixed(data);
+            let stored_size = data.len() + 5 * (data.len() / 65535 + 1);
+            if compressed.len() < stored_size { compressed } else { deflate_stored(data) }
+        }
+    }
+}
+
+// Deflate stream wrapped in a zlib header and Adler32 trailer
+pub fn zlib_compress(data: &[u8], compression: Compression) -> Vec<u8>
+{
+    // 32K window, compression level hint matching the method, header check bits making it a multiple of 31
+    let level: u8 = match compression { Compression::Stored => 0, Compression::Deflate => 1 };
+    let cmf: u8 = 0x78;
+    let mut flg: u8 = level << 6;
+    flg += (31 - ((cmf as u16) << 8 | flg as u16) % 31) as u8 % 31;
+
+    let mut output = vec![cmf, flg];
+    output.extend_from_slice(&deflate(data, compression));
+    output.extend_from_slice(&adler32(data).to_be_bytes());
+    output
+}
+
+fn invalid_data(message: &str) -> Error
+{
+    Error::new(ErrorKind::InvalidData, format!("invalid deflate stream: {}", message))
+}
+
+// Reads bits least significant first
+struct BitReader<'a>
+{
+    data: &'a [u8],
+    position: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl<'a> BitReader<'a>
+{
+    fn new(data: &'a [u8]) -> Self
+    {
+        BitReader{ data, position: 0, buffer: 0, count: 0 }
+    }
+
+    fn read_bits(&mut self, count: u32) -> Result<u32, Error>
+    {
+        while self.count < count
+        {
+            let byte = *self.data.get(self.position).ok_or_else(|| invalid_data("unexpected end of data"))?;
+            self.position += 1;
+            self.buffer |= (byte as u64) << self.count;
+            self.count += 8;
+        }
+        let value = (self.buffer & ((1u64 << count) - 1)) as u32;
+        self.buffer >>= count;
+        self.count -= count;
+        Ok(value)
+    }
+
+    // Drops the bits left in the current byte, stored blocks start on a byte boundary
+    fn align_to_byte(&mut self)
+    {
+        self.buffer >>= self.count % 8;
+        self.count -= self.count % 8;
+    }
+
+    fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], Error>
+    {
+        // Whole bytes still in the buffer go back to the data
+        self.position -= (self.count / 8) as usize;
+        self.buffer = 0;
+        self.count = 0;
+        let bytes = self.data.get(self.position..self.position + length).ok_or_else(|| invalid_data("unexpected end of data"))?;
+        self.position += length;
+        Ok(bytes)
+    }
+}
+
+const MAX_CODE_LENGTH: usize = 15;
+
+// Canonical Huffman code stored as the number of codes of every length and the symbols sorted by code
+struct HuffmanTable
+{
+    counts: [u16; MAX_CODE_LENGTH + 1],
+    symbols: Vec<u16>,
+}
+
+impl HuffmanTable
+{
+    fn make_new(lengths: &[u8]) -> Result<Self, Error>
+    {
+        let mut counts = [0u16; MAX_CODE_LENGTH + 1];
+        for length in lengths
+        {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // More codes than a length allows can't be decoded, incomplete codes are fine
+        let mut left = 1i32;
+        for count in &counts[1..]
+        {
+            left = 2 * left - *count as i32;
+            if left < 0
+            {
+                return Err(invalid_data("over-subscribed Huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; MAX_CODE_LENGTH + 2];
+        for length in 1..=MAX_CODE_LENGTH
+        {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0u16; offsets[MAX_CODE_LENGTH + 1] as usize];
+        for (symbol, length) in lengths.iter().enumerate()
+        {
+            if *length != 0
+            {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+        Ok(HuffmanTable{ counts, symbols })
+    }
+
+    // Walks the code one bit at a time, codes of one length are consecutive numbers
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, Error>
+    {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..=MAX_CODE_LENGTH
+        {
+            code |= reader.read_bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count
+            {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid_data("invalid Huffman code"))
+    }
+}
+
+fn fixed_tables() -> (HuffmanTable, HuffmanTable)
+{
+    let mut lengths = [8u8; 288];
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    // Both tables are complete, they can't fail
+    (HuffmanTable::make_new(&lengths).unwrap(), HuffmanTable::make_new(&[5u8; 30]).unwrap())
+}
+
+// Order in which the code length code lengths are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+fn dynamic_tables(reader: &mut BitReader) -> Result<(HuffmanTable, HuffmanTable), Error>
+{
+    let literal_count = reader.read_bits(5)? as usize + 257;
+    let distance_count = reader.read_bits(5)? as usize + 1;
+    let code_length_count = reader.read_bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30
+    {
+        return Err(invalid_data("too many length or distance codes"));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for ind in CODE_LENGTH_ORDER.iter().take(code_length_count)
+    {
+        code_lengths[*ind] = reader.read_bits(3)? as u8;
+    }
+    let code_length_table = HuffmanTable::make_new(&code_lengths)?;
+
+    // Literal/length and distance code lengths form one sequence, repeats may cross from one to the other
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count
+    {
+        let symbol = code_length_table.decode(reader)?;
+        let (value, repeat) = match symbol
+        {
+            0..=15 => (symbol as u8, 1),
+            16 =>
+            {
+                let previous = *lengths.last().ok_or_else(|| invalid_data("repeated length without a previous one"))?;
+                (previous, 3 + reader.read_bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.read_bits(3)? as usize),
+            _ => (0, 11 + reader.read_bits(7)? as usize),
+        };
+        if lengths.len() + repeat > literal_count + distance_count
+        {
+            return Err(invalid_data("code lengths overflow the table"));
+        }
+        lengths.resize(lengths.len() + repeat, value);
+    }
+    if lengths[256] == 0
+    {
+        return Err(invalid_data("missing end of block code"));
+    }
+
+    Ok((HuffmanTable::make_new(&lengths[..literal_count])?, HuffmanTable::make_new(&lengths[literal_count..])?))
+}
+
+fn inflate_block(reader: &mut BitReader, literals: &HuffmanTable, distances: &HuffmanTable, output: &mut Vec<u8>) -> Result<(), Error>
+{
+    loop
+    {
+        let symbol = literals.decode(reader)? as usize;
+        match symbol
+        {
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            257..=285 =>
+            {
+                let length = LENGTH_BASE[symbol - 257] as usize + reader.read_bits(LENGTH_EXTRA[symbol - 257] as u32)? as usize;
+                let distance_code = distances.decode(reader)? as usize;
+                if distance_code >= 30
+                {
+                    return Err(invalid_data("invalid distance code"));
+                }
+                let distance = DISTANCE_BASE[distance_code] as usize + reader.read_bits(DISTANCE_EXTRA[distance_code] as u32)? as usize;
+                if distance > output.len()
+                {
+                    return Err(invalid_data("distance reaches before the start of the data"));
+                }
+                // The match may overlap the bytes it produces
+                let start = output.len() - distance;
+                for ind in 0..length
+                {
+                    output.push(output[start + ind]);
+                }
+            }
+            _ => return Err(invalid_data("invalid length code")),
+        }
+    }
+}
+
+// Decompresses a raw deflate stream
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, Error>
+{
+    let mut reader = BitReader::new(data);
+    let mut output = Vec::new();
+    loop
+    {
+        let last = reader.read_bits(1)? == 1;
+        match reader.read_bits(2)?
+        {
+            0 =>
+            {
+                reader.align_to_byte();
+                let header = reader.read_bytes(4)?;
+                let length = u16::from_le_bytes([header[0], header[1]]);
+                if length != !u16::from_le_bytes([header[2], header[3]])
+                {
+                    return Err(invalid_data("stored block length doesn't match its complement"));
+                }
+                output.extend_from_slice(reader.read_bytes(length as usize)?);
+            }
+            1 =>
+            {
+                let (literals, distances) = fixed_tables();
+                inflate_block(&mut reader, &literals, &distances, &mut output)?;
+            }
+            2 =>
+            {
+                let (literals, distances) = dynamic_tables(&mut reader)?;
+                inflate_block(&mut reader, &literals, &distances, &mut output)?;
+            }
+            _ => return Err(invalid_data("invalid block type")),
+        }
+        if last
+        {
+            return Ok(output);
+        }
+    }
+}
+
+// Checks the zlib header and Adler32 trailer around a deflate stream
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, Error>
+{
+    if data.len() < 6 || data[0] & 0x0F != 8 || !((data[0] as u16) << 8 | data[1] as u16).is_multiple_of(31)
+    {
+        return Err(invalid_data("invalid zlib header"));
+    }
+    if data[1] & 0x20 != 0
+    {
+        return Err(invalid_data("preset dictionaries aren't supported"));
+    }
+    let output = inflate(&data[2..data.len() - 4])?;
+    if adler32(&output).to_be_bytes() != data[data.len() - 4..]
+    {
+        return Err(invalid_data("Adler32 checksum mismatch"));
+    }
+    Ok(output)
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn checksum_test()
+    {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32_update(crc32(b"1234"), b"56789"), 0xCBF43926);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+        // Long enough to need the intermediate modulus
+        assert_eq!(adler32(&vec![0xFF; 100000]), 0x149A_302C);
+    }
+
+    #[test]
+    fn stored_test()
+    {
+        let data: Vec<u8> = (0..70000).map(|ind| (ind % 251) as u8).collect();
+        let stream = zlib_compress(&data, Compression::Stored);
+        assert_eq!(((stream[0] as u16) << 8 | stream[1] as u16) % 31, 0);
+        // Two blocks: a full one and a final one
+        assert_eq!(&stream[2..7], &[0, 0xFF, 0xFF, 0, 0]);
+        assert_eq!(&stream[7..7 + 65535], &data[..65535]);
+        assert_eq!(stream[7 + 65535], 1);
+        assert_eq!(stream.len(), 2 + 2 * 5 + data.len() + 4);
+        assert_eq!(&stream[stream.len() - 4..], &adler32(&data).to_be_bytes());
+
+        assert_eq!(deflate(b"", Compression::Stored), vec![1, 0, 0, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn deflate_test()
+    {
+        // Fixed Huffman encoding of "a" followed by a match of length 5 at distance 1
+        assert_eq!(deflate(b"aaaaaa", Compression::Deflate), vec![0x4B, 0x04, 0x03, 0x00]);
+
+        let data: Vec<u8> = b"the quick brown fox jumps over the lazy dog. ".iter().cycle().take(10000).cloned().collect();
+        let stream = zlib_compress(&data, Compression::Deflate);
+        assert!(stream.len() < 200);
+        assert_eq!(((stream[0] as u16) << 8 | stream[1] as u16) % 31, 0);
+
+        // Incompressible data falls back to stored blocks
+        let mut state = 0x2545F4914F6CDD1Du64;
+        let noise: Vec<u8> = (0..1000).map(|_|
+            {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 32) as u8
+            }).collect();
+        assert_eq!(deflate(&noise, Compression::Deflate), deflate(&noise, Compression::Stored));
+    }
+
+    fn hex(text: &str) -> Vec<u8>
+    {
+        (0..text.len()).step_by(2).map(|ind| u8::from_str_radix(&text[ind..ind + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn inflate_test()
+    {
+        // Every block type written by the compressor comes back unchanged
+        let data: Vec<u8> = b"the quick brown fox jumps over the lazy dog. ".iter().cycle().take(10000).cloned().collect();
+        assert_eq!(zlib_decompress(&zlib_compress(&data, Compression::Deflate)).unwrap(), data);
+        let data: Vec<u8> = (0..70000).map(|ind| (ind % 251) as u8).collect();
+        assert_eq!(zlib_decompress(&zlib_compress(&data, Compression::Stored)).unwrap(), data);
+        assert_eq!(inflate(&deflate(b"", Compression::Deflate)).unwrap(), b"");
+
+        // Dynamic Huffman codes, as written by zlib itself
+        let expected: Vec<u8> = (0..30).map(|ind: u32| ((ind * ind * 7 + ind / 3) % 13 + 97) as u8).collect();
+        assert_eq!(zlib_decompress(&hex("78da05c1411200200803b1b7629129b8fcff6a12d68eede61417672c84eaa9137fbc050c21")).unwrap(), expected);
+        assert_eq!(zlib_decompress(&hex("78dacb48cdc9c957c84090003a2e067d")).unwrap(), b"hello hello hello");
+
+        // Corrupted streams are reported instead of panicking
+        let mut stream = hex("78dacb48cdc9c957c84090003a2e067d");
+        *stream.last_mut().unwrap() ^= 1;
+        assert_eq!(zlib_decompress(&stream).unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(zlib_decompress(&stream[..8]).is_err());
+        assert!(inflate(&[0x07]).is_err());
+        assert!(inflate(&[0x01, 0x05, 0x00, 0x00, 0x00]).is_err());
+    }
+}
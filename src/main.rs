@@ -1,225 +1,212 @@
 #![allow(dead_code)]
 
-use std::fs::File;
-use std::io::Write;
-use std::path::PathBuf;
-use std::thread;
-use std::thread::available_parallelism;
-use std::thread::Builder;
-use std::sync::Arc;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
 
 mod math;
 mod utils;
+mod formats;
+mod render;
 
-use math::core::*;
-use math::vect::Vect;
 use math::ray::Ray;
-use math::point::Point;
 use math::camera::Camera;
-use math::sphere::Sphere;
-use math::hittable::*;
+use math::bvh::BvhNode;
 use math::random::*;
 use math::framebuffer::*;
-use math::material::*;
 use utils::color::FColor;
+use utils::cli::*;
+use formats::image::*;
+use formats::exr::ExrOptions;
+use formats::scene::load_scene;
+use render::builtin::*;
+use render::scene::*;
+use render::integrator::*;
+use render::settings::RenderSettings;
+use render::scheduler::*;
+use utils::threadpool::ThreadPool;
+
+fn render_tile(camera: &Camera, tracer: &PathTracer, settings: &RenderSettings, tile: &mut FrameBufferTile)
+{
+    let mut u: f64;
+    let mut v: f64;
+    let mut ray: Ray;
+    let mut color = FColor::make_new(0.0, 0.0, 0.0);
 
-const RENDER_HEIGHT: usize = 720;
-const RENDER_WIDTH: usize = ((RENDER_HEIGHT as f64) * ASPECT_RATIO) as usize;
-const NUM_SAMPLES: usize = 500;
-const MAX_CHILD_RAYS: u32 = 50;
+    for (x, y, pixel) in tile.iter_mut()
+    {
+        // Seed per pixel so the image doesn't depend on how the frame is split between threads
+        let pixel_index = y * settings.width + x;
+        seed_thread_rand(mix_seed(settings.seed, pixel_index as u64));
 
-const PROJECT_PATH: &str = env!("CARGO_MANIFEST_DIR");
-const IMAGE_OUT_PATH: &str = "out/output.ppm";
+        color.reset();
+        for _sample_number in 0..settings.samples
+        {
+            // Row 0 is the top of the image
+            u = 2.0 * (x as f64 + thread_rand()) / settings.width as f64 - 1.0;
+            v = 1.0 - 2.0 * (y as f64 + thread_rand()) / settings.height as f64;
 
-// Background Gradient:
+            ray = camera.get_ray(u, v);
 
-fn ray_color(ray: &Ray, world: &dyn Hittable, depth: u32) -> FColor
-{
-    if depth <= 0
-    {
-        return FColor::make_new(0.0, 0.0, 0.0);
+            color += &tracer.ray_color(&ray);
+        }
+        *pixel = color / settings.samples as f64;
     }
-    let hit_result = world.hit(ray, 0.001, INFINITY);
-    if hit_result.is_some()
+}
+
+fn load_scene_or_builtin(name: &str) -> Result<Scene, String>
+{
+    let path = Path::new(name);
+    if !path.is_file()
     {
-        let hit_result = hit_result.unwrap();
-        let scatter_result = hit_result.material().scatter(ray, &hit_result);
-        if scatter_result.is_none()
+        if let Some(scene) = make_builtin_scene(name)
         {
-            return FColor::make_new(0.0, 0.0, 0.0);
+            return Ok(scene);
+        }
+        if !path.exists()
+        {
+            return Err(format!("'{}' is neither a scene file nor a built-in scene ({})", name, BUILTIN_SCENES.join(", ")));
         }
-        let (attenuation, scattered_ray) = scatter_result.unwrap();
-        return attenuation * ray_color(&scattered_ray, world, depth - 1);
     }
-    let dir = ray.direction().get_normalized();
-    let y = 0.5 * dir.y() + 0.5;
-    (1.0 - y) * FColor::new_color(1.0, 1.0, 1.0) + y * FColor::new_color(0.5, 0.7, 1.0)
+    load_scene(path).map_err(|error| error.to_string())
 }
 
-fn render_slice(camera: &Camera, world: &HittableList, start: usize, slice: &mut [Vec<FColor>])
+// Applies the command line overrides on top of the scene's own settings
+fn apply_options(scene: &mut Scene, options: &CliOptions)
 {
-    // Setup Deterministic Random Genrator:
-    let mut rand_gen = SimpleDeterministicRandomGenerator::new();
-
-    let mut u: f64;
-    let mut v: f64;
-    let mut ray: Ray;
-    let mut color = FColor::make_new(0.0, 0.0, 0.0);
-
-    for height_iterator in 0..slice.len()
+    let settings = &mut scene.settings;
+    let aspect_ratio = settings.aspect_ratio();
+    match (options.width, options.height)
     {
-        for width_iterator in 0..slice[height_iterator].len()
+        (Some(width), Some(height)) =>
         {
-            color.reset();
-            for _sample_number in 0..NUM_SAMPLES
-            {
-                //println!("casting ray {}, {}, {}", width_iterator, start + height_iterator, sample_number);
-                u = 2.0 * (width_iterator as f64 + rand_gen.rand()) / RENDER_WIDTH as f64 - 1.0;
-                v = 2.0 * ((start + height_iterator) as f64 + rand_gen.rand()) / RENDER_HEIGHT as f64 - 1.0;
-
-                ray = camera.get_ray(u, v);
-
-                color += &ray_color(&ray, world, MAX_CHILD_RAYS);
-            }
-            slice[height_iterator][width_iterator] = (&color / NUM_SAMPLES as f64).sqrt();
+            settings.width = width;
+            settings.height = height;
         }
+        (Some(width), None) =>
+        {
+            settings.width = width;
+            settings.height = ((width as f64 / aspect_ratio).round() as usize).max(1);
+        }
+        (None, Some(height)) =>
+        {
+            settings.height = height;
+            settings.width = ((height as f64 * aspect_ratio).round() as usize).max(1);
+        }
+        (None, None) => {}
+    }
+    scene.camera.set_aspect_ratio(settings.aspect_ratio());
+
+    if let Some(samples) = options.samples
+    {
+        settings.samples = samples;
+    }
+    if let Some(max_depth) = options.max_depth
+    {
+        settings.max_depth = max_depth;
+    }
+    if let Some(mis) = options.mis
+    {
+        settings.mis = mis;
+    }
+    if let Some(spectral) = options.spectral
+    {
+        settings.spectral = spectral;
+    }
+    if let Some(threads) = options.threads
+    {
+        settings.threads = threads;
+    }
+
+    if let Some(output) = &options.output
+    {
+        scene.output = output.clone();
+    }
+    else if let Some(format) = options.format
+    {
+        scene.output.set_extension(format.extension());
     }
 }
 
-fn make_random_scene() -> Box<HittableList>
+fn render(scene: &Scene) -> Result<FrameBuffer, String>
 {
-    let mut world = Box::new(HittableList::default());
+    let settings = &scene.settings;
 
-    let ground_material = Arc::new(Lambertian::make_new(FColor::make_new(0.5, 0.5, 0.5)));
-    let ground_sphere = Arc::new(Sphere::make_new(Point::make_new(0.0, -1000.0, 0.0), 1000.0, ground_material));
+    // Setup Frame Buffer (a.k.a. Render Target)
+    let mut fb = FrameBuffer::new(settings.width, settings.height);
 
-    world.add(ground_sphere);
+    // Setup World:
+    let world = BvhNode::new(&scene.world);
+    let tracer = PathTracer::make_new(&world, make_emitter_list(&scene.world), &scene.lights, scene.environment.as_ref(),
+                                      scene.fog.as_ref(), settings);
 
-    let mut rand_gen = SimpleDeterministicRandomGenerator::new();
+    let pool = ThreadPool::new(settings.threads).map_err(|error| format!("can't start the render threads: {}", error))?;
+    render_tiles(&pool, &mut fb, settings.tile_size, |tile| render_tile(&scene.camera, &tracer, settings, tile))?;
 
-    // Add some random small balls
-    for i in -11..11
-    {
-        for j in -11..11
-        {
-            let material_decider = rand_gen.rand();
-            let center = Point::make_new(i as f64 + 0.9 * rand_gen.rand(), 0.2, j as f64+ 0.9 * rand_gen.rand());
-
-            if (center - Point::make_new(4.0, 0.2, 0.0)).length() > 0.9
-            {
-                if material_decider < 0.5 // 50% chance for lambertian ball
-                {
-                    let albedo = FColor::rand();
-                    let material = Arc::new(Lambertian::make_new(albedo));
-                    let sphere = Arc::new(Sphere::make_new(center, 0.2, material));
-                    world.add(sphere);
-                }
-                else if material_decider < 0.9 // 40% chance for metal ball
-                {
-                    let albedo = FColor::rand();
-                    let roughness = rand_gen.rand();
-                    let material = Arc::new(Metal::make_new(albedo, roughness));
-                    let sphere = Arc::new(Sphere::make_new(center, 0.2, material));
-                    world.add(sphere);
-                }
-                else // 10% chance for glass ball
-                {
-                    let material = Arc::new(Dielectric::make_new(1.5));
-                    let sphere = Arc::new(Sphere::make_new(center, 0.2, material.clone()));
-                    world.add(sphere);
-                    if rand_gen.rand() < 0.3 // 30% for hollow glass ball
-                    {
-                        let inner_bubble = Arc::new(Sphere::make_new(center, -0.15, material));
-                        world.add(inner_bubble);
-                    }
-                }
-            }
-        }
-    }
-    // add three big balls, one for each material
-    let glass = Arc::new(Dielectric::make_new(1.5));
-    let glass_ball = Arc::new(Sphere::make_new(Point::make_new(0.0, 1.0, 0.0), 1.0, glass));
-    world.add(glass_ball);
-    
-    let diffuse = Arc::new(Lambertian::make_new(FColor::rand()));
-    let diffuse_ball = Arc::new(Sphere::make_new(Point::make_new(-4.0, 1.0, 0.0), 1.0, diffuse));
-    world.add(diffuse_ball);
-
-    let metal = Arc::new(Metal::make_new(FColor::rand(), 0.5));
-    let metal_ball = Arc::new(Sphere::make_new(Point::make_new(4.0, 1.0, 0.0), 1.0, metal));
-    world.add(metal_ball);
-
-    world
+    Ok(fb)
 }
 
-fn make_simple_scene() -> Box<HittableList>
+fn run(options: &CliOptions) -> Result<(), String>
 {
-    let mut world = Box::new(HittableList::default());
-    
-    // Materials:
-    let material_ground = Arc::new(Lambertian::make_new(FColor::make_new(0.8, 0.8, 0.0)));
-    let material_center = Arc::new(Lambertian::make_new(FColor::make_new(0.1, 0.2, 0.5)));
-    let material_left   = Arc::new(Dielectric::make_new(1.5));
-    let material_right  = Arc::new(Metal::make_new(FColor::make_new(0.8, 0.6, 0.2), 0.0));
-
-    // Spheres:
-    let ground_sphere = Arc::new(Sphere::make_new(Point::make_new(0.0, -100.5, -1.0), 100.0, material_ground));
-    let center_sphere = Arc::new(Sphere::make_new(Point::make_new(0.0, 0.0, -1.0), 0.5, material_center));
-    let left_sphere = Arc::new(Sphere::make_new(Point::make_new(-1.0, 0.0, -1.0), 0.5, material_left.clone()));
-    let left_sphere_inner = Arc::new(Sphere::make_new(Point::make_new(-1.0, 0.0, -1.0), -0.45, material_left));
-    let right_sphere = Arc::new(Sphere::make_new(Point::make_new(1.0, 0.0, -1.0), 0.5, material_right));
-    world.add(ground_sphere);
-    world.add(center_sphere);
-    world.add(left_sphere);
-    world.add(left_sphere_inner);
-    world.add(right_sphere);
-    
-    world
-}
+    // The seed also drives the layout of the built-in random scene
+    let seed = options.seed.unwrap_or_else(time_seed);
+    seed_thread_rand(seed);
 
-fn main() -> std::io::Result<()>
-{
-    let mut out_path = PathBuf::from(PROJECT_PATH);
-    out_path.push(IMAGE_OUT_PATH);
-    let mut file = File::create(out_path)?;
-    file.write_all(b"P3\n")?;
-    file.write_all(format!("{} {}\n", RENDER_WIDTH, RENDER_HEIGHT).as_bytes())?;
-    file.write_all(b"255\n")?;
-
-    // Setup Camera:
-    let pos = Point::make_new(13.0, 2.0, 3.0);
-    let lookat = Point::make_new(0.0, 0.0, 0.0);
-    let world_up = Vect::make_new(0.0, 1.0, 0.0);
-    let depth_of_field = (pos - lookat).length();
-    let aperture = 0.1;
-    let camera = Camera::new(pos, lookat, world_up, 20.0,  ASPECT_RATIO, aperture, depth_of_field);
+    let mut scene = load_scene_or_builtin(options.scene.as_deref().unwrap_or("random"))?;
+    apply_options(&mut scene, options);
+    scene.settings.seed = seed;
 
-    // Setup Frame Buffer (a.k.a. Render Target)
-    let mut fb = FrameBuffer::<RENDER_WIDTH, RENDER_HEIGHT>::new();
+    let format = options.format.or_else(|| ImageFormat::from_path(&scene.output));
+    let format = match format
+    {
+        Some(format) => format,
+        None if scene.output.extension().is_none() => ImageFormat::Ppm,
+        None => return Err(format!("can't tell the image format of '{}', use --format", scene.output.display())),
+    };
 
-    // Setup World:
-    //let world = make_random_scene();
-    let world = make_random_scene();
+    let fb = render(&scene)?;
+
+    if let Some(directory) = scene.output.parent()
+    {
+        fs::create_dir_all(directory).map_err(|error| format!("{}: {}", directory.display(), error))?;
+    }
+    let mut exr_options = ExrOptions::default();
+    if let Some(compression) = options.exr_compression
+    {
+        exr_options.compression = compression;
+    }
+    if let Some(pixel_type) = options.exr_pixel_type
+    {
+        exr_options.pixel_type = pixel_type;
+    }
+    write_image(&fb, &scene.output, format, &exr_options).map_err(|error| format!("{}: {}", scene.output.display(), error))
+}
 
-    let num_available_threads = available_parallelism().unwrap().get() * 2;
-    let stride = RENDER_HEIGHT / num_available_threads;
-    
-    thread::scope(|s|
+fn main() -> ExitCode
+{
+    let options = match parse_args(env::args().skip(1))
+    {
+        Ok(options) => options,
+        Err(error) =>
         {
-            let camera = &camera;
-            let world = &world;
-            let mut slice = &mut fb.pixels[..];
-            for thread_id in 0..num_available_threads-1
-            {
-                let (first_slice, rest_slice) = slice.split_at_mut(stride);
-                Builder::new().spawn_scoped(s, move || render_slice(camera, world, thread_id * stride, first_slice)).unwrap();
-                slice = rest_slice;
-            }
-            Builder::new().spawn_scoped(s, move || render_slice(camera, world, (num_available_threads - 1) * stride, slice)).unwrap();
+            eprintln!("error: {}\nRun with --help for usage.", error);
+            return ExitCode::from(2);
         }
-    );
-    
-    fb.write_to_file(&mut file)?;
+    };
+    if options.help
+    {
+        print!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
 
-    Ok(())
+    match run(&options)
+    {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) =>
+        {
+            eprintln!("error: {}", error);
+            ExitCode::from(1)
+        }
+    }
 }
@@ -0,0 +1,189 @@
+use std::mem::swap;
+
+use super::core::*;
+use super::point::Point;
+use super::ray::Ray;
+use super::vect::Vect;
+
+// Axis-aligned bounding box used to cull rays before testing the objects inside it
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Aabb
+{
+    pub minimum: Point,
+    pub maximum: Point,
+}
+
+impl Aabb
+{
+    // An inverted box that contains nothing and acts as the identity for surrounding()
+    pub fn empty() -> Self
+    {
+        Aabb
+        {
+            minimum: Point::make_new(INFINITY, INFINITY, INFINITY),
+            maximum: Point::make_new(-INFINITY, -INFINITY, -INFINITY),
+        }
+    }
+
+    pub fn new(minimum: &Point, maximum: &Point) -> Self
+    {
+        Aabb
+        {
+            minimum: *minimum,
+            maximum: *maximum,
+        }
+    }
+
+    pub fn make_new(minimum: Point, maximum: Point) -> Self
+    {
+        Aabb
+        {
+            minimum,
+            maximum,
+        }
+    }
+
+    pub fn minimum(&self) -> &Point
+    {
+        &self.minimum
+    }
+
+    pub fn maximum(&self) -> &Point
+    {
+        &self.maximum
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        (0..3).any(|axis| self.minimum[axis] > self.maximum[axis])
+    }
+
+    pub fn surrounding(a: &Aabb, b: &Aabb) -> Aabb
+    {
+        let mut result = *a;
+        for axis in 0..3
+        {
+            result.minimum[axis] = a.minimum[axis].min(b.minimum[axis]);
+            result.maximum[axis] = a.maximum[axis].max(b.maximum[axis]);
+        }
+        result
+    }
+
+    pub fn extend(&mut self, point: &Point)
+    {
+        for axis in 0..3
+        {
+            self.minimum[axis] = self.minimum[axis].min(point[axis]);
+            self.maximum[axis] = self.maximum[axis].max(point[axis]);
+        }
+    }
+
+    pub fn extent(&self) -> Vect
+    {
+        self.maximum - self.minimum
+    }
+
+    pub fn centroid(&self) -> Point
+    {
+        0.5 * (self.minimum + self.maximum)
+    }
+
+    pub fn longest_axis(&self) -> usize
+    {
+        let extent = self.extent();
+        if extent.x() > extent.y() && extent.x() > extent.z()
+        {
+            0
+        }
+        else if extent.y() > extent.z()
+        {
+            1
+        }
+        else
+        {
+            2
+        }
+    }
+
+    pub fn surface_area(&self) -> f64
+    {
+        if self.is_empty()
+        {
+            return 0.0;
+        }
+        let extent = self.extent();
+        2.0 * (extent.x() * extent.y() + extent.y() * extent.z() + extent.z() * extent.x())
+    }
+
+    // Slab test, only reports whether the ray overlaps the box within [t_min, t_max]
+    pub fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> bool
+    {
+        self.intersect(r, t_min, t_max).is_some()
+    }
+
+    // Part of [t_min, t_max] where the ray is inside the box
+    pub fn intersect(&self, r: &Ray, mut t_min: f64, mut t_max: f64) -> Option<(f64, f64)>
+    {
+        for axis in 0..3
+        {
+            let inverse_direction = 1.0 / r.direction()[axis];
+            let mut t0 = (self.minimum[axis] - r.origin()[axis]) * inverse_direction;
+            let mut t1 = (self.maximum[axis] - r.origin()[axis]) * inverse_direction;
+            if inverse_direction < 0.0
+            {
+                swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min
+            {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn surrounding_test()
+    {
+        let a = Aabb::make_new(Point::make_new(0.0, 0.0, 0.0), Point::make_new(1.0, 1.0, 1.0));
+        let b = Aabb::make_new(Point::make_new(-2.0, 0.5, 0.5), Point::make_new(0.5, 2.0, 0.75));
+        let c = Aabb::surrounding(&a, &b);
+        assert_eq!(c, Aabb::make_new(Point::make_new(-2.0, 0.0, 0.0), Point::make_new(1.0, 2.0, 1.0)));
+        assert_eq!(Aabb::surrounding(&Aabb::empty(), &a), a);
+        assert!(Aabb::empty().is_empty());
+        assert_eq!(Aabb::empty().surface_area(), 0.0);
+        assert_eq!(a.surface_area(), 6.0);
+        assert_eq!(c.longest_axis(), 0);
+    }
+
+    #[test]
+    fn hit_test()
+    {
+        let b = Aabb::make_new(Point::make_new(-1.0, -1.0, -1.0), Point::make_new(1.0, 1.0, 1.0));
+
+        let r = Ray::make_new(Point::make_new(0.0, 0.0, -5.0), Vect::make_new(0.0, 0.0, 1.0));
+        assert!(b.hit(&r, 0.0, INFINITY));
+        assert!(!b.hit(&r, 0.0, 3.0));
+
+        let r = Ray::make_new(Point::make_new(0.0, 2.0, -5.0), Vect::make_new(0.0, 0.0, 1.0));
+        assert!(!b.hit(&r, 0.0, INFINITY));
+
+        let r = Ray::make_new(Point::make_new(5.0, 5.0, 5.0), Vect::make_new(-1.0, -1.0, -1.0));
+        assert!(b.hit(&r, 0.0, INFINITY));
+        assert!(!Aabb::empty().hit(&r, 0.0, INFINITY));
+    }
+}
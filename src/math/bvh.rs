@@ -0,0 +1,458 @@
+use std::sync::Arc;
+
+use super::aabb::Aabb;
+use super::hittable::*;
+use super::point::Point;
+use super::ray::Ray;
+
+// Bounding volume hierarchy built with the surface area heuristic (SAH).
+// Each split is chosen by binning primitive centroids along every axis and picking the
+// partition that minimizes the expected cost of tracing a ray through the two children.
+// Objects without a bounding box (e.g. infinite planes) can't be partitioned, so the root keeps
+// them in a separate list that is tested alongside the tree.
+
+struct BvhPrimitive
+{
+    hittable: Arc<dyn Hittable>,
+    bbox: Aabb,
+    centroid: Point,
+}
+
+enum BvhChildren
+{
+    Leaf(Vec<Arc<dyn Hittable>>),
+    Interior(Box<BvhNode>, Box<BvhNode>),
+}
+
+pub struct BvhNode
+{
+    bbox: Aabb,
+    axis: usize,
+    children: BvhChildren,
+    unbounded: Vec<Arc<dyn Hittable>>,
+}
+
+impl BvhNode
+{
+    const SAH_BUCKETS: usize = 16;
+    const MAX_LEAF_SIZE: usize = 4;
+    // Cost of visiting a node relative to intersecting a single primitive
+    const TRAVERSAL_COST: f64 = 0.125;
+
+    pub fn new(list: &HittableList) -> Self
+    {
+        BvhNode::make_new(list.hittables.clone())
+    }
+
+    pub fn make_new(hittables: Vec<Arc<dyn Hittable>>) -> Self
+    {
+        let mut primitives = Vec::with_capacity(hittables.len());
+        let mut unbounded = Vec::default();
+        for hittable in hittables
+        {
+            match hittable.bounding_box()
+            {
+                Some(bbox) => primitives.push(BvhPrimitive
+                    {
+                        hittable,
+                        bbox,
+                        centroid: bbox.centroid(),
+                    }),
+                None => unbounded.push(hittable),
+            }
+        }
+
+        let mut root = BvhNode::build(&mut primitives);
+        root.unbounded = unbounded;
+        root
+    }
+
+    fn make_leaf(primitives: &[BvhPrimitive], bbox: Aabb) -> Self
+    {
+        BvhNode
+        {
+            bbox,
+            axis: 0,
+            children: BvhChildren::Leaf(primitives.iter().map(|p| p.hittable.clone()).collect()),
+            unbounded: Vec::default(),
+        }
+    }
+
+    fn build(primitives: &mut [BvhPrimitive]) -> Self
+    {
+        let mut bbox = Aabb::empty();
+        let mut centroid_bounds = Aabb::empty();
+        for primitive in primitives.iter()
+        {
+            bbox = Aabb::surrounding(&bbox, &primitive.bbox);
+            centroid_bounds.extend(&primitive.centroid);
+        }
+
+        let count = primitives.len();
+        if count <= 1
+        {
+            return BvhNode::make_leaf(primitives, bbox);
+        }
+
+        let (axis, split_position, split_cost) = BvhNode::find_split(primitives, &bbox, &centroid_bounds);
+
+        let mut mid;
+        if split_cost.is_finite()
+        {
+            // A leaf costs one intersection per primitive, keep it if splitting doesn't pay off
+            if count <= BvhNode::MAX_LEAF_SIZE && split_cost >= count as f64
+            {
+                return BvhNode::make_leaf(primitives, bbox);
+            }
+            mid = partition(primitives, |p| p.centroid[axis] < split_position);
+        }
+        else
+        {
+            mid = 0;
+        }
+
+        // All centroids coincide (or binning failed to separate them), fall back to a median split
+        if mid == 0 || mid == count
+        {
+            if count <= BvhNode::MAX_LEAF_SIZE
+            {
+                return BvhNode::make_leaf(primitives, bbox);
+            }
+            primitives.sort_by(|a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+            mid = count / 2;
+        }
+
+        let (left, right) = primitives.split_at_mut(mid);
+        BvhNode
+        {
+            bbox,
+            axis,
+            children: BvhChildren::Interior(Box::new(BvhNode::build(left)), Box::new(BvhNode::build(right))),
+            unbounded: Vec::default(),
+        }
+    }
+
+    // Returns the best (axis, split plane, cost) over all binned candidate planes.
+    // The cost is infinite when no axis has any centroid extent.
+    fn find_split(primitives: &[BvhPrimitive], bbox: &Aabb, centroid_bounds: &Aabb) -> (usize, f64, f64)
+    {
+        let mut best = (centroid_bounds.longest_axis(), 0.0, f64::INFINITY);
+        let parent_area = bbox.surface_area();
+        if parent_area <= 0.0
+        {
+            return best;
+        }
+
+        for axis in 0..3
+        {
+            let min = centroid_bounds.minimum[axis];
+            let extent = centroid_bounds.maximum[axis] - min;
+            if extent <= 0.0
+            {
+                continue;
+            }
+
+            let mut counts = [0usize; BvhNode::SAH_BUCKETS];
+            let mut bounds = [Aabb::empty(); BvhNode::SAH_BUCKETS];
+            for primitive in primitives
+            {
+                let bucket = BvhNode::bucket_index(primitive.centroid[axis], min, extent);
+                counts[bucket] += 1;
+                bounds[bucket] = Aabb::surrounding(&bounds[bucket], &primitive.bbox);
+            }
+
+            // Sweep from the right to get the area and count of every suffix of buckets
+            let mut right_areas = [0.0; BvhNode::SAH_BUCKETS];
+            let mut right_counts = [0usize; BvhNode::SAH_BUCKETS];
+            let mut accumulated = Aabb::empty();
+            let mut accumulated_count = 0;
+            for bucket in (1..BvhNode::SAH_BUCKETS).rev()
+            {
+                accumulated = Aabb::surrounding(&accumulated, &bounds[bucket]);
+                accumulated_count += counts[bucket];
+                right_areas[bucket] = accumulated.surface_area();
+                right_counts[bucket] = accumulated_count;
+            }
+
+            let mut accumulated = Aabb::empty();
+            let mut accumulated_count = 0;
+            for split in 1..BvhNode::SAH_BUCKETS
+            {
+                accumulated = Aabb::surrounding(&accumulated, &bounds[split - 1]);
+                accumulated_count += counts[split - 1];
+                if accumulated_count == 0 || right_counts[split] == 0
+                {
+                    continue;
+                }
+                let cost = BvhNode::TRAVERSAL_COST +
+                    (accumulated.surface_area() * accumulated_count as f64 +
+                     right_areas[split] * right_counts[split] as f64) / parent_area;
+                if cost < best.2
+                {
+                    best = (axis, min + extent * split as f64 / BvhNode::SAH_BUCKETS as f64, cost);
+                }
+            }
+        }
+        best
+    }
+
+    fn bucket_index(value: f64, min: f64, extent: f64) -> usize
+    {
+        let bucket = (BvhNode::SAH_BUCKETS as f64 * (value - min) / extent) as usize;
+        bucket.min(BvhNode::SAH_BUCKETS - 1)
+    }
+}
+
+// Moves every element matching the predicate to the front and returns how many there are
+fn partition<T, F: Fn(&T) -> bool>(items: &mut [T], predicate: F) -> usize
+{
+    let mut first_false = 0;
+    for ind in 0..items.len()
+    {
+        if predicate(&items[ind])
+        {
+            items.swap(ind, first_false);
+            first_false += 1;
+        }
+    }
+    first_false
+}
+
+impl BvhNode
+{
+    fn hit_tree(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitResult>
+    {
+        if !self.bbox.hit(r, t_min, t_max)
+        {
+            return None;
+        }
+
+        match &self.children
+        {
+            BvhChildren::Leaf(hittables) =>
+            {
+                let mut final_result = None;
+                let mut closest_hit = t_max;
+                for hittable in hittables
+                {
+                    if let Some(hit_result) = hittable.hit(r, t_min, closest_hit)
+                    {
+                        closest_hit = hit_result.t();
+                        final_result = Some(hit_result);
+                    }
+                }
+                final_result
+            }
+            BvhChildren::Interior(left, right) =>
+            {
+                // Visit the nearer child first so the farther one can be culled by the closer hit
+                let (first, second) = if r.direction()[self.axis] < 0.0 { (right, left) } else { (left, right) };
+                let first_result = first.hit_tree(r, t_min, t_max);
+                let closest_hit = first_result.as_ref().map_or(t_max, |hit_result| hit_result.t());
+                second.hit_tree(r, t_min, closest_hit).or(first_result)
+            }
+        }
+    }
+
+    fn transmittance_tree(&self, r: &Ray, t_min: f64, t_max: f64) -> f64
+    {
+        if !self.bbox.hit(r, t_min, t_max)
+        {
+            return 1.0;
+        }
+
+        match &self.children
+        {
+            BvhChildren::Leaf(hittables) =>
+            {
+                let mut transmittance = 1.0;
+                for hittable in hittables
+                {
+                    transmittance *= hittable.transmittance(r, t_min, t_max);
+                    if transmittance <= 0.0
+                    {
+                        break;
+                    }
+                }
+                transmittance
+            }
+            BvhChildren::Interior(left, right) =>
+            {
+                let transmittance = left.transmittance_tree(r, t_min, t_max);
+                if transmittance <= 0.0 { 0.0 } else { transmittance * right.transmittance_tree(r, t_min, t_max) }
+            }
+        }
+    }
+}
+
+impl Hittable for BvhNode
+{
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitResult>
+    {
+        let mut final_result = None;
+        let mut closest_hit = t_max;
+        for hittable in &self.unbounded
+        {
+            if let Some(hit_result) = hittable.hit(r, t_min, closest_hit)
+            {
+                closest_hit = hit_result.t();
+                final_result = Some(hit_result);
+            }
+        }
+        self.hit_tree(r, t_min, closest_hit).or(final_result)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb>
+    {
+        if self.unbounded.is_empty() { Some(self.bbox) } else { None }
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64
+    {
+        let mut transmittance = 1.0;
+        for hittable in &self.unbounded
+        {
+            transmittance *= hittable.transmittance(r, t_min, t_max);
+            if transmittance <= 0.0
+            {
+                return 0.0;
+            }
+        }
+        transmittance * self.transmittance_tree(r, t_min, t_max)
+    }
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::material::*;
+    use super::super::random::SimpleDeterministicRandomGenerator;
+    use super::super::sphere::Sphere;
+    use super::super::vect::Vect;
+
+    fn make_test_list(count: usize) -> HittableList
+    {
+        let mut rand_gen = SimpleDeterministicRandomGenerator::new_seeded(42);
+        let material = Arc::new(Lambertian::default());
+        let mut list = HittableList::default();
+        for _ in 0..count
+        {
+            let center = Point::make_new(rand_gen.rand_between(-10.0, 10.0),
+                                         rand_gen.rand_between(-10.0, 10.0),
+                                         rand_gen.rand_between(-10.0, 10.0));
+            list.add(Arc::new(Sphere::make_new(center, rand_gen.rand_between(0.1, 1.0), material.clone())));
+        }
+        list
+    }
+
+    #[test]
+    fn matches_list_test()
+    {
+        let list = make_test_list(500);
+        let bvh = BvhNode::new(&list);
+        assert_eq!(bvh.bounding_box(), list.bounding_box());
+
+        let mut rand_gen = SimpleDeterministicRandomGenerator::new_seeded(7);
+        for _ in 0..1000
+        {
+            let origin = Point::make_new(rand_gen.rand_between(-20.0, 20.0),
+                                         rand_gen.rand_between(-20.0, 20.0),
+                                         rand_gen.rand_between(-20.0, 20.0));
+            let target = Point::make_new(rand_gen.rand_between(-10.0, 10.0),
+                                         rand_gen.rand_between(-10.0, 10.0),
+                                         rand_gen.rand_between(-10.0, 10.0));
+            let r = Ray::make_new(origin, target - origin);
+
+            let expected = list.hit(&r, 0.001, f64::INFINITY).map(|h| h.t());
+            let actual = bvh.hit(&r, 0.001, f64::INFINITY).map(|h| h.t());
+            assert_eq!(expected, actual);
+        }
+    }
+
+    // Infinite y = 0 plane, which has no bounding box
+    struct Ground
+    {
+        material: Arc<dyn Material>,
+    }
+
+    impl Hittable for Ground
+    {
+        fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitResult>
+        {
+            let t = -r.origin()[1] / r.direction()[1];
+            if !t.is_finite() || t < t_min || t > t_max
+            {
+                return None;
+            }
+            Some(HitResult::make_new(r.at(t), Vect::make_new(0.0, 1.0, 0.0), self.material.clone(), t, r))
+        }
+
+        fn bounding_box(&self) -> Option<Aabb>
+        {
+            None
+        }
+
+        fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64
+        {
+            if self.hit(r, t_min, t_max).is_some() { 0.0 } else { 1.0 }
+        }
+    }
+
+    #[test]
+    fn unbounded_test()
+    {
+        let material = Arc::new(Lambertian::default());
+        let mut list = make_test_list(50);
+        list.add(Arc::new(Ground{material: material.clone()}));
+        let bvh = BvhNode::new(&list);
+        assert!(bvh.bounding_box().is_none());
+
+        let mut rand_gen = SimpleDeterministicRandomGenerator::new_seeded(11);
+        for _ in 0..500
+        {
+            let origin = Point::make_new(rand_gen.rand_between(-20.0, 20.0),
+                                         rand_gen.rand_between(-20.0, 20.0),
+                                         rand_gen.rand_between(-20.0, 20.0));
+            let target = Point::make_new(rand_gen.rand_between(-10.0, 10.0),
+                                         rand_gen.rand_between(-10.0, 10.0),
+                                         rand_gen.rand_between(-10.0, 10.0));
+            let r = Ray::make_new(origin, target - origin);
+
+            let expected = list.hit(&r, 0.001, f64::INFINITY).map(|h| h.t());
+            let actual = bvh.hit(&r, 0.001, f64::INFINITY).map(|h| h.t());
+            assert_eq!(expected, actual);
+            assert_eq!(list.transmittance(&r, 0.001, 100.0), bvh.transmittance(&r, 0.001, 100.0));
+        }
+
+        // Only the plane, so the tree itself is empty
+        let ground_only = BvhNode::make_new(vec![Arc::new(Ground{material})]);
+        let r = Ray::make_new(Point::make_new(0.0, 5.0, 0.0), Vect::make_new(0.0, -1.0, 0.0));
+        assert_eq!(ground_only.hit(&r, 0.001, f64::INFINITY).map(|h| h.t()), Some(5.0));
+        assert_eq!(ground_only.transmittance(&r, 0.001, 10.0), 0.0);
+    }
+
+    #[test]
+    fn degenerate_test()
+    {
+        let empty = BvhNode::new(&HittableList::default());
+        let r = Ray::make_new(Point::make_new(0.0, 0.0, 0.0), Vect::make_new(0.0, 0.0, 1.0));
+        assert!(empty.hit(&r, 0.0, f64::INFINITY).is_none());
+
+        // Many objects sharing one centroid must still build and be found
+        let material = Arc::new(Lambertian::default());
+        let mut list = HittableList::default();
+        for ind in 0..20
+        {
+            list.add(Arc::new(Sphere::make_new(Point::make_new(0.0, 0.0, 5.0), 1.0 + ind as f64 * 0.01, material.clone())));
+        }
+        let bvh = BvhNode::new(&list);
+        assert_eq!(list.hit(&r, 0.001, f64::INFINITY).map(|h| h.t()), bvh.hit(&r, 0.001, f64::INFINITY).map(|h| h.t()));
+    }
+}
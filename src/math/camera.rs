@@ -1,57 +1,85 @@
-use super::vect::Vect;
-use super::point::Point;
-use super::ray::Ray;
-use super::core::*;
-
-#[derive(Debug, PartialEq)]
-pub struct Camera
-{
-    pub origin: Point,
-    pub right: Vect,
-    pub up: Vect,
-    pub dof: f64,
-    pub lens_radius: f64,
-}
-
-impl Camera
-{
-    const VIEWPORT_HEIGHT: f64 = 2.0;
-    const VIEWPORT_WIDTH: f64 = ASPECT_RATIO * Camera::VIEWPORT_HEIGHT; 
-    const FOCAL_LENGTH: f64 = 1.0;
-
-    pub fn new(pos: Point, look_at: Point, mut world_up: Vect,
-               vfov: f64, aspect_ratio: f64, aperture: f64, dof: f64) -> Self
-    {
-        let theta = degrees_to_radians(vfov);
-        let h = (theta / 2.0).tan();
-        let viewport_height = 2.0 * h;
-        let viewport_width = aspect_ratio * viewport_height;
-
-        let forward = (look_at - pos).get_normalized();
-        world_up.normalize();
-        let right = Vect::cross(&forward, &world_up).get_normalized();
-        let up = Vect::cross(&right, &forward).get_normalized();
-
-        Camera
-        {
-            origin: pos,
-            right: viewport_width * right / 2.0,
-            up: viewport_height * up / 2.0,
-            dof,
-            lens_radius: aperture / 2.0,
-        }
-    }
-
-    pub fn get_ray(&self, u: f64, v: f64) -> Ray
-    {
-        let rand = self.lens_radius * Vect::random_in_disk(1.0);
-        let offset = self.right * rand.x() + self.up * rand.y();
-
-        let forward = Vect::cross(&self.up, &self.right).get_normalized();
-        Ray::make_new
-        (
-            self.origin + offset,
-            self.dof * (u * &self.right + v * &self.up + forward) - offset,
-        )
-    }
+use super::vect::Vect;
+use super::point::Point;
+use super::ray::Ray;
+use super::core::*;
+use super::random::thread_rand;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Camera
+{
+    pub origin: Point,
+    pub right: Vect,
+    pub up: Vect,
+    pub dof: f64,
+    pub lens_radius: f64,
+    // Rays are spread over the time the shutter is open, blurring whatever moves meanwhile
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+}
+
+impl Camera
+{
+    const VIEWPORT_HEIGHT: f64 = 2.0;
+    const VIEWPORT_WIDTH: f64 = ASPECT_RATIO * Camera::VIEWPORT_HEIGHT; 
+    const FOCAL_LENGTH: f64 = 1.0;
+
+    pub fn new(pos: Point, look_at: Point, mut world_up: Vect,
+               vfov: f64, aspect_ratio: f64, aperture: f64, dof: f64) -> Self
+    {
+        let theta = degrees_to_radians(vfov);
+        let h = (theta / 2.0).tan();
+        let viewport_height = 2.0 * h;
+        let viewport_width = aspect_ratio * viewport_height;
+
+        let forward = (look_at - pos).get_normalized();
+        world_up.normalize();
+        let right = Vect::cross(&forward, &world_up).get_normalized();
+        let up = Vect::cross(&right, &forward).get_normalized();
+
+        Camera
+        {
+            origin: pos,
+            right: viewport_width * right / 2.0,
+            up: viewport_height * up / 2.0,
+            dof,
+            lens_radius: aperture / 2.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+        }
+    }
+
+    // Keeps the vertical field of view and widens or narrows the horizontal one
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f64)
+    {
+        self.right = self.right.get_normalized() * self.up.length() * aspect_ratio;
+    }
+
+    pub fn set_shutter(&mut self, open: f64, close: f64)
+    {
+        self.shutter_open = open;
+        self.shutter_close = close;
+    }
+
+    pub fn get_ray(&self, u: f64, v: f64) -> Ray
+    {
+        let rand = self.lens_radius * Vect::random_in_disk(1.0);
+        let offset = self.right * rand.x() + self.up * rand.y();
+
+        let forward = Vect::cross(&self.up, &self.right).get_normalized();
+        let mut ray = Ray::make_new
+        (
+            self.origin + offset,
+            self.dof * (u * self.right + v * self.up + forward) - offset,
+        );
+        // Still images don't draw a time, keeping their random sequences as they were
+        if self.shutter_close > self.shutter_open
+        {
+            ray.set_time(self.shutter_open + thread_rand() * (self.shutter_close - self.shutter_open));
+        }
+        else
+        {
+            ray.set_time(self.shutter_open);
+        }
+        ray
+    }
 }
\ No newline at end of file
@@ -0,0 +1,172 @@
+// Piecewise constant distributions for importance sampling tabulated functions, e.g. the pixels of an image.
+// Samples are drawn by inverting the cumulative distribution, so uniform inputs map to values in [0, 1)
+// with a density proportional to the function.
+
+pub struct Distribution1D
+{
+    function: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D
+{
+    // Negative values are treated as zero. A function that is zero everywhere is sampled uniformly.
+    pub fn make_new(function: Vec<f64>) -> Self
+    {
+        assert!(!function.is_empty(), "A distribution needs at least one value");
+        let function: Vec<f64> = function.into_iter().map(|value| value.max(0.0)).collect();
+        let count = function.len() as f64;
+
+        let mut cdf = Vec::with_capacity(function.len() + 1);
+        cdf.push(0.0);
+        for value in &function
+        {
+            cdf.push(cdf[cdf.len() - 1] + value / count);
+        }
+        let integral = cdf[function.len()];
+        for (ind, value) in cdf.iter_mut().enumerate()
+        {
+            *value = if integral > 0.0 { *value / integral } else { ind as f64 / count };
+        }
+
+        Distribution1D
+        {
+            function,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn count(&self) -> usize
+    {
+        self.function.len()
+    }
+
+    // Integral of the function over [0, 1]
+    pub fn integral(&self) -> f64
+    {
+        self.integral
+    }
+
+    // Density of the values inside segment ind
+    pub fn pdf(&self, ind: usize) -> f64
+    {
+        if self.integral > 0.0
+        {
+            self.function[ind] / self.integral
+        }
+        else
+        {
+            1.0
+        }
+    }
+
+    // Maps u in [0, 1) to a value in [0, 1), returning it with its density and the segment it fell into
+    pub fn sample(&self, u: f64) -> (f64, f64, usize)
+    {
+        let ind = (self.cdf.partition_point(|value| *value <= u).max(1) - 1).min(self.count() - 1);
+        let mut offset = u - self.cdf[ind];
+        let width = self.cdf[ind + 1] - self.cdf[ind];
+        if width > 0.0
+        {
+            offset /= width;
+        }
+        let value = ((ind as f64 + offset.clamp(0.0, 1.0)) / self.count() as f64).min(1.0 - f64::EPSILON);
+        (value, self.pdf(ind), ind)
+    }
+}
+
+// Function of two variables tabulated row by row, sampled by picking a row from the marginal distribution
+// and then a column from that row's conditional distribution
+pub struct Distribution2D
+{
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D
+{
+    pub fn make_new(function: &[f64], width: usize, height: usize) -> Self
+    {
+        assert_eq!(function.len(), width * height, "The function doesn't match the table size");
+        let rows: Vec<Distribution1D> = function.chunks(width).map(|row| Distribution1D::make_new(row.to_vec())).collect();
+        let marginal = Distribution1D::make_new(rows.iter().map(|row| row.integral()).collect());
+        Distribution2D
+        {
+            rows,
+            marginal,
+        }
+    }
+
+    pub fn integral(&self) -> f64
+    {
+        self.marginal.integral()
+    }
+
+    // Maps (u0, u1) to a point (x, y) of the unit square and its density
+    pub fn sample(&self, u0: f64, u1: f64) -> ((f64, f64), f64)
+    {
+        let (y, y_pdf, row) = self.marginal.sample(u1);
+        let (x, x_pdf, _) = self.rows[row].sample(u0);
+        ((x, y), x_pdf * y_pdf)
+    }
+
+    pub fn pdf(&self, x: f64, y: f64) -> f64
+    {
+        let row = ((y * self.rows.len() as f64) as usize).min(self.rows.len() - 1);
+        let column = ((x * self.rows[row].count() as f64) as usize).min(self.rows[row].count() - 1);
+        self.marginal.pdf(row) * self.rows[row].pdf(column)
+    }
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn distribution_1d_test()
+    {
+        let distribution = Distribution1D::make_new(vec![1.0, 0.0, 3.0, -2.0]);
+        assert_eq!(distribution.integral(), 1.0);
+        assert_eq!(distribution.pdf(0), 1.0);
+        assert_eq!(distribution.pdf(2), 3.0);
+        assert_eq!(distribution.pdf(3), 0.0);
+
+        // The first quarter of the inputs goes to the first segment, the rest to the third
+        assert_eq!(distribution.sample(0.0), (0.0, 1.0, 0));
+        assert_eq!(distribution.sample(0.125), (0.125, 1.0, 0));
+        assert_eq!(distribution.sample(0.25), (0.5, 3.0, 2));
+        assert_eq!(distribution.sample(0.625), (0.625, 3.0, 2));
+        assert!(distribution.sample(0.999999).0 < 0.75);
+
+        let uniform = Distribution1D::make_new(vec![0.0, 0.0]);
+        assert_eq!(uniform.sample(0.75), (0.75, 1.0, 1));
+    }
+
+    #[test]
+    fn distribution_2d_test()
+    {
+        // Only the bottom right cell and, three times as likely, the top left one
+        let distribution = Distribution2D::make_new(&[3.0, 0.0, 0.0, 1.0], 2, 2);
+        assert_eq!(distribution.integral(), 1.0);
+        assert_eq!(distribution.pdf(0.25, 0.25), 3.0);
+        assert_eq!(distribution.pdf(0.75, 0.25), 0.0);
+        assert_eq!(distribution.pdf(0.75, 0.75), 1.0);
+
+        let ((x, y), pdf) = distribution.sample(0.5, 0.5);
+        assert!(x < 0.5 && y < 0.5);
+        assert_eq!(pdf, 3.0);
+        let ((x, y), pdf) = distribution.sample(0.5, 0.9);
+        assert!(x >= 0.5 && y >= 0.5);
+        assert_eq!(pdf, 1.0);
+        assert_eq!(distribution.pdf(x, y), pdf);
+    }
+}
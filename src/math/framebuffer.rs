@@ -1,57 +1,437 @@
-use super::super::utils::color::*;
-use std::fs::File;
-use std::io::Write;
-use std::io::Error;
-
-#[derive(Debug)]
-pub struct FrameBuffer<const WIDTH: usize = 1280, const HEIGHT: usize = 720>
-{
-    pub pixels: Vec<Vec<FColor>>
-}
-
-impl<const WIDTH: usize, const HEIGHT: usize> FrameBuffer<WIDTH, HEIGHT>
-{
-    pub fn new() -> Self
-    {
-        let v = vec![FColor::make_new(0.0, 0.0, 0.0); WIDTH];
-        FrameBuffer
-        {
-            pixels: vec![v; HEIGHT]
-        }
-    }
-
-    pub fn write_to_file(&self, file: &mut File) -> Result<(), Error>
-    {
-        for x in (0..HEIGHT).rev()
-        {
-            for y in 0..WIDTH
-            {
-                file.write_all(self.pixels[x][y].display_color().as_bytes())?;
-            }
-        }
-        Ok(())
-    }
-
-    pub fn set_at(&mut self, x: usize, y: usize, color: FColor)
-    {
-        self.pixels[x][y] = color;
-    }
-}
-
-pub struct FrameBufferSlice<'a>
-{
-    y: usize,
-    pub pixels: &'a mut [Vec<FColor>],
-}
-
-impl<'a, const WIDTH: usize, const HEIGHT: usize> FrameBuffer<WIDTH, HEIGHT>
-{
-    pub fn get_slice(&'a mut self, y_start: usize, num_rows: usize) -> FrameBufferSlice<'a>
-    {
-        FrameBufferSlice::<'a>
-        {
-            y: y_start,
-            pixels: self.pixels.split_at_mut(y_start).1.split_at_mut(num_rows).0,
-        }
-    }
-}
\ No newline at end of file
+use super::super::utils::color::*;
+use std::io::Write;
+use std::io::Error;
+use std::ops::Range;
+
+// Render target with its size chosen at runtime, holding linear radiance.
+// Pixels are stored in one contiguous row-major buffer, (0, 0) being the top left pixel.
+// Parallel writers get disjoint views through rows_mut(), split_rows_mut() and tiles_mut().
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameBuffer
+{
+    width: usize,
+    height: usize,
+    pixels: Vec<FColor>,
+}
+
+impl FrameBuffer
+{
+    pub fn new(width: usize, height: usize) -> Self
+    {
+        FrameBuffer
+        {
+            width,
+            height,
+            pixels: vec![FColor::make_new(0.0, 0.0, 0.0); width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize
+    {
+        self.width
+    }
+
+    pub fn height(&self) -> usize
+    {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[FColor]
+    {
+        &self.pixels
+    }
+
+    pub fn row(&self, y: usize) -> &[FColor]
+    {
+        &self.pixels[y * self.width..(y + 1) * self.width]
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> &FColor
+    {
+        assert!(x < self.width && y < self.height, "Pixel ({}, {}) is outside of the frame buffer", x, y);
+        &self.pixels[y * self.width + x]
+    }
+
+    pub fn get_mut(&mut self, x: usize, y: usize) -> &mut FColor
+    {
+        assert!(x < self.width && y < self.height, "Pixel ({}, {}) is outside of the frame buffer", x, y);
+        &mut self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: FColor)
+    {
+        *self.get_mut(x, y) = color;
+    }
+
+    pub fn write_to_file<W: Write>(&self, file: &mut W) -> Result<(), Error>
+    {
+        for pixel in &self.pixels
+        {
+            file.write_all(pixel.gamma_corrected().display_color().as_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> FrameBuffer
+    {
+        assert!(x + width <= self.width && y + height <= self.height, "Crop rectangle is outside of the frame buffer");
+        let mut result = FrameBuffer::new(width, height);
+        for row in 0..height
+        {
+            let source = &self.row(y + row)[x..x + width];
+            result.pixels[row * width..(row + 1) * width].copy_from_slice(source);
+        }
+        result
+    }
+
+    // Copies source into this buffer with its top left corner at (x, y)
+    pub fn paste(&mut self, source: &FrameBuffer, x: usize, y: usize)
+    {
+        assert!(x + source.width <= self.width && y + source.height <= self.height, "Pasted image is outside of the frame buffer");
+        for row in 0..source.height
+        {
+            let start = (y + row) * self.width + x;
+            self.pixels[start..start + source.width].copy_from_slice(source.row(row));
+        }
+    }
+
+    // Separable resampling: box filtering along axes that shrink, bilinear along axes that grow
+    pub fn resize(&self, width: usize, height: usize) -> FrameBuffer
+    {
+        if width == 0 || height == 0 || self.width == 0 || self.height == 0
+        {
+            return FrameBuffer::new(width, height);
+        }
+
+        let horizontal = resample_weights(self.width, width);
+        let mut intermediate = FrameBuffer::new(width, self.height);
+        for y in 0..self.height
+        {
+            let source = self.row(y);
+            for (x, weights) in horizontal.iter().enumerate()
+            {
+                intermediate.pixels[y * width + x] = weights.iter().fold(FColor::make_new(0.0, 0.0, 0.0), |sum, &(ind, weight)| sum + weight * source[ind]);
+            }
+        }
+
+        let vertical = resample_weights(self.height, height);
+        let mut result = FrameBuffer::new(width, height);
+        for (y, weights) in vertical.iter().enumerate()
+        {
+            for x in 0..width
+            {
+                result.pixels[y * width + x] = weights.iter().fold(FColor::make_new(0.0, 0.0, 0.0), |sum, &(ind, weight)| sum + weight * intermediate.pixels[ind * width + x]);
+            }
+        }
+        result
+    }
+
+    pub fn rows_mut(&mut self, rows: Range<usize>) -> FrameBufferRows<'_>
+    {
+        assert!(rows.start <= rows.end && rows.end <= self.height, "Row range is outside of the frame buffer");
+        FrameBufferRows
+        {
+            width: self.width,
+            y_start: rows.start,
+            pixels: &mut self.pixels[rows.start * self.width..rows.end * self.width],
+        }
+    }
+
+    // Splits the whole buffer into bands of rows_per_band rows (the last one may be shorter)
+    pub fn split_rows_mut(&mut self, rows_per_band: usize) -> Vec<FrameBufferRows<'_>>
+    {
+        assert!(rows_per_band > 0);
+        let width = self.width;
+        if width == 0
+        {
+            return Vec::new();
+        }
+        self.pixels.chunks_mut(rows_per_band * width).enumerate().map(|(band, pixels)|
+            {
+                FrameBufferRows
+                {
+                    width,
+                    y_start: band * rows_per_band,
+                    pixels,
+                }
+            }).collect()
+    }
+
+    // Splits the whole buffer into tiles, ordered row by row. Tiles on the right and bottom edges may be smaller.
+    pub fn tiles_mut(&mut self, tile_width: usize, tile_height: usize) -> Vec<FrameBufferTile<'_>>
+    {
+        assert!(tile_width > 0 && tile_height > 0);
+        let width = self.width;
+        if width == 0
+        {
+            return Vec::new();
+        }
+        let tiles_per_row = width.div_ceil(tile_width);
+        let mut tiles: Vec<FrameBufferTile> = Vec::with_capacity(tiles_per_row * self.height.div_ceil(tile_height));
+
+        for (y, row) in self.pixels.chunks_mut(width).enumerate()
+        {
+            if y % tile_height == 0
+            {
+                for tile_x in 0..tiles_per_row
+                {
+                    let x = tile_x * tile_width;
+                    tiles.push(FrameBufferTile
+                    {
+                        x,
+                        y,
+                        width: tile_width.min(width - x),
+                        rows: Vec::with_capacity(tile_height),
+                    });
+                }
+            }
+            let first_tile = tiles.len() - tiles_per_row;
+            for (tile_x, span) in row.chunks_mut(tile_width).enumerate()
+            {
+                tiles[first_tile + tile_x].rows.push(span);
+            }
+        }
+        tiles
+    }
+}
+
+// For every destination index, the source indices and weights that contribute to it
+fn resample_weights(source_len: usize, destination_len: usize) -> Vec<Vec<(usize, f64)>>
+{
+    let scale = source_len as f64 / destination_len as f64;
+    (0..destination_len).map(|ind|
+        {
+            if scale > 1.0
+            {
+                let start = ind as f64 * scale;
+                let end = start + scale;
+                let mut weights = Vec::new();
+                let mut source = start.floor() as usize;
+                while (source as f64) < end && source < source_len
+                {
+                    let overlap = (end.min(source as f64 + 1.0) - start.max(source as f64)).max(0.0);
+                    if overlap > 0.0
+                    {
+                        weights.push((source, overlap / scale));
+                    }
+                    source += 1;
+                }
+                weights
+            }
+            else
+            {
+                let center = ((ind as f64 + 0.5) * scale - 0.5).max(0.0);
+                let left = (center.floor() as usize).min(source_len - 1);
+                let right = (left + 1).min(source_len - 1);
+                let fraction = center - left as f64;
+                vec![(left, 1.0 - fraction), (right, fraction)]
+            }
+        }).collect()
+}
+
+// Mutable view of a range of full rows
+pub struct FrameBufferRows<'a>
+{
+    width: usize,
+    y_start: usize,
+    pixels: &'a mut [FColor],
+}
+
+impl FrameBufferRows<'_>
+{
+    pub fn width(&self) -> usize
+    {
+        self.width
+    }
+
+    pub fn y_range(&self) -> Range<usize>
+    {
+        self.y_start..self.y_start + self.pixels.len() / self.width
+    }
+
+    // (x, y) are frame buffer coordinates
+    pub fn set(&mut self, x: usize, y: usize, color: FColor)
+    {
+        assert!(x < self.width && self.y_range().contains(&y), "Pixel ({}, {}) is outside of the view", x, y);
+        self.pixels[(y - self.y_start) * self.width + x] = color;
+    }
+
+    // Iterates over the pixels of the view along with their frame buffer coordinates
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (usize, usize, &mut FColor)>
+    {
+        let width = self.width;
+        let y_start = self.y_start;
+        self.pixels.iter_mut().enumerate().map(move |(ind, pixel)| (ind % width, y_start + ind / width, pixel))
+    }
+}
+
+// Mutable view of a rectangular block of pixels
+pub struct FrameBufferTile<'a>
+{
+    x: usize,
+    y: usize,
+    width: usize,
+    rows: Vec<&'a mut [FColor]>,
+}
+
+impl<'a> FrameBufferTile<'a>
+{
+    pub fn x(&self) -> usize
+    {
+        self.x
+    }
+
+    pub fn y(&self) -> usize
+    {
+        self.y
+    }
+
+    pub fn width(&self) -> usize
+    {
+        self.width
+    }
+
+    pub fn height(&self) -> usize
+    {
+        self.rows.len()
+    }
+
+    // (x, y) are frame buffer coordinates
+    pub fn set(&mut self, x: usize, y: usize, color: FColor)
+    {
+        assert!(x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.rows.len(),
+                "Pixel ({}, {}) is outside of the tile", x, y);
+        self.rows[y - self.y][x - self.x] = color;
+    }
+
+    // Iterates over the pixels of the tile along with their frame buffer coordinates
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (usize, usize, &mut FColor)> + use<'_, 'a>
+    {
+        let x_start = self.x;
+        let y_start = self.y;
+        self.rows.iter_mut().enumerate().flat_map(move |(row, pixels)|
+            {
+                pixels.iter_mut().enumerate().map(move |(column, pixel)| (x_start + column, y_start + row, pixel))
+            })
+    }
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn make_gradient(width: usize, height: usize) -> FrameBuffer
+    {
+        let mut fb = FrameBuffer::new(width, height);
+        for y in 0..height
+        {
+            for x in 0..width
+            {
+                fb.set(x, y, FColor::new_color(x as f64, y as f64, 1.0));
+            }
+        }
+        fb
+    }
+
+    #[test]
+    fn access_test()
+    {
+        let fb = make_gradient(4, 3);
+        assert_eq!((fb.width(), fb.height()), (4, 3));
+        assert_eq!(*fb.get(3, 2), FColor::new_color(3.0, 2.0, 1.0));
+        assert_eq!(fb.row(1)[2], FColor::new_color(2.0, 1.0, 1.0));
+        assert_eq!(fb.pixels().len(), 12);
+    }
+
+    #[test]
+    fn rows_test()
+    {
+        let mut fb = FrameBuffer::new(3, 5);
+        {
+            let mut rows = fb.rows_mut(1..3);
+            assert_eq!(rows.y_range(), 1..3);
+            rows.set(2, 2, FColor::new_color(1.0, 1.0, 1.0));
+        }
+        assert_eq!(*fb.get(2, 2), FColor::new_color(1.0, 1.0, 1.0));
+
+        let bands = fb.split_rows_mut(2);
+        assert_eq!(bands.iter().map(|band| band.y_range()).collect::<Vec<_>>(), vec![0..2, 2..4, 4..5]);
+        for mut band in bands
+        {
+            for (x, y, pixel) in band.iter_mut()
+            {
+                *pixel = FColor::new_color(x as f64, y as f64, 0.0);
+            }
+        }
+        assert_eq!(*fb.get(1, 4), FColor::new_color(1.0, 4.0, 0.0));
+    }
+
+    #[test]
+    fn tiles_test()
+    {
+        let mut fb = FrameBuffer::new(10, 7);
+        let tiles = fb.tiles_mut(4, 3);
+        assert_eq!(tiles.len(), 9);
+        assert_eq!((tiles[2].x(), tiles[2].y(), tiles[2].width(), tiles[2].height()), (8, 0, 2, 3));
+        assert_eq!((tiles[8].x(), tiles[8].y(), tiles[8].width(), tiles[8].height()), (8, 6, 2, 1));
+
+        for mut tile in tiles
+        {
+            for (_, _, pixel) in tile.iter_mut()
+            {
+                *pixel += FColor::new_color(1.0, 0.0, 0.0);
+            }
+            let (x, y) = (tile.x(), tile.y());
+            tile.set(x, y, FColor::new_color(1.0, x as f64, y as f64));
+        }
+        // Every pixel belongs to exactly one tile
+        assert!(fb.pixels().iter().all(|pixel| pixel.r() == 1.0));
+        assert_eq!(*fb.get(4, 3), FColor::new_color(1.0, 4.0, 3.0));
+    }
+
+    #[test]
+    fn crop_test()
+    {
+        let fb = make_gradient(6, 4);
+        let cropped = fb.crop(2, 1, 3, 2);
+        assert_eq!((cropped.width(), cropped.height()), (3, 2));
+        assert_eq!(*cropped.get(0, 0), FColor::new_color(2.0, 1.0, 1.0));
+        assert_eq!(*cropped.get(2, 1), FColor::new_color(4.0, 2.0, 1.0));
+
+        let mut target = FrameBuffer::new(6, 4);
+        target.paste(&cropped, 2, 1);
+        assert_eq!(target.crop(2, 1, 3, 2), cropped);
+        assert_eq!(*target.get(1, 1), FColor::make_new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn resize_test()
+    {
+        let fb = make_gradient(4, 4);
+
+        let same = fb.resize(4, 4);
+        assert_eq!(same, fb);
+
+        // Halving averages 2x2 blocks
+        let half = fb.resize(2, 2);
+        assert_eq!(*half.get(0, 0), FColor::new_color(0.5, 0.5, 1.0));
+        assert_eq!(*half.get(1, 1), FColor::new_color(2.5, 2.5, 1.0));
+
+        // Upscaling keeps a constant channel constant and stays within the source range
+        let double = fb.resize(8, 3);
+        assert_eq!((double.width(), double.height()), (8, 3));
+        for pixel in double.pixels()
+        {
+            assert!((pixel.b() - 1.0).abs() < 1e-12);
+            assert!(pixel.r() >= 0.0 && pixel.r() <= 3.0 + 1e-12);
+        }
+    }
+}
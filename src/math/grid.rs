@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+
+// Voxel grids holding one value per voxel, like the density of smoke or its temperature.
+// Voxel (x, y, z) is the unit cube from index position (x, y, z) to (x + 1, y + 1, z + 1) and its value is at
+// the center of the cube. Voxels that were never set hold the grid's background value.
+
+pub trait VoxelGrid: Send + Sync
+{
+    fn value(&self, x: i64, y: i64, z: i64) -> f32;
+
+    // Smallest and one past the largest index of the voxels that can differ from the background
+    fn index_bounds(&self) -> ([i64; 3], [i64; 3]);
+
+    // Largest value in the grid, background included
+    fn max_value(&self) -> f32;
+
+    // Trilinear interpolation between the centers of the voxels around an index position
+    fn interpolate(&self, position: [f64; 3]) -> f64
+    {
+        let shifted = position.map(|coordinate| coordinate - 0.5);
+        let base = shifted.map(|coordinate| coordinate.floor());
+        let weights = [shifted[0] - base[0], shifted[1] - base[1], shifted[2] - base[2]];
+        let [x, y, z] = base.map(|coordinate| coordinate as i64);
+        let mut result = 0.0;
+        for corner in 0..8
+        {
+            let offsets = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let mut weight = 1.0;
+            for axis in 0..3
+            {
+                weight *= if offsets[axis] == 1 { weights[axis] } else { 1.0 - weights[axis] };
+            }
+            if weight > 0.0
+            {
+                result += weight * self.value(x + offsets[0] as i64, y + offsets[1] as i64, z + offsets[2] as i64) as f64;
+            }
+        }
+        result
+    }
+}
+
+// Every voxel of a box stored in one array, x varying the fastest
+pub struct DenseGrid
+{
+    minimum: [i64; 3],
+    size: [usize; 3],
+    values: Vec<f32>,
+    background: f32,
+    max_value: f32,
+}
+
+impl DenseGrid
+{
+    // The values of the voxels from minimum to minimum + size, x varying the fastest then y
+    pub fn make_new(minimum: [i64; 3], size: [usize; 3], values: Vec<f32>, background: f32) -> Self
+    {
+        assert_eq!(values.len(), size[0] * size[1] * size[2], "a dense grid needs a value for every voxel");
+        let max_value = values.iter().fold(background, |max, value| max.max(*value));
+        DenseGrid{ minimum, size, values, background, max_value }
+    }
+}
+
+impl VoxelGrid for DenseGrid
+{
+    fn value(&self, x: i64, y: i64, z: i64) -> f32
+    {
+        let local = [x - self.minimum[0], y - self.minimum[1], z - self.minimum[2]];
+        if (0..3).any(|axis| local[axis] < 0 || local[axis] >= self.size[axis] as i64)
+        {
+            return self.background;
+        }
+        let [x, y, z] = local.map(|coordinate| coordinate as usize);
+        self.values[(z * self.size[1] + y) * self.size[0] + x]
+    }
+
+    fn index_bounds(&self) -> ([i64; 3], [i64; 3])
+    {
+        (self.minimum, [0, 1, 2].map(|axis| self.minimum[axis] + self.size[axis] as i64))
+    }
+
+    fn max_value(&self) -> f32
+    {
+        self.max_value
+    }
+}
+
+// Only stores the blocks of LEAF_DIM^3 voxels that were written to, like the leaf nodes of OpenVDB
+pub struct SparseGrid
+{
+    leaves: HashMap<[i64; 3], Box<[f32; LEAF_VOXELS]>>,
+    background: f32,
+    max_value: f32,
+    minimum: [i64; 3],
+    maximum: [i64; 3],
+}
+
+pub const LOG2_LEAF_DIM: i64 = 3;
+pub const LEAF_DIM: i64 = 1 << LOG2_LEAF_DIM;
+pub const LEAF_VOXELS: usize = (LEAF_DIM * LEAF_DIM * LEAF_DIM) as usize;
+
+// Position of a voxel in its leaf, x varying the slowest as in OpenVDB and NanoVDB
+pub fn leaf_offset(x: i64, y: i64, z: i64) -> usize
+{
+    let mask = LEAF_DIM - 1;
+    (((x & mask) << (2 * LOG2_LEAF_DIM)) | ((y & mask) << LOG2_LEAF_DIM) | (z & mask)) as usize
+}
+
+// Index of the first voxel of the leaf holding a voxel
+pub fn leaf_origin(x: i64, y: i64, z: i64) -> [i64; 3]
+{
+    [x, y, z].map(|coordinate| coordinate & !(LEAF_DIM - 1))
+}
+
+impl SparseGrid
+{
+    pub fn make_new(background: f32) -> Self
+    {
+        SparseGrid
+        {
+            leaves: HashMap::new(),
+            background,
+            max_value: background,
+            minimum: [i64::MAX; 3],
+            maximum: [i64::MIN; 3],
+        }
+    }
+
+    pub fn set(&mut self, x: i64, y: i64, z: i64, value: f32)
+    {
+        let background = self.background;
+        let leaf = self.leaves.entry(leaf_origin(x, y, z)).or_insert_with(|| Box::new([background; LEAF_VOXELS]));
+        leaf[leaf_offset(x, y, z)] = value;
+        self.max_value = self.max_value.max(value);
+        for (axis, coordinate) in [x, y, z].into_iter().enumerate()
+        {
+            self.minimum[axis] = self.minimum[axis].min(coordinate);
+            self.maximum[axis] = self.maximum[axis].max(coordinate + 1);
+        }
+    }
+
+    pub fn leaf_count(&self) -> usize
+    {
+        self.leaves.len()
+    }
+}
+
+impl VoxelGrid for SparseGrid
+{
+    fn value(&self, x: i64, y: i64, z: i64) -> f32
+    {
+        self.leaves.get(&leaf_origin(x, y, z)).map_or(self.background, |leaf| leaf[leaf_offset(x, y, z)])
+    }
+
+    fn index_bounds(&self) -> ([i64; 3], [i64; 3])
+    {
+        if self.leaves.is_empty()
+        {
+            return ([0; 3], [0; 3]);
+        }
+        (self.minimum, self.maximum)
+    }
+
+    fn max_value(&self) -> f32
+    {
+        self.max_value
+    }
+}
+
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn dense_grid_test()
+    {
+        let values: Vec<f32> = (0..24).map(|value| value as f32).collect();
+        let grid = DenseGrid::make_new([-1, 0, 2], [2, 3, 4], values, 0.5);
+        assert_eq!(grid.value(-1, 0, 2), 0.0);
+        assert_eq!(grid.value(0, 0, 2), 1.0);
+        assert_eq!(grid.value(-1, 1, 2), 2.0);
+        assert_eq!(grid.value(0, 2, 5), 23.0);
+        assert_eq!(grid.value(1, 0, 2), 0.5);
+        assert_eq!(grid.value(-1, 0, 6), 0.5);
+        assert_eq!(grid.index_bounds(), ([-1, 0, 2], [1, 3, 6]));
+        assert_eq!(grid.max_value(), 23.0);
+
+        // Voxel centers give back their values, halfway between two the average
+        assert_eq!(grid.interpolate([-0.5, 0.5, 2.5]), 0.0);
+        assert_eq!(grid.interpolate([0.0, 0.5, 2.5]), 0.5);
+        assert_eq!(grid.interpolate([0.0, 1.0, 3.0]), (1.0 + 3.0 + 7.0 + 9.0 + 0.0 + 2.0 + 6.0 + 8.0) / 8.0);
+    }
+
+    #[test]
+    fn sparse_grid_test()
+    {
+        let mut grid = SparseGrid::make_new(0.0);
+        assert_eq!(grid.index_bounds(), ([0; 3], [0; 3]));
+        grid.set(1, 2, 3, 4.0);
+        grid.set(-9, 20, 3, 2.0);
+        assert_eq!(grid.leaf_count(), 2);
+        assert_eq!(grid.value(1, 2, 3), 4.0);
+        assert_eq!(grid.value(-9, 20, 3), 2.0);
+        assert_eq!(grid.value(1, 2, 4), 0.0);
+        assert_eq!(grid.value(100, 2, 3), 0.0);
+        assert_eq!(grid.index_bounds(), ([-9, 2, 3], [2, 21, 4]));
+        assert_eq!(grid.max_value(), 4.0);
+
+        // The layout of OpenVDB leaves
+        assert_eq!(leaf_origin(-9, 20, 3), [-16, 16, 0]);
+        assert_eq!(leaf_offset(1, 2, 3), 64 + 16 + 3);
+        assert_eq!(leaf_offset(-9, 20, 3), 7 * 64 + 4 * 8 + 3);
+    }
+}
@@ -1,148 +1,291 @@
-use std::marker;
-use std::sync::Arc;
-
-use super::ray::Ray;
-use super::point::Point;
-use super::vect::Vect;
-use super::material::*;
-
-pub struct HitResult
-{
-    point: Point,
-    normal: Vect,
-    material: Arc<dyn Material>,
-    t: f64,
-    front_face: bool,
-}
-
-impl HitResult
-{
-    pub fn point(&self) -> &Point
-    {
-        &self.point
-    }
-
-    pub fn normal(&self) -> &Vect
-    {
-        &self.normal
-    }
-
-    pub fn material(&self) -> &dyn Material
-    {
-        self.material.as_ref()
-    }
-
-    pub fn t(&self) -> f64
-    {
-        self.t
-    }
-
-    pub fn front_face(&self) -> bool
-    {
-        self.front_face
-    }
-
-    pub fn default() -> Self
-    {
-        HitResult
-        {
-            point: Point::new(),
-            normal: Vect::new(),
-            material: Arc::new(Lambertian::default()),
-            t: 0.0,
-            front_face: true
-        }
-    }
-
-    pub fn make_new(point: Point, mut normal: Vect, material: Arc<dyn Material>, t: f64, ray: &Ray) -> Self
-    {
-        let mut front_face = true;
-
-        normal.normalize();
-
-        if (Vect::dot(&normal, ray.direction())) > 0.0
-        {
-            front_face = false;
-            normal = -normal;
-        }
-
-        HitResult
-        {
-            point,
-            normal,
-            material,
-            t,
-            front_face,
-        }
-    }
-}
-
-pub trait Hittable: marker::Sync
-{
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitResult>;
-}
-
-pub struct HittableList
-{
-    pub hittables: Vec<Arc<dyn Hittable>>,
-}
-
-impl Hittable for HittableList
-{
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitResult>
-    {
-        let mut final_result = HitResult::default();
-        let mut hit_anything = false;
-        let mut closest_hit = t_max;
-
-        for hittable in &self.hittables
-        {
-            let hit_result = hittable.hit(r, t_min, closest_hit);
-            if hit_result.is_some()
-            {
-                let hit_result = hit_result.unwrap();
-                hit_anything = true;
-                closest_hit = hit_result.t;
-                final_result = hit_result;
-            }
-        }
-
-        if !hit_anything
-        {
-            return None;
-        }
-
-        Some(final_result)
-    }
-}
-
-impl HittableList
-{
-    pub fn default() -> Self
-    {
-        HittableList
-        {
-            hittables: Vec::default(),
-        }
-    }
-
-    pub fn new(hittable: Arc<dyn Hittable>) -> Self
-    {
-        HittableList
-        {
-            hittables: vec!(hittable),
-        }
-    }
-
-    pub fn add(&mut self, hittable: Arc<dyn Hittable>)
-    {
-        self.hittables.push(hittable);
-    }
-
-    pub fn clear(&mut self)
-    {
-        self.hittables.clear();
-    }
-}
-
-unsafe impl marker::Sync for HittableList {}
\ No newline at end of file
+use std::sync::Arc;
+
+use super::aabb::Aabb;
+use super::matrix::Matrix;
+use super::random::thread_rand;
+use super::ray::Ray;
+use super::point::Point;
+use super::vect::Vect;
+use super::material::*;
+
+pub struct HitResult
+{
+    point: Point,
+    normal: Vect,
+    shading_normal: Vect,
+    material: Arc<dyn Material>,
+    t: f64,
+    front_face: bool,
+    // Surface coordinates for texturing
+    u: f64,
+    v: f64,
+    // Direction in which u grows, zero when the surface doesn't have one
+    tangent: Vect,
+}
+
+impl HitResult
+{
+    pub fn point(&self) -> &Point
+    {
+        &self.point
+    }
+
+    pub fn normal(&self) -> &Vect
+    {
+        &self.normal
+    }
+
+    // Normal used for lighting, e.g. interpolated from mesh vertex normals.
+    // Equals the geometric normal unless overridden with set_shading_normal().
+    pub fn shading_normal(&self) -> &Vect
+    {
+        &self.shading_normal
+    }
+
+    pub fn set_shading_normal(&mut self, mut shading_normal: Vect)
+    {
+        shading_normal.normalize();
+        if Vect::dot(&shading_normal, &self.normal) < 0.0
+        {
+            shading_normal = -shading_normal;
+        }
+        self.shading_normal = shading_normal;
+    }
+
+    pub fn material(&self) -> &dyn Material
+    {
+        self.material.as_ref()
+    }
+
+    pub fn uv(&self) -> (f64, f64)
+    {
+        (self.u, self.v)
+    }
+
+    pub fn set_uv(&mut self, u: f64, v: f64)
+    {
+        self.u = u;
+        self.v = v;
+    }
+
+    // Orients anisotropic materials
+    pub fn tangent(&self) -> &Vect
+    {
+        &self.tangent
+    }
+
+    pub fn set_tangent(&mut self, tangent: Vect)
+    {
+        self.tangent = tangent;
+    }
+
+    pub fn t(&self) -> f64
+    {
+        self.t
+    }
+
+    pub fn front_face(&self) -> bool
+    {
+        self.front_face
+    }
+
+    // Moves a hit found in the local space of a transformed object out to the world.
+    // to_local is the inverse of to_world and t the distance along the world ray.
+    pub fn transform(&mut self, to_world: &Matrix, to_local: &Matrix, t: f64)
+    {
+        self.point = to_world.transform_point(&self.point);
+        self.normal = to_local.transform_normal(&self.normal).get_normalized();
+        self.shading_normal = to_local.transform_normal(&self.shading_normal).get_normalized();
+        self.tangent = to_world.transform_vector(&self.tangent);
+        self.t = t;
+    }
+
+    pub fn default() -> Self
+    {
+        HitResult
+        {
+            point: Point::new(),
+            normal: Vect::new(),
+            shading_normal: Vect::new(),
+            material: Arc::new(Lambertian::default()),
+            t: 0.0,
+            front_face: true,
+            u: 0.0,
+            v: 0.0,
+            tangent: Vect::new(),
+        }
+    }
+
+    pub fn make_new(point: Point, mut normal: Vect, material: Arc<dyn Material>, t: f64, ray: &Ray) -> Self
+    {
+        let mut front_face = true;
+
+        normal.normalize();
+
+        if (Vect::dot(&normal, ray.direction())) > 0.0
+        {
+            front_face = false;
+            normal = -normal;
+        }
+
+        HitResult
+        {
+            point,
+            normal,
+            shading_normal: normal,
+            material,
+            t,
+            front_face,
+            u: 0.0,
+            v: 0.0,
+            tangent: Vect::new(),
+        }
+    }
+}
+
+pub trait Hittable: Send + Sync
+{
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitResult>;
+
+    // Returns None for objects that extend infinitely and therefore can't be bounded.
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    // Solid angle density, as seen from origin, of random_direction() picking direction.
+    // Objects that can't be sampled as lights return 0. Moving objects are sampled where they are at time.
+    fn pdf_value(&self, _origin: &Point, _direction: &Vect, _time: f64) -> f64
+    {
+        0.0
+    }
+
+    // Direction from origin towards a random point on the object, not normalized
+    fn random_direction(&self, _origin: &Point, _time: f64) -> Vect
+    {
+        Vect::make_new(1.0, 0.0, 0.0)
+    }
+
+    fn is_emissive(&self) -> bool
+    {
+        false
+    }
+
+    // Fraction of the light that gets through the object along the ray between t_min and t_max.
+    // Surfaces block all of it, media can let some of it through.
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64
+    {
+        if self.hit(r, t_min, t_max).is_some() { 0.0 } else { 1.0 }
+    }
+}
+
+pub struct HittableList
+{
+    pub hittables: Vec<Arc<dyn Hittable>>,
+}
+
+impl Hittable for HittableList
+{
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitResult>
+    {
+        let mut final_result = None;
+        let mut closest_hit = t_max;
+
+        for hittable in &self.hittables
+        {
+            if let Some(hit_result) = hittable.hit(r, t_min, closest_hit)
+            {
+                closest_hit = hit_result.t;
+                final_result = Some(hit_result);
+            }
+        }
+
+        final_result
+    }
+
+    fn bounding_box(&self) -> Option<Aabb>
+    {
+        let mut result = Aabb::empty();
+        for hittable in &self.hittables
+        {
+            result = Aabb::surrounding(&result, &hittable.bounding_box()?);
+        }
+        Some(result)
+    }
+
+    // Picks one of the hittables uniformly, so the density is the average of theirs
+    fn pdf_value(&self, origin: &Point, direction: &Vect, time: f64) -> f64
+    {
+        if self.hittables.is_empty()
+        {
+            return 0.0;
+        }
+        let sum: f64 = self.hittables.iter().map(|hittable| hittable.pdf_value(origin, direction, time)).sum();
+        sum / self.hittables.len() as f64
+    }
+
+    fn random_direction(&self, origin: &Point, time: f64) -> Vect
+    {
+        if self.hittables.is_empty()
+        {
+            return Vect::make_new(1.0, 0.0, 0.0);
+        }
+        let ind = ((thread_rand() * self.hittables.len() as f64) as usize).min(self.hittables.len() - 1);
+        self.hittables[ind].random_direction(origin, time)
+    }
+
+    fn is_emissive(&self) -> bool
+    {
+        self.hittables.iter().any(|hittable| hittable.is_emissive())
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64
+    {
+        let mut transmittance = 1.0;
+        for hittable in &self.hittables
+        {
+            transmittance *= hittable.transmittance(r, t_min, t_max);
+            if transmittance <= 0.0
+            {
+                break;
+            }
+        }
+        transmittance
+    }
+}
+
+impl HittableList
+{
+    pub fn default() -> Self
+    {
+        HittableList
+        {
+            hittables: Vec::default(),
+        }
+    }
+
+    pub fn new(hittable: Arc<dyn Hittable>) -> Self
+    {
+        HittableList
+        {
+            hittables: vec!(hittable),
+        }
+    }
+
+    pub fn add(&mut self, hittable: Arc<dyn Hittable>)
+    {
+        self.hittables.push(hittable);
+    }
+
+    pub fn clear(&mut self)
+    {
+        self.hittables.clear();
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.hittables.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.hittables.is_empty()
+    }
+}
\ No newline at end of file
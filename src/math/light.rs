@@ -0,0 +1,236 @@
+use super::core::*;
+use super::point::Point;
+use super::random::thread_rand;
+use super::vect::Vect;
+use super::super::utils::color::FColor;
+
+// Punctual lights have no geometry, so rays can never hit them and they are only reached through shadow rays.
+
+// Light arriving at a point from one light, before any occlusion
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LightSample
+{
+    // Unit vector from the lit point towards the light
+    pub direction: Vect,
+    // Distance the shadow ray has to cover, infinite for directional lights
+    pub distance: f64,
+    // Irradiance on a surface facing the light
+    pub irradiance: FColor,
+}
+
+pub trait Light: Send + Sync
+{
+    // None when the point doesn't receive any light, e.g. outside of a spot light's cone
+    fn sample(&self, point: &Point) -> Option<LightSample>;
+}
+
+// Gives off the same intensity in every direction, falling off with the squared distance
+#[derive(Debug, Clone)]
+pub struct PointLight
+{
+    position: Point,
+    intensity: FColor,
+}
+
+impl Light for PointLight
+{
+    fn sample(&self, point: &Point) -> Option<LightSample>
+    {
+        let to_light = self.position - point;
+        let distance_squared = to_light.length_squared();
+        if distance_squared < FLOAT_MARGIN_OF_ERROR
+        {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        Some(LightSample{ direction: to_light / distance, distance, irradiance: self.intensity / distance_squared })
+    }
+}
+
+impl PointLight
+{
+    pub fn new(position: &Point, intensity: &FColor) -> Self
+    {
+        PointLight{ position: *position, intensity: *intensity }
+    }
+
+    pub fn make_new(position: Point, intensity: FColor) -> Self
+    {
+        PointLight{ position, intensity }
+    }
+}
+
+// Point light limited to a cone: full intensity inside the inner angle, fading smoothly to nothing at the outer one
+#[derive(Debug, Clone)]
+pub struct SpotLight
+{
+    position: Point,
+    direction: Vect,
+    intensity: FColor,
+    cos_inner: f64,
+    cos_outer: f64,
+}
+
+impl Light for SpotLight
+{
+    fn sample(&self, point: &Point) -> Option<LightSample>
+    {
+        let to_light = self.position - point;
+        let distance_squared = to_light.length_squared();
+        if distance_squared < FLOAT_MARGIN_OF_ERROR
+        {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        let direction = to_light / distance;
+
+        let falloff = self.falloff(-Vect::dot(&direction, &self.direction));
+        if falloff <= 0.0
+        {
+            return None;
+        }
+        Some(LightSample{ direction, distance, irradiance: self.intensity * (falloff / distance_squared) })
+    }
+}
+
+impl SpotLight
+{
+    // Angles are in degrees and measured from the axis of the cone
+    pub fn make_new(position: Point, target: Point, intensity: FColor, inner_angle: f64, outer_angle: f64) -> Self
+    {
+        let outer_angle = clamp(outer_angle, 0.0, 180.0);
+        let inner_angle = clamp(inner_angle, 0.0, outer_angle);
+        SpotLight
+        {
+            position,
+            direction: (target - position).get_normalized(),
+            intensity,
+            cos_inner: degrees_to_radians(inner_angle).cos(),
+            cos_outer: degrees_to_radians(outer_angle).cos(),
+        }
+    }
+
+    // Smoothstep between the outer and the inner cone
+    fn falloff(&self, cos_angle: f64) -> f64
+    {
+        if cos_angle >= self.cos_inner
+        {
+            return 1.0;
+        }
+        if cos_angle <= self.cos_outer
+        {
+            return 0.0;
+        }
+        let t = (cos_angle - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+// Light from infinitely far away, like the sun. A non zero angular diameter spreads the
+// directions over a small disk in the sky, which softens the shadows.
+#[derive(Debug, Clone)]
+pub struct DirectionalLight
+{
+    // Unit vector pointing towards the light
+    direction: Vect,
+    irradiance: FColor,
+    cos_radius: f64,
+}
+
+impl Light for DirectionalLight
+{
+    fn sample(&self, _point: &Point) -> Option<LightSample>
+    {
+        let mut direction = self.direction;
+        if self.cos_radius < 1.0
+        {
+            // Uniform over the cone of the disk, every direction carries an equal share of the irradiance
+            let cos_theta = 1.0 + thread_rand() * (self.cos_radius - 1.0);
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi = 2.0 * PI * thread_rand();
+            let (u, v) = self.direction.orthonormal_basis();
+            direction = (phi.cos() * sin_theta) * u + (phi.sin() * sin_theta) * v + cos_theta * self.direction;
+        }
+        Some(LightSample{ direction, distance: INFINITY, irradiance: self.irradiance })
+    }
+}
+
+impl DirectionalLight
+{
+    // direction points from the scene towards the light, angular_diameter is in degrees (the sun is about 0.53)
+    pub fn make_new(direction: Vect, irradiance: FColor, angular_diameter: f64) -> Self
+    {
+        let radius = degrees_to_radians(clamp(angular_diameter, 0.0, 180.0) / 2.0);
+        DirectionalLight
+        {
+            direction: direction.get_normalized(),
+            irradiance,
+            cos_radius: radius.cos(),
+        }
+    }
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::random::seed_thread_rand;
+
+    #[test]
+    fn point_light_test()
+    {
+        let light = PointLight::make_new(Point::make_new(0.0, 4.0, 0.0), FColor::new_color(16.0, 32.0, 8.0));
+        let sample = light.sample(&Point::make_new(0.0, 0.0, 0.0)).unwrap();
+        assert_eq!(sample.direction, Vect::make_new(0.0, 1.0, 0.0));
+        assert_eq!(sample.distance, 4.0);
+        assert_eq!(sample.irradiance, FColor::new_color(1.0, 2.0, 0.5));
+        assert!(light.sample(&Point::make_new(0.0, 4.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn spot_light_test()
+    {
+        let light = SpotLight::make_new(Point::make_new(0.0, 1.0, 0.0), Point::make_new(0.0, 0.0, 0.0),
+                                        FColor::new_color(1.0, 1.0, 1.0), 30.0, 45.0);
+        // On the axis
+        assert_eq!(light.sample(&Point::make_new(0.0, 0.0, 0.0)).unwrap().irradiance, FColor::new_color(1.0, 1.0, 1.0));
+        // Inside the inner cone only the distance matters
+        let sample = light.sample(&Point::make_new(0.5, 0.0, 0.0)).unwrap();
+        assert!((sample.irradiance.r() - 1.0 / 1.25).abs() < 1e-9);
+        // Between the cones the light fades
+        let sample = light.sample(&Point::make_new(0.8, 0.0, 0.0)).unwrap();
+        assert!(sample.irradiance.r() > 0.0 && sample.irradiance.r() < 1.0 / 1.64);
+        // Outside the outer cone and behind the light
+        assert!(light.sample(&Point::make_new(1.5, 0.0, 0.0)).is_none());
+        assert!(light.sample(&Point::make_new(0.0, 2.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn directional_light_test()
+    {
+        seed_thread_rand(1);
+        let light = DirectionalLight::make_new(Vect::make_new(0.0, 2.0, 0.0), FColor::new_color(3.0, 3.0, 3.0), 0.0);
+        let sample = light.sample(&Point::make_new(5.0, 0.0, 5.0)).unwrap();
+        assert_eq!(sample.direction, Vect::make_new(0.0, 1.0, 0.0));
+        assert_eq!(sample.distance, INFINITY);
+        assert_eq!(sample.irradiance, FColor::new_color(3.0, 3.0, 3.0));
+
+        let light = DirectionalLight::make_new(Vect::make_new(0.0, 1.0, 0.0), FColor::new_color(3.0, 3.0, 3.0), 10.0);
+        let cos_radius = degrees_to_radians(5.0).cos();
+        let mut spread = false;
+        for _ in 0..100
+        {
+            let direction = light.sample(&Point::make_new(0.0, 0.0, 0.0)).unwrap().direction;
+            assert!((direction.length() - 1.0).abs() < 1e-9);
+            assert!(direction.y() >= cos_radius - 1e-12);
+            spread |= direction.y() < 0.9999;
+        }
+        assert!(spread);
+    }
+}
@@ -1,187 +1,797 @@
-use std::cmp::min_by;
-
-use super::core::*;
-use super::random::SimpleDeterministicRandomGenerator;
-use super::ray::Ray;
-use super::vect::Vect;
-use super::hittable::HitResult;
-use super::super::utils::color::FColor;
-
-pub trait Material
-{
-    fn scatter(&self, ray: &Ray, hit_result: &HitResult) -> Option<(FColor, Ray)>;
-}
-
-#[derive(Clone)]
-pub struct Lambertian
-{
-    albedo: FColor,
-}
-
-impl Material for Lambertian
-{
-    fn scatter(&self, _ray: &Ray, hit_result: &HitResult) -> Option<(FColor, Ray)>
-    {
-        let mut dir = Vect::random_in_hemisphere(hit_result.normal());
-        if dir.is_zero()
-        {
-            dir = *hit_result.normal();
-        }
-        let ray = Ray::make_new(*hit_result.point(), dir);
-
-        Some((self.albedo.clone(), ray))
-    }
-}
-
-impl Lambertian
-{
-    pub const fn default() -> Self
-    {
-        Lambertian{ albedo: FColor{data: [0.4, 0.2, 0.6]} }
-    }
-
-    pub fn new(color: &FColor) -> Self
-    {
-        Lambertian{ albedo: color.clone() }
-    }
-
-    pub fn make_new(color: FColor) -> Self
-    {
-        Lambertian{ albedo: color }
-    }
-}
-
-pub static DEFAULT_LAMBERTIAN: Lambertian = Lambertian::default();
-
-#[derive(Clone)]
-pub struct Metal
-{
-    albedo: FColor,
-    roughness: f64
-}
-
-impl Material for Metal
-{
-    fn scatter(&self, ray: &Ray, hit_result: &HitResult) -> Option<(FColor, Ray)>
-    {
-        let mut direction = Vect::reflect(ray.direction(), hit_result.normal());
-        direction += self.roughness * Vect::random_in_hemisphere(&direction);
-        let ray = Ray::make_new(*hit_result.point(), direction);
-
-        if Vect::dot(ray.direction(), hit_result.normal()) <= 0.0
-        {
-            return None;
-        }
-
-        Some((self.albedo.clone(), ray))
-    }
-}
-
-impl Metal
-{
-    pub const fn default() -> Self
-    {
-        Metal{ albedo: FColor{data: [0.4, 0.2, 0.6]}, roughness: 0.0 }
-    }
-
-    pub fn new(color: &FColor, roughness: f64) -> Self
-    {
-        Metal{ albedo: color.clone(), roughness: clamp(roughness, 0.0, 1.0) }
-    }
-
-    pub fn make_new(color: FColor, roughness: f64) -> Self
-    {
-        Metal{ albedo: color, roughness: clamp(roughness, 0.0, 1.0) }
-    }
-}
-
-pub static DEFAULT_METAL: Metal = Metal::default();
-
-#[derive(Clone)]
-pub struct Dielectric
-{
-    index_of_refraction: f64,
-}
-
-impl Material for Dielectric
-{
-    fn scatter(&self, ray: &Ray, hit_result: &HitResult) -> Option<(FColor, Ray)>
-    {
-        let mut rand_gen = SimpleDeterministicRandomGenerator::new();
-        let mut refraction_ratio = self.index_of_refraction;
-        if hit_result.front_face()
-        {
-            refraction_ratio = 1.0 / refraction_ratio;
-        }
-
-        let cos_theta = min_by(Vect::dot(&-ray.direction(), &hit_result.normal()), 1.0, |a, b| a.partial_cmp(b).unwrap());
-        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-
-        let mut direction = Vect::refract(ray.direction(), hit_result.normal(), refraction_ratio);
-
-        if (refraction_ratio * sin_theta > 1.0) || (Dielectric::reflectance(cos_theta, refraction_ratio) > rand_gen.rand())
-        {
-            direction = Vect::reflect(ray.direction(), hit_result.normal());
-        }
-        
-        direction.normalize();
-
-        Some((FColor::make_new(1.0, 1.0, 1.0), Ray::make_new(*hit_result.point(), direction)))
-    }
-}
-
-impl Dielectric
-{
-    pub const fn default() -> Self
-    {
-        Dielectric{ index_of_refraction: 1.5 }
-    }
-
-    pub fn new(index_of_refraction: f64) -> Self
-    {
-        Dielectric{ index_of_refraction }
-    }
-
-    pub fn make_new(index_of_refraction: f64) -> Self
-    {
-        Dielectric{ index_of_refraction }
-    }
-
-    fn reflectance(cos: f64, ir: f64) -> f64
-    {
-        let mut r0 = (1.0 - ir) / (1.0 + ir);
-        r0 = r0 * r0;
-        r0 + (1.0 - r0) * (1.0 - cos).powf(5.0)
-    }
-}
-
-pub static DEFAULT_DIELECTRIC: Dielectric = Dielectric::default();
-
-//============================================
-//============================================
-//===============Unit Tests===================
-//============================================
-//============================================
-
-#[cfg(test)]
-mod tests
-{
-    use super::*;
-    use std::sync::Arc;
-
-    #[test]
-    fn creation_test()
-    {
-        let orig = Vect{data:[1f64, 2f64, 3f64]};
-        let dir = Vect{data:[0f64, 0f64, 1f64]};
-        let r = Ray::make_new(orig, dir);
-
-        let p = Vect{data:[1f64, 2f64, 4f64]};
-        let n = Vect{data:[0f64, -1f64, -1f64]};
-        let mat = Arc::new(Dielectric::make_new(2.0));
-        let hr = HitResult::make_new(p, n, mat, 1.0, &r);
-        let (col, scat) = hr.material().scatter(&r, &hr).unwrap();
-        println!("{}\n {}\n {}", col, scat.origin(), scat.direction());
-        assert_eq!(2, 3);
-    }
-}
\ No newline at end of file
+use std::cmp::min_by;
+use std::sync::Arc;
+
+use super::core::*;
+use super::random::thread_rand;
+use super::ray::Ray;
+use super::vect::Vect;
+use super::hittable::HitResult;
+use super::microfacet::*;
+use super::texture::*;
+use super::super::utils::color::FColor;
+use super::super::utils::spectrum::*;
+
+pub trait Material: Send + Sync
+{
+    fn scatter(&self, ray: &Ray, hit_result: &HitResult) -> Option<(FColor, Ray)>;
+
+    // Radiance given off at the hit point towards the ray's origin, most materials don't emit any
+    fn emitted(&self, _ray: &Ray, _hit_result: &HitResult) -> FColor
+    {
+        FColor::make_new(0.0, 0.0, 0.0)
+    }
+
+    // Whether hittables made of this material belong in the light list
+    fn is_emissive(&self) -> bool
+    {
+        false
+    }
+
+    // Directions below are unit vectors pointing away from the hit point: wi towards the light and wo towards the viewer.
+
+    // Reflected radiance towards wo per unit of radiance arriving from wi, the BSDF times the cosine at the surface
+    fn eval(&self, _wi: &Vect, _wo: &Vect, _hit_result: &HitResult) -> FColor
+    {
+        FColor::make_new(0.0, 0.0, 0.0)
+    }
+
+    // Solid angle density of scatter() picking wi when seen from wo
+    fn pdf(&self, _wi: &Vect, _wo: &Vect, _hit_result: &HitResult) -> f64
+    {
+        0.0
+    }
+
+    // Perfect mirrors and glass only scatter in single directions, which light sampling can never hit
+    fn is_specular(&self) -> bool
+    {
+        false
+    }
+
+    // Phase functions of media, which scatter at points inside a volume rather than on a surface.
+    // Light reaches those from every side, so the normal at the hit point means nothing for them.
+    fn is_volumetric(&self) -> bool
+    {
+        false
+    }
+
+    // Surfaces that let light through, which can then reach the hit point from below the surface
+    fn is_transmissive(&self, _hit_result: &HitResult) -> bool
+    {
+        false
+    }
+
+    // Versions of scatter() and eval() for spectral paths, giving values at the path's wavelengths.
+    // By default the RGB results are turned into spectra, materials that depend on the wavelength override them.
+    fn scatter_spectral(&self, ray: &Ray, hit_result: &HitResult, wavelengths: &mut SampledWavelengths) -> Option<(SampledSpectrum, Ray)>
+    {
+        let (attenuation, scattered) = self.scatter(ray, hit_result)?;
+        Some((wavelengths.upsample(&attenuation), scattered))
+    }
+
+    fn eval_spectral(&self, wi: &Vect, wo: &Vect, hit_result: &HitResult, wavelengths: &SampledWavelengths) -> SampledSpectrum
+    {
+        wavelengths.upsample(&self.eval(wi, wo, hit_result))
+    }
+}
+
+// Texture color at the hit point
+pub fn texture_value(texture: &dyn Texture, hit_result: &HitResult) -> FColor
+{
+    let (u, v) = hit_result.uv();
+    texture.value(u, v, hit_result.point())
+}
+
+// Local frame around the shading normal, lined up with the surface tangent when there is one
+pub fn shading_frame(hit_result: &HitResult) -> Frame
+{
+    Frame::make_with_tangent(*hit_result.shading_normal(), hit_result.tangent())
+}
+
+// Index of the far side over the index of the side the normal faces
+pub fn relative_index(index_of_refraction: f64, hit_result: &HitResult) -> f64
+{
+    if hit_result.front_face() { index_of_refraction } else { 1.0 / index_of_refraction }
+}
+
+// Glossy reflection off GGX microfacets shared by the metals, fresnel gives the reflectance
+// for the cosine between wo and the microfacet normal
+fn microfacet_scatter(alpha: f64, ray: &Ray, hit_result: &HitResult, fresnel: impl Fn(f64) -> FColor) -> Option<(FColor, Ray)>
+{
+    let frame = shading_frame(hit_result);
+    let wo = frame.to_local(&-ray.direction());
+    if wo.z() <= 0.0
+    {
+        return None;
+    }
+
+    let (wi, weight) = if alpha < MIN_ALPHA
+    {
+        (reflect(&wo, &Vect::make_new(0.0, 0.0, 1.0)), fresnel(wo.z()))
+    }
+    else
+    {
+        let ggx = Ggx::make_new(alpha);
+        let m = ggx.sample_visible_normal(&wo, thread_rand(), thread_rand());
+        let wi = reflect(&wo, &m);
+        if wi.z() <= 0.0
+        {
+            return None;
+        }
+        // D and the visible normal density cancel out
+        (wi, fresnel(Vect::dot(&wo, &m)) * (ggx.g(&wo, &wi) / ggx.g1(&wo)))
+    };
+
+    let direction = frame.to_world(&wi);
+    if Vect::dot(&direction, hit_result.normal()) <= 0.0
+    {
+        return None;
+    }
+    Some((weight, Ray::make_new(*hit_result.point(), direction)))
+}
+
+fn microfacet_eval(alpha: f64, wi: &Vect, wo: &Vect, hit_result: &HitResult, fresnel: impl Fn(f64) -> FColor) -> FColor
+{
+    let frame = shading_frame(hit_result);
+    let (wi, wo) = (frame.to_local(wi), frame.to_local(wo));
+    if alpha < MIN_ALPHA || wi.z() <= 0.0 || wo.z() <= 0.0
+    {
+        return FColor::make_new(0.0, 0.0, 0.0);
+    }
+    let ggx = Ggx::make_new(alpha);
+    let m = (wi + wo).get_normalized();
+    // Cook-Torrance D G F / (4 cos_i cos_o), times cos_i
+    fresnel(Vect::dot(&wo, &m)) * (ggx.d(&m) * ggx.g(&wo, &wi) / (4.0 * wo.z()))
+}
+
+fn microfacet_pdf(alpha: f64, wi: &Vect, wo: &Vect, hit_result: &HitResult) -> f64
+{
+    let frame = shading_frame(hit_result);
+    let (wi, wo) = (frame.to_local(wi), frame.to_local(wo));
+    if alpha < MIN_ALPHA || wi.z() <= 0.0 || wo.z() <= 0.0
+    {
+        return 0.0;
+    }
+    let m = (wi + wo).get_normalized();
+    Ggx::make_new(alpha).visible_normal_pdf(&wo, &m) / (4.0 * Vect::dot(&wo, &m))
+}
+
+#[derive(Clone)]
+pub struct Lambertian
+{
+    albedo: Arc<dyn Texture>,
+}
+
+impl Material for Lambertian
+{
+    fn scatter(&self, _ray: &Ray, hit_result: &HitResult) -> Option<(FColor, Ray)>
+    {
+        let dir = Vect::random_cosine_direction(hit_result.shading_normal());
+        let ray = Ray::make_new(*hit_result.point(), dir);
+
+        Some((texture_value(self.albedo.as_ref(), hit_result), ray))
+    }
+
+    fn eval(&self, wi: &Vect, _wo: &Vect, hit_result: &HitResult) -> FColor
+    {
+        let cosine = Vect::dot(wi, hit_result.shading_normal());
+        if cosine <= 0.0
+        {
+            return FColor::make_new(0.0, 0.0, 0.0);
+        }
+        texture_value(self.albedo.as_ref(), hit_result) * (cosine / PI)
+    }
+
+    fn pdf(&self, wi: &Vect, _wo: &Vect, hit_result: &HitResult) -> f64
+    {
+        Vect::dot(wi, hit_result.shading_normal()).max(0.0) / PI
+    }
+}
+
+impl Lambertian
+{
+    pub fn default() -> Self
+    {
+        Lambertian::make_new(FColor{data: [0.4, 0.2, 0.6]})
+    }
+
+    pub fn new(color: &FColor) -> Self
+    {
+        Lambertian::make_new(*color)
+    }
+
+    pub fn make_new(color: FColor) -> Self
+    {
+        Lambertian{ albedo: Arc::new(SolidColor::make_new(color)) }
+    }
+
+    pub fn make_textured(albedo: Arc<dyn Texture>) -> Self
+    {
+        Lambertian{ albedo }
+    }
+}
+
+// Conductor whose reflectance at normal incidence is the albedo, with Schlick's Fresnel approximation.
+// The roughness (0 is a mirror) sets the width of the GGX microfacet distribution.
+#[derive(Clone)]
+pub struct Metal
+{
+    albedo: Arc<dyn Texture>,
+    alpha: f64,
+}
+
+impl Material for Metal
+{
+    fn scatter(&self, ray: &Ray, hit_result: &HitResult) -> Option<(FColor, Ray)>
+    {
+        let albedo = texture_value(self.albedo.as_ref(), hit_result);
+        microfacet_scatter(self.alpha, ray, hit_result, |cosine| fresnel_schlick(cosine, &albedo))
+    }
+
+    fn eval(&self, wi: &Vect, wo: &Vect, hit_result: &HitResult) -> FColor
+    {
+        let albedo = texture_value(self.albedo.as_ref(), hit_result);
+        microfacet_eval(self.alpha, wi, wo, hit_result, |cosine| fresnel_schlick(cosine, &albedo))
+    }
+
+    fn pdf(&self, wi: &Vect, wo: &Vect, hit_result: &HitResult) -> f64
+    {
+        microfacet_pdf(self.alpha, wi, wo, hit_result)
+    }
+
+    fn is_specular(&self) -> bool
+    {
+        self.alpha < MIN_ALPHA
+    }
+}
+
+impl Metal
+{
+    pub fn default() -> Self
+    {
+        Metal::make_new(FColor{data: [0.4, 0.2, 0.6]}, 0.0)
+    }
+
+    pub fn new(color: &FColor, roughness: f64) -> Self
+    {
+        Metal::make_new(*color, roughness)
+    }
+
+    pub fn make_new(color: FColor, roughness: f64) -> Self
+    {
+        Metal::make_textured(Arc::new(SolidColor::make_new(color)), roughness)
+    }
+
+    pub fn make_textured(albedo: Arc<dyn Texture>, roughness: f64) -> Self
+    {
+        Metal{ albedo, alpha: roughness_to_alpha(roughness) }
+    }
+}
+
+// Metal described by its complex index of refraction eta + i k, which gives the exact Fresnel reflectance
+#[derive(Clone)]
+pub struct Conductor
+{
+    eta: FColor,
+    k: FColor,
+    alpha: f64,
+}
+
+impl Material for Conductor
+{
+    fn scatter(&self, ray: &Ray, hit_result: &HitResult) -> Option<(FColor, Ray)>
+    {
+        microfacet_scatter(self.alpha, ray, hit_result, |cosine| fresnel_conductor(cosine, &self.eta, &self.k))
+    }
+
+    fn eval(&self, wi: &Vect, wo: &Vect, hit_result: &HitResult) -> FColor
+    {
+        microfacet_eval(self.alpha, wi, wo, hit_result, |cosine| fresnel_conductor(cosine, &self.eta, &self.k))
+    }
+
+    fn pdf(&self, wi: &Vect, wo: &Vect, hit_result: &HitResult) -> f64
+    {
+        microfacet_pdf(self.alpha, wi, wo, hit_result)
+    }
+
+    fn is_specular(&self) -> bool
+    {
+        self.alpha < MIN_ALPHA
+    }
+}
+
+impl Conductor
+{
+    pub fn new(eta: &FColor, k: &FColor, roughness: f64) -> Self
+    {
+        Conductor::make_new(*eta, *k, roughness)
+    }
+
+    pub fn make_new(eta: FColor, k: FColor, roughness: f64) -> Self
+    {
+        Conductor{ eta, k, alpha: roughness_to_alpha(roughness) }
+    }
+
+    // One of the metals in CONDUCTOR_PRESETS, e.g. "gold"
+    pub fn from_preset(name: &str, roughness: f64) -> Option<Self>
+    {
+        let (eta, k) = conductor_preset(name)?;
+        Some(Conductor::make_new(eta, k, roughness))
+    }
+}
+
+// Index of refraction as a function of the wavelength
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RefractiveIndex
+{
+    Constant(f64),
+    // n = a + b / wavelength^2, with the wavelength in micrometers
+    Cauchy{ a: f64, b: f64 },
+    // n^2 = 1 + sum of b_i wavelength^2 / (wavelength^2 - c_i), with the wavelength in micrometers
+    Sellmeier{ b: [f64; 3], c: [f64; 3] },
+}
+
+// The sodium D line, where catalogs quote the index of a glass
+pub const REFERENCE_WAVELENGTH: f64 = 589.3;
+
+impl RefractiveIndex
+{
+    // Index at a wavelength in nanometers
+    pub fn at(&self, wavelength: f64) -> f64
+    {
+        let micrometers2 = (wavelength * 1e-3) * (wavelength * 1e-3);
+        match self
+        {
+            RefractiveIndex::Constant(index) => *index,
+            RefractiveIndex::Cauchy{ a, b } => a + b / micrometers2,
+            RefractiveIndex::Sellmeier{ b, c } =>
+            {
+                let sum: f64 = (0..3).map(|ind| b[ind] * micrometers2 / (micrometers2 - c[ind])).sum();
+                (1.0 + sum).max(1.0).sqrt()
+            }
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool
+    {
+        !matches!(self, RefractiveIndex::Constant(_))
+    }
+
+    // Sellmeier fits of common optical materials: "bk7", "fused_silica", "diamond" and "sapphire"
+    pub fn from_preset(name: &str) -> Option<Self>
+    {
+        let (b, c) = match name
+        {
+            "bk7" => ([1.03961212, 0.231792344, 1.01046945], [0.00600069867, 0.0200179144, 103.560653]),
+            "fused_silica" => ([0.6961663, 0.4079426, 0.8974794], [0.00467914826, 0.0135120631, 97.9340025]),
+            "diamond" => ([0.3306, 4.3356, 0.0], [0.030625, 0.011236, 0.0]),
+            // Ordinary ray
+            "sapphire" => ([1.4313493, 0.65054713, 5.3414021], [0.00527992610, 0.0142382647, 325.017834]),
+            _ => return None,
+        };
+        Some(RefractiveIndex::Sellmeier{ b, c })
+    }
+}
+
+// Smooth glass. Light travelling inside is absorbed following the Beer-Lambert law, with the absorption
+// coefficient giving the fraction lost per unit of distance for each channel. Dispersive indices split
+// white light when the renderer traces single wavelengths, other rays use the reference wavelength.
+#[derive(Clone)]
+pub struct Dielectric
+{
+    index_of_refraction: RefractiveIndex,
+    absorption: FColor,
+}
+
+impl Material for Dielectric
+{
+    fn scatter(&self, ray: &Ray, hit_result: &HitResult) -> Option<(FColor, Ray)>
+    {
+        let mut refraction_ratio = self.index_of_refraction.at(ray.wavelength().unwrap_or(REFERENCE_WAVELENGTH));
+        if hit_result.front_face()
+        {
+            refraction_ratio = 1.0 / refraction_ratio;
+        }
+
+        let normal = hit_result.shading_normal();
+        let cos_theta = min_by(Vect::dot(&-ray.direction(), normal), 1.0, |a, b| a.partial_cmp(b).unwrap());
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+        let mut direction = Vect::refract(ray.direction(), normal, refraction_ratio);
+
+        if (refraction_ratio * sin_theta > 1.0) || (Dielectric::reflectance(cos_theta, refraction_ratio) > thread_rand())
+        {
+            direction = Vect::reflect(ray.direction(), normal);
+        }
+        
+        direction.normalize();
+
+        Some((beer_lambert(&self.absorption, hit_result), Ray::make_new(*hit_result.point(), direction)))
+    }
+
+    // The direction picked for the hero wavelength is wrong for the others when the index depends on the
+    // wavelength, and the absorption is evaluated for each wavelength
+    fn scatter_spectral(&self, ray: &Ray, hit_result: &HitResult, wavelengths: &mut SampledWavelengths) -> Option<(SampledSpectrum, Ray)>
+    {
+        if self.index_of_refraction.is_dispersive()
+        {
+            wavelengths.terminate_secondary();
+        }
+        let (_, scattered) = self.scatter(ray, hit_result)?;
+        let mut attenuation = SampledSpectrum{ data: [1.0; WAVELENGTH_COUNT] };
+        if !hit_result.front_face()
+        {
+            attenuation = wavelengths.upsample(&self.absorption);
+            for value in attenuation.data.iter_mut()
+            {
+                *value = (-*value * hit_result.t()).exp();
+            }
+        }
+        Some((attenuation, scattered))
+    }
+
+    fn is_specular(&self) -> bool
+    {
+        true
+    }
+
+    fn is_transmissive(&self, _hit_result: &HitResult) -> bool
+    {
+        true
+    }
+}
+
+impl Dielectric
+{
+    pub const fn default() -> Self
+    {
+        Dielectric{ index_of_refraction: RefractiveIndex::Constant(1.5), absorption: FColor{data: [0.0, 0.0, 0.0]} }
+    }
+
+    pub fn new(index_of_refraction: f64) -> Self
+    {
+        Dielectric::make_new(index_of_refraction)
+    }
+
+    pub fn make_new(index_of_refraction: f64) -> Self
+    {
+        Dielectric::make_absorbing(RefractiveIndex::Constant(index_of_refraction), FColor::make_new(0.0, 0.0, 0.0))
+    }
+
+    pub fn make_absorbing(index_of_refraction: RefractiveIndex, absorption: FColor) -> Self
+    {
+        Dielectric{ index_of_refraction, absorption }
+    }
+
+    fn reflectance(cos: f64, ir: f64) -> f64
+    {
+        let mut r0 = (1.0 - ir) / (1.0 + ir);
+        r0 = r0 * r0;
+        r0 + (1.0 - r0) * (1.0 - cos).powf(5.0)
+    }
+}
+
+// Fraction of the light left after crossing the medium up to the hit point, when the ray hit the surface from inside
+fn beer_lambert(absorption: &FColor, hit_result: &HitResult) -> FColor
+{
+    if hit_result.front_face()
+    {
+        return FColor::make_new(1.0, 1.0, 1.0);
+    }
+    let distance = hit_result.t();
+    FColor::make_new((-absorption.r() * distance).exp(), (-absorption.g() * distance).exp(), (-absorption.b() * distance).exp())
+}
+
+pub static DEFAULT_DIELECTRIC: Dielectric = Dielectric::default();
+
+// Frosted glass: GGX microfacets that reflect or refract (Walter et al., "Microfacet Models for
+// Refraction through Rough Surfaces", 2007), picking between the two with the Fresnel reflectance
+#[derive(Clone)]
+pub struct RoughDielectric
+{
+    index_of_refraction: f64,
+    alpha: f64,
+    // Beer-Lambert absorption coefficient, as for Dielectric
+    absorption: FColor,
+}
+
+impl RoughDielectric
+{
+    pub fn new(index_of_refraction: f64, roughness: f64) -> Self
+    {
+        RoughDielectric::make_new(index_of_refraction, roughness)
+    }
+
+    pub fn make_new(index_of_refraction: f64, roughness: f64) -> Self
+    {
+        RoughDielectric::make_absorbing(index_of_refraction, roughness, FColor::make_new(0.0, 0.0, 0.0))
+    }
+
+    pub fn make_absorbing(index_of_refraction: f64, roughness: f64, absorption: FColor) -> Self
+    {
+        RoughDielectric{ index_of_refraction, alpha: roughness_to_alpha(roughness), absorption }
+    }
+
+    fn interface(&self, hit_result: &HitResult) -> DielectricInterface
+    {
+        DielectricInterface::make_new(relative_index(self.index_of_refraction, hit_result), self.alpha, self.alpha)
+    }
+}
+
+impl Material for RoughDielectric
+{
+    fn scatter(&self, ray: &Ray, hit_result: &HitResult) -> Option<(FColor, Ray)>
+    {
+        let frame = shading_frame(hit_result);
+        let (wi, weight) = self.interface(hit_result).sample(&frame.to_local(&-ray.direction()), thread_rand(), thread_rand(), thread_rand())?;
+        Some((beer_lambert(&self.absorption, hit_result) * weight, Ray::make_new(*hit_result.point(), frame.to_world(&wi).get_normalized())))
+    }
+
+    fn eval(&self, wi: &Vect, wo: &Vect, hit_result: &HitResult) -> FColor
+    {
+        let frame = shading_frame(hit_result);
+        beer_lambert(&self.absorption, hit_result) * self.interface(hit_result).eval(&frame.to_local(wi), &frame.to_local(wo))
+    }
+
+    fn pdf(&self, wi: &Vect, wo: &Vect, hit_result: &HitResult) -> f64
+    {
+        let frame = shading_frame(hit_result);
+        self.interface(hit_result).pdf(&frame.to_local(wi), &frame.to_local(wo))
+    }
+
+    fn is_specular(&self) -> bool
+    {
+        self.alpha < MIN_ALPHA
+    }
+
+    fn is_transmissive(&self, _hit_result: &HitResult) -> bool
+    {
+        true
+    }
+}
+
+// Emits light equally in all directions from both sides of the surface and doesn't reflect any
+#[derive(Clone)]
+pub struct DiffuseLight
+{
+    emit: FColor,
+}
+
+impl Material for DiffuseLight
+{
+    fn scatter(&self, _ray: &Ray, _hit_result: &HitResult) -> Option<(FColor, Ray)>
+    {
+        None
+    }
+
+    fn emitted(&self, _ray: &Ray, _hit_result: &HitResult) -> FColor
+    {
+        self.emit
+    }
+
+    fn is_emissive(&self) -> bool
+    {
+        true
+    }
+}
+
+impl DiffuseLight
+{
+    pub const fn default() -> Self
+    {
+        DiffuseLight{ emit: FColor{data: [1.0, 1.0, 1.0]} }
+    }
+
+    pub fn new(color: &FColor) -> Self
+    {
+        DiffuseLight{ emit: *color }
+    }
+
+    pub fn make_new(color: FColor) -> Self
+    {
+        DiffuseLight{ emit: color }
+    }
+}
+
+pub static DEFAULT_DIFFUSE_LIGHT: DiffuseLight = DiffuseLight::default();
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::sync::Arc;
+    use super::super::random::seed_thread_rand;
+
+    #[test]
+    fn creation_test()
+    {
+        let orig = Vect{data:[1f64, 2f64, 3f64]};
+        let dir = Vect{data:[0f64, 0f64, 1f64]};
+        let r = Ray::make_new(orig, dir);
+
+        let p = Vect{data:[1f64, 2f64, 4f64]};
+        let n = Vect{data:[0f64, -1f64, -1f64]};
+        let mat = Arc::new(Dielectric::make_new(2.0));
+        let hr = HitResult::make_new(p, n, mat, 1.0, &r);
+        let (col, scat) = hr.material().scatter(&r, &hr).unwrap();
+        assert_eq!(col, Vect{data:[1f64, 1f64, 1f64]});
+        assert_eq!(*scat.origin(), p);
+        assert!((scat.direction().length() - 1f64).abs() < 1e-9);
+    }
+
+    #[test]
+    fn emission_test()
+    {
+        let r = Ray::make_new(Vect::make_new(0.0, 0.0, 1.0), Vect::make_new(0.0, 0.0, -1.0));
+        let light = Arc::new(DiffuseLight::make_new(FColor::new_color(4.0, 4.0, 2.0)));
+        let hr = HitResult::make_new(Vect::make_new(0.0, 0.0, 0.0), Vect::make_new(0.0, 0.0, 1.0), light, 1.0, &r);
+        assert_eq!(hr.material().emitted(&r, &hr), FColor::new_color(4.0, 4.0, 2.0));
+        assert!(hr.material().scatter(&r, &hr).is_none());
+
+        let diffuse = Arc::new(Lambertian::default());
+        let hr = HitResult::make_new(Vect::make_new(0.0, 0.0, 0.0), Vect::make_new(0.0, 0.0, 1.0), diffuse, 1.0, &r);
+        assert!(hr.material().emitted(&r, &hr).is_zero());
+    }
+
+    #[test]
+    fn eval_pdf_test()
+    {
+        seed_thread_rand(9);
+        let r = Ray::make_new(Vect::make_new(1.0, 0.0, 1.0), Vect::make_new(-1.0, 0.0, -1.0));
+        let wo = -r.direction();
+        let materials: [Arc<dyn Material>; 5] = [Arc::new(Lambertian::make_new(FColor::new_color(0.5, 0.25, 1.0))),
+                                                 Arc::new(Metal::make_new(FColor::new_color(0.9, 0.8, 0.7), 0.3)),
+                                                 Arc::new(Metal::make_new(FColor::new_color(0.9, 0.8, 0.7), 1.0)),
+                                                 Arc::new(Conductor::from_preset("gold", 0.2).unwrap()),
+                                                 Arc::new(Conductor::from_preset("aluminum", 0.7).unwrap())];
+        for material in materials
+        {
+            assert!(!material.is_specular());
+            let hr = HitResult::make_new(Vect::make_new(0.0, 0.0, 0.0), Vect::make_new(0.0, 0.0, 1.0), material, 1.0, &r);
+            // The attenuation scatter() reports is eval over pdf for the direction it picked
+            for _ in 0..100
+            {
+                let Some((attenuation, scattered)) = hr.material().scatter(&r, &hr) else { continue };
+                let pdf = hr.material().pdf(scattered.direction(), &wo, &hr);
+                assert!(pdf > 0.0);
+                let ratio = hr.material().eval(scattered.direction(), &wo, &hr) / pdf;
+                assert!((ratio - attenuation).length() < 1e-9);
+            }
+            // Nothing is reflected below the surface
+            assert!(hr.material().eval(&Vect::make_new(0.0, 0.0, -1.0), &wo, &hr).is_zero());
+        }
+
+        assert!(Metal::make_new(FColor::new_color(1.0, 1.0, 1.0), 0.0).is_specular());
+        assert!(Dielectric::default().is_specular());
+        assert!(Conductor::from_preset("copper", 0.0).unwrap().is_specular());
+        assert!(Conductor::from_preset("unobtainium", 0.5).is_none());
+    }
+
+    #[test]
+    fn rough_dielectric_test()
+    {
+        seed_thread_rand(23);
+        let material: Arc<dyn Material> = Arc::new(RoughDielectric::make_new(1.5, 0.4));
+        assert!(!material.is_specular());
+        assert!(RoughDielectric::make_new(1.5, 0.0).is_specular());
+
+        // Entering the glass from outside, then leaving it from inside
+        for direction in [Vect::make_new(-1.0, 0.0, -2.0), Vect::make_new(-1.0, 0.0, 2.0)]
+        {
+            let r = Ray::make_new(Vect::make_new(0.0, 0.0, 0.0) - direction, direction);
+            let wo = -r.direction();
+            let hr = HitResult::make_new(Vect::make_new(0.0, 0.0, 0.0), Vect::make_new(0.0, 0.0, 1.0), material.clone(), 1.0, &r);
+            let eta = if hr.front_face() { 1.5 } else { 1.0 / 1.5 };
+
+            let (mut reflected, mut transmitted, mut total) = (0, 0, 0.0);
+            for _ in 0..2000
+            {
+                let Some((attenuation, scattered)) = hr.material().scatter(&r, &hr) else { continue };
+                let wi = scattered.direction();
+                let pdf = hr.material().pdf(wi, &wo, &hr);
+                assert!(pdf > 0.0);
+                let ratio = hr.material().eval(wi, &wo, &hr) / pdf;
+                assert!((ratio - attenuation).length() < 1e-6 * attenuation.length().max(1.0));
+
+                if Vect::dot(wi, hr.normal()) > 0.0
+                {
+                    reflected += 1;
+                    total += attenuation.r();
+                }
+                else
+                {
+                    transmitted += 1;
+                    // Undo the radiance scaling to count the energy that gets through
+                    total += attenuation.r() * eta * eta;
+                }
+            }
+            assert!(reflected > 0 && transmitted > reflected);
+            // Close to energy conserving, the only loss is light blocked by other microfacets
+            let albedo = total / 2000.0;
+            assert!(albedo > 0.85 && albedo <= 1.02, "albedo {albedo}");
+        }
+    }
+
+    #[test]
+    fn dispersion_test()
+    {
+        // Catalog values at the sodium D line, blue bends more than red
+        let bk7 = RefractiveIndex::from_preset("bk7").unwrap();
+        assert!((bk7.at(REFERENCE_WAVELENGTH) - 1.5168).abs() < 1e-3);
+        assert!((RefractiveIndex::from_preset("diamond").unwrap().at(REFERENCE_WAVELENGTH) - 2.417).abs() < 2e-3);
+        assert!(bk7.at(450.0) > bk7.at(650.0));
+        assert!(RefractiveIndex::from_preset("glass").is_none());
+
+        let cauchy = RefractiveIndex::Cauchy{ a: 1.5, b: 0.01 };
+        assert!((cauchy.at(500.0) - 1.54).abs() < 1e-12);
+        assert!(cauchy.is_dispersive() && !RefractiveIndex::Constant(1.5).is_dispersive());
+
+        // Rays carrying a wavelength refract with the index at that wavelength
+        let prism: Arc<dyn Material> = Arc::new(Dielectric::make_absorbing(RefractiveIndex::Cauchy{ a: 1.5, b: 0.05 }, FColor::new_color(0.0, 0.0, 0.0)));
+        let refracted = |wavelength: f64|
+        {
+            let mut r = Ray::make_new(Vect::make_new(-1.0, 0.0, 1.0), Vect::make_new(1.0, 0.0, -1.0));
+            r.set_wavelength(Some(wavelength));
+            let hr = HitResult::make_new(Vect::make_new(0.0, 0.0, 0.0), Vect::make_new(0.0, 0.0, 1.0), prism.clone(), 1.0, &r);
+            // Fresnel reflection picks the other branch now and then
+            loop
+            {
+                let (_, scattered) = hr.material().scatter(&r, &hr).unwrap();
+                if scattered.direction().z() < 0.0
+                {
+                    return scattered.direction().x();
+                }
+            }
+        };
+        assert!(refracted(420.0) < refracted(680.0));
+    }
+
+    #[test]
+    fn absorption_test()
+    {
+        let absorption = FColor::new_color(0.5, 0.1, 0.0);
+        let materials: [Arc<dyn Material>; 2] = [Arc::new(Dielectric::make_absorbing(RefractiveIndex::Constant(1.5), absorption)),
+                                                 Arc::new(RoughDielectric::make_absorbing(1.5, 0.3, absorption))];
+        for material in materials
+        {
+            // Leaving the glass after 2 units inside, and entering it
+            let r = Ray::make_new(Vect::make_new(0.0, 0.0, -2.0), Vect::make_new(0.0, 0.0, 1.0));
+            let hr = HitResult::make_new(Vect::make_new(0.0, 0.0, 0.0), Vect::make_new(0.0, 0.0, 1.0), material.clone(), 2.0, &r);
+            let expected = FColor::new_color((-1.0f64).exp(), (-0.2f64).exp(), 1.0);
+            let (attenuation, _) = (0..100).find_map(|_| hr.material().scatter(&r, &hr)).unwrap();
+            assert!((attenuation / attenuation.b() - expected).length() < 1e-9);
+
+            let r = Ray::make_new(Vect::make_new(0.0, 0.0, 2.0), Vect::make_new(0.0, 0.0, -1.0));
+            let hr = HitResult::make_new(Vect::make_new(0.0, 0.0, 0.0), Vect::make_new(0.0, 0.0, 1.0), material, 2.0, &r);
+            let (attenuation, _) = (0..100).find_map(|_| hr.material().scatter(&r, &hr)).unwrap();
+            assert!(attenuation.r() == attenuation.b() && attenuation.g() == attenuation.b());
+        }
+    }
+
+    #[test]
+    fn texture_test()
+    {
+        let white: Arc<dyn Texture> = Arc::new(SolidColor::make_new(FColor::new_color(1.0, 1.0, 1.0)));
+        let red: Arc<dyn Texture> = Arc::new(SolidColor::make_new(FColor::new_color(1.0, 0.0, 0.0)));
+        let checker: Arc<dyn Texture> = Arc::new(CheckerTexture::make_new(white, red, 1.0));
+        let materials: [Arc<dyn Material>; 2] = [Arc::new(Lambertian::make_textured(checker.clone())), Arc::new(Metal::make_textured(checker, 0.5))];
+
+        let r = Ray::make_new(Vect::make_new(0.5, 0.5, 1.0), Vect::make_new(0.0, 0.0, -1.0));
+        let wi = Vect::make_new(0.0, 0.0, 1.0);
+        for material in materials
+        {
+            // The albedo follows the texture at the hit point
+            let hr = HitResult::make_new(Vect::make_new(0.5, 0.5, 0.5), Vect::make_new(0.0, 0.0, 1.0), material.clone(), 0.5, &r);
+            let even = hr.material().eval(&wi, &wi, &hr);
+            let hr = HitResult::make_new(Vect::make_new(1.5, 0.5, 0.5), Vect::make_new(0.0, 0.0, 1.0), material, 0.5, &r);
+            let odd = hr.material().eval(&wi, &wi, &hr);
+            assert!(even.g() > 0.0 && even.r() == even.g());
+            assert!(odd.r() == even.r() && odd.g() == 0.0);
+        }
+    }
+}
@@ -0,0 +1,456 @@
+use std::ops::Mul;
+
+use super::core::degrees_to_radians;
+use super::point::Point;
+use super::vect::Vect;
+
+// 4x4 matrix acting on column vectors, so (a * b) applies b first then a.
+// Points are extended with w = 1 and pick up the translation, vectors with w = 0 don't.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Matrix
+{
+    pub data: [[f64; 4]; 4],
+}
+
+impl Matrix
+{
+    pub fn identity() -> Self
+    {
+        Matrix::make_new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Rows from top to bottom
+    pub fn make_new(data: [[f64; 4]; 4]) -> Self
+    {
+        Matrix{ data }
+    }
+
+    pub fn translation(offset: &Vect) -> Self
+    {
+        let mut result = Matrix::identity();
+        for row in 0..3
+        {
+            result.data[row][3] = offset[row];
+        }
+        result
+    }
+
+    pub fn scaling(factors: &Vect) -> Self
+    {
+        let mut result = Matrix::identity();
+        for axis in 0..3
+        {
+            result.data[axis][axis] = factors[axis];
+        }
+        result
+    }
+
+    // Counterclockwise when looking down the axis towards the origin
+    pub fn rotation(axis: &Vect, degrees: f64) -> Self
+    {
+        let axis = axis.get_normalized();
+        let (sin, cos) = degrees_to_radians(degrees).sin_cos();
+        let (x, y, z) = (axis.x(), axis.y(), axis.z());
+        let k = 1.0 - cos;
+        Matrix::make_new([
+            [cos + x * x * k, x * y * k - z * sin, x * z * k + y * sin, 0.0],
+            [y * x * k + z * sin, cos + y * y * k, y * z * k - x * sin, 0.0],
+            [z * x * k - y * sin, z * y * k + x * sin, cos + z * z * k, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Matrix
+    {
+        let mut result = *self;
+        for (row, values) in self.data.iter().enumerate()
+        {
+            for (column, value) in values.iter().enumerate()
+            {
+                result.data[column][row] = *value;
+            }
+        }
+        result
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, None for singular matrices
+    pub fn inverse(&self) -> Option<Matrix>
+    {
+        let mut left = self.data;
+        let mut right = Matrix::identity().data;
+        for column in 0..4
+        {
+            let pivot = (column..4).max_by(|a, b| left[*a][column].abs().total_cmp(&left[*b][column].abs()))?;
+            if left[pivot][column].abs() < 1e-12
+            {
+                return None;
+            }
+            left.swap(column, pivot);
+            right.swap(column, pivot);
+
+            let scale = 1.0 / left[column][column];
+            for index in 0..4
+            {
+                left[column][index] *= scale;
+                right[column][index] *= scale;
+            }
+            for row in 0..4
+            {
+                let factor = left[row][column];
+                if row == column || factor == 0.0
+                {
+                    continue;
+                }
+                for index in 0..4
+                {
+                    left[row][index] -= factor * left[column][index];
+                    right[row][index] -= factor * right[column][index];
+                }
+            }
+        }
+        Some(Matrix::make_new(right))
+    }
+
+    // Determinant of the upper 3x3 part, how much the matrix scales volumes
+    pub fn determinant3(&self) -> f64
+    {
+        let m = &self.data;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn transform_point(&self, point: &Point) -> Point
+    {
+        let m = &self.data;
+        let mut result = Point::new();
+        for row in 0..3
+        {
+            result[row] = m[row][0] * point.x() + m[row][1] * point.y() + m[row][2] * point.z() + m[row][3];
+        }
+        let w = m[3][0] * point.x() + m[3][1] * point.y() + m[3][2] * point.z() + m[3][3];
+        if w != 1.0 && w != 0.0
+        {
+            result /= w;
+        }
+        result
+    }
+
+    pub fn transform_vector(&self, vector: &Vect) -> Vect
+    {
+        let m = &self.data;
+        let mut result = Vect::new();
+        for row in 0..3
+        {
+            result[row] = m[row][0] * vector.x() + m[row][1] * vector.y() + m[row][2] * vector.z();
+        }
+        result
+    }
+
+    // Normals follow the inverse transpose of the matrix that moves the surface.
+    // Called on that inverse, this multiplies by its transpose without building it.
+    pub fn transform_normal(&self, normal: &Vect) -> Vect
+    {
+        let m = &self.data;
+        let mut result = Vect::new();
+        for column in 0..3
+        {
+            result[column] = m[0][column] * normal.x() + m[1][column] * normal.y() + m[2][column] * normal.z();
+        }
+        result
+    }
+}
+
+impl Matrix
+{
+    // Splits an affine matrix into translation * rotation * stretch, with the scaling and shearing in the stretch.
+    // The rotation is the closest one to the upper 3x3 part, found by polar decomposition; mirroring goes to the stretch.
+    pub fn decompose(&self) -> (Vect, Quaternion, Matrix)
+    {
+        let translation = Vect::make_new(self.data[0][3], self.data[1][3], self.data[2][3]);
+        let mut linear = *self;
+        for index in 0..3
+        {
+            linear.data[index][3] = 0.0;
+            linear.data[3][index] = 0.0;
+        }
+        linear.data[3][3] = 1.0;
+
+        // Averaging with the inverse transpose converges to the orthogonal factor
+        let mut rotation = linear;
+        for _ in 0..100
+        {
+            let Some(inverse) = rotation.inverse() else { break };
+            let inverse_transpose = inverse.transpose();
+            let mut change: f64 = 0.0;
+            for row in 0..3
+            {
+                for column in 0..3
+                {
+                    let average = 0.5 * (rotation.data[row][column] + inverse_transpose.data[row][column]);
+                    change = change.max((average - rotation.data[row][column]).abs());
+                    rotation.data[row][column] = average;
+                }
+            }
+            if change < 1e-12
+            {
+                break;
+            }
+        }
+        if rotation.determinant3() < 0.0
+        {
+            for row in 0..3
+            {
+                for column in 0..3
+                {
+                    rotation.data[row][column] = -rotation.data[row][column];
+                }
+            }
+        }
+        let stretch = rotation.transpose() * linear;
+        (translation, Quaternion::from_matrix(&rotation), stretch)
+    }
+}
+
+// Unit quaternion standing for a rotation, the form in which rotations blend smoothly
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Quaternion
+{
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub w: f64,
+}
+
+impl Quaternion
+{
+    pub fn identity() -> Self
+    {
+        Quaternion{ x: 0.0, y: 0.0, z: 0.0, w: 1.0 }
+    }
+
+    // From the upper 3x3 part of a rotation matrix
+    pub fn from_matrix(matrix: &Matrix) -> Self
+    {
+        let m = &matrix.data;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        let result = if trace > 0.0
+        {
+            let s = 2.0 * (trace + 1.0).sqrt();
+            Quaternion{ x: (m[2][1] - m[1][2]) / s, y: (m[0][2] - m[2][0]) / s, z: (m[1][0] - m[0][1]) / s, w: 0.25 * s }
+        }
+        else if m[0][0] > m[1][1] && m[0][0] > m[2][2]
+        {
+            let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+            Quaternion{ x: 0.25 * s, y: (m[0][1] + m[1][0]) / s, z: (m[0][2] + m[2][0]) / s, w: (m[2][1] - m[1][2]) / s }
+        }
+        else if m[1][1] > m[2][2]
+        {
+            let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+            Quaternion{ x: (m[0][1] + m[1][0]) / s, y: 0.25 * s, z: (m[1][2] + m[2][1]) / s, w: (m[0][2] - m[2][0]) / s }
+        }
+        else
+        {
+            let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+            Quaternion{ x: (m[0][2] + m[2][0]) / s, y: (m[1][2] + m[2][1]) / s, z: 0.25 * s, w: (m[1][0] - m[0][1]) / s }
+        };
+        result.normalized()
+    }
+
+    pub fn to_matrix(self) -> Matrix
+    {
+        let Quaternion{ x, y, z, w } = self;
+        Matrix::make_new([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - z * w), 2.0 * (x * z + y * w), 0.0],
+            [2.0 * (x * y + z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - x * w), 0.0],
+            [2.0 * (x * z - y * w), 2.0 * (y * z + x * w), 1.0 - 2.0 * (x * x + y * y), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn dot(a: &Quaternion, b: &Quaternion) -> f64
+    {
+        a.x * b.x + a.y * b.y + a.z * b.z + a.w * b.w
+    }
+
+    pub fn normalized(&self) -> Quaternion
+    {
+        let length = Quaternion::dot(self, self).sqrt();
+        Quaternion{ x: self.x / length, y: self.y / length, z: self.z / length, w: self.w / length }
+    }
+
+    // Angle of the rotation taking one orientation to the other, in radians
+    pub fn angle_between(a: &Quaternion, b: &Quaternion) -> f64
+    {
+        2.0 * Quaternion::dot(a, b).abs().min(1.0).acos()
+    }
+
+    // Turns at a constant rate from a (t = 0) to b (t = 1) the short way around
+    pub fn slerp(a: &Quaternion, b: &Quaternion, t: f64) -> Quaternion
+    {
+        let mut cosine = Quaternion::dot(a, b);
+        let sign = if cosine < 0.0 { -1.0 } else { 1.0 };
+        cosine *= sign;
+        let (weight_a, weight_b) = if cosine > 0.9995
+        {
+            // Nearly the same orientation, where lerping is as good and doesn't divide by ~0
+            (1.0 - t, t)
+        }
+        else
+        {
+            let theta = cosine.acos();
+            (((1.0 - t) * theta).sin() / theta.sin(), (t * theta).sin() / theta.sin())
+        };
+        let weight_b = weight_b * sign;
+        Quaternion
+        {
+            x: weight_a * a.x + weight_b * b.x,
+            y: weight_a * a.y + weight_b * b.y,
+            z: weight_a * a.z + weight_b * b.z,
+            w: weight_a * a.w + weight_b * b.w,
+        }.normalized()
+    }
+}
+
+impl Mul<Matrix> for Matrix
+{
+    type Output = Matrix;
+
+    fn mul(self, other: Matrix) -> Matrix
+    {
+        let mut result = Matrix::make_new([[0.0; 4]; 4]);
+        for row in 0..4
+        {
+            for column in 0..4
+            {
+                result.data[row][column] = (0..4).map(|index| self.data[row][index] * other.data[index][column]).sum();
+            }
+        }
+        result
+    }
+}
+
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn assert_near(a: &Vect, b: &Vect)
+    {
+        assert!((a - b).length() < 1e-9, "{} != {}", a, b);
+    }
+
+    fn assert_identity(matrix: &Matrix)
+    {
+        let identity = Matrix::identity();
+        for row in 0..4
+        {
+            for column in 0..4
+            {
+                assert!((matrix.data[row][column] - identity.data[row][column]).abs() < 1e-9, "{:?}", matrix);
+            }
+        }
+    }
+
+    #[test]
+    fn transform_test()
+    {
+        let point = Point::make_new(1.0, 2.0, 3.0);
+        let vector = Vect::make_new(1.0, 2.0, 3.0);
+        let translation = Matrix::translation(&Vect::make_new(1.0, -1.0, 0.5));
+        assert_near(&translation.transform_point(&point), &Point::make_new(2.0, 1.0, 3.5));
+        assert_near(&translation.transform_vector(&vector), &vector);
+
+        let scaling = Matrix::scaling(&Vect::make_new(2.0, 3.0, -1.0));
+        assert_near(&scaling.transform_point(&point), &Point::make_new(2.0, 6.0, -3.0));
+        assert_eq!(scaling.determinant3(), -6.0);
+
+        let rotation = Matrix::rotation(&Vect::make_new(0.0, 0.0, 2.0), 90.0);
+        assert_near(&rotation.transform_vector(&Vect::make_new(1.0, 0.0, 0.0)), &Vect::make_new(0.0, 1.0, 0.0));
+        let rotation = Matrix::rotation(&Vect::make_new(0.0, 1.0, 0.0), 90.0);
+        assert_near(&rotation.transform_vector(&Vect::make_new(0.0, 0.0, 1.0)), &Vect::make_new(1.0, 0.0, 0.0));
+        assert!((rotation.determinant3() - 1.0).abs() < 1e-12);
+
+        // The rightmost matrix applies first
+        let combined = translation * scaling;
+        assert_near(&combined.transform_point(&point), &Point::make_new(3.0, 5.0, -2.5));
+        assert_eq!(Matrix::identity() * combined, combined);
+        assert_eq!(combined.transpose().transpose(), combined);
+        assert_eq!(combined.transpose().data[3][0], 1.0);
+    }
+
+    #[test]
+    fn inverse_test()
+    {
+        let matrix = Matrix::translation(&Vect::make_new(1.0, 2.0, 3.0))
+            * Matrix::rotation(&Vect::make_new(1.0, 1.0, 0.0), 30.0)
+            * Matrix::scaling(&Vect::make_new(2.0, 0.5, 4.0));
+        let inverse = matrix.inverse().unwrap();
+        assert_identity(&(matrix * inverse));
+        assert_identity(&(inverse * matrix));
+
+        let point = Point::make_new(-1.0, 0.5, 2.0);
+        assert_near(&inverse.transform_point(&matrix.transform_point(&point)), &point);
+        assert!(Matrix::scaling(&Vect::make_new(1.0, 0.0, 1.0)).inverse().is_none());
+
+        // A normal stays perpendicular to the vectors lying in the surface
+        let tangent = Vect::make_new(1.0, -1.0, 0.0);
+        let normal = Vect::make_new(1.0, 1.0, 0.0);
+        assert!(Vect::dot(&inverse.transform_normal(&normal), &matrix.transform_vector(&tangent)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn decompose_test()
+    {
+        let rotation = Matrix::rotation(&Vect::make_new(1.0, 2.0, -1.0), 130.0);
+        let matrix = Matrix::translation(&Vect::make_new(1.0, 2.0, 3.0)) * rotation * Matrix::scaling(&Vect::make_new(2.0, 0.5, 4.0));
+        let (translation, quaternion, stretch) = matrix.decompose();
+        assert_near(&translation, &Vect::make_new(1.0, 2.0, 3.0));
+        assert_identity(&(quaternion.to_matrix() * rotation.inverse().unwrap()));
+        let recomposed = Matrix::translation(&translation) * quaternion.to_matrix() * stretch;
+        assert_identity(&(recomposed * matrix.inverse().unwrap()));
+
+        // Mirrors can't be rotations, they end up in the stretch
+        let mirrored = matrix * Matrix::scaling(&Vect::make_new(1.0, -1.0, 1.0));
+        let (translation, quaternion, stretch) = mirrored.decompose();
+        assert!(stretch.determinant3() < 0.0);
+        let recomposed = Matrix::translation(&translation) * quaternion.to_matrix() * stretch;
+        assert_identity(&(recomposed * mirrored.inverse().unwrap()));
+
+        // Every branch of the matrix to quaternion conversion gives the rotation back
+        for axis in [Vect::make_new(1.0, 0.0, 0.0), Vect::make_new(0.0, 1.0, 0.0), Vect::make_new(0.0, 0.0, 1.0)]
+        {
+            let rotation = Matrix::rotation(&axis, 170.0);
+            assert_identity(&(Quaternion::from_matrix(&rotation).to_matrix() * rotation.transpose()));
+        }
+    }
+
+    #[test]
+    fn slerp_test()
+    {
+        let axis = Vect::make_new(0.0, 1.0, 0.0);
+        let start = Quaternion::identity();
+        let end = Quaternion::from_matrix(&Matrix::rotation(&axis, 120.0));
+        assert!((Quaternion::angle_between(&start, &end) - degrees_to_radians(120.0)).abs() < 1e-9);
+
+        let halfway = Quaternion::slerp(&start, &end, 0.5).to_matrix();
+        assert_identity(&(halfway * Matrix::rotation(&axis, 60.0).transpose()));
+        assert_identity(&Quaternion::slerp(&start, &end, 0.0).to_matrix());
+
+        // q and -q are the same rotation, slerp still takes the short way
+        let flipped = Quaternion{ x: -end.x, y: -end.y, z: -end.z, w: -end.w };
+        assert_identity(&(Quaternion::slerp(&start, &flipped, 0.5).to_matrix() * Matrix::rotation(&axis, 60.0).transpose()));
+    }
+}
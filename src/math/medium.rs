@@ -0,0 +1,660 @@
+use std::sync::Arc;
+
+use super::aabb::Aabb;
+use super::core::*;
+use super::grid::VoxelGrid;
+use super::hittable::*;
+use super::material::*;
+use super::microfacet::Frame;
+use super::point::Point;
+use super::random::thread_rand;
+use super::ray::Ray;
+use super::texture::*;
+use super::vect::Vect;
+use super::super::utils::color::FColor;
+use super::super::utils::spectrum::blackbody_color;
+
+// Participating media: smoke, fog and murky liquids that scatter light inside their volume rather than at a surface.
+// Rays fly an exponentially distributed distance through them (free-flight sampling), and when that ends before
+// the next surface they scatter following a phase function. Its albedo gives the fraction of the light that is
+// scattered rather than absorbed.
+
+// Boundary crossings closer than this to the previous one are the same crossing
+const BOUNDARY_EPSILON: f64 = 1e-4;
+
+// Henyey-Greenstein phase function, the density of scattering by the angle between the incoming and outgoing
+// directions of propagation. g > 0 favors forward scattering, g < 0 backward and g = 0 is isotropic.
+pub fn henyey_greenstein(cos_theta: f64, g: f64) -> f64
+{
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+}
+
+// Cosine of the scattering angle distributed like henyey_greenstein() for a uniform u in [0, 1)
+pub fn sample_henyey_greenstein(g: f64, u: f64) -> f64
+{
+    if g.abs() < 1e-3
+    {
+        return 1.0 - 2.0 * u;
+    }
+    let ratio = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+    clamp((1.0 + g * g - ratio * ratio) / (2.0 * g), -1.0, 1.0)
+}
+
+// Where a medium scatters a ray: the point at distance t along it, facing back along the ray
+fn medium_hit(ray: &Ray, t: f64, phase_function: &Arc<dyn Material>) -> HitResult
+{
+    HitResult::make_new(ray.at(t), -ray.direction(), Arc::clone(phase_function), t, ray)
+}
+
+// Scatters equally in all directions
+#[derive(Clone)]
+pub struct Isotropic
+{
+    albedo: Arc<dyn Texture>,
+}
+
+impl Material for Isotropic
+{
+    fn scatter(&self, _ray: &Ray, hit_result: &HitResult) -> Option<(FColor, Ray)>
+    {
+        Some((texture_value(self.albedo.as_ref(), hit_result), Ray::make_new(*hit_result.point(), Vect::random_unit_vect())))
+    }
+
+    fn eval(&self, _wi: &Vect, _wo: &Vect, hit_result: &HitResult) -> FColor
+    {
+        texture_value(self.albedo.as_ref(), hit_result) / (4.0 * PI)
+    }
+
+    fn pdf(&self, _wi: &Vect, _wo: &Vect, _hit_result: &HitResult) -> f64
+    {
+        1.0 / (4.0 * PI)
+    }
+
+    fn is_volumetric(&self) -> bool
+    {
+        true
+    }
+}
+
+impl Isotropic
+{
+    pub fn default() -> Self
+    {
+        Isotropic::make_new(FColor::new_color(1.0, 1.0, 1.0))
+    }
+
+    pub fn make_new(albedo: FColor) -> Self
+    {
+        Isotropic{ albedo: Arc::new(SolidColor::make_new(albedo)) }
+    }
+
+    pub fn make_textured(albedo: Arc<dyn Texture>) -> Self
+    {
+        Isotropic{ albedo }
+    }
+}
+
+// Scatters mostly forward (g > 0) or backward (g < 0), like haze, clouds or milk
+#[derive(Clone)]
+pub struct HenyeyGreenstein
+{
+    albedo: Arc<dyn Texture>,
+    g: f64,
+}
+
+impl Material for HenyeyGreenstein
+{
+    fn scatter(&self, ray: &Ray, hit_result: &HitResult) -> Option<(FColor, Ray)>
+    {
+        // The angle is measured from the direction the ray was going
+        let cos_theta = sample_henyey_greenstein(self.g, thread_rand());
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * thread_rand();
+        let local = Vect::make_new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        let direction = Frame::make_new(*ray.direction()).to_world(&local);
+        Some((texture_value(self.albedo.as_ref(), hit_result), Ray::make_new(*hit_result.point(), direction)))
+    }
+
+    fn eval(&self, wi: &Vect, wo: &Vect, hit_result: &HitResult) -> FColor
+    {
+        texture_value(self.albedo.as_ref(), hit_result) * self.pdf(wi, wo, hit_result)
+    }
+
+    // Light arrives going along -wi and leaves along wo
+    fn pdf(&self, wi: &Vect, wo: &Vect, _hit_result: &HitResult) -> f64
+    {
+        henyey_greenstein(-Vect::dot(wi, wo), self.g)
+    }
+
+    fn is_volumetric(&self) -> bool
+    {
+        true
+    }
+}
+
+impl HenyeyGreenstein
+{
+    pub fn make_new(albedo: FColor, g: f64) -> Self
+    {
+        HenyeyGreenstein::make_textured(Arc::new(SolidColor::make_new(albedo)), g)
+    }
+
+    // g is kept strictly inside (-1, 1), where the distribution is finite
+    pub fn make_textured(albedo: Arc<dyn Texture>, g: f64) -> Self
+    {
+        HenyeyGreenstein{ albedo, g: clamp(g, -0.99, 0.99) }
+    }
+}
+
+// Medium of constant density filling a closed boundary, the boundary's own materials are ignored.
+// Shadow rays go through it or not at random, with a probability equal to its transmittance.
+pub struct ConstantMedium
+{
+    boundary: Arc<dyn Hittable>,
+    // Mean free path, the inverse of the density, negated
+    neg_inv_density: f64,
+    phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium
+{
+    pub fn make_new(boundary: Arc<dyn Hittable>, density: f64, phase_function: Arc<dyn Material>) -> Self
+    {
+        ConstantMedium{ boundary, neg_inv_density: -1.0 / density, phase_function }
+    }
+}
+
+impl Hittable for ConstantMedium
+{
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitResult>
+    {
+        // Walks through the stretches of the ray inside the boundary, which can be several for concave shapes.
+        // Free paths have no memory, so a new one can be drawn in each stretch.
+        let mut start = -INFINITY;
+        loop
+        {
+            let entry = self.boundary.hit(r, start, INFINITY)?;
+            let exit = self.boundary.hit(r, entry.t() + BOUNDARY_EPSILON, INFINITY)?;
+            let (near, far) = (entry.t().max(t_min), exit.t().min(t_max));
+            if near < far
+            {
+                let t = near + self.neg_inv_density * (1.0 - thread_rand()).ln();
+                if t < far
+                {
+                    return Some(medium_hit(r, t, &self.phase_function));
+                }
+            }
+            if exit.t() >= t_max
+            {
+                return None;
+            }
+            start = exit.t() + BOUNDARY_EPSILON;
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb>
+    {
+        self.boundary.bounding_box()
+    }
+
+    // Beer-Lambert over the stretches inside the boundary
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64
+    {
+        let mut distance = 0.0;
+        let mut start = -INFINITY;
+        while let Some(entry) = self.boundary.hit(r, start, INFINITY)
+        {
+            let Some(exit) = self.boundary.hit(r, entry.t() + BOUNDARY_EPSILON, INFINITY) else { break };
+            distance += (exit.t().min(t_max) - entry.t().max(t_min)).max(0.0);
+            if exit.t() >= t_max
+            {
+                break;
+            }
+            start = exit.t() + BOUNDARY_EPSILON;
+        }
+        (distance / self.neg_inv_density).exp()
+    }
+}
+
+// Where a voxel grid sits in the scene: the position of index (0, 0, 0) and the side of a voxel
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct GridPlacement
+{
+    pub origin: Point,
+    pub voxel_size: f64,
+}
+
+impl GridPlacement
+{
+    pub fn index_position(&self, point: &Point) -> [f64; 3]
+    {
+        let local = (point - self.origin) / self.voxel_size;
+        [local.x(), local.y(), local.z()]
+    }
+
+    pub fn bounds(&self, grid: &dyn VoxelGrid) -> Aabb
+    {
+        let (minimum, maximum) = grid.index_bounds();
+        let corner = |index: [i64; 3]| self.origin + self.voxel_size * Vect::make_new(index[0] as f64, index[1] as f64, index[2] as f64);
+        Aabb::make_new(corner(minimum), corner(maximum))
+    }
+}
+
+// Everything describing a grid medium besides its density, which is required
+pub struct GridParameters
+{
+    pub placement: GridPlacement,
+    pub density_scale: f64,
+    pub phase_function: Arc<dyn Material>,
+    // Temperature in kelvins once multiplied by the scale, glowing like a black body
+    pub temperature: Option<Arc<dyn VoxelGrid>>,
+    pub temperature_scale: f64,
+    // Brightness of the glow at 1000 K, it grows with the fourth power of the temperature
+    pub blackbody_intensity: f64,
+    // Glow of the given color, scaled by the grid value
+    pub emission: Option<Arc<dyn VoxelGrid>>,
+    pub emission_color: FColor,
+}
+
+impl GridParameters
+{
+    pub fn default() -> Self
+    {
+        GridParameters
+        {
+            placement: GridPlacement{ origin: Point::make_new(0.0, 0.0, 0.0), voxel_size: 1.0 },
+            density_scale: 1.0,
+            phase_function: Arc::new(Isotropic::default()),
+            temperature: None,
+            temperature_scale: 1.0,
+            blackbody_intensity: 1.0,
+            emission: None,
+            emission_color: FColor::new_color(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+// What a grid medium scatters and glows with, looked up at the point of each collision.
+// The glow is the radiance seen through a thick enough region, collisions in thinner parts find it less often.
+struct GridMaterial
+{
+    placement: GridPlacement,
+    phase_function: Arc<dyn Material>,
+    temperature: Option<Arc<dyn VoxelGrid>>,
+    temperature_scale: f64,
+    blackbody_intensity: f64,
+    emission: Option<Arc<dyn VoxelGrid>>,
+    emission_color: FColor,
+}
+
+impl Material for GridMaterial
+{
+    fn scatter(&self, ray: &Ray, hit_result: &HitResult) -> Option<(FColor, Ray)>
+    {
+        self.phase_function.scatter(ray, hit_result)
+    }
+
+    fn emitted(&self, _ray: &Ray, hit_result: &HitResult) -> FColor
+    {
+        let index = self.placement.index_position(hit_result.point());
+        let mut radiance = FColor::make_new(0.0, 0.0, 0.0);
+        if let Some(temperature) = &self.temperature
+        {
+            let kelvin = temperature.interpolate(index) * self.temperature_scale;
+            if kelvin > 0.0
+            {
+                radiance += blackbody_color(kelvin) * (self.blackbody_intensity * (kelvin / 1000.0).powi(4));
+            }
+        }
+        if let Some(emission) = &self.emission
+        {
+            radiance += self.emission_color * emission.interpolate(index).max(0.0);
+        }
+        radiance
+    }
+
+    fn eval(&self, wi: &Vect, wo: &Vect, hit_result: &HitResult) -> FColor
+    {
+        self.phase_function.eval(wi, wo, hit_result)
+    }
+
+    fn pdf(&self, wi: &Vect, wo: &Vect, hit_result: &HitResult) -> f64
+    {
+        self.phase_function.pdf(wi, wo, hit_result)
+    }
+
+    fn is_volumetric(&self) -> bool
+    {
+        true
+    }
+}
+
+// Medium whose density comes from a voxel grid, like a smoke simulation. Collisions are found by delta tracking
+// against the densest voxel and shadow rays are dimmed by ratio tracking, see Novák et al.,
+// "Monte Carlo Methods for Volumetric Light Transport Simulation" (2018).
+pub struct GridMedium
+{
+    density: Arc<dyn VoxelGrid>,
+    placement: GridPlacement,
+    density_scale: f64,
+    // No point is denser than this
+    majorant: f64,
+    bounds: Aabb,
+    material: Arc<dyn Material>,
+}
+
+impl GridMedium
+{
+    pub fn make_new(density: Arc<dyn VoxelGrid>, parameters: GridParameters) -> Self
+    {
+        let placement = parameters.placement;
+        let majorant = density.max_value().max(0.0) as f64 * parameters.density_scale;
+        let bounds = placement.bounds(density.as_ref());
+        let material = GridMaterial
+        {
+            placement,
+            phase_function: parameters.phase_function,
+            temperature: parameters.temperature,
+            temperature_scale: parameters.temperature_scale,
+            blackbody_intensity: parameters.blackbody_intensity,
+            emission: parameters.emission,
+            emission_color: parameters.emission_color,
+        };
+        GridMedium{ density, placement, density_scale: parameters.density_scale, majorant, bounds, material: Arc::new(material) }
+    }
+
+    pub fn density(&self, point: &Point) -> f64
+    {
+        self.density.interpolate(self.placement.index_position(point)).max(0.0) * self.density_scale
+    }
+
+    // Distance to the next tentative collision, the ones with the majorant density
+    fn free_path(&self) -> f64
+    {
+        -(1.0 - thread_rand()).ln() / self.majorant
+    }
+}
+
+impl Hittable for GridMedium
+{
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitResult>
+    {
+        if self.majorant <= 0.0
+        {
+            return None;
+        }
+        let (mut t, end) = self.bounds.intersect(r, t_min, t_max)?;
+        loop
+        {
+            t += self.free_path();
+            if t >= end
+            {
+                return None;
+            }
+            // Real collisions are the fraction of the majorant the medium has there
+            if thread_rand() * self.majorant < self.density(&r.at(t))
+            {
+                return Some(medium_hit(r, t, &self.material));
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb>
+    {
+        Some(self.bounds)
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64
+    {
+        if self.majorant <= 0.0
+        {
+            return 1.0;
+        }
+        let Some((mut t, end)) = self.bounds.intersect(r, t_min, t_max) else { return 1.0 };
+        let mut transmittance = 1.0;
+        loop
+        {
+            t += self.free_path();
+            if t >= end
+            {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.density(&r.at(t)) / self.majorant;
+            // Russian roulette once little light is left
+            if transmittance < 0.1
+            {
+                if thread_rand() < 0.5
+                {
+                    return 0.0;
+                }
+                transmittance *= 2.0;
+            }
+        }
+    }
+}
+
+// Homogeneous fog filling the whole scene below a height, infinitely far in every other direction
+pub struct Fog
+{
+    density: f64,
+    phase_function: Arc<dyn Material>,
+    height: f64,
+}
+
+impl Fog
+{
+    pub fn make_new(density: f64, phase_function: Arc<dyn Material>, height: f64) -> Self
+    {
+        Fog{ density, phase_function, height }
+    }
+
+    // Start and end of the part of the ray before t_max that is inside the fog
+    fn span(&self, ray: &Ray, t_max: f64) -> Option<(f64, f64)>
+    {
+        let (origin, direction) = (ray.origin().y(), ray.direction().y());
+        let (start, end) = if direction == 0.0
+        {
+            if origin < self.height { (0.0, t_max) } else { return None }
+        }
+        else
+        {
+            // The ray crosses the top of the fog going up or coming down
+            let crossing = (self.height - origin) / direction;
+            if direction > 0.0 { (0.0, crossing.min(t_max)) } else { (crossing.max(0.0), t_max) }
+        };
+        if start < end { Some((start, end)) } else { None }
+    }
+
+    // Fraction of the light that makes it through the first t_max units of the ray
+    pub fn transmittance(&self, ray: &Ray, t_max: f64) -> f64
+    {
+        self.span(ray, t_max).map_or(1.0, |(start, end)| (-self.density * (end - start)).exp())
+    }
+
+    // Scattering event of a ray in the fog before it reaches t_max, if there is one
+    pub fn sample(&self, ray: &Ray, t_max: f64) -> Option<HitResult>
+    {
+        let (start, end) = self.span(ray, t_max)?;
+        let t = start - (1.0 - thread_rand()).ln() / self.density;
+        if t < end { Some(medium_hit(ray, t, &self.phase_function)) } else { None }
+    }
+}
+
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::grid::DenseGrid;
+    use super::super::random::seed_thread_rand;
+    use super::super::sphere::Sphere;
+
+    #[test]
+    fn phase_function_test()
+    {
+        seed_thread_rand(23);
+        // Both phase functions integrate to one over the sphere
+        for g in [-0.7, 0.0, 0.3, 0.9]
+        {
+            let steps = 20000;
+            let total: f64 = (0..steps).map(|step|
+                {
+                    let cos_theta = -1.0 + 2.0 * (step as f64 + 0.5) / steps as f64;
+                    henyey_greenstein(cos_theta, g) * 2.0 * PI * 2.0 / steps as f64
+                }).sum();
+            assert!((total - 1.0).abs() < 1e-3);
+
+            // The mean cosine of the sampled angles is g
+            let samples = 20000;
+            let mean: f64 = (0..samples).map(|_| sample_henyey_greenstein(g, thread_rand())).sum::<f64>() / samples as f64;
+            assert!((mean - g).abs() < 0.02);
+        }
+
+        // Forward scattering keeps rays going the same way
+        let ray = Ray::make_new(Point::make_new(0.0, 0.0, 0.0), Vect::make_new(0.0, 0.0, -1.0));
+        let phase_function: Arc<dyn Material> = Arc::new(HenyeyGreenstein::make_new(FColor::new_color(0.5, 0.5, 0.5), 0.8));
+        let hit_result = medium_hit(&ray, 1.0, &phase_function);
+        let wo = -ray.direction();
+        assert!(phase_function.pdf(ray.direction(), &wo, &hit_result) > phase_function.pdf(&wo, &wo, &hit_result));
+        let forward = (0..1000).filter(|_| phase_function.scatter(&ray, &hit_result).unwrap().1.direction().z() < 0.0).count();
+        assert!(forward > 800);
+        assert_eq!(phase_function.eval(&wo, &wo, &hit_result), FColor::new_color(0.5, 0.5, 0.5) * phase_function.pdf(&wo, &wo, &hit_result));
+        assert!(phase_function.is_volumetric());
+        assert!(!Lambertian::default().is_volumetric());
+    }
+
+    #[test]
+    fn constant_medium_test()
+    {
+        seed_thread_rand(29);
+        let boundary = Arc::new(Sphere::make_new(Point::make_new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::default())));
+        let medium = ConstantMedium::make_new(boundary, 0.5, Arc::new(Isotropic::default()));
+        assert_eq!(medium.bounding_box(), Some(Aabb::make_new(Point::make_new(-1.0, -1.0, -1.0), Point::make_new(1.0, 1.0, 1.0))));
+
+        // Crossing 2 units at density 0.5 lets exp(-1) of the rays through
+        let ray = Ray::make_new(Point::make_new(0.0, 0.0, 5.0), Vect::make_new(0.0, 0.0, -1.0));
+        let samples = 20000;
+        let mut passed = 0;
+        for _ in 0..samples
+        {
+            match medium.hit(&ray, 0.001, INFINITY)
+            {
+                Some(hit_result) => assert!(hit_result.t() > 4.0 && hit_result.t() < 6.0 && hit_result.material().is_volumetric()),
+                None => passed += 1,
+            }
+        }
+        assert!((passed as f64 / samples as f64 - (-1f64).exp()).abs() < 0.01);
+
+        // From the center only one unit is left, and nothing past t_max is hit
+        let ray = Ray::make_new(Point::make_new(0.0, 0.0, 0.0), Vect::make_new(0.0, 0.0, -1.0));
+        let passed = (0..samples).filter(|_| medium.hit(&ray, 0.001, INFINITY).is_none()).count();
+        assert!((passed as f64 / samples as f64 - (-0.5f64).exp()).abs() < 0.01);
+        assert!((0..100).all(|_| medium.hit(&ray, 0.001, 0.01).is_none_or(|hit_result| hit_result.t() < 0.01)));
+        let ray = Ray::make_new(Point::make_new(0.0, 2.0, 0.0), Vect::make_new(0.0, 0.0, -1.0));
+        assert!(medium.hit(&ray, 0.001, INFINITY).is_none());
+    }
+
+    #[test]
+    fn fog_test()
+    {
+        seed_thread_rand(31);
+        let fog = Fog::make_new(0.1, Arc::new(Isotropic::default()), 1.0);
+
+        // Going up, the ray leaves the fog after 2 units
+        let ray = Ray::make_new(Point::make_new(0.0, -1.0, 0.0), Vect::make_new(0.0, 1.0, 0.0));
+        assert!((fog.transmittance(&ray, INFINITY) - (-0.2f64).exp()).abs() < 1e-12);
+        assert!((fog.transmittance(&ray, 1.0) - (-0.1f64).exp()).abs() < 1e-12);
+        // Coming down it only enters the fog at the top
+        let ray = Ray::make_new(Point::make_new(0.0, 3.0, 0.0), Vect::make_new(0.0, -1.0, 0.0));
+        assert_eq!(fog.transmittance(&ray, 2.0), 1.0);
+        assert!((fog.transmittance(&ray, 4.0) - (-0.2f64).exp()).abs() < 1e-12);
+        assert_eq!(fog.transmittance(&ray, INFINITY), 0.0);
+        // Level rays are either always or never in it
+        assert_eq!(fog.transmittance(&Ray::make_new(Point::make_new(0.0, 2.0, 0.0), Vect::make_new(1.0, 0.0, 0.0)), INFINITY), 1.0);
+        assert_eq!(fog.transmittance(&Ray::make_new(Point::make_new(0.0, 0.0, 0.0), Vect::make_new(1.0, 0.0, 0.0)), INFINITY), 0.0);
+
+        // Sampling scatters as often as the fog blocks the light, only where the fog is
+        let samples = 20000;
+        let mut scattered = 0;
+        for _ in 0..samples
+        {
+            if let Some(hit_result) = fog.sample(&ray, 4.0)
+            {
+                assert!(hit_result.point().y() < 1.0 && hit_result.t() < 4.0);
+                scattered += 1;
+            }
+        }
+        assert!((scattered as f64 / samples as f64 - (1.0 - (-0.2f64).exp())).abs() < 0.01);
+
+        // Without a height it goes on forever
+        let fog = Fog::make_new(0.1, Arc::new(Isotropic::default()), INFINITY);
+        assert!((0..100).all(|_| fog.sample(&ray, INFINITY).is_some()));
+    }
+
+    #[test]
+    fn grid_medium_test()
+    {
+        seed_thread_rand(41);
+        // A 4^3 box of voxels of half a unit, half of them at density 2 and the other half at 0
+        let values: Vec<f32> = (0..64).map(|index| if index % 4 < 2 { 2.0 } else { 0.0 }).collect();
+        let density: Arc<dyn VoxelGrid> = Arc::new(DenseGrid::make_new([0, 0, 0], [4, 4, 4], values, 0.0));
+        let mut parameters = GridParameters::default();
+        parameters.placement = GridPlacement{ origin: Point::make_new(-1.0, -1.0, -1.0), voxel_size: 0.5 };
+        parameters.density_scale = 0.5;
+        let medium = GridMedium::make_new(density.clone(), parameters);
+        assert_eq!(medium.bounding_box(), Some(Aabb::make_new(Point::make_new(-1.0, -1.0, -1.0), Point::make_new(1.0, 1.0, 1.0))));
+        assert_eq!(medium.density(&Point::make_new(-0.75, 0.0, 0.0)), 1.0);
+        assert_eq!(medium.density(&Point::make_new(0.75, 0.0, 0.0)), 0.0);
+
+        // Along z the density is 1 across 2 units, except that it fades towards the empty background over the
+        // outer half voxels, which takes 0.125 off the optical depth
+        let ray = Ray::make_new(Point::make_new(-0.75, 0.25, 5.0), Vect::make_new(0.0, 0.0, -1.0));
+        let expected = (-1.875f64).exp();
+        let samples = 20000;
+        let passed = (0..samples).filter(|_| medium.hit(&ray, 0.001, INFINITY).is_none()).count();
+        assert!((passed as f64 / samples as f64 - expected).abs() < 0.01);
+        let transmittance: f64 = (0..samples).map(|_| medium.transmittance(&ray, 0.001, INFINITY)).sum();
+        assert!((transmittance / samples as f64 - expected).abs() < 0.01);
+
+        // Along x it only crosses the dense half, where collisions happen
+        let ray = Ray::make_new(Point::make_new(5.0, 0.25, 0.25), Vect::make_new(-1.0, 0.0, 0.0));
+        let mut passed = 0;
+        for _ in 0..samples
+        {
+            match medium.hit(&ray, 0.001, INFINITY)
+            {
+                Some(hit_result) => assert!(hit_result.point().x() < 0.25 && hit_result.material().is_volumetric()),
+                None => passed += 1,
+            }
+        }
+        // A half voxel at the edge, a full one and the fade over the voxel between the halves
+        assert!((passed as f64 / samples as f64 - (-0.9375f64).exp()).abs() < 0.01);
+        assert_eq!(medium.transmittance(&Ray::make_new(Point::make_new(5.0, 5.0, 5.0), Vect::make_new(1.0, 0.0, 0.0)), 0.001, INFINITY), 1.0);
+
+        // Glowing like a black body, and with an emission channel
+        let temperature: Arc<dyn VoxelGrid> = Arc::new(DenseGrid::make_new([0, 0, 0], [1, 1, 1], vec![1.5], 0.0));
+        let mut parameters = GridParameters::default();
+        parameters.temperature = Some(temperature.clone());
+        parameters.temperature_scale = 1000.0;
+        parameters.blackbody_intensity = 2.0;
+        let medium = GridMedium::make_new(density.clone(), parameters);
+        let ray = Ray::make_new(Point::make_new(0.5, 0.5, 5.0), Vect::make_new(0.0, 0.0, -1.0));
+        let hit_result = medium_hit(&ray, 4.5, &medium.material);
+        let glow = hit_result.material().emitted(&ray, &hit_result);
+        assert!((glow - blackbody_color(1500.0) * (2.0 * 1.5f64.powi(4))).length() < 1e-9);
+
+        let mut parameters = GridParameters::default();
+        parameters.emission = Some(temperature);
+        parameters.emission_color = FColor::new_color(1.0, 0.5, 0.0);
+        let medium = GridMedium::make_new(density, parameters);
+        let hit_result = medium_hit(&ray, 4.5, &medium.material);
+        assert_eq!(hit_result.material().emitted(&ray, &hit_result), FColor::new_color(1.5, 0.75, 0.0));
+    }
+}
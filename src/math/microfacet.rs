@@ -0,0 +1,438 @@
+use super::core::*;
+use super::vect::Vect;
+use super::super::utils::color::FColor;
+
+// Building blocks of microfacet BSDFs: the GGX (Trowbridge-Reitz) distribution of microfacet normals,
+// with visible normal sampling after Heitz, "Sampling the GGX Distribution of Visible Normals" (2018),
+// and the Fresnel equations for dielectrics and conductors.
+// Vectors are expressed in a local frame whose z axis is the shading normal.
+
+// Below this alpha the distribution is too sharp to evaluate and the surface is treated as a perfect mirror
+pub const MIN_ALPHA: f64 = 1e-3;
+
+// Squaring the roughness spreads the perceived blurriness more evenly over [0, 1]
+pub fn roughness_to_alpha(roughness: f64) -> f64
+{
+    let roughness = clamp(roughness, 0.0, 1.0);
+    roughness * roughness
+}
+
+// Orthonormal frame around a normal, to move directions in and out of the local space
+pub struct Frame
+{
+    tangent: Vect,
+    bitangent: Vect,
+    normal: Vect,
+}
+
+impl Frame
+{
+    pub fn make_new(normal: Vect) -> Self
+    {
+        let (tangent, bitangent) = normal.orthonormal_basis();
+        Frame{ tangent, bitangent, normal }
+    }
+
+    // Frame whose x axis follows the given tangent, projected onto the plane of the normal.
+    // Falls back to an arbitrary tangent when it is zero or parallel to the normal.
+    pub fn make_with_tangent(normal: Vect, tangent: &Vect) -> Self
+    {
+        let tangent = tangent - Vect::dot(tangent, &normal) * normal;
+        if tangent.length_squared() < 1e-12
+        {
+            return Frame::make_new(normal);
+        }
+        let tangent = tangent.get_normalized();
+        Frame{ tangent, bitangent: Vect::cross(&normal, &tangent), normal }
+    }
+
+    pub fn to_local(&self, direction: &Vect) -> Vect
+    {
+        Vect::make_new(Vect::dot(direction, &self.tangent), Vect::dot(direction, &self.bitangent), Vect::dot(direction, &self.normal))
+    }
+
+    pub fn to_world(&self, direction: &Vect) -> Vect
+    {
+        direction.x() * self.tangent + direction.y() * self.bitangent + direction.z() * self.normal
+    }
+}
+
+pub fn reflect(wo: &Vect, normal: &Vect) -> Vect
+{
+    2.0 * Vect::dot(wo, normal) * normal - wo
+}
+
+// Refracts wo, on the side normal points to, through a surface with relative index eta (other side over wo's side).
+// Returns None on total internal reflection.
+pub fn refract(wo: &Vect, normal: &Vect, eta: f64) -> Option<Vect>
+{
+    let cos_i = Vect::dot(wo, normal);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0
+    {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-wo / eta + (cos_i / eta - cos_t) * normal)
+}
+
+// Unpolarized reflectance of a dielectric interface, cos_i on the side of the incoming light
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64
+{
+    let cos_i = clamp(cos_i, 0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0
+    {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+// Reflectance of a metal with complex index of refraction eta + i k, for one wavelength
+fn fresnel_conductor_channel(cos_i: f64, eta: f64, k: f64) -> f64
+{
+    let cos2 = cos_i * cos_i;
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * cos_i * a;
+    let perpendicular = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let parallel = perpendicular * (t3 - t4) / (t3 + t4);
+    0.5 * (parallel + perpendicular)
+}
+
+pub fn fresnel_conductor(cos_i: f64, eta: &FColor, k: &FColor) -> FColor
+{
+    let cos_i = clamp(cos_i, 0.0, 1.0);
+    FColor::new_color(fresnel_conductor_channel(cos_i, eta.r(), k.r()),
+                      fresnel_conductor_channel(cos_i, eta.g(), k.g()),
+                      fresnel_conductor_channel(cos_i, eta.b(), k.b()))
+}
+
+// Schlick's approximation from the reflectance at normal incidence
+pub fn fresnel_schlick(cos_i: f64, f0: &FColor) -> FColor
+{
+    let weight = (1.0 - clamp(cos_i, 0.0, 1.0)).powi(5);
+    f0 + (FColor::new_color(1.0, 1.0, 1.0) - f0) * weight
+}
+
+// Complex indices of refraction (eta, k) of common metals at red, green and blue wavelengths
+pub const CONDUCTOR_PRESETS: [(&str, [f64; 3], [f64; 3]); 7] =
+[
+    ("gold", [0.1431, 0.3749, 1.4425], [3.9831, 2.3857, 1.6032]),
+    ("silver", [0.1552, 0.1167, 0.1383], [4.8283, 3.1222, 2.1469]),
+    ("copper", [0.2004, 0.9240, 1.1022], [3.9129, 2.4528, 2.1421]),
+    ("aluminum", [1.6574, 0.8803, 0.5212], [9.2238, 6.2695, 4.8370]),
+    ("chromium", [4.3696, 2.9167, 1.6547], [5.2064, 4.2313, 3.7549]),
+    ("iron", [2.9114, 2.9497, 2.5845], [3.0893, 2.9318, 2.7670]),
+    ("titanium", [2.7407, 2.5418, 2.2670], [3.8143, 3.4345, 3.0385]),
+];
+
+pub fn conductor_preset(name: &str) -> Option<(FColor, FColor)>
+{
+    CONDUCTOR_PRESETS.iter().find(|(preset, _, _)| *preset == name).map(|(_, eta, k)|
+        (FColor::new_color(eta[0], eta[1], eta[2]), FColor::new_color(k[0], k[1], k[2])))
+}
+
+// Isotropic GGX distribution
+pub struct Ggx
+{
+    // Widths along the tangent and the bitangent of the local frame
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl Ggx
+{
+    pub fn make_new(alpha: f64) -> Self
+    {
+        Ggx::make_anisotropic(alpha, alpha)
+    }
+
+    pub fn make_anisotropic(alpha_x: f64, alpha_y: f64) -> Self
+    {
+        Ggx{ alpha_x: alpha_x.max(MIN_ALPHA), alpha_y: alpha_y.max(MIN_ALPHA) }
+    }
+
+    // Density of microfacet normals per unit of projected area
+    pub fn d(&self, m: &Vect) -> f64
+    {
+        if m.z() <= 0.0
+        {
+            return 0.0;
+        }
+        let (x, y) = (m.x() / self.alpha_x, m.y() / self.alpha_y);
+        let denominator = x * x + y * y + m.z() * m.z();
+        1.0 / (PI * self.alpha_x * self.alpha_y * denominator * denominator)
+    }
+
+    fn lambda(&self, v: &Vect) -> f64
+    {
+        let cos2 = v.z() * v.z();
+        if cos2 <= 0.0
+        {
+            return INFINITY;
+        }
+        let (x, y) = (self.alpha_x * v.x(), self.alpha_y * v.y());
+        0.5 * ((1.0 + (x * x + y * y) / cos2).sqrt() - 1.0)
+    }
+
+    // Fraction of the microfacets visible from v
+    pub fn g1(&self, v: &Vect) -> f64
+    {
+        1.0 / (1.0 + self.lambda(v))
+    }
+
+    // Height correlated masking and shadowing
+    pub fn g(&self, wo: &Vect, wi: &Vect) -> f64
+    {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Microfacet normal seen from wo (in the upper hemisphere), for uniform u1 and u2
+    pub fn sample_visible_normal(&self, wo: &Vect, u1: f64, u2: f64) -> Vect
+    {
+        // Stretch the view so the distribution becomes a hemisphere
+        let view = Vect::make_new(self.alpha_x * wo.x(), self.alpha_y * wo.y(), wo.z()).get_normalized();
+        let length2 = view.x() * view.x() + view.y() * view.y();
+        let t1 = if length2 > 0.0 { Vect::make_new(-view.y(), view.x(), 0.0) / length2.sqrt() } else { Vect::make_new(1.0, 0.0, 0.0) };
+        let t2 = Vect::cross(&view, &t1);
+
+        // Uniform point on the disk, squeezed onto the visible half
+        let radius = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = radius * phi.cos();
+        let s = 0.5 * (1.0 + view.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * radius * phi.sin();
+        let normal = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * view;
+
+        // Unstretch
+        Vect::make_new(self.alpha_x * normal.x(), self.alpha_y * normal.y(), normal.z().max(0.0)).get_normalized()
+    }
+
+    // Density of sample_visible_normal() returning m
+    pub fn visible_normal_pdf(&self, wo: &Vect, m: &Vect) -> f64
+    {
+        if wo.z() <= 0.0
+        {
+            return 0.0;
+        }
+        self.g1(wo) * Vect::dot(wo, m).max(0.0) * self.d(m) / wo.z()
+    }
+}
+
+// Rough boundary between two dielectrics that reflects or refracts (Walter et al., "Microfacet Models for
+// Refraction through Rough Surfaces", 2007), picking between the two with the Fresnel reflectance.
+// Directions below the local xy plane are on the far side, and eta is its index over the index on wo's side.
+pub struct DielectricInterface
+{
+    eta: f64,
+    ggx: Ggx,
+    smooth: bool,
+}
+
+impl DielectricInterface
+{
+    pub fn make_new(eta: f64, alpha_x: f64, alpha_y: f64) -> Self
+    {
+        DielectricInterface{ eta, ggx: Ggx::make_anisotropic(alpha_x, alpha_y), smooth: alpha_x.max(alpha_y) < MIN_ALPHA }
+    }
+
+    // Picks wi for uniform u1, u2 and u3 and returns it with eval over pdf
+    pub fn sample(&self, wo: &Vect, u1: f64, u2: f64, u3: f64) -> Option<(Vect, f64)>
+    {
+        if wo.z() <= 0.0
+        {
+            return None;
+        }
+        let m = if self.smooth { Vect::make_new(0.0, 0.0, 1.0) } else { self.ggx.sample_visible_normal(wo, u1, u2) };
+
+        let reflectance = fresnel_dielectric(Vect::dot(wo, &m), self.eta);
+        let (wi, scale) = match refract(wo, &m, self.eta)
+        {
+            // Radiance is compressed into a smaller solid angle when entering the denser medium
+            Some(refracted) if u3 >= reflectance => (refracted, 1.0 / (self.eta * self.eta)),
+            _ => (reflect(wo, &m), 1.0),
+        };
+        // Reflections must stay above the surface and refractions below
+        if (wi.z() > 0.0) != (scale == 1.0) || wi.z() == 0.0
+        {
+            return None;
+        }
+
+        let shadowing = if self.smooth { 1.0 } else { self.ggx.g(wo, &wi) / self.ggx.g1(wo) };
+        Some((wi, scale * shadowing))
+    }
+
+    // BSDF times the cosine, zero for smooth interfaces
+    pub fn eval(&self, wi: &Vect, wo: &Vect) -> f64
+    {
+        if self.smooth || wo.z() <= 0.0 || wi.z() == 0.0
+        {
+            return 0.0;
+        }
+        let eta = self.eta;
+        if wi.z() > 0.0
+        {
+            let m = (wi + wo).get_normalized();
+            self.ggx.d(&m) * self.ggx.g(wo, wi) * fresnel_dielectric(Vect::dot(wo, &m), eta) / (4.0 * wo.z())
+        }
+        else
+        {
+            let Some((m, cos_i, cos_o)) = refraction_half_vector(wi, wo, eta) else { return 0.0 };
+            let transmittance = 1.0 - fresnel_dielectric(cos_o, eta);
+            let denominator = (cos_i + cos_o / eta) * (cos_i + cos_o / eta);
+            self.ggx.d(&m) * self.ggx.g(wo, wi) * transmittance * (cos_i * cos_o).abs() / (denominator * wo.z() * eta * eta)
+        }
+    }
+
+    pub fn pdf(&self, wi: &Vect, wo: &Vect) -> f64
+    {
+        if self.smooth || wo.z() <= 0.0 || wi.z() == 0.0
+        {
+            return 0.0;
+        }
+        let eta = self.eta;
+        if wi.z() > 0.0
+        {
+            let m = (wi + wo).get_normalized();
+            let reflectance = fresnel_dielectric(Vect::dot(wo, &m), eta);
+            self.ggx.visible_normal_pdf(wo, &m) / (4.0 * Vect::dot(wo, &m)) * reflectance
+        }
+        else
+        {
+            let Some((m, cos_i, cos_o)) = refraction_half_vector(wi, wo, eta) else { return 0.0 };
+            let transmittance = 1.0 - fresnel_dielectric(cos_o, eta);
+            // Jacobian of the refraction from the microfacet normal to wi
+            let jacobian = cos_i.abs() / ((cos_i + cos_o / eta) * (cos_i + cos_o / eta));
+            self.ggx.visible_normal_pdf(wo, &m) * jacobian * transmittance
+        }
+    }
+}
+
+// Microfacet normal that refracts wo into wi, with the cosines of wi and wo against it.
+// None when the pair can't come from a refraction through a microfacet facing wo.
+fn refraction_half_vector(wi: &Vect, wo: &Vect, eta: f64) -> Option<(Vect, f64, f64)>
+{
+    let mut m = (eta * wi + wo).get_normalized();
+    if m.z() < 0.0
+    {
+        m = -m;
+    }
+    let (cos_i, cos_o) = (Vect::dot(wi, &m), Vect::dot(wo, &m));
+    if cos_o <= 0.0 || cos_i >= 0.0 || !m.z().is_finite()
+    {
+        return None;
+    }
+    Some((m, cos_i, cos_o))
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::random::{seed_thread_rand, thread_rand};
+
+    #[test]
+    fn fresnel_test()
+    {
+        // Glass reflects 4% head on, everything beyond the critical angle from the inside
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert_eq!(fresnel_dielectric(0.0, 1.5), 1.0);
+        assert_eq!(fresnel_dielectric(0.5, 1.0 / 1.5), 1.0);
+
+        // A conductor with k = 0 is a dielectric
+        let conductor = fresnel_conductor(0.7, &FColor::new_color(1.5, 1.5, 1.5), &FColor::new_color(0.0, 0.0, 0.0));
+        assert!((conductor.r() - fresnel_dielectric(0.7, 1.5)).abs() < 1e-9);
+
+        // Gold is yellow and every metal turns white at grazing angles
+        let (eta, k) = conductor_preset("gold").unwrap();
+        let head_on = fresnel_conductor(1.0, &eta, &k);
+        assert!(head_on.r() > 0.9 && head_on.b() < 0.4);
+        assert!((fresnel_conductor(0.0, &eta, &k) - FColor::new_color(1.0, 1.0, 1.0)).length() < 1e-9);
+        assert!(conductor_preset("unobtainium").is_none());
+
+        assert_eq!(fresnel_schlick(1.0, &FColor::new_color(0.5, 0.5, 0.5)), FColor::new_color(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn refract_test()
+    {
+        let normal = Vect::make_new(0.0, 0.0, 1.0);
+        let wo = Vect::make_new(0.6, 0.0, 0.8);
+        let refracted = refract(&wo, &normal, 1.5).unwrap();
+        // Snell's law, and the ray keeps going the same way sideways
+        assert!((refracted.length() - 1.0).abs() < 1e-12);
+        assert!((refracted.x() * 1.5 + wo.x()).abs() < 1e-12);
+        assert!(refracted.z() < 0.0);
+        assert!(refract(&wo, &normal, 0.5).is_none());
+        assert_eq!(reflect(&wo, &normal), Vect::make_new(-0.6, 0.0, 0.8));
+    }
+
+    #[test]
+    fn ggx_test()
+    {
+        seed_thread_rand(31);
+        for alpha in [(0.1, 0.1), (0.5, 0.5), (1.0, 1.0), (0.2, 0.7)]
+        {
+            let ggx = Ggx::make_anisotropic(alpha.0, alpha.1);
+            // The projected microfacet area adds up to the macro surface
+            let samples = 200000;
+            let mut projected = 0.0;
+            for _ in 0..samples
+            {
+                let m = Vect::random_unit_vect();
+                projected += ggx.d(&m) * m.z().max(0.0);
+            }
+            assert!((projected / samples as f64 * 4.0 * PI - 1.0).abs() < 0.1, "alpha {:?}", alpha);
+
+            // Visible normals face the viewer and match their density
+            let wo = Vect::make_new(0.5, -0.3, 0.6).get_normalized();
+            let mut total = 0.0;
+            for _ in 0..samples
+            {
+                total += ggx.visible_normal_pdf(&wo, &Vect::random_unit_vect());
+            }
+            assert!((total / samples as f64 * 4.0 * PI - 1.0).abs() < 0.1, "alpha {:?}", alpha);
+            for _ in 0..100
+            {
+                let m = ggx.sample_visible_normal(&wo, thread_rand(), thread_rand());
+                assert!((m.length() - 1.0).abs() < 1e-9);
+                assert!(Vect::dot(&wo, &m) >= 0.0 && m.z() >= 0.0);
+            }
+
+            assert!(ggx.g(&wo, &wo) <= ggx.g1(&wo));
+            assert!((ggx.g1(&Vect::make_new(0.0, 0.0, 1.0)) - 1.0).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn frame_test()
+    {
+        let frame = Frame::make_new(Vect::make_new(1.0, 2.0, -2.0).get_normalized());
+        let direction = Vect::make_new(0.3, -0.2, 0.9);
+        assert!((frame.to_world(&frame.to_local(&direction)) - direction).length() < 1e-12);
+        assert!((frame.to_local(&Vect::make_new(1.0, 2.0, -2.0).get_normalized()) - Vect::make_new(0.0, 0.0, 1.0)).length() < 1e-12);
+
+        // The tangent is kept in the plane of the normal, and ignored when it is useless
+        let normal = Vect::make_new(0.0, 1.0, 0.0);
+        let frame = Frame::make_with_tangent(normal, &Vect::make_new(2.0, 1.0, 0.0));
+        assert!((frame.to_local(&Vect::make_new(1.0, 0.0, 0.0)) - Vect::make_new(1.0, 0.0, 0.0)).length() < 1e-12);
+        assert!((frame.to_world(&frame.to_local(&direction)) - direction).length() < 1e-12);
+        let frame = Frame::make_with_tangent(normal, &Vect::make_new(0.0, 3.0, 0.0));
+        assert!((frame.to_world(&frame.to_local(&direction)) - direction).length() < 1e-12);
+    }
+}
@@ -1,10 +1,23 @@
-pub mod vect;
-pub mod point;
-pub mod ray;
-pub mod camera;
-pub mod hittable;
-pub mod sphere;
-pub mod core;
-pub mod random;
-pub mod framebuffer;
-pub mod material;
\ No newline at end of file
+pub mod vect;
+pub mod matrix;
+pub mod point;
+pub mod ray;
+pub mod camera;
+pub mod hittable;
+pub mod sphere;
+pub mod core;
+pub mod random;
+pub mod framebuffer;
+pub mod material;
+pub mod aabb;
+pub mod bvh;
+pub mod triangle;
+pub mod light;
+pub mod distribution;
+pub mod noise;
+pub mod texture;
+pub mod microfacet;
+pub mod principled;
+pub mod medium;
+pub mod grid;
+pub mod transform;
@@ -0,0 +1,215 @@
+use super::point::Point;
+use super::random::{mix_seed, thread_rand};
+use super::vect::Vect;
+
+// Coherent noise functions for procedural textures. Every generator picks its own random
+// layout when it is created, so two textures built from the same seed look the same.
+
+const POINT_COUNT: usize = 256;
+
+// Ken Perlin's gradient noise with random unit gradients at the lattice points
+pub struct Perlin
+{
+    gradients: Vec<Vect>,
+    permutations: [Vec<usize>; 3],
+}
+
+fn make_permutation() -> Vec<usize>
+{
+    let mut permutation: Vec<usize> = (0..POINT_COUNT).collect();
+    for ind in (1..POINT_COUNT).rev()
+    {
+        let target = ((thread_rand() * (ind + 1) as f64) as usize).min(ind);
+        permutation.swap(ind, target);
+    }
+    permutation
+}
+
+impl Perlin
+{
+    pub fn new() -> Self
+    {
+        Perlin
+        {
+            gradients: (0..POINT_COUNT).map(|_| Vect::random_unit_vect()).collect(),
+            permutations: [make_permutation(), make_permutation(), make_permutation()],
+        }
+    }
+
+    fn gradient(&self, x: i64, y: i64, z: i64) -> &Vect
+    {
+        let [px, py, pz] = &self.permutations;
+        let mask = POINT_COUNT as i64 - 1;
+        &self.gradients[px[(x & mask) as usize] ^ py[(y & mask) as usize] ^ pz[(z & mask) as usize]]
+    }
+
+    // Smooth value in about [-1, 1], zero on the lattice points
+    pub fn noise(&self, point: &Point) -> f64
+    {
+        let floor = [point.x().floor(), point.y().floor(), point.z().floor()];
+        let fraction = [point.x() - floor[0], point.y() - floor[1], point.z() - floor[2]];
+        // Hermite smoothing hides the lattice
+        let smooth = fraction.map(|t| t * t * (3.0 - 2.0 * t));
+        let [x, y, z] = floor.map(|value| value as i64);
+
+        let mut total = 0.0;
+        for di in 0..2
+        {
+            for dj in 0..2
+            {
+                for dk in 0..2
+                {
+                    let offset = Vect::make_new(fraction[0] - di as f64, fraction[1] - dj as f64, fraction[2] - dk as f64);
+                    let weight = (if di == 1 { smooth[0] } else { 1.0 - smooth[0] })
+                               * (if dj == 1 { smooth[1] } else { 1.0 - smooth[1] })
+                               * (if dk == 1 { smooth[2] } else { 1.0 - smooth[2] });
+                    total += weight * Vect::dot(self.gradient(x + di, y + dj, z + dk), &offset);
+                }
+            }
+        }
+        total
+    }
+
+    // Sum of the absolute noise over octaves of doubling frequency and halving amplitude, in [0, 2)
+    pub fn turbulence(&self, point: &Point, octaves: usize) -> f64
+    {
+        let mut total = 0.0;
+        let mut point = *point;
+        let mut weight = 1.0;
+        for _ in 0..octaves
+        {
+            total += weight * self.noise(&point).abs();
+            weight *= 0.5;
+            point *= 2.0;
+        }
+        total
+    }
+}
+
+// Cellular (Worley) noise: one random feature point per unit cell, every position belongs to the closest one
+pub struct Voronoi
+{
+    seed: u64,
+}
+
+pub struct VoronoiSample
+{
+    // Distances to the closest and second closest feature points
+    pub closest: f64,
+    pub second: f64,
+    // Random value in [0, 1) shared by every position of the closest point's cell
+    pub cell_value: f64,
+}
+
+fn unit_from_hash(hash: u64) -> f64
+{
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+impl Voronoi
+{
+    pub fn new() -> Self
+    {
+        Voronoi{ seed: (thread_rand() * u64::MAX as f64) as u64 }
+    }
+
+    fn cell_hash(&self, x: i64, y: i64, z: i64) -> u64
+    {
+        mix_seed(mix_seed(mix_seed(self.seed, x as u64), y as u64), z as u64)
+    }
+
+    pub fn sample(&self, point: &Point) -> VoronoiSample
+    {
+        let cell = [point.x().floor() as i64, point.y().floor() as i64, point.z().floor() as i64];
+        let mut result = VoronoiSample{ closest: f64::INFINITY, second: f64::INFINITY, cell_value: 0.0 };
+        // Feature points stay inside their cells, so the neighbours are enough
+        for dx in -1..=1
+        {
+            for dy in -1..=1
+            {
+                for dz in -1..=1
+                {
+                    let (x, y, z) = (cell[0] + dx, cell[1] + dy, cell[2] + dz);
+                    let hash = self.cell_hash(x, y, z);
+                    let feature = Point::make_new(x as f64 + unit_from_hash(mix_seed(hash, 1)),
+                                                  y as f64 + unit_from_hash(mix_seed(hash, 2)),
+                                                  z as f64 + unit_from_hash(mix_seed(hash, 3)));
+                    let distance = (feature - point).length();
+                    if distance < result.closest
+                    {
+                        result.second = result.closest;
+                        result.closest = distance;
+                        result.cell_value = unit_from_hash(hash);
+                    }
+                    else if distance < result.second
+                    {
+                        result.second = distance;
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::random::seed_thread_rand;
+
+    #[test]
+    fn perlin_test()
+    {
+        seed_thread_rand(12);
+        let perlin = Perlin::new();
+        // Zero on the lattice, bounded and continuous in between
+        assert_eq!(perlin.noise(&Point::make_new(3.0, -2.0, 7.0)), 0.0);
+        let mut previous = perlin.noise(&Point::make_new(0.5, 0.3, 0.1));
+        let mut spread = 0.0f64;
+        for ind in 1..2000
+        {
+            let value = perlin.noise(&Point::make_new(0.5 + ind as f64 * 0.001, 0.3, 0.1));
+            assert!(value.abs() <= 1.5);
+            assert!((value - previous).abs() < 0.01);
+            spread = spread.max(value.abs());
+            previous = value;
+        }
+        assert!(spread > 0.1);
+
+        let turbulence = perlin.turbulence(&Point::make_new(1.3, 2.7, -0.4), 7);
+        assert!((0.0..2.0 * 1.5).contains(&turbulence));
+
+        // The same seed builds the same noise
+        seed_thread_rand(12);
+        assert_eq!(Perlin::new().noise(&Point::make_new(0.25, 0.5, 0.75)), perlin.noise(&Point::make_new(0.25, 0.5, 0.75)));
+    }
+
+    #[test]
+    fn voronoi_test()
+    {
+        seed_thread_rand(4);
+        let voronoi = Voronoi::new();
+        for ind in 0..500
+        {
+            let point = Point::make_new(ind as f64 * 0.137, ind as f64 * -0.071, 2.5);
+            let sample = voronoi.sample(&point);
+            assert!(sample.closest <= sample.second);
+            assert!(sample.closest < 3f64.sqrt());
+            assert!((0.0..1.0).contains(&sample.cell_value));
+
+            // Moving slightly towards the inside of a cell keeps the same cell
+            if sample.second - sample.closest > 0.1
+            {
+                let nearby = voronoi.sample(&(point + Vect::make_new(0.01, 0.0, 0.0)));
+                assert_eq!(nearby.cell_value, sample.cell_value);
+            }
+        }
+    }
+}
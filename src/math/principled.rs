@@ -0,0 +1,458 @@
+use std::sync::Arc;
+
+use super::core::*;
+use super::hittable::HitResult;
+use super::material::*;
+use super::microfacet::*;
+use super::random::thread_rand;
+use super::ray::Ray;
+use super::texture::*;
+use super::vect::Vect;
+use super::super::utils::color::FColor;
+
+// Single material covering plastics, metals, glass and everything in between, after Burley,
+// "Physically Based Shading at Disney" (2012) and "Extending the Disney BRDF to a BSDF with Integrated
+// Subsurface Scattering" (2015). It layers a diffuse base with sheen, a GGX specular lobe, a clearcoat
+// and a rough glass interface, weighted by the parameters.
+
+// Every parameter but the index of refraction is read from a texture. Scalar parameters use the
+// average of the texture's channels and are clamped to [0, 1].
+#[derive(Clone)]
+pub struct PrincipledParameters
+{
+    pub base_color: Arc<dyn Texture>,
+    // 0 is a dielectric, 1 a metal tinted by the base color
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    // Reflectance of dielectrics at normal incidence, 0.5 is 4% like most plastics and glass
+    pub specular: Arc<dyn Texture>,
+    // How much dielectric reflections take the hue of the base color
+    pub specular_tint: Arc<dyn Texture>,
+    // Stretches highlights along the surface tangent
+    pub anisotropic: Arc<dyn Texture>,
+    // Soft grazing reflection of cloth
+    pub sheen: Arc<dyn Texture>,
+    // Second, white and glossier specular layer like a varnish
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_gloss: Arc<dyn Texture>,
+    // Share of the dielectric part that goes through the surface like glass
+    pub transmission: Arc<dyn Texture>,
+    pub index_of_refraction: f64,
+}
+
+fn constant(value: f64) -> Arc<dyn Texture>
+{
+    Arc::new(SolidColor::make_new(FColor::new_color(value, value, value)))
+}
+
+impl PrincipledParameters
+{
+    pub fn default() -> Self
+    {
+        PrincipledParameters
+        {
+            base_color: constant(0.8),
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            specular_tint: constant(0.0),
+            anisotropic: constant(0.0),
+            sheen: constant(0.0),
+            clearcoat: constant(0.0),
+            clearcoat_gloss: constant(1.0),
+            transmission: constant(0.0),
+            index_of_refraction: 1.5,
+        }
+    }
+
+    // Sets a scalar parameter to a constant by name, as used by the scene files
+    pub fn set_constant(&mut self, name: &str, value: f64) -> bool
+    {
+        match self.scalar_mut(name)
+        {
+            Some(parameter) => *parameter = constant(value),
+            None => return false,
+        }
+        true
+    }
+
+    // Texture slot of a scalar parameter, None for unknown names
+    pub fn scalar_mut(&mut self, name: &str) -> Option<&mut Arc<dyn Texture>>
+    {
+        match name
+        {
+            "metallic" => Some(&mut self.metallic),
+            "roughness" => Some(&mut self.roughness),
+            "specular" => Some(&mut self.specular),
+            "specular_tint" => Some(&mut self.specular_tint),
+            "anisotropic" => Some(&mut self.anisotropic),
+            "sheen" => Some(&mut self.sheen),
+            "clearcoat" => Some(&mut self.clearcoat),
+            "clearcoat_gloss" => Some(&mut self.clearcoat_gloss),
+            "transmission" => Some(&mut self.transmission),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Principled
+{
+    parameters: PrincipledParameters,
+}
+
+impl Principled
+{
+    pub fn default() -> Self
+    {
+        Principled::make_new(PrincipledParameters::default())
+    }
+
+    pub fn new(parameters: &PrincipledParameters) -> Self
+    {
+        Principled::make_new(parameters.clone())
+    }
+
+    pub fn make_new(parameters: PrincipledParameters) -> Self
+    {
+        Principled{ parameters }
+    }
+
+    pub fn parameters(&self) -> &PrincipledParameters
+    {
+        &self.parameters
+    }
+
+    // Looks the textures up at the hit point
+    fn lobes(&self, hit_result: &HitResult) -> Lobes
+    {
+        let parameters = &self.parameters;
+        let scalar = |texture: &Arc<dyn Texture>| scalar_value(texture, hit_result);
+        let base_color = texture_value(parameters.base_color.as_ref(), hit_result);
+        let metallic = scalar(&parameters.metallic);
+        let roughness = scalar(&parameters.roughness);
+        let transmission = scalar(&parameters.transmission);
+
+        // Hue of the base color without its brightness
+        let luminance = 0.2126 * base_color.r() + 0.7152 * base_color.g() + 0.0722 * base_color.b();
+        let tint = if luminance > 0.0 { base_color / luminance } else { FColor::new_color(1.0, 1.0, 1.0) };
+        let white = FColor::new_color(1.0, 1.0, 1.0);
+
+        let specular_tint = scalar(&parameters.specular_tint);
+        let dielectric_f0 = (white * (1.0 - specular_tint) + tint * specular_tint) * (0.08 * scalar(&parameters.specular));
+        let specular_f0 = dielectric_f0 * (1.0 - metallic) + base_color * metallic;
+
+        let aspect = (1.0 - 0.9 * scalar(&parameters.anisotropic)).sqrt();
+        let alpha = roughness_to_alpha(roughness).max(MIN_ALPHA);
+        let clearcoat_gloss = scalar(&parameters.clearcoat_gloss);
+
+        let diffuse = (1.0 - metallic) * (1.0 - transmission);
+        let glass = (1.0 - metallic) * transmission;
+        let clearcoat = 0.25 * scalar(&parameters.clearcoat);
+        let eta = relative_index(parameters.index_of_refraction, hit_result);
+        Lobes
+        {
+            base_color,
+            roughness,
+            // Sheen is slightly tinted towards the base color
+            sheen: (white + tint) * (0.5 * scalar(&parameters.sheen)),
+            specular_f0,
+            ggx: Ggx::make_anisotropic(alpha / aspect, alpha * aspect),
+            clearcoat_alpha: 0.1 * (1.0 - clearcoat_gloss) + 0.001 * clearcoat_gloss,
+            interface: DielectricInterface::make_new(eta, alpha / aspect, alpha * aspect),
+            weights: [diffuse, 1.0 - glass, clearcoat, glass],
+        }
+    }
+}
+
+// Value of a scalar parameter at the hit point
+fn scalar_value(texture: &Arc<dyn Texture>, hit_result: &HitResult) -> f64
+{
+    let value = texture_value(texture.as_ref(), hit_result);
+    clamp((value.r() + value.g() + value.b()) / 3.0, 0.0, 1.0)
+}
+
+const DIFFUSE: usize = 0;
+const SPECULAR: usize = 1;
+const CLEARCOAT: usize = 2;
+const GLASS: usize = 3;
+
+// The parameters at one hit point, in the local shading frame
+struct Lobes
+{
+    base_color: FColor,
+    roughness: f64,
+    sheen: FColor,
+    specular_f0: FColor,
+    ggx: Ggx,
+    clearcoat_alpha: f64,
+    interface: DielectricInterface,
+    // Weights of the diffuse, specular, clearcoat and glass lobes, also used to pick one of them
+    weights: [f64; 4],
+}
+
+// Generalized Trowbridge-Reitz distribution with gamma = 1, whose long tail gives the clearcoat its halo
+fn gtr1(cos_theta: f64, alpha: f64) -> f64
+{
+    let alpha2 = alpha * alpha;
+    (alpha2 - 1.0) / (PI * alpha2.ln() * (1.0 + (alpha2 - 1.0) * cos_theta * cos_theta))
+}
+
+impl Lobes
+{
+    fn eval(&self, wi: &Vect, wo: &Vect) -> FColor
+    {
+        let mut value = FColor::new_color(0.0, 0.0, 0.0);
+        if wo.z() <= 0.0 || wi.z() == 0.0
+        {
+            return value;
+        }
+        if self.weights[GLASS] > 0.0
+        {
+            let glass = self.weights[GLASS] * self.interface.eval(wi, wo);
+            // Light going through is colored by the base color, reflections stay white
+            value += if wi.z() < 0.0 { self.base_color * glass } else { FColor::new_color(glass, glass, glass) };
+        }
+        if wi.z() < 0.0
+        {
+            return value;
+        }
+
+        let half = (wi + wo).get_normalized();
+        let cos_d = Vect::dot(wi, &half);
+        if self.weights[DIFFUSE] > 0.0
+        {
+            // Burley's diffuse gets darker at grazing angles when smooth and brighter when rough
+            let schlick = |cosine: f64| (1.0 - cosine).max(0.0).powi(5);
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let diffuse = (1.0 + (fd90 - 1.0) * schlick(wi.z())) * (1.0 + (fd90 - 1.0) * schlick(wo.z())) / PI;
+            value += (self.base_color * diffuse + self.sheen * schlick(cos_d)) * (self.weights[DIFFUSE] * wi.z());
+        }
+        if self.weights[SPECULAR] > 0.0
+        {
+            let specular = self.ggx.d(&half) * self.ggx.g(wo, wi) / (4.0 * wo.z());
+            value += fresnel_schlick(Vect::dot(wo, &half), &self.specular_f0) * (self.weights[SPECULAR] * specular);
+        }
+        if self.weights[CLEARCOAT] > 0.0
+        {
+            let reflectance = fresnel_schlick(Vect::dot(wo, &half), &FColor::new_color(0.04, 0.04, 0.04)).r();
+            let clearcoat = gtr1(half.z(), self.clearcoat_alpha) * Ggx::make_new(0.25).g(wo, wi) * reflectance / (4.0 * wo.z());
+            value += FColor::new_color(1.0, 1.0, 1.0) * (self.weights[CLEARCOAT] * clearcoat);
+        }
+        value
+    }
+
+    fn lobe_pdf(&self, lobe: usize, wi: &Vect, wo: &Vect) -> f64
+    {
+        if lobe == GLASS
+        {
+            return self.interface.pdf(wi, wo);
+        }
+        if wi.z() <= 0.0 || wo.z() <= 0.0
+        {
+            return 0.0;
+        }
+        let half = (wi + wo).get_normalized();
+        match lobe
+        {
+            DIFFUSE => wi.z() / PI,
+            SPECULAR => self.ggx.visible_normal_pdf(wo, &half) / (4.0 * Vect::dot(wo, &half)),
+            _ => gtr1(half.z(), self.clearcoat_alpha) * half.z() / (4.0 * Vect::dot(wo, &half)),
+        }
+    }
+
+    fn pdf(&self, wi: &Vect, wo: &Vect) -> f64
+    {
+        let total: f64 = self.weights.iter().sum();
+        (0..self.weights.len()).filter(|&lobe| self.weights[lobe] > 0.0)
+                               .map(|lobe| self.weights[lobe] / total * self.lobe_pdf(lobe, wi, wo))
+                               .sum()
+    }
+
+    fn sample(&self, wo: &Vect) -> Option<Vect>
+    {
+        let total: f64 = self.weights.iter().sum();
+        let mut choice = thread_rand() * total;
+        let lobe = (0..self.weights.len()).find(|&lobe|
+        {
+            choice -= self.weights[lobe];
+            choice < 0.0 && self.weights[lobe] > 0.0
+        }).unwrap_or(SPECULAR);
+
+        let wi = match lobe
+        {
+            DIFFUSE => Vect::random_cosine_direction(&Vect::make_new(0.0, 0.0, 1.0)),
+            SPECULAR => reflect(wo, &self.ggx.sample_visible_normal(wo, thread_rand(), thread_rand())),
+            CLEARCOAT =>
+            {
+                let alpha2 = self.clearcoat_alpha * self.clearcoat_alpha;
+                let cos2 = (1.0 - alpha2.powf(1.0 - thread_rand())) / (1.0 - alpha2);
+                let sin = (1.0 - cos2).max(0.0).sqrt();
+                let phi = 2.0 * PI * thread_rand();
+                let half = Vect::make_new(sin * phi.cos(), sin * phi.sin(), cos2.sqrt());
+                reflect(wo, &half)
+            }
+            _ => self.interface.sample(wo, thread_rand(), thread_rand(), thread_rand())?.0,
+        };
+        if lobe != GLASS && wi.z() <= 0.0
+        {
+            return None;
+        }
+        Some(wi)
+    }
+}
+
+impl Material for Principled
+{
+    fn scatter(&self, ray: &Ray, hit_result: &HitResult) -> Option<(FColor, Ray)>
+    {
+        let frame = shading_frame(hit_result);
+        let wo = frame.to_local(&-ray.direction());
+        if wo.z() <= 0.0
+        {
+            return None;
+        }
+        let lobes = self.lobes(hit_result);
+        let wi = lobes.sample(&wo)?;
+
+        // Every lobe could have produced wi, so the weight uses the combined density
+        let pdf = lobes.pdf(&wi, &wo);
+        if pdf <= 0.0
+        {
+            return None;
+        }
+        let direction = frame.to_world(&wi).get_normalized();
+        Some((lobes.eval(&wi, &wo) / pdf, Ray::make_new(*hit_result.point(), direction)))
+    }
+
+    fn eval(&self, wi: &Vect, wo: &Vect, hit_result: &HitResult) -> FColor
+    {
+        let frame = shading_frame(hit_result);
+        self.lobes(hit_result).eval(&frame.to_local(wi), &frame.to_local(wo))
+    }
+
+    fn pdf(&self, wi: &Vect, wo: &Vect, hit_result: &HitResult) -> f64
+    {
+        let frame = shading_frame(hit_result);
+        self.lobes(hit_result).pdf(&frame.to_local(wi), &frame.to_local(wo))
+    }
+
+    // Only where the glass lobe has some weight
+    fn is_transmissive(&self, hit_result: &HitResult) -> bool
+    {
+        scalar_value(&self.parameters.transmission, hit_result) > 0.0 && scalar_value(&self.parameters.metallic, hit_result) < 1.0
+    }
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::random::seed_thread_rand;
+
+    fn hit(material: Arc<dyn Material>, ray: &Ray) -> HitResult
+    {
+        HitResult::make_new(Vect::make_new(0.0, 0.0, 0.0), Vect::make_new(0.0, 0.0, 1.0), material, 1.0, ray)
+    }
+
+    // Average weight of the scattered rays, the fraction of the light the surface doesn't absorb
+    fn albedo(material: Arc<dyn Material>, ray: &Ray) -> FColor
+    {
+        let hr = hit(material, ray);
+        let wo = -ray.direction();
+        let samples = 20000;
+        let mut total = FColor::new_color(0.0, 0.0, 0.0);
+        for _ in 0..samples
+        {
+            let Some((attenuation, scattered)) = hr.material().scatter(ray, &hr) else { continue };
+            // The attenuation is eval over pdf for the picked direction
+            let pdf = hr.material().pdf(scattered.direction(), &wo, &hr);
+            assert!(pdf > 0.0);
+            let ratio = hr.material().eval(scattered.direction(), &wo, &hr) / pdf;
+            assert!((ratio - attenuation).length() < 1e-6 * attenuation.length().max(1.0));
+            total += attenuation;
+        }
+        total / samples as f64
+    }
+
+    #[test]
+    fn lobes_test()
+    {
+        seed_thread_rand(5);
+        let ray = Ray::make_new(Vect::make_new(-1.0, 0.5, 1.0), Vect::make_new(1.0, -0.5, -1.0));
+        let mut parameters = PrincipledParameters::default();
+        parameters.set_constant("anisotropic", 0.8);
+        parameters.set_constant("sheen", 1.0);
+        parameters.set_constant("clearcoat", 1.0);
+        parameters.set_constant("clearcoat_gloss", 0.7);
+        let plastic = albedo(Arc::new(Principled::make_new(parameters.clone())), &ray);
+        assert!(plastic.r() > 0.6 && plastic.r() < 1.05, "{:?}", plastic);
+
+        // A white metal reflects everything but what single scattering off the microfacets misses,
+        // a black dielectric only its specular highlight
+        parameters.base_color = constant(1.0);
+        parameters.set_constant("metallic", 1.0);
+        let metal = albedo(Arc::new(Principled::make_new(parameters.clone())), &ray);
+        assert!(metal.r() > 0.75 && metal.r() < 1.02, "{:?}", metal);
+
+        let mut parameters = PrincipledParameters::default();
+        parameters.base_color = constant(0.0);
+        let black = albedo(Arc::new(Principled::make_new(parameters)), &ray);
+        assert!(black.r() > 0.0 && black.r() < 0.15, "{:?}", black);
+    }
+
+    #[test]
+    fn transmission_test()
+    {
+        seed_thread_rand(8);
+        let mut parameters = PrincipledParameters::default();
+        parameters.base_color = constant(1.0);
+        parameters.set_constant("transmission", 1.0);
+        parameters.set_constant("roughness", 0.2);
+        let material: Arc<dyn Material> = Arc::new(Principled::make_new(parameters));
+
+        let ray = Ray::make_new(Vect::make_new(0.0, 0.3, 1.0), Vect::make_new(0.0, -0.3, -1.0));
+        let hr = hit(material.clone(), &ray);
+        let mut transmitted = 0;
+        for _ in 0..1000
+        {
+            if let Some((_, scattered)) = hr.material().scatter(&ray, &hr)
+            {
+                transmitted += (scattered.direction().z() < 0.0) as usize;
+            }
+        }
+        // Glass lets most of the light through head on
+        assert!(transmitted > 850);
+        assert!(albedo(material, &ray).r() > 0.3);
+
+        // Only glass takes light from below the surface
+        assert!(hr.material().is_transmissive(&hr));
+        let opaque = hit(Arc::new(Principled::default()), &ray);
+        assert!(!opaque.material().is_transmissive(&opaque));
+    }
+
+    #[test]
+    fn anisotropic_test()
+    {
+        let mut parameters = PrincipledParameters::default();
+        parameters.set_constant("metallic", 1.0);
+        parameters.set_constant("roughness", 0.4);
+        parameters.set_constant("anisotropic", 1.0);
+        let material: Arc<dyn Material> = Arc::new(Principled::make_new(parameters));
+
+        // Highlights stretch along the tangent
+        let ray = Ray::make_new(Vect::make_new(0.0, 0.0, 1.0), Vect::make_new(0.0, 0.0, -1.0));
+        let mut hr = hit(material, &ray);
+        hr.set_tangent(Vect::make_new(1.0, 0.0, 0.0));
+        let wo = Vect::make_new(0.0, 0.0, 1.0);
+        let along = hr.material().eval(&Vect::make_new(0.5, 0.0, 1.0).get_normalized(), &wo, &hr);
+        let across = hr.material().eval(&Vect::make_new(0.0, 0.5, 1.0).get_normalized(), &wo, &hr);
+        assert!(along.r() > 2.0 * across.r());
+    }
+}
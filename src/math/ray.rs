@@ -1,94 +1,126 @@
-use super::point::Point;
-use super::vect::Vect;
-
-#[derive(Debug, PartialEq)]
-pub struct Ray
-{
-    origin: Point,
-    direction: Vect,
-}
-
-impl Ray
-{
-    pub fn default() -> Ray
-    {
-        Ray
-        {
-            origin: Point{data: [0f64, 0f64, 0f64]},
-            direction: Vect{data: [1f64, 0f64, 0f64]},
-        }
-    }
-
-    pub fn new(p: &Point, d: &Vect) -> Ray
-    {
-        Ray{origin: *p, direction: d.get_normalized()}
-    }
-
-    pub fn make_new(p: Point, d: Vect) -> Ray
-    {
-        Ray{origin: p, direction: d.get_normalized()}
-    }
-
-    pub fn origin(&self) -> &Point
-    {
-        &self.origin
-    }
-
-    pub fn direction(&self) -> &Vect
-    {
-        &self.direction
-    }
-
-    pub fn at(&self, t: f64) -> Point
-    {
-        &self.origin + (t * &self.direction)
-    }
-}
-
-
-//============================================
-//============================================
-//===============Unit Tests===================
-//============================================
-//============================================
-
-#[cfg(test)]
-mod tests
-{
-    use super::*;
-
-    #[test]
-    fn creation_test()
-    {
-        let r = Ray::default();
-        assert_eq!(r, Ray
-                        {
-                            origin: Point{data: [0f64, 0f64, 0f64]},
-                            direction: Vect{data: [1f64, 0f64, 0f64]},
-                        });
-
-        let u = Vect{data:[1f64, 2f64, 3f64]};
-        let v = Vect{data:[4f64, 5f64, 6f64]};
-        let r = Ray::new(&u, &v);
-        assert_eq!(r, Ray
-                        {
-                            origin: Point{data: [1f64, 2f64, 3f64]},
-                            direction: Vect{data: [4f64, 5f64, 6f64]},
-                        });
-        let r = Ray::make_new(u, v);
-        assert_eq!(r, Ray
-            {
-                origin: Point{data: [1f64, 2f64, 3f64]},
-                direction: Vect{data: [4f64, 5f64, 6f64]},
-            });
-    }
-
-    #[test]
-    fn at_test()
-    {
-        let u = Vect{data:[1f64, 2f64, 3f64]};
-        let v = Vect{data:[4f64, 5f64, 6f64]};
-        let r = Ray::make_new(u, v);
-        assert_eq!(r.at(2f64), Vect{data:[9f64, 12f64, 15f64]});
-    }
+use super::point::Point;
+use super::vect::Vect;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Ray
+{
+    origin: Point,
+    direction: Vect,
+    // Wavelength in nanometers carried by spectral paths, None for paths covering the whole spectrum
+    wavelength: Option<f64>,
+    // Moment within the camera shutter interval at which the ray is traced, placing moving objects
+    time: f64,
+}
+
+impl Ray
+{
+    pub fn default() -> Ray
+    {
+        Ray
+        {
+            origin: Point{data: [0f64, 0f64, 0f64]},
+            direction: Vect{data: [1f64, 0f64, 0f64]},
+            wavelength: None,
+            time: 0.0,
+        }
+    }
+
+    pub fn new(p: &Point, d: &Vect) -> Ray
+    {
+        Ray{origin: *p, direction: d.get_normalized(), wavelength: None, time: 0.0}
+    }
+
+    pub fn make_new(p: Point, d: Vect) -> Ray
+    {
+        Ray{origin: p, direction: d.get_normalized(), wavelength: None, time: 0.0}
+    }
+
+    pub fn origin(&self) -> &Point
+    {
+        &self.origin
+    }
+
+    pub fn direction(&self) -> &Vect
+    {
+        &self.direction
+    }
+
+    pub fn wavelength(&self) -> Option<f64>
+    {
+        self.wavelength
+    }
+
+    pub fn set_wavelength(&mut self, wavelength: Option<f64>)
+    {
+        self.wavelength = wavelength;
+    }
+
+    pub fn time(&self) -> f64
+    {
+        self.time
+    }
+
+    pub fn set_time(&mut self, time: f64)
+    {
+        self.time = time;
+    }
+
+    pub fn at(&self, t: f64) -> Point
+    {
+        self.origin + (t * self.direction)
+    }
+}
+
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn creation_test()
+    {
+        let r = Ray::default();
+        assert_eq!(r, Ray
+                        {
+                            origin: Point{data: [0f64, 0f64, 0f64]},
+                            direction: Vect{data: [1f64, 0f64, 0f64]},
+                            wavelength: None,
+                            time: 0.0,
+                        });
+
+        let u = Vect{data:[1f64, 2f64, 3f64]};
+        let v = Vect{data:[4f64, 5f64, 6f64]};
+        let r = Ray::new(&u, &v);
+        assert_eq!(r, Ray
+                        {
+                            origin: Point{data: [1f64, 2f64, 3f64]},
+                            direction: v.get_normalized(),
+                            wavelength: None,
+                            time: 0.0,
+                        });
+        let r = Ray::make_new(u, v);
+        assert_eq!(r, Ray
+            {
+                origin: Point{data: [1f64, 2f64, 3f64]},
+                direction: v.get_normalized(),
+                wavelength: None,
+                time: 0.0,
+            });
+    }
+
+    #[test]
+    fn at_test()
+    {
+        let u = Vect{data:[1f64, 2f64, 3f64]};
+        let v = Vect{data:[4f64, 5f64, 6f64]};
+        let r = Ray::make_new(u, v);
+        assert_eq!(r.at(2f64), u + 2f64 * v.get_normalized());
+    }
 }
\ No newline at end of file
@@ -1,89 +1,281 @@
-use std::marker;
-use std::sync::Arc;
-
-use super::hittable::*;
-use super::vect::Vect;
-use super::ray::Ray;
-use super::point::Point;
-use super::material::*;
-
-pub struct Sphere
-{
-    pub center: Point,
-    pub radius: f64,
-    pub material: Arc<dyn Material>,
-}
-
-impl Sphere
-{
-    pub fn default() -> Self
-    {
-        Sphere
-        {
-            center: Point{data: [0.0, 0.0, 0.0]},
-            radius: 1.0,
-            material: Arc::new(Lambertian::default()),
-        }
-    }
-
-    pub fn new(c: &Point, r: f64, material: Arc<dyn Material>) -> Self
-    {
-        Sphere
-        {
-            center: Point{data: c.data},
-            radius: r,
-            material,
-        }
-    }
-
-    pub fn make_new(c: Point, r: f64, material: Arc<dyn Material>) -> Self
-    {
-        Sphere
-        {
-            center: c,
-            radius: r,
-            material,
-        }
-    }
-}
-
-impl Hittable for Sphere
-{
-
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitResult>
-    {
-        let oc = r.origin() - &self.center;
-        let a = r.direction().length_squared();
-        let h = Vect::dot(&oc, &r.direction());
-        let c = oc.length_squared() - self.radius*self.radius;
-
-        let delta = h*h - a *c;
-        if delta < 0.0
-        {
-            return None;
-        }
-        let sqrt_delta = delta.sqrt();
-
-        let mut potential_hit = (-h - sqrt_delta) / a;
-        if potential_hit < t_min || potential_hit > t_max
-        {
-            potential_hit = (-h + sqrt_delta) / a;
-            if potential_hit < t_min || potential_hit > t_max
-            {
-                return None;
-            }
-        }
-
-        let hit_point = r.at(potential_hit);
-        let result = HitResult::make_new(
-            hit_point, 
-            (hit_point - &self.center) / self.radius,
-            self.material.clone(),
-            potential_hit,
-            r,
-        );
-        Some(result)
-    }
-}
-
-unsafe impl marker::Sync for Sphere {}
\ No newline at end of file
+use std::sync::Arc;
+
+use super::aabb::Aabb;
+use super::core::*;
+use super::hittable::*;
+use super::vect::Vect;
+use super::ray::Ray;
+use super::point::Point;
+use super::material::*;
+use super::random::thread_rand;
+
+// Longitude and latitude of a point on the unit sphere, both in [0, 1].
+// u goes around the y axis starting from -x, v from the bottom to the top.
+pub fn sphere_uv(point: &Point) -> (f64, f64)
+{
+    let theta = clamp(-point.y(), -1.0, 1.0).acos();
+    let phi = (-point.z()).atan2(point.x()) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+pub struct Sphere
+{
+    // Center at time 0
+    pub center: Point,
+    // How far the center moves by time 1, zero for spheres standing still
+    pub motion: Vect,
+    pub radius: f64,
+    pub material: Arc<dyn Material>,
+}
+
+impl Sphere
+{
+    pub fn default() -> Self
+    {
+        Sphere
+        {
+            center: Point{data: [0.0, 0.0, 0.0]},
+            motion: Vect::new(),
+            radius: 1.0,
+            material: Arc::new(Lambertian::default()),
+        }
+    }
+
+    pub fn new(c: &Point, r: f64, material: Arc<dyn Material>) -> Self
+    {
+        Sphere
+        {
+            center: Point{data: c.data},
+            motion: Vect::new(),
+            radius: r,
+            material,
+        }
+    }
+
+    pub fn make_new(c: Point, r: f64, material: Arc<dyn Material>) -> Self
+    {
+        Sphere
+        {
+            center: c,
+            motion: Vect::new(),
+            radius: r,
+            material,
+        }
+    }
+
+    // Sphere moving in a straight line from one center at time 0 to the other at time 1
+    pub fn make_moving(from: Point, to: Point, r: f64, material: Arc<dyn Material>) -> Self
+    {
+        Sphere
+        {
+            center: from,
+            motion: to - from,
+            radius: r,
+            material,
+        }
+    }
+
+    // Stays at the ends of the motion before time 0 and after time 1
+    pub fn center_at(&self, time: f64) -> Point
+    {
+        self.center + clamp(time, 0.0, 1.0) * self.motion
+    }
+}
+
+impl Hittable for Sphere
+{
+
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitResult>
+    {
+        let center = self.center_at(r.time());
+        let oc = r.origin() - center;
+        let a = r.direction().length_squared();
+        let h = Vect::dot(&oc, r.direction());
+        let c = oc.length_squared() - self.radius*self.radius;
+
+        let delta = h*h - a *c;
+        if delta < 0.0
+        {
+            return None;
+        }
+        let sqrt_delta = delta.sqrt();
+
+        let mut potential_hit = (-h - sqrt_delta) / a;
+        if potential_hit < t_min || potential_hit > t_max
+        {
+            potential_hit = (-h + sqrt_delta) / a;
+            if potential_hit < t_min || potential_hit > t_max
+            {
+                return None;
+            }
+        }
+
+        let hit_point = r.at(potential_hit);
+        let mut result = HitResult::make_new(
+            hit_point, 
+            (hit_point - center) / self.radius,
+            self.material.clone(),
+            potential_hit,
+            r,
+        );
+        let local = (hit_point - center) / self.radius.abs();
+        let (u, v) = sphere_uv(&local);
+        result.set_uv(u, v);
+        // Along the parallels, towards growing u
+        result.set_tangent(Vect::make_new(local.z(), 0.0, -local.x()));
+        Some(result)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb>
+    {
+        // Hollow spheres are modelled with a negative radius
+        let extent = Vect::make_new(self.radius.abs(), self.radius.abs(), self.radius.abs());
+        let end = self.center + self.motion;
+        Some(Aabb::surrounding(&Aabb::make_new(self.center - extent, self.center + extent), &Aabb::make_new(end - extent, end + extent)))
+    }
+
+    fn pdf_value(&self, origin: &Point, direction: &Vect, time: f64) -> f64
+    {
+        let mut ray = Ray::make_new(*origin, *direction);
+        ray.set_time(time);
+        let Some(hit_result) = self.hit(&ray, 0.001, INFINITY)
+        else
+        {
+            return 0.0;
+        };
+
+        let radius = self.radius.abs();
+        let distance_squared = (self.center_at(time) - origin).length_squared();
+        if distance_squared > radius * radius
+        {
+            // Uniform over the cone of directions that the sphere covers
+            let cos_theta_max = (1.0 - radius * radius / distance_squared).sqrt();
+            return 1.0 / (2.0 * PI * (1.0 - cos_theta_max));
+        }
+        // From the inside, points are picked uniformly over the surface area
+        let cosine = Vect::dot(hit_result.normal(), ray.direction()).abs();
+        if cosine < FLOAT_MARGIN_OF_ERROR
+        {
+            return 0.0;
+        }
+        hit_result.t() * hit_result.t() / (cosine * 4.0 * PI * radius * radius)
+    }
+
+    fn random_direction(&self, origin: &Point, time: f64) -> Vect
+    {
+        let radius = self.radius.abs();
+        let center = self.center_at(time);
+        let to_center = center - origin;
+        let distance_squared = to_center.length_squared();
+        if distance_squared <= radius * radius
+        {
+            return center + radius * Vect::random_unit_vect() - origin;
+        }
+
+        let cos_theta_max = (1.0 - radius * radius / distance_squared).sqrt();
+        let cos_theta = 1.0 + thread_rand() * (cos_theta_max - 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * thread_rand();
+
+        let w = to_center.get_normalized();
+        let (u, v) = w.orthonormal_basis();
+        (phi.cos() * sin_theta) * u + (phi.sin() * sin_theta) * v + cos_theta * w
+    }
+
+    fn is_emissive(&self) -> bool
+    {
+        self.material.is_emissive()
+    }
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::random::seed_thread_rand;
+
+    #[test]
+    fn uv_test()
+    {
+        let sphere = Sphere::make_new(Point::make_new(0.0, 0.0, -2.0), 0.5, Arc::new(Lambertian::default()));
+        // Hitting the sphere on its +z side, a quarter of the way around from -x
+        let r = Ray::make_new(Point::make_new(0.0, 0.0, 0.0), Vect::make_new(0.0, 0.0, -1.0));
+        let hit = sphere.hit(&r, 0.001, INFINITY).unwrap();
+        let (u, v) = hit.uv();
+        assert!((u - 0.25).abs() < 1e-9 && (v - 0.5).abs() < 1e-9);
+        // u grows towards +x there
+        assert_eq!(*hit.tangent(), Vect::make_new(1.0, 0.0, 0.0));
+
+        assert_eq!(sphere_uv(&Point::make_new(-1.0, 0.0, 0.0)), (0.0, 0.5));
+        assert_eq!(sphere_uv(&Point::make_new(1.0, 0.0, 0.0)), (0.5, 0.5));
+        assert_eq!(sphere_uv(&Point::make_new(0.0, 1.0, 0.0)).1, 1.0);
+        assert_eq!(sphere_uv(&Point::make_new(0.0, -1.0, 0.0)).1, 0.0);
+
+        // Hollow spheres map the same way from the inside
+        let bubble = Sphere::make_new(Point::make_new(0.0, 0.0, 0.0), -1.0, Arc::new(Lambertian::default()));
+        let (u, v) = bubble.hit(&r, 0.001, INFINITY).unwrap().uv();
+        assert!((u - 0.75).abs() < 1e-9 && (v - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn light_sampling_test()
+    {
+        seed_thread_rand(7);
+        let sphere = Sphere::make_new(Point::make_new(0.0, 0.0, -4.0), 2.0, Arc::new(DiffuseLight::default()));
+        let origin = Point::make_new(0.0, 0.0, 0.0);
+
+        // Seen from 4 units away, the sphere covers a cone with cos(theta_max) = sqrt(3) / 2
+        let expected = 1.0 / (2.0 * PI * (1.0 - 0.75f64.sqrt()));
+        assert!((sphere.pdf_value(&origin, &Vect::make_new(0.0, 0.0, -1.0), 0.0) - expected).abs() < 1e-9);
+        assert_eq!(sphere.pdf_value(&origin, &Vect::make_new(0.0, 1.0, 0.0), 0.0), 0.0);
+        for _ in 0..100
+        {
+            let direction = sphere.random_direction(&origin, 0.0);
+            assert!(sphere.pdf_value(&origin, &direction, 0.0) > 0.0);
+        }
+
+        // Inside, area sampling covers every direction once so the density integrates to one
+        let inside = Point::make_new(0.0, 0.5, -4.0);
+        let samples = 20000;
+        let total: f64 = (0..samples).map(|_| sphere.pdf_value(&inside, &Vect::random_unit_vect(), 0.0)).sum();
+        assert!((total / samples as f64 * 4.0 * PI - 1.0).abs() < 0.02);
+        assert!(sphere.is_emissive());
+        assert!(!Sphere::default().is_emissive());
+    }
+
+    #[test]
+    fn motion_test()
+    {
+        let sphere = Sphere::make_moving(Point::make_new(0.0, 0.0, -4.0), Point::make_new(2.0, 0.0, -4.0), 0.5, Arc::new(DiffuseLight::default()));
+        assert_eq!(sphere.center_at(0.5), Point::make_new(1.0, 0.0, -4.0));
+        assert_eq!(sphere.center_at(2.0), Point::make_new(2.0, 0.0, -4.0));
+        let bbox = sphere.bounding_box().unwrap();
+        assert_eq!((*bbox.minimum(), *bbox.maximum()), (Point::make_new(-0.5, -0.5, -4.5), Point::make_new(2.5, 0.5, -3.5)));
+
+        // The ray's time decides where the sphere is
+        let mut r = Ray::make_new(Point::make_new(2.0, 0.0, 0.0), Vect::make_new(0.0, 0.0, -1.0));
+        assert!(sphere.hit(&r, 0.001, INFINITY).is_none());
+        r.set_time(1.0);
+        let hit = sphere.hit(&r, 0.001, INFINITY).unwrap();
+        assert!((hit.t() - 3.5).abs() < 1e-9);
+        assert_eq!(*hit.normal(), Vect::make_new(0.0, 0.0, 1.0));
+
+        // and so does the time given to light sampling
+        let origin = Point::make_new(0.0, 0.0, 0.0);
+        for time in [0.0, 0.5, 1.0]
+        {
+            let direction = sphere.random_direction(&origin, time);
+            let mut r = Ray::make_new(origin, direction);
+            r.set_time(time);
+            assert!(sphere.hit(&r, 0.001, INFINITY).is_some());
+            assert!(sphere.pdf_value(&origin, &direction, time) > 0.0);
+        }
+        assert_eq!(sphere.pdf_value(&origin, &Vect::make_new(0.0, 0.0, -1.0), 1.0), 0.0);
+    }
+}
\ No newline at end of file
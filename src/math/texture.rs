@@ -0,0 +1,373 @@
+use std::sync::Arc;
+
+use super::core::*;
+use super::framebuffer::FrameBuffer;
+use super::noise::*;
+use super::point::Point;
+use super::super::utils::color::FColor;
+
+// Colors that vary over a surface, looked up with the (u, v) coordinates and the position of a hit.
+// Procedural textures are solid: they use the position and look carved out of a block of material.
+
+pub trait Texture: Send + Sync
+{
+    fn value(&self, u: f64, v: f64, point: &Point) -> FColor;
+}
+
+fn lerp(a: &FColor, b: &FColor, t: f64) -> FColor
+{
+    (1.0 - t) * a + t * b
+}
+
+pub struct SolidColor
+{
+    color: FColor,
+}
+
+impl SolidColor
+{
+    pub fn new(color: &FColor) -> Self
+    {
+        SolidColor{ color: *color }
+    }
+
+    pub fn make_new(color: FColor) -> Self
+    {
+        SolidColor{ color }
+    }
+}
+
+impl Texture for SolidColor
+{
+    fn value(&self, _u: f64, _v: f64, _point: &Point) -> FColor
+    {
+        self.color
+    }
+}
+
+// Alternates between two textures in cubes of side scale
+pub struct CheckerTexture
+{
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+    scale: f64,
+}
+
+impl CheckerTexture
+{
+    pub fn make_new(even: Arc<dyn Texture>, odd: Arc<dyn Texture>, scale: f64) -> Self
+    {
+        CheckerTexture{ even, odd, scale }
+    }
+}
+
+impl Texture for CheckerTexture
+{
+    fn value(&self, u: f64, v: f64, point: &Point) -> FColor
+    {
+        let cell = (point.x() / self.scale).floor() + (point.y() / self.scale).floor() + (point.z() / self.scale).floor();
+        if cell.rem_euclid(2.0) == 0.0
+        {
+            self.even.value(u, v, point)
+        }
+        else
+        {
+            self.odd.value(u, v, point)
+        }
+    }
+}
+
+// Perlin noise between black and color, scale is the frequency of the features
+pub struct NoiseTexture
+{
+    perlin: Perlin,
+    color: FColor,
+    scale: f64,
+}
+
+impl NoiseTexture
+{
+    pub fn make_new(color: FColor, scale: f64) -> Self
+    {
+        NoiseTexture{ perlin: Perlin::new(), color, scale }
+    }
+}
+
+impl Texture for NoiseTexture
+{
+    fn value(&self, _u: f64, _v: f64, point: &Point) -> FColor
+    {
+        self.color * clamp(0.5 * (1.0 + self.perlin.noise(&(self.scale * point))), 0.0, 1.0)
+    }
+}
+
+// Several octaves of noise added together, which looks like smoke or clouds
+pub struct TurbulenceTexture
+{
+    perlin: Perlin,
+    color: FColor,
+    scale: f64,
+    octaves: usize,
+}
+
+impl TurbulenceTexture
+{
+    pub fn make_new(color: FColor, scale: f64, octaves: usize) -> Self
+    {
+        TurbulenceTexture{ perlin: Perlin::new(), color, scale, octaves: octaves.max(1) }
+    }
+}
+
+impl Texture for TurbulenceTexture
+{
+    fn value(&self, _u: f64, _v: f64, point: &Point) -> FColor
+    {
+        self.color * self.perlin.turbulence(&(self.scale * point), self.octaves).min(1.0)
+    }
+}
+
+const MARBLE_OCTAVES: usize = 7;
+
+// Veins running across the z axis, bent by turbulence
+pub struct MarbleTexture
+{
+    perlin: Perlin,
+    base: FColor,
+    vein: FColor,
+    scale: f64,
+}
+
+impl MarbleTexture
+{
+    pub fn make_new(base: FColor, vein: FColor, scale: f64) -> Self
+    {
+        MarbleTexture{ perlin: Perlin::new(), base, vein, scale }
+    }
+}
+
+impl Texture for MarbleTexture
+{
+    fn value(&self, _u: f64, _v: f64, point: &Point) -> FColor
+    {
+        let phase = self.scale * point.z() + 10.0 * self.perlin.turbulence(point, MARBLE_OCTAVES);
+        lerp(&self.vein, &self.base, 0.5 * (1.0 + phase.sin()))
+    }
+}
+
+// Growth rings around the y axis, scale rings per unit of radius, wobbling with noise
+pub struct WoodTexture
+{
+    perlin: Perlin,
+    light: FColor,
+    dark: FColor,
+    scale: f64,
+}
+
+impl WoodTexture
+{
+    pub fn make_new(light: FColor, dark: FColor, scale: f64) -> Self
+    {
+        WoodTexture{ perlin: Perlin::new(), light, dark, scale }
+    }
+}
+
+impl Texture for WoodTexture
+{
+    fn value(&self, _u: f64, _v: f64, point: &Point) -> FColor
+    {
+        let radius = (point.x() * point.x() + point.z() * point.z()).sqrt();
+        let rings = self.scale * radius + 0.5 * self.perlin.noise(&(self.scale * 0.5 * point));
+        // Sharp dark edge at the end of every ring fading into the light wood
+        let grain = rings.rem_euclid(1.0).powf(3.0);
+        lerp(&self.light, &self.dark, grain)
+    }
+}
+
+// Relative width of the dark lines between Voronoi cells
+const VORONOI_EDGE: f64 = 0.05;
+
+// Cells of random shades between two colors, separated by dark lines
+pub struct VoronoiTexture
+{
+    voronoi: Voronoi,
+    a: FColor,
+    b: FColor,
+    scale: f64,
+}
+
+impl VoronoiTexture
+{
+    pub fn make_new(a: FColor, b: FColor, scale: f64) -> Self
+    {
+        VoronoiTexture{ voronoi: Voronoi::new(), a, b, scale }
+    }
+}
+
+impl Texture for VoronoiTexture
+{
+    fn value(&self, _u: f64, _v: f64, point: &Point) -> FColor
+    {
+        let sample = self.voronoi.sample(&(self.scale * point));
+        let edge = clamp((sample.second - sample.closest) / VORONOI_EDGE, 0.0, 1.0);
+        lerp(&self.a, &self.b, sample.cell_value) * (edge * edge * (3.0 - 2.0 * edge))
+    }
+}
+
+// How image coordinates outside of [0, 1] are brought back onto the image
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WrapMode
+{
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode
+{
+    pub fn from_name(name: &str) -> Option<WrapMode>
+    {
+        match name
+        {
+            "repeat" => Some(WrapMode::Repeat),
+            "clamp" => Some(WrapMode::Clamp),
+            "mirror" => Some(WrapMode::Mirror),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, ind: i64, size: usize) -> usize
+    {
+        let size = size as i64;
+        let wrapped = match self
+        {
+            WrapMode::Repeat => ind.rem_euclid(size),
+            WrapMode::Clamp => ind.clamp(0, size - 1),
+            WrapMode::Mirror =>
+            {
+                let period = ind.rem_euclid(2 * size);
+                if period < size { period } else { 2 * size - 1 - period }
+            }
+        };
+        wrapped as usize
+    }
+}
+
+// Image stretched over the (u, v) square with bilinear filtering, v = 0 is the bottom row
+pub struct ImageTexture
+{
+    image: FrameBuffer,
+    wrap: WrapMode,
+}
+
+impl ImageTexture
+{
+    pub fn make_new(image: FrameBuffer, wrap: WrapMode) -> Self
+    {
+        ImageTexture{ image, wrap }
+    }
+
+    fn texel(&self, x: i64, y: i64) -> FColor
+    {
+        *self.image.get(self.wrap.apply(x, self.image.width()), self.wrap.apply(y, self.image.height()))
+    }
+}
+
+impl Texture for ImageTexture
+{
+    fn value(&self, u: f64, v: f64, _point: &Point) -> FColor
+    {
+        if self.image.width() == 0 || self.image.height() == 0
+        {
+            return FColor::new_color(0.0, 0.0, 0.0);
+        }
+        // Pixel centers sit at half integer coordinates
+        let x = u * self.image.width() as f64 - 0.5;
+        let y = (1.0 - v) * self.image.height() as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = lerp(&self.texel(x0, y0), &self.texel(x0 + 1, y0), tx);
+        let bottom = lerp(&self.texel(x0, y0 + 1), &self.texel(x0 + 1, y0 + 1), tx);
+        lerp(&top, &bottom, ty)
+    }
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::random::seed_thread_rand;
+
+    #[test]
+    fn checker_test()
+    {
+        let white: Arc<dyn Texture> = Arc::new(SolidColor::make_new(FColor::new_color(1.0, 1.0, 1.0)));
+        let black: Arc<dyn Texture> = Arc::new(SolidColor::make_new(FColor::new_color(0.0, 0.0, 0.0)));
+        let checker = CheckerTexture::make_new(white, black, 0.5);
+        assert_eq!(checker.value(0.0, 0.0, &Point::make_new(0.1, 0.1, 0.1)), FColor::new_color(1.0, 1.0, 1.0));
+        assert_eq!(checker.value(0.0, 0.0, &Point::make_new(0.6, 0.1, 0.1)), FColor::new_color(0.0, 0.0, 0.0));
+        assert_eq!(checker.value(0.0, 0.0, &Point::make_new(-0.1, 0.1, 0.1)), FColor::new_color(0.0, 0.0, 0.0));
+        assert_eq!(checker.value(0.0, 0.0, &Point::make_new(-0.1, -0.1, 0.1)), FColor::new_color(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn procedural_test()
+    {
+        seed_thread_rand(17);
+        let a = FColor::new_color(0.9, 0.8, 0.7);
+        let b = FColor::new_color(0.2, 0.1, 0.05);
+        let textures: [Box<dyn Texture>; 5] = [Box::new(NoiseTexture::make_new(a, 4.0)),
+                                               Box::new(TurbulenceTexture::make_new(a, 4.0, 7)),
+                                               Box::new(MarbleTexture::make_new(a, b, 4.0)),
+                                               Box::new(WoodTexture::make_new(a, b, 4.0)),
+                                               Box::new(VoronoiTexture::make_new(a, b, 4.0))];
+        for texture in &textures
+        {
+            // Colors stay between black and the brightest input and actually vary
+            let values: Vec<FColor> = (0..200).map(|ind| texture.value(0.0, 0.0, &Point::make_new(ind as f64 * 0.0173, 0.31, ind as f64 * -0.0091))).collect();
+            for value in &values
+            {
+                assert!(value.r() >= 0.0 && value.r() <= 0.9 + 1e-9);
+            }
+            let (min, max) = values.iter().fold((f64::INFINITY, 0.0f64), |(min, max), value| (min.min(value.r()), max.max(value.r())));
+            assert!(max - min > 0.1);
+        }
+    }
+
+    #[test]
+    fn image_test()
+    {
+        // Left column black, right column white, on two rows
+        let mut image = FrameBuffer::new(2, 2);
+        image.set(1, 0, FColor::new_color(1.0, 1.0, 1.0));
+        image.set(1, 1, FColor::new_color(1.0, 1.0, 1.0));
+        image.set(0, 0, FColor::new_color(0.0, 0.0, 1.0));
+
+        let texture = ImageTexture::make_new(image.clone(), WrapMode::Clamp);
+        let point = Point::make_new(0.0, 0.0, 0.0);
+        // Pixel centers return the pixel, the top left one is at v = 0.75
+        assert_eq!(texture.value(0.25, 0.75, &point), FColor::new_color(0.0, 0.0, 1.0));
+        assert_eq!(texture.value(0.75, 0.25, &point), FColor::new_color(1.0, 1.0, 1.0));
+        // Halfway between the columns of the bottom row
+        assert_eq!(texture.value(0.5, 0.25, &point), FColor::new_color(0.5, 0.5, 0.5));
+        // Clamped at the edges
+        assert_eq!(texture.value(1.5, 0.25, &point), FColor::new_color(1.0, 1.0, 1.0));
+
+        let repeat = ImageTexture::make_new(image.clone(), WrapMode::Repeat);
+        assert_eq!(repeat.value(1.25, 0.25, &point), FColor::new_color(0.0, 0.0, 0.0));
+        assert_eq!(repeat.value(0.0, 0.25, &point), FColor::new_color(0.5, 0.5, 0.5));
+        let mirror = ImageTexture::make_new(image, WrapMode::Mirror);
+        assert_eq!(mirror.value(1.25, 0.25, &point), FColor::new_color(1.0, 1.0, 1.0));
+        assert_eq!(mirror.value(-0.25, 0.25, &point), FColor::new_color(0.0, 0.0, 0.0));
+
+        assert_eq!(WrapMode::from_name("mirror"), Some(WrapMode::Mirror));
+        assert_eq!(WrapMode::from_name("wrap"), None);
+    }
+}
@@ -0,0 +1,409 @@
+use std::sync::Arc;
+
+use super::aabb::Aabb;
+use super::hittable::*;
+use super::matrix::*;
+use super::point::Point;
+use super::ray::Ray;
+use super::vect::Vect;
+
+// Places an object in the world through an affine matrix. Rays are moved into the object's own space
+// and hits back out, so the object itself is shared: any number of instances can point at one mesh.
+// Animated transforms go through keyframes at increasing times, moving the object with the ray's time.
+pub struct Transform
+{
+    object: Arc<dyn Hittable>,
+    keyframes: Vec<Keyframe>,
+    bbox: Option<Aabb>,
+}
+
+// Pose at one time, also split into parts so that rotations blend as rotations between keyframes
+struct Keyframe
+{
+    time: f64,
+    to_world: Matrix,
+    to_local: Matrix,
+    translation: Vect,
+    rotation: Quaternion,
+    stretch: Matrix,
+}
+
+// Steps each keyframe interval is checked at when bounding the motion
+const MOTION_STEPS: usize = 32;
+
+fn transform_box(bbox: &Aabb, to_world: &Matrix) -> Aabb
+{
+    let mut result = Aabb::empty();
+    for corner in box_corners(bbox)
+    {
+        result.extend(&to_world.transform_point(&corner));
+    }
+    result
+}
+
+fn box_corners(bbox: &Aabb) -> [Point; 8]
+{
+    [0, 1, 2, 3, 4, 5, 6, 7].map(|corner|
+        {
+            let mut point = *bbox.minimum();
+            for axis in 0..3
+            {
+                if corner & (1 << axis) != 0
+                {
+                    point[axis] = bbox.maximum()[axis];
+                }
+            }
+            point
+        })
+}
+
+impl Transform
+{
+    // None when the matrix can't be inverted, e.g. when it scales an axis by 0
+    pub fn make_new(object: Arc<dyn Hittable>, to_world: Matrix) -> Option<Self>
+    {
+        Transform::make_animated(object, vec![(0.0, to_world)])
+    }
+
+    // Keyframes as (time, matrix) in increasing time. The object holds the first pose before the
+    // first keyframe and the last one after the last keyframe.
+    pub fn make_animated(object: Arc<dyn Hittable>, keyframes: Vec<(f64, Matrix)>) -> Option<Self>
+    {
+        if keyframes.is_empty()
+        {
+            return None;
+        }
+        let mut poses = Vec::with_capacity(keyframes.len());
+        for (time, to_world) in keyframes
+        {
+            let to_local = to_world.inverse()?;
+            let (translation, rotation, stretch) = to_world.decompose();
+            poses.push(Keyframe{ time, to_world, to_local, translation, rotation, stretch });
+        }
+        let bbox = object.bounding_box().map(|bbox| if bbox.is_empty() { bbox } else { motion_bounds(&bbox, &poses) });
+        Some(Transform{ object, keyframes: poses, bbox })
+    }
+
+    pub fn object(&self) -> &Arc<dyn Hittable>
+    {
+        &self.object
+    }
+
+    // Placement at the first keyframe
+    pub fn matrix(&self) -> &Matrix
+    {
+        &self.keyframes[0].to_world
+    }
+
+    pub fn is_animated(&self) -> bool
+    {
+        self.keyframes.len() > 1
+    }
+
+    // Object to world and world to object matrices at a time, None at the moments an animation
+    // squashes the object flat
+    pub fn matrices_at(&self, time: f64) -> Option<(Matrix, Matrix)>
+    {
+        let first = &self.keyframes[0];
+        let last = &self.keyframes[self.keyframes.len() - 1];
+        if time <= first.time
+        {
+            return Some((first.to_world, first.to_local));
+        }
+        if time >= last.time
+        {
+            return Some((last.to_world, last.to_local));
+        }
+        let next = self.keyframes.partition_point(|keyframe| keyframe.time <= time);
+        let (from, to) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        let to_world = interpolate(from, to, (time - from.time) / (to.time - from.time));
+        Some((to_world, to_world.inverse()?))
+    }
+
+    // The ray in the object's space, and how much longer a distance along it is than in the world
+    fn local_ray(r: &Ray, to_local: &Matrix) -> (Ray, f64)
+    {
+        let direction = to_local.transform_vector(r.direction());
+        let mut local = Ray::make_new(to_local.transform_point(r.origin()), direction);
+        local.set_wavelength(r.wavelength());
+        local.set_time(r.time());
+        (local, direction.length())
+    }
+}
+
+fn interpolate(from: &Keyframe, to: &Keyframe, t: f64) -> Matrix
+{
+    let translation = (1.0 - t) * from.translation + t * to.translation;
+    let rotation = Quaternion::slerp(&from.rotation, &to.rotation, t);
+    let mut stretch = from.stretch;
+    for row in 0..3
+    {
+        for column in 0..3
+        {
+            stretch.data[row][column] = (1.0 - t) * from.stretch.data[row][column] + t * to.stretch.data[row][column];
+        }
+    }
+    Matrix::translation(&translation) * rotation.to_matrix() * stretch
+}
+
+// Box around the object through the whole animation, from the boxes at evenly spaced steps. Between two steps
+// a corner of the object's box strays from the straight line joining its two positions only because of the
+// rotation: by at most (angle^2 * reach + 2 * angle * stretching) / (8 * steps^2), so the boxes grow by that.
+fn motion_bounds(bbox: &Aabb, keyframes: &[Keyframe]) -> Aabb
+{
+    let mut result = transform_box(bbox, &keyframes[0].to_world);
+    for pair in keyframes.windows(2)
+    {
+        let (from, to) = (&pair[0], &pair[1]);
+        let mut segment = Aabb::empty();
+        for step in 0..=MOTION_STEPS
+        {
+            segment = Aabb::surrounding(&segment, &transform_box(bbox, &interpolate(from, to, step as f64 / MOTION_STEPS as f64)));
+        }
+
+        let mut reach: f64 = 0.0;
+        let mut stretching: f64 = 0.0;
+        for corner in box_corners(bbox)
+        {
+            let (start, end) = (from.stretch.transform_vector(&corner), to.stretch.transform_vector(&corner));
+            reach = reach.max(start.length()).max(end.length());
+            stretching = stretching.max((end - start).length());
+        }
+        let angle = Quaternion::angle_between(&from.rotation, &to.rotation);
+        let steps = MOTION_STEPS as f64;
+        let margin = Vect::make_new(1.0, 1.0, 1.0) * ((angle * angle * reach + 2.0 * angle * stretching) / (8.0 * steps * steps));
+        result = Aabb::surrounding(&result, &Aabb::make_new(segment.minimum() - margin, segment.maximum() + margin));
+    }
+    result
+}
+
+impl Hittable for Transform
+{
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitResult>
+    {
+        let (to_world, to_local) = self.matrices_at(r.time())?;
+        let (local, scale) = Transform::local_ray(r, &to_local);
+        let mut hit_result = self.object.hit(&local, t_min * scale, t_max * scale)?;
+        let t = hit_result.t() / scale;
+        hit_result.transform(&to_world, &to_local, t);
+        Some(hit_result)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb>
+    {
+        self.bbox
+    }
+
+    // A linear map A stretches solid angle around a unit direction w by |A w|^3 / |det A|
+    fn pdf_value(&self, origin: &Point, direction: &Vect, time: f64) -> f64
+    {
+        let Some((_, to_local)) = self.matrices_at(time) else { return 0.0 };
+        let local_direction = to_local.transform_vector(&direction.get_normalized());
+        let length = local_direction.length();
+        let pdf = self.object.pdf_value(&to_local.transform_point(origin), &(local_direction / length), time);
+        pdf * to_local.determinant3().abs() / (length * length * length)
+    }
+
+    fn random_direction(&self, origin: &Point, time: f64) -> Vect
+    {
+        let Some((to_world, to_local)) = self.matrices_at(time) else { return Vect::make_new(1.0, 0.0, 0.0) };
+        to_world.transform_vector(&self.object.random_direction(&to_local.transform_point(origin), time))
+    }
+
+    fn is_emissive(&self) -> bool
+    {
+        self.object.is_emissive()
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64
+    {
+        let Some((_, to_local)) = self.matrices_at(r.time()) else { return 1.0 };
+        let (local, scale) = Transform::local_ray(r, &to_local);
+        self.object.transmittance(&local, t_min * scale, t_max * scale)
+    }
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::core::*;
+    use super::super::material::*;
+    use super::super::medium::*;
+    use super::super::random::seed_thread_rand;
+    use super::super::sphere::Sphere;
+    use super::super::super::utils::color::FColor;
+
+    fn unit_sphere(material: Arc<dyn Material>) -> Arc<dyn Hittable>
+    {
+        Arc::new(Sphere::make_new(Point::make_new(0.0, 0.0, 0.0), 1.0, material))
+    }
+
+    fn assert_near(a: &Vect, b: &Vect)
+    {
+        assert!((a - b).length() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn hit_test()
+    {
+        // An ellipsoid twice as wide as it is high, 5 units in front of the origin
+        let to_world = Matrix::translation(&Vect::make_new(0.0, 0.0, -5.0)) * Matrix::scaling(&Vect::make_new(2.0, 1.0, 1.0));
+        let ellipsoid = Transform::make_new(unit_sphere(Arc::new(Lambertian::default())), to_world).unwrap();
+
+        let r = Ray::make_new(Point::make_new(0.0, 0.0, 0.0), Vect::make_new(0.0, 0.0, -1.0));
+        let hit = ellipsoid.hit(&r, 0.001, INFINITY).unwrap();
+        assert!((hit.t() - 4.0).abs() < 1e-9);
+        assert_near(hit.point(), &Point::make_new(0.0, 0.0, -4.0));
+        assert_near(hit.normal(), &Vect::make_new(0.0, 0.0, 1.0));
+        assert!(ellipsoid.hit(&r, 0.001, 3.9).is_none());
+
+        let r = Ray::make_new(Point::make_new(10.0, 0.0, -5.0), Vect::make_new(-1.0, 0.0, 0.0));
+        assert!((ellipsoid.hit(&r, 0.001, INFINITY).unwrap().t() - 8.0).abs() < 1e-9);
+
+        // Away from the axes the normal follows the gradient of x^2 / 4 + y^2, not the stretched sphere normal
+        let angle = PI / 4.0;
+        let point = Point::make_new(2.0 * angle.cos(), angle.sin(), -5.0);
+        let r = Ray::make_new(point + 3.0 * Vect::make_new(1.0, 1.0, 0.0).get_normalized(), Vect::make_new(-1.0, -1.0, 0.0));
+        let hit = ellipsoid.hit(&r, 0.001, INFINITY).unwrap();
+        assert!((hit.t() - 3.0).abs() < 1e-9);
+        assert_near(hit.point(), &point);
+        assert_near(hit.normal(), &Vect::make_new(angle.cos() / 2.0, angle.sin(), 0.0).get_normalized());
+        assert!(hit.front_face());
+
+        // Rotating a sphere about the z axis spins its surface directions with it
+        let to_world = Matrix::rotation(&Vect::make_new(0.0, 0.0, 1.0), 90.0);
+        let spun = Transform::make_new(unit_sphere(Arc::new(Lambertian::default())), to_world).unwrap();
+        let r = Ray::make_new(Point::make_new(0.0, 0.0, 5.0), Vect::make_new(0.0, 0.0, -1.0));
+        assert_near(spun.hit(&r, 0.001, INFINITY).unwrap().tangent(), &Vect::make_new(0.0, 1.0, 0.0));
+
+        assert!(Transform::make_new(unit_sphere(Arc::new(Lambertian::default())), Matrix::scaling(&Vect::new())).is_none());
+    }
+
+    #[test]
+    fn bounding_box_test()
+    {
+        let to_world = Matrix::translation(&Vect::make_new(1.0, 2.0, 3.0)) * Matrix::rotation(&Vect::make_new(0.0, 0.0, 1.0), 45.0);
+        let rotated = Transform::make_new(unit_sphere(Arc::new(Lambertian::default())), to_world).unwrap();
+        let bbox = rotated.bounding_box().unwrap();
+        let half_diagonal = 2.0_f64.sqrt();
+        assert_near(bbox.minimum(), &Point::make_new(1.0 - half_diagonal, 2.0 - half_diagonal, 2.0));
+        assert_near(bbox.maximum(), &Point::make_new(1.0 + half_diagonal, 2.0 + half_diagonal, 4.0));
+    }
+
+    #[test]
+    fn instancing_test()
+    {
+        let sphere = unit_sphere(Arc::new(Lambertian::default()));
+        let instances: Vec<Transform> = (0..1000).map(|index|
+            {
+                let offset = Vect::make_new(3.0 * index as f64, 0.0, 0.0);
+                Transform::make_new(sphere.clone(), Matrix::translation(&offset)).unwrap()
+            }).collect();
+        // Every instance shares the one sphere
+        assert_eq!(Arc::strong_count(&sphere), 1001);
+        assert!(Arc::ptr_eq(instances[999].object(), &sphere));
+
+        let r = Ray::make_new(Point::make_new(2997.0, 0.0, 5.0), Vect::make_new(0.0, 0.0, -1.0));
+        assert!((instances[999].hit(&r, 0.001, INFINITY).unwrap().t() - 4.0).abs() < 1e-9);
+        assert!(instances[998].hit(&r, 0.001, INFINITY).is_none());
+    }
+
+    #[test]
+    fn light_sampling_test()
+    {
+        seed_thread_rand(3);
+        let to_world = Matrix::translation(&Vect::make_new(0.0, 0.0, -4.0))
+            * Matrix::rotation(&Vect::make_new(1.0, 1.0, 0.0), 30.0)
+            * Matrix::scaling(&Vect::make_new(2.0, 1.0, 0.5));
+        let light = Transform::make_new(unit_sphere(Arc::new(DiffuseLight::make_new(FColor::make_new(1.0, 1.0, 1.0)))), to_world).unwrap();
+        assert!(light.is_emissive());
+
+        // The density stays normalized over the sphere of directions despite the stretch
+        let origin = Point::make_new(0.0, 0.0, 0.0);
+        let count = 200000;
+        let integral: f64 = (0..count).map(|_| light.pdf_value(&origin, &Vect::random_unit_vect(), 0.0)).sum::<f64>() * 4.0 * PI / count as f64;
+        assert!((integral - 1.0).abs() < 0.05, "{}", integral);
+
+        // and sampled directions always reach the light
+        for _ in 0..100
+        {
+            let direction = light.random_direction(&origin, 0.0);
+            assert!(light.hit(&Ray::make_new(origin, direction), 0.001, INFINITY).is_some());
+            assert!(light.pdf_value(&origin, &direction, 0.0) > 0.0);
+        }
+    }
+
+    #[test]
+    fn keyframe_test()
+    {
+        // Sliding 4 units along x while turning half a turn about y
+        let axis = Vect::make_new(0.0, 1.0, 0.0);
+        let keyframes = vec![(0.0, Matrix::identity()),
+                             (1.0, Matrix::translation(&Vect::make_new(4.0, 0.0, 0.0)) * Matrix::rotation(&axis, 180.0))];
+        let moving = Transform::make_animated(unit_sphere(Arc::new(Lambertian::default())), keyframes).unwrap();
+        assert!(moving.is_animated());
+
+        let mut r = Ray::make_new(Point::make_new(2.0, 0.0, 5.0), Vect::make_new(0.0, 0.0, -1.0));
+        assert!(moving.hit(&r, 0.001, INFINITY).is_none());
+        r.set_time(0.5);
+        let hit = moving.hit(&r, 0.001, INFINITY).unwrap();
+        assert!((hit.t() - 4.0).abs() < 1e-9);
+        // A quarter turn in, the ray meets the side of the sphere that faced -x, where u starts
+        let (u, v) = hit.uv();
+        assert!(u.min(1.0 - u) < 1e-9 && (v - 0.5).abs() < 1e-9, "{} {}", u, v);
+
+        // Before the first keyframe and after the last one the pose holds
+        let (start, _) = moving.matrices_at(-1.0).unwrap();
+        assert_eq!(start, Matrix::identity());
+        let (end, _) = moving.matrices_at(3.0).unwrap();
+        assert_near(&end.transform_point(&Point::make_new(1.0, 0.0, 0.0)), &Point::make_new(3.0, 0.0, 0.0));
+
+        assert!(Transform::make_animated(unit_sphere(Arc::new(Lambertian::default())), Vec::new()).is_none());
+        assert!(!Transform::make_new(unit_sphere(Arc::new(Lambertian::default())), Matrix::identity()).unwrap().is_animated());
+    }
+
+    #[test]
+    fn motion_bounds_test()
+    {
+        // A long thin box swung around while it grows, the box of its motion holds it at every moment
+        let stick: Arc<dyn Hittable> = Arc::new(Transform::make_new(unit_sphere(Arc::new(Lambertian::default())),
+            Matrix::translation(&Vect::make_new(2.0, 0.0, 0.0)) * Matrix::scaling(&Vect::make_new(3.0, 0.2, 0.2))).unwrap());
+        let keyframes = vec![(0.0, Matrix::identity()),
+                             (1.0, Matrix::rotation(&Vect::make_new(0.0, 0.0, 1.0), 150.0)),
+                             (2.0, Matrix::translation(&Vect::make_new(0.0, 3.0, 0.0)) * Matrix::rotation(&Vect::make_new(1.0, 0.0, 1.0), 270.0)
+                                   * Matrix::scaling(&Vect::make_new(2.0, 1.0, 1.0)))];
+        let swung = Transform::make_animated(stick.clone(), keyframes).unwrap();
+        let bbox = swung.bounding_box().unwrap();
+        let local = stick.bounding_box().unwrap();
+        for step in 0..=1000
+        {
+            let (to_world, _) = swung.matrices_at(step as f64 / 500.0).unwrap();
+            for corner in box_corners(&local)
+            {
+                let point = to_world.transform_point(&corner);
+                assert!((0..3).all(|axis| point[axis] >= bbox.minimum()[axis] && point[axis] <= bbox.maximum()[axis]), "{} at step {}", point, step);
+            }
+        }
+        // without growing much past the swept space
+        let extent = bbox.extent();
+        assert!((0..3).all(|axis| extent[axis] < 16.0), "{}", extent);
+    }
+
+    #[test]
+    fn transmittance_test()
+    {
+        // Doubling the size of a medium doubles the distance through it but not its optical depth
+        let medium: Arc<dyn Hittable> = Arc::new(ConstantMedium::make_new(unit_sphere(Arc::new(Isotropic::default())), 1.0, Arc::new(Isotropic::default())));
+        let scaled = Transform::make_new(medium, Matrix::scaling(&Vect::make_new(2.0, 2.0, 2.0))).unwrap();
+        let r = Ray::make_new(Point::make_new(0.0, 0.0, 5.0), Vect::make_new(0.0, 0.0, -1.0));
+        assert!((scaled.transmittance(&r, 0.001, INFINITY) - (-2.0_f64).exp()).abs() < 1e-6);
+        assert!((scaled.transmittance(&r, 0.001, 5.0) - (-1.0_f64).exp()).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,582 @@
+use std::sync::Arc;
+
+use super::aabb::Aabb;
+use super::bvh::BvhNode;
+use super::core::*;
+use super::hittable::*;
+use super::material::*;
+use super::point::Point;
+use super::random::thread_rand;
+use super::ray::Ray;
+use super::vect::Vect;
+
+// Möller–Trumbore ray/triangle intersection.
+// Returns the ray parameter and the barycentric coordinates (b1, b2) of v1 and v2.
+fn intersect(vertices: [&Point; 3], r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)>
+{
+    let edge1 = vertices[1] - vertices[0];
+    let edge2 = vertices[2] - vertices[0];
+    let p = Vect::cross(r.direction(), &edge2);
+    // The determinant scales with the triangle's area, so only rays exactly parallel to it are skipped
+    let determinant = Vect::dot(&edge1, &p);
+    let inverse_determinant = 1.0 / determinant;
+    if determinant == 0.0 || !inverse_determinant.is_finite()
+    {
+        return None;
+    }
+
+    let s = r.origin() - vertices[0];
+    let b1 = Vect::dot(&s, &p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&b1)
+    {
+        return None;
+    }
+
+    let q = Vect::cross(&s, &edge1);
+    let b2 = Vect::dot(r.direction(), &q) * inverse_determinant;
+    if b2 < 0.0 || b1 + b2 > 1.0
+    {
+        return None;
+    }
+
+    let t = Vect::dot(&edge2, &q) * inverse_determinant;
+    if t < t_min || t > t_max
+    {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+fn interpolate<const COUNT: usize>(values: [&Vect<COUNT>; 3], b1: f64, b2: f64) -> Vect<COUNT>
+{
+    (1.0 - b1 - b2) * values[0] + b1 * values[1] + b2 * values[2]
+}
+
+fn triangle_bounding_box(vertices: [&Point; 3]) -> Aabb
+{
+    let mut bbox = Aabb::empty();
+    for vertex in vertices
+    {
+        bbox.extend(vertex);
+    }
+    bbox
+}
+
+fn triangle_area(vertices: [&Point; 3]) -> f64
+{
+    0.5 * Vect::cross(&(vertices[1] - vertices[0]), &(vertices[2] - vertices[0])).length()
+}
+
+// Uniformly distributed point on the triangle
+fn sample_triangle(vertices: [&Point; 3]) -> Point
+{
+    let root = thread_rand().sqrt();
+    let b1 = root * (1.0 - thread_rand());
+    let b2 = root - b1;
+    interpolate(vertices, b1, b2)
+}
+
+// Converts a density of 1 / area over the surface to solid angle around the direction that reached hit_result
+fn area_to_solid_angle_pdf(hit_result: &HitResult, direction: &Vect, area: f64) -> f64
+{
+    let cosine = Vect::dot(hit_result.normal(), direction).abs();
+    if cosine < FLOAT_MARGIN_OF_ERROR || area <= 0.0
+    {
+        return 0.0;
+    }
+    hit_result.t() * hit_result.t() / (cosine * area)
+}
+
+pub struct Triangle
+{
+    pub vertices: [Point; 3],
+    pub normals: Option<[Vect; 3]>,
+    pub material: Arc<dyn Material>,
+}
+
+impl Triangle
+{
+    pub fn new(v0: &Point, v1: &Point, v2: &Point, material: Arc<dyn Material>) -> Self
+    {
+        Triangle
+        {
+            vertices: [*v0, *v1, *v2],
+            normals: None,
+            material,
+        }
+    }
+
+    pub fn make_new(v0: Point, v1: Point, v2: Point, material: Arc<dyn Material>) -> Self
+    {
+        Triangle
+        {
+            vertices: [v0, v1, v2],
+            normals: None,
+            material,
+        }
+    }
+
+    pub fn make_new_with_normals(vertices: [Point; 3], normals: [Vect; 3], material: Arc<dyn Material>) -> Self
+    {
+        Triangle
+        {
+            vertices,
+            normals: Some(normals),
+            material,
+        }
+    }
+}
+
+impl Hittable for Triangle
+{
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitResult>
+    {
+        let vertices = [&self.vertices[0], &self.vertices[1], &self.vertices[2]];
+        let (t, b1, b2) = intersect(vertices, r, t_min, t_max)?;
+
+        let geometric_normal = Vect::cross(&(vertices[1] - vertices[0]), &(vertices[2] - vertices[0]));
+        let mut result = HitResult::make_new(r.at(t), geometric_normal, self.material.clone(), t, r);
+        result.set_uv(b1, b2);
+        result.set_tangent(vertices[1] - vertices[0]);
+        if let Some(normals) = &self.normals
+        {
+            result.set_shading_normal(interpolate([&normals[0], &normals[1], &normals[2]], b1, b2));
+        }
+        Some(result)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb>
+    {
+        Some(triangle_bounding_box([&self.vertices[0], &self.vertices[1], &self.vertices[2]]))
+    }
+
+    fn pdf_value(&self, origin: &Point, direction: &Vect, _time: f64) -> f64
+    {
+        let ray = Ray::make_new(*origin, *direction);
+        match self.hit(&ray, 0.001, INFINITY)
+        {
+            Some(hit_result) =>
+            {
+                let area = triangle_area([&self.vertices[0], &self.vertices[1], &self.vertices[2]]);
+                area_to_solid_angle_pdf(&hit_result, ray.direction(), area)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random_direction(&self, origin: &Point, _time: f64) -> Vect
+    {
+        sample_triangle([&self.vertices[0], &self.vertices[1], &self.vertices[2]]) - origin
+    }
+
+    fn is_emissive(&self) -> bool
+    {
+        self.material.is_emissive()
+    }
+}
+
+// Indices of one mesh face into the shared vertex buffers of its TriangleMesh
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct MeshFace
+{
+    pub positions: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+}
+
+impl MeshFace
+{
+    pub fn new(positions: [usize; 3]) -> Self
+    {
+        MeshFace
+        {
+            positions,
+            normals: None,
+            uvs: None,
+        }
+    }
+
+    pub fn make_new(positions: [usize; 3], normals: Option<[usize; 3]>, uvs: Option<[usize; 3]>) -> Self
+    {
+        MeshFace
+        {
+            positions,
+            normals,
+            uvs,
+        }
+    }
+}
+
+pub struct MeshData
+{
+    pub positions: Vec<Point>,
+    pub normals: Vec<Vect>,
+    pub uvs: Vec<Vect<2>>,
+    pub faces: Vec<MeshFace>,
+    pub material: Arc<dyn Material>,
+}
+
+fn face_vertices(mesh: &MeshData, face: usize) -> [&Point; 3]
+{
+    let indices = &mesh.faces[face].positions;
+    [&mesh.positions[indices[0]], &mesh.positions[indices[1]], &mesh.positions[indices[2]]]
+}
+
+// A single face of a mesh, only holds a reference to the shared mesh data
+struct MeshTriangle
+{
+    mesh: Arc<MeshData>,
+    face: usize,
+}
+
+impl MeshTriangle
+{
+    fn vertices(&self) -> [&Point; 3]
+    {
+        face_vertices(&self.mesh, self.face)
+    }
+}
+
+// Derivative of the position with respect to u, zero when the texture coordinates are degenerate
+fn uv_tangent(vertices: [&Point; 3], uvs: [&Vect<2>; 3]) -> Vect
+{
+    let (edge1, edge2) = (vertices[1] - vertices[0], vertices[2] - vertices[0]);
+    let (du1, dv1) = (uvs[1][0] - uvs[0][0], uvs[1][1] - uvs[0][1]);
+    let (du2, dv2) = (uvs[2][0] - uvs[0][0], uvs[2][1] - uvs[0][1]);
+    let determinant = du1 * dv2 - du2 * dv1;
+    if determinant.abs() < 1e-12
+    {
+        return Vect::new();
+    }
+    (dv2 * edge1 - dv1 * edge2) / determinant
+}
+
+impl Hittable for MeshTriangle
+{
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitResult>
+    {
+        let vertices = self.vertices();
+        let (t, b1, b2) = intersect(vertices, r, t_min, t_max)?;
+
+        let geometric_normal = Vect::cross(&(vertices[1] - vertices[0]), &(vertices[2] - vertices[0]));
+        let mut result = HitResult::make_new(r.at(t), geometric_normal, self.mesh.material.clone(), t, r);
+        if let Some(indices) = &self.mesh.faces[self.face].normals
+        {
+            let normals = &self.mesh.normals;
+            result.set_shading_normal(interpolate([&normals[indices[0]], &normals[indices[1]], &normals[indices[2]]], b1, b2));
+        }
+        // Faces without texture coordinates fall back to the barycentric ones
+        match &self.mesh.faces[self.face].uvs
+        {
+            Some(indices) =>
+            {
+                let uvs = &self.mesh.uvs;
+                let corners = [&uvs[indices[0]], &uvs[indices[1]], &uvs[indices[2]]];
+                let uv = interpolate(corners, b1, b2);
+                result.set_uv(uv[0], uv[1]);
+                result.set_tangent(uv_tangent(vertices, corners));
+            }
+            None =>
+            {
+                result.set_uv(b1, b2);
+                result.set_tangent(vertices[1] - vertices[0]);
+            }
+        }
+        Some(result)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb>
+    {
+        Some(triangle_bounding_box(self.vertices()))
+    }
+}
+
+// Triangle mesh with shared position, normal and UV buffers indexed per face.
+// The faces are kept in their own BVH so a mesh can be placed in a scene as one object.
+pub struct TriangleMesh
+{
+    data: Arc<MeshData>,
+    bvh: BvhNode,
+    // Running total of the face areas, used to pick faces proportionally to their size
+    area_cdf: Vec<f64>,
+}
+
+impl TriangleMesh
+{
+    // Fails when a face references a vertex that isn't in the buffers
+    pub fn make_new(positions: Vec<Point>, normals: Vec<Vect>, uvs: Vec<Vect<2>>, faces: Vec<MeshFace>,
+                    material: Arc<dyn Material>) -> Result<Self, String>
+    {
+        for (ind, face) in faces.iter().enumerate()
+        {
+            let missing = if !face.positions.iter().all(|&vertex| vertex < positions.len()) { "position" }
+                else if !face.normals.iter().flatten().all(|&vertex| vertex < normals.len()) { "normal" }
+                else if !face.uvs.iter().flatten().all(|&vertex| vertex < uvs.len()) { "UV" }
+                else { continue };
+            return Err(format!("mesh face {} references a missing {}", ind, missing));
+        }
+
+        let data = Arc::new(MeshData
+        {
+            positions,
+            normals,
+            uvs,
+            faces,
+            material,
+        });
+
+        let triangles: Vec<Arc<dyn Hittable>> = (0..data.faces.len()).map(|face|
+            {
+                Arc::new(MeshTriangle
+                {
+                    mesh: data.clone(),
+                    face,
+                }) as Arc<dyn Hittable>
+            }).collect();
+
+        let area_cdf = (0..data.faces.len()).scan(0.0, |total, face|
+            {
+                *total += triangle_area(face_vertices(&data, face));
+                Some(*total)
+            }).collect();
+
+        Ok(TriangleMesh
+        {
+            data,
+            bvh: BvhNode::make_new(triangles),
+            area_cdf,
+        })
+    }
+
+    pub fn data(&self) -> &MeshData
+    {
+        &self.data
+    }
+
+    pub fn triangle_count(&self) -> usize
+    {
+        self.data.faces.len()
+    }
+}
+
+impl Hittable for TriangleMesh
+{
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitResult>
+    {
+        self.bvh.hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb>
+    {
+        self.bvh.bounding_box()
+    }
+
+    // Any of the faces crossed along the direction could have been sampled, so all of them add to the density
+    fn pdf_value(&self, origin: &Point, direction: &Vect, _time: f64) -> f64
+    {
+        let Some(total_area) = self.area_cdf.last() else { return 0.0 };
+        let ray = Ray::make_new(*origin, *direction);
+        let mut pdf = 0.0;
+        let mut t_min = 0.001;
+        while let Some(hit_result) = self.bvh.hit(&ray, t_min, INFINITY)
+        {
+            pdf += area_to_solid_angle_pdf(&hit_result, ray.direction(), *total_area);
+            t_min = hit_result.t() + 0.001;
+        }
+        pdf
+    }
+
+    fn random_direction(&self, origin: &Point, _time: f64) -> Vect
+    {
+        let Some(total_area) = self.area_cdf.last()
+        else
+        {
+            return Vect::make_new(1.0, 0.0, 0.0);
+        };
+        let target = thread_rand() * total_area;
+        let face = self.area_cdf.partition_point(|area| *area <= target).min(self.area_cdf.len() - 1);
+        sample_triangle(face_vertices(&self.data, face)) - origin
+    }
+
+    fn is_emissive(&self) -> bool
+    {
+        self.data.material.is_emissive()
+    }
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::random::seed_thread_rand;
+
+    fn make_triangle() -> Triangle
+    {
+        Triangle::make_new(Point::make_new(-1.0, -1.0, 0.0),
+                           Point::make_new(1.0, -1.0, 0.0),
+                           Point::make_new(0.0, 1.0, 0.0),
+                           Arc::new(Lambertian::default()))
+    }
+
+    #[test]
+    fn hit_test()
+    {
+        let triangle = make_triangle();
+
+        let r = Ray::make_new(Point::make_new(0.0, 0.0, -2.0), Vect::make_new(0.0, 0.0, 1.0));
+        let hit_result = triangle.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(hit_result.t(), 2.0);
+        assert_eq!(*hit_result.point(), Point::make_new(0.0, 0.0, 0.0));
+        assert_eq!(*hit_result.normal(), Vect::make_new(0.0, 0.0, -1.0));
+        assert!(!hit_result.front_face());
+
+        let r = Ray::make_new(Point::make_new(2.0, 0.0, -2.0), Vect::make_new(0.0, 0.0, 1.0));
+        assert!(triangle.hit(&r, 0.001, f64::INFINITY).is_none());
+
+        let r = Ray::make_new(Point::make_new(0.0, 0.0, -2.0), Vect::make_new(0.0, 1.0, 0.0));
+        assert!(triangle.hit(&r, 0.001, f64::INFINITY).is_none());
+
+        // Tiny triangles are hit just as well, whatever the scale of the scene
+        let tiny = Triangle::make_new(Point::make_new(-1e-5, -1e-5, 0.0), Point::make_new(1e-5, -1e-5, 0.0),
+                                      Point::make_new(0.0, 1e-5, 0.0), Arc::new(Lambertian::default()));
+        let r = Ray::make_new(Point::make_new(0.0, 0.0, -2.0), Vect::make_new(0.0, 0.0, 1.0));
+        assert_eq!(tiny.hit(&r, 0.001, f64::INFINITY).map(|h| h.t()), Some(2.0));
+        let r = Ray::make_new(Point::make_new(0.5e-5, 0.5e-5, -2.0), Vect::make_new(0.0, 0.0, 1.0));
+        assert!(tiny.hit(&r, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn light_sampling_test()
+    {
+        seed_thread_rand(3);
+        let origin = Point::make_new(0.0, 0.0, -2.0);
+        let triangle = make_triangle();
+        assert!(!triangle.is_emissive());
+
+        // Area 2 seen head on from 2 units away
+        assert!((triangle.pdf_value(&origin, &Vect::make_new(0.0, 0.0, 1.0), 0.0) - 2.0).abs() < 1e-9);
+        assert_eq!(triangle.pdf_value(&origin, &Vect::make_new(0.0, 0.0, -1.0), 0.0), 0.0);
+
+        // Integrated over all directions the density is one
+        let samples = 20000;
+        let total: f64 = (0..samples).map(|_| triangle.pdf_value(&origin, &Vect::random_unit_vect(), 0.0)).sum();
+        assert!((total / samples as f64 * 4.0 * PI - 1.0).abs() < 0.1);
+
+        for _ in 0..100
+        {
+            let direction = triangle.random_direction(&origin, 0.0);
+            assert!(triangle.pdf_value(&origin, &direction, 0.0) > 0.0);
+        }
+    }
+
+    #[test]
+    fn mesh_light_sampling_test()
+    {
+        seed_thread_rand(5);
+        // Two unit squares side by side, the second made of a single large face
+        let positions = vec![Point::make_new(0.0, 0.0, 0.0), Point::make_new(1.0, 0.0, 0.0), Point::make_new(1.0, 1.0, 0.0),
+                             Point::make_new(0.0, 1.0, 0.0), Point::make_new(3.0, 0.0, 0.0), Point::make_new(3.0, 2.0, 0.0)];
+        let faces = vec![MeshFace::new([0, 1, 2]), MeshFace::new([0, 2, 3]), MeshFace::new([1, 4, 5])];
+        let mesh = TriangleMesh::make_new(positions, Vec::new(), Vec::new(), faces, Arc::new(DiffuseLight::default())).unwrap();
+        assert!(mesh.is_emissive());
+        assert_eq!(mesh.area_cdf, vec![0.5, 1.0, 3.0]);
+
+        let origin = Point::make_new(0.5, 0.5, 1.0);
+        // The whole mesh area is used, no matter which face is hit
+        assert!((mesh.pdf_value(&origin, &Vect::make_new(0.0, 0.0, -1.0), 0.0) - 1.0 / 3.0).abs() < 1e-9);
+
+        let mut large_face = 0;
+        for _ in 0..3000
+        {
+            let direction = mesh.random_direction(&origin, 0.0);
+            assert!(mesh.pdf_value(&origin, &direction, 0.0) > 0.0);
+            large_face += ((origin + direction).x() > 1.0) as usize;
+        }
+        // Two thirds of the area belongs to the large face
+        assert!((large_face as f64 / 3000.0 - 2.0 / 3.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn concave_mesh_light_sampling_test()
+    {
+        seed_thread_rand(9);
+        // Unit cube without its top, the walls hide each other from outside
+        let positions = vec![Point::make_new(0.0, 0.0, 0.0), Point::make_new(1.0, 0.0, 0.0), Point::make_new(1.0, 1.0, 0.0), Point::make_new(0.0, 1.0, 0.0),
+                             Point::make_new(0.0, 0.0, 1.0), Point::make_new(1.0, 0.0, 1.0), Point::make_new(1.0, 1.0, 1.0), Point::make_new(0.0, 1.0, 1.0)];
+        let quads = [[0, 1, 2, 3], [0, 1, 5, 4], [1, 2, 6, 5], [2, 3, 7, 6], [3, 0, 4, 7]];
+        let faces = quads.iter().flat_map(|q| [MeshFace::new([q[0], q[1], q[2]]), MeshFace::new([q[0], q[2], q[3]])]).collect();
+        let mesh = TriangleMesh::make_new(positions, Vec::new(), Vec::new(), faces, Arc::new(DiffuseLight::default())).unwrap();
+
+        // Head on through two walls 1 and 2 units away, out of an area of 5
+        let origin = Point::make_new(0.5, -1.0, 0.5);
+        assert!((mesh.pdf_value(&origin, &Vect::make_new(0.0, 1.0, 0.0), 0.0) - 1.0).abs() < 1e-9);
+
+        // Integrated over all directions the density is still one
+        let samples = 40000;
+        let total: f64 = (0..samples).map(|_| mesh.pdf_value(&origin, &Vect::random_unit_vect(), 0.0)).sum();
+        assert!((total / samples as f64 * 4.0 * PI - 1.0).abs() < 0.05, "{}", total / samples as f64 * 4.0 * PI);
+    }
+
+    #[test]
+    fn shading_normal_test()
+    {
+        let n0 = Vect::make_new(-1.0, 0.0, 1.0).get_normalized();
+        let n1 = Vect::make_new(1.0, 0.0, 1.0).get_normalized();
+        let n2 = Vect::make_new(0.0, 0.0, 1.0);
+        let mut triangle = make_triangle();
+        triangle.normals = Some([n0, n1, n2]);
+
+        let r = Ray::make_new(Point::make_new(0.0, -1.0, 2.0), Vect::make_new(0.0, 0.0, -1.0));
+        let hit_result = triangle.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(*hit_result.normal(), Vect::make_new(0.0, 0.0, 1.0));
+        assert!((*hit_result.shading_normal() - Vect::make_new(0.0, 0.0, 1.0)).length() < 1e-9);
+
+        let r = Ray::make_new(Point::make_new(0.5, -1.0, 2.0), Vect::make_new(0.0, 0.0, -1.0));
+        let hit_result = triangle.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!(hit_result.shading_normal().x() > 0.0);
+        assert!((hit_result.shading_normal().length() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn mesh_test()
+    {
+        // Unit quad in the z = 0 plane made of two faces sharing an edge
+        let positions = vec![Point::make_new(0.0, 0.0, 0.0), Point::make_new(1.0, 0.0, 0.0),
+                             Point::make_new(1.0, 1.0, 0.0), Point::make_new(0.0, 1.0, 0.0)];
+        let normals = vec![Vect::make_new(0.0, 0.0, 1.0)];
+        let uvs = vec![Vect{data: [0.0, 0.0]}, Vect{data: [2.0, 2.0]}, Vect{data: [0.0, 2.0]}];
+        let faces = vec![MeshFace::make_new([0, 1, 2], Some([0, 0, 0]), None), MeshFace::make_new([0, 2, 3], None, Some([0, 1, 2]))];
+        let mesh = TriangleMesh::make_new(positions, normals, uvs, faces, Arc::new(Lambertian::default())).unwrap();
+
+        assert_eq!(mesh.triangle_count(), 2);
+        assert_eq!(mesh.bounding_box(), Some(Aabb::make_new(Point::make_new(0.0, 0.0, 0.0), Point::make_new(1.0, 1.0, 0.0))));
+
+        // The first face has no texture coordinates and uses barycentric ones, the second one interpolates its own
+        let r = Ray::make_new(Point::make_new(0.75, 0.25, 1.0), Vect::make_new(0.0, 0.0, -1.0));
+        let hit_result = mesh.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(hit_result.t(), 1.0);
+        assert_eq!(hit_result.uv(), (0.5, 0.25));
+        let r = Ray::make_new(Point::make_new(0.25, 0.75, 1.0), Vect::make_new(0.0, 0.0, -1.0));
+        let hit_result = mesh.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(hit_result.t(), 1.0);
+        assert_eq!(hit_result.uv(), (0.5, 1.5));
+        // u = 2x on that face
+        assert!((*hit_result.tangent() - Vect::make_new(0.5, 0.0, 0.0)).length() < 1e-12);
+        let r = Ray::make_new(Point::make_new(1.25, 0.75, 1.0), Vect::make_new(0.0, 0.0, -1.0));
+        assert!(mesh.hit(&r, 0.001, f64::INFINITY).is_none());
+
+        // Faces pointing outside of the buffers are an error rather than a panic
+        let positions = vec![Point::make_new(0.0, 0.0, 0.0), Point::make_new(1.0, 0.0, 0.0), Point::make_new(1.0, 1.0, 0.0)];
+        let faces = vec![MeshFace::new([0, 1, 2]), MeshFace::make_new([0, 1, 2], Some([0, 0, 0]), None)];
+        let error = TriangleMesh::make_new(positions.clone(), Vec::new(), Vec::new(), faces, Arc::new(Lambertian::default())).err();
+        assert_eq!(error.as_deref(), Some("mesh face 1 references a missing normal"));
+        let faces = vec![MeshFace::new([0, 1, 3])];
+        assert!(TriangleMesh::make_new(positions, Vec::new(), Vec::new(), faces, Arc::new(Lambertian::default())).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use super::environment::*;
+use super::scene::*;
+use super::settings::RenderSettings;
+use super::sky::*;
+use super::super::math::camera::Camera;
+use super::super::math::hittable::HittableList;
+use super::super::math::light::Light;
+use super::super::math::material::*;
+use super::super::math::point::Point;
+use super::super::math::random::thread_rand;
+use super::super::math::sphere::Sphere;
+use super::super::math::triangle::Triangle;
+use super::super::math::vect::Vect;
+use super::super::utils::color::FColor;
+
+// Scenes that are built in code and can be selected by name instead of a scene file
+
+pub const BUILTIN_SCENES: [&str; 3] = ["random", "simple", "cornell"];
+
+const PROJECT_PATH: &str = env!("CARGO_MANIFEST_DIR");
+const IMAGE_OUT_PATH: &str = "out/output.ppm";
+
+pub fn make_builtin_scene(name: &str) -> Option<Scene>
+{
+    let mut settings = RenderSettings::default();
+    let mut environment: Arc<dyn Environment> = Arc::new(SkyGradient);
+    let mut lights: Vec<Arc<dyn Light>> = Vec::new();
+
+    let (world, camera) = match name
+    {
+        "random" =>
+        {
+            let pos = Point::make_new(13.0, 2.0, 3.0);
+            let lookat = Point::make_new(0.0, 0.0, 0.0);
+            let world_up = Vect::make_new(0.0, 1.0, 0.0);
+            let depth_of_field = (pos - lookat).length();
+            let aperture = 0.1;
+            // Afternoon daylight coming from behind the camera's left
+            let sky = PreethamSky::make_new(sun_direction(40.0, 150.0), 3.0, 1.0);
+            lights.extend(sky.sun_light().map(|sun| Arc::new(sun) as Arc<dyn Light>));
+            environment = Arc::new(sky);
+            (make_random_scene(), Camera::new(pos, lookat, world_up, 20.0, settings.aspect_ratio(), aperture, depth_of_field))
+        }
+        "simple" =>
+        {
+            let pos = Point::make_new(0.0, 0.0, 1.0);
+            let lookat = Point::make_new(0.0, 0.0, -1.0);
+            let world_up = Vect::make_new(0.0, 1.0, 0.0);
+            let depth_of_field = (pos - lookat).length();
+            (make_simple_scene(), Camera::new(pos, lookat, world_up, 60.0, settings.aspect_ratio(), 0.0, depth_of_field))
+        }
+        "cornell" =>
+        {
+            settings.width = 600;
+            settings.height = 600;
+            // Lit by the ceiling lamp alone
+            environment = Arc::new(ConstantEnvironment::make_new(FColor::make_new(0.0, 0.0, 0.0)));
+            let pos = Point::make_new(278.0, 278.0, -800.0);
+            let lookat = Point::make_new(278.0, 278.0, 0.0);
+            let world_up = Vect::make_new(0.0, 1.0, 0.0);
+            let depth_of_field = (pos - lookat).length();
+            (make_cornell_box(), Camera::new(pos, lookat, world_up, 40.0, settings.aspect_ratio(), 0.0, depth_of_field))
+        }
+        _ => return None,
+    };
+
+    let mut output = PathBuf::from(PROJECT_PATH);
+    output.push(IMAGE_OUT_PATH);
+
+    Some(Scene
+    {
+        camera,
+        world: *world,
+        lights,
+        environment,
+        fog: None,
+        settings,
+        output,
+    })
+}
+
+fn make_random_scene() -> Box<HittableList>
+{
+    let mut world = Box::new(HittableList::default());
+
+    let ground_material = Arc::new(Lambertian::make_new(FColor::make_new(0.5, 0.5, 0.5)));
+    let ground_sphere = Arc::new(Sphere::make_new(Point::make_new(0.0, -1000.0, 0.0), 1000.0, ground_material));
+
+    world.add(ground_sphere);
+
+    // Add some random small balls
+    for i in -11..11
+    {
+        for j in -11..11
+        {
+            let material_decider = thread_rand();
+            let center = Point::make_new(i as f64 + 0.9 * thread_rand(), 0.2, j as f64+ 0.9 * thread_rand());
+
+            if (center - Point::make_new(4.0, 0.2, 0.0)).length() > 0.9
+            {
+                if material_decider < 0.5 // 50% chance for lambertian ball
+                {
+                    let albedo = FColor::rand();
+                    let material = Arc::new(Lambertian::make_new(albedo));
+                    let sphere = Arc::new(Sphere::make_new(center, 0.2, material));
+                    world.add(sphere);
+                }
+                else if material_decider < 0.9 // 40% chance for metal ball
+                {
+                    let albedo = FColor::rand();
+                    let roughness = thread_rand();
+                    let material = Arc::new(Metal::make_new(albedo, roughness));
+                    let sphere = Arc::new(Sphere::make_new(center, 0.2, material));
+                    world.add(sphere);
+                }
+                else // 10% chance for glass ball
+                {
+                    let material = Arc::new(Dielectric::make_new(1.5));
+                    let sphere = Arc::new(Sphere::make_new(center, 0.2, material.clone()));
+                    world.add(sphere);
+                    if thread_rand() < 0.3 // 30% for hollow glass ball
+                    {
+                        let inner_bubble = Arc::new(Sphere::make_new(center, -0.15, material));
+                        world.add(inner_bubble);
+                    }
+                }
+            }
+        }
+    }
+    // add three big balls, one for each material
+    let glass = Arc::new(Dielectric::make_new(1.5));
+    let glass_ball = Arc::new(Sphere::make_new(Point::make_new(0.0, 1.0, 0.0), 1.0, glass));
+    world.add(glass_ball);
+    
+    let diffuse = Arc::new(Lambertian::make_new(FColor::rand()));
+    let diffuse_ball = Arc::new(Sphere::make_new(Point::make_new(-4.0, 1.0, 0.0), 1.0, diffuse));
+    world.add(diffuse_ball);
+
+    let metal = Arc::new(Metal::make_new(FColor::rand(), 0.5));
+    let metal_ball = Arc::new(Sphere::make_new(Point::make_new(4.0, 1.0, 0.0), 1.0, metal));
+    world.add(metal_ball);
+
+    world
+}
+
+fn make_simple_scene() -> Box<HittableList>
+{
+    let mut world = Box::new(HittableList::default());
+    
+    // Materials:
+    let material_ground = Arc::new(Lambertian::make_new(FColor::make_new(0.8, 0.8, 0.0)));
+    let material_center = Arc::new(Lambertian::make_new(FColor::make_new(0.1, 0.2, 0.5)));
+    let material_left   = Arc::new(Dielectric::make_new(1.5));
+    let material_right  = Arc::new(Metal::make_new(FColor::make_new(0.8, 0.6, 0.2), 0.0));
+
+    // Spheres:
+    let ground_sphere = Arc::new(Sphere::make_new(Point::make_new(0.0, -100.5, -1.0), 100.0, material_ground));
+    let center_sphere = Arc::new(Sphere::make_new(Point::make_new(0.0, 0.0, -1.0), 0.5, material_center));
+    let left_sphere = Arc::new(Sphere::make_new(Point::make_new(-1.0, 0.0, -1.0), 0.5, material_left.clone()));
+    let left_sphere_inner = Arc::new(Sphere::make_new(Point::make_new(-1.0, 0.0, -1.0), -0.45, material_left));
+    let right_sphere = Arc::new(Sphere::make_new(Point::make_new(1.0, 0.0, -1.0), 0.5, material_right));
+    world.add(ground_sphere);
+    world.add(center_sphere);
+    world.add(left_sphere);
+    world.add(left_sphere_inner);
+    world.add(right_sphere);
+    
+    world
+}
+
+// Adds the parallelogram spanned by u and v from corner as two triangles
+fn add_quad(world: &mut HittableList, corner: Point, u: Vect, v: Vect, material: Arc<dyn Material>)
+{
+    world.add(Arc::new(Triangle::make_new(corner, corner + u, corner + u + v, material.clone())));
+    world.add(Arc::new(Triangle::make_new(corner, corner + u + v, corner + v, material)));
+}
+
+// Box standing on the floor at (x, z), turned by angle degrees around its vertical axis
+fn add_box(world: &mut HittableList, x: f64, z: f64, size: Vect, angle: f64, material: Arc<dyn Material>)
+{
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let u = Vect::make_new(cos * size.x(), 0.0, -sin * size.x());
+    let v = Vect::make_new(0.0, size.y(), 0.0);
+    let w = Vect::make_new(sin * size.z(), 0.0, cos * size.z());
+    let corner = Point::make_new(x, 0.0, z);
+    let opposite = corner + u + v + w;
+
+    add_quad(world, corner, u, v, material.clone());
+    add_quad(world, corner, v, w, material.clone());
+    add_quad(world, corner, w, u, material.clone());
+    add_quad(world, opposite, -u, -v, material.clone());
+    add_quad(world, opposite, -v, -w, material.clone());
+    add_quad(world, opposite, -w, -u, material);
+}
+
+fn make_cornell_box() -> Box<HittableList>
+{
+    let mut world = Box::new(HittableList::default());
+
+    let red: Arc<dyn Material> = Arc::new(Lambertian::make_new(FColor::make_new(0.65, 0.05, 0.05)));
+    let white: Arc<dyn Material> = Arc::new(Lambertian::make_new(FColor::make_new(0.73, 0.73, 0.73)));
+    let green: Arc<dyn Material> = Arc::new(Lambertian::make_new(FColor::make_new(0.12, 0.45, 0.15)));
+    let light: Arc<dyn Material> = Arc::new(DiffuseLight::make_new(FColor::make_new(15.0, 15.0, 15.0)));
+
+    let x = Vect::make_new(555.0, 0.0, 0.0);
+    let y = Vect::make_new(0.0, 555.0, 0.0);
+    let z = Vect::make_new(0.0, 0.0, 555.0);
+    let origin = Point::make_new(0.0, 0.0, 0.0);
+
+    // Walls, floor and ceiling, open towards the camera
+    add_quad(&mut world, origin + x, y, z, green);
+    add_quad(&mut world, origin, y, z, red);
+    add_quad(&mut world, origin, x, z, white.clone());
+    add_quad(&mut world, origin + y, x, z, white.clone());
+    add_quad(&mut world, origin + z, x, y, white.clone());
+
+    // The lamp hangs just below the ceiling so it doesn't share its plane
+    add_quad(&mut world, Point::make_new(213.0, 554.0, 227.0), Vect::make_new(130.0, 0.0, 0.0), Vect::make_new(0.0, 0.0, 105.0), light);
+
+    add_box(&mut world, 265.0, 295.0, Vect::make_new(165.0, 330.0, 165.0), 15.0, white.clone());
+    add_box(&mut world, 130.0, 65.0, Vect::make_new(165.0, 165.0, 165.0), -18.0, white);
+
+    world
+}
@@ -0,0 +1,249 @@
+use super::super::math::core::*;
+use super::super::math::distribution::Distribution2D;
+use super::super::math::framebuffer::FrameBuffer;
+use super::super::math::random::thread_rand;
+use super::super::math::vect::Vect;
+use super::super::utils::color::FColor;
+
+// Light arriving from infinitely far away, seen by every ray that leaves the scene
+pub trait Environment: Send + Sync
+{
+    // Radiance seen when looking along direction
+    fn radiance(&self, direction: &Vect) -> FColor;
+
+    // Unit direction picked for light sampling, with its solid angle density.
+    // Environments that return None are only found by rays escaping the scene.
+    fn sample(&self) -> Option<(Vect, f64)>
+    {
+        None
+    }
+
+    fn pdf(&self, _direction: &Vect) -> f64
+    {
+        0.0
+    }
+}
+
+// White at the horizon fading to light blue overhead
+pub struct SkyGradient;
+
+impl Environment for SkyGradient
+{
+    fn radiance(&self, direction: &Vect) -> FColor
+    {
+        let y = 0.5 * direction.get_normalized().y() + 0.5;
+        (1.0 - y) * FColor::new_color(1.0, 1.0, 1.0) + y * FColor::new_color(0.5, 0.7, 1.0)
+    }
+}
+
+pub struct ConstantEnvironment
+{
+    color: FColor,
+}
+
+impl Environment for ConstantEnvironment
+{
+    fn radiance(&self, _direction: &Vect) -> FColor
+    {
+        self.color
+    }
+}
+
+impl ConstantEnvironment
+{
+    pub fn new(color: &FColor) -> Self
+    {
+        ConstantEnvironment{ color: *color }
+    }
+
+    pub fn make_new(color: FColor) -> Self
+    {
+        ConstantEnvironment{ color }
+    }
+}
+
+// Rotation about the up (y) axis, counter clockwise seen from above
+fn rotate_y(direction: &Vect, angle: f64) -> Vect
+{
+    let (sin, cos) = angle.sin_cos();
+    Vect::make_new(direction.x() * cos + direction.z() * sin, direction.y(), direction.z() * cos - direction.x() * sin)
+}
+
+// Latitude-longitude (equirectangular) image wrapped around the scene. The top row is straight up and
+// the center of the image looks down -z before the rotation is applied.
+// Directions are importance sampled proportionally to the luminance of the pixels.
+pub struct ImageEnvironment
+{
+    image: FrameBuffer,
+    intensity: f64,
+    rotation: f64,
+    distribution: Distribution2D,
+}
+
+impl ImageEnvironment
+{
+    // rotation is in degrees, intensity scales the radiance of every pixel
+    pub fn make_new(image: FrameBuffer, intensity: f64, rotation: f64) -> Self
+    {
+        let (width, height) = (image.width(), image.height());
+        let mut function = Vec::with_capacity(width * height);
+        for y in 0..height
+        {
+            // Rows near the poles cover less solid angle
+            let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+            function.extend(image.row(y).iter().map(|pixel| luminance(pixel) * sin_theta));
+        }
+
+        ImageEnvironment
+        {
+            distribution: Distribution2D::make_new(&function, width, height),
+            image,
+            intensity,
+            rotation: degrees_to_radians(rotation),
+        }
+    }
+
+    // Image coordinates in [0, 1) of a world direction
+    fn direction_to_uv(&self, direction: &Vect) -> (f64, f64)
+    {
+        let direction = rotate_y(&direction.get_normalized(), -self.rotation);
+        let theta = clamp(direction.y(), -1.0, 1.0).acos();
+        let phi = direction.x().atan2(-direction.z());
+        (((phi + PI) / (2.0 * PI)).min(1.0 - f64::EPSILON), (theta / PI).min(1.0 - f64::EPSILON))
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> Vect
+    {
+        let (sin_theta, cos_theta) = (PI * v).sin_cos();
+        let (sin_phi, cos_phi) = (2.0 * PI * u - PI).sin_cos();
+        rotate_y(&Vect::make_new(sin_theta * sin_phi, cos_theta, -sin_theta * cos_phi), self.rotation)
+    }
+}
+
+fn luminance(color: &FColor) -> f64
+{
+    0.2126 * color.r() + 0.7152 * color.g() + 0.0722 * color.b()
+}
+
+impl Environment for ImageEnvironment
+{
+    fn radiance(&self, direction: &Vect) -> FColor
+    {
+        let (u, v) = self.direction_to_uv(direction);
+        let x = (u * self.image.width() as f64) as usize;
+        let y = (v * self.image.height() as f64) as usize;
+        *self.image.get(x, y) * self.intensity
+    }
+
+    fn sample(&self) -> Option<(Vect, f64)>
+    {
+        if self.distribution.integral() <= 0.0
+        {
+            return None;
+        }
+        let ((u, v), pdf) = self.distribution.sample(thread_rand(), thread_rand());
+        let sin_theta = (PI * v).sin();
+        if pdf <= 0.0 || sin_theta <= 0.0
+        {
+            return None;
+        }
+        // The image covers 2 PI by PI radians, squeezed by sin(theta) towards the poles
+        Some((self.uv_to_direction(u, v), pdf / (2.0 * PI * PI * sin_theta)))
+    }
+
+    fn pdf(&self, direction: &Vect) -> f64
+    {
+        if self.distribution.integral() <= 0.0
+        {
+            return 0.0;
+        }
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0.0
+        {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::super::math::random::seed_thread_rand;
+
+    #[test]
+    fn gradient_test()
+    {
+        assert_eq!(SkyGradient.radiance(&Vect::make_new(0.0, 2.0, 0.0)), FColor::new_color(0.5, 0.7, 1.0));
+        assert_eq!(SkyGradient.radiance(&Vect::make_new(0.0, -1.0, 0.0)), FColor::new_color(1.0, 1.0, 1.0));
+        assert!(SkyGradient.sample().is_none());
+        assert_eq!(ConstantEnvironment::make_new(FColor::new_color(0.1, 0.2, 0.3)).radiance(&Vect::make_new(1.0, 0.0, 0.0)), FColor::new_color(0.1, 0.2, 0.3));
+    }
+
+    // 4 x 2 image, a bright pixel right of the center in the upper half
+    fn make_image() -> FrameBuffer
+    {
+        let mut image = FrameBuffer::new(4, 2);
+        for (x, y, pixel) in image.rows_mut(0..2).iter_mut()
+        {
+            *pixel = FColor::new_color(0.1, 0.1, 0.1) * (x + 4 * y) as f64;
+        }
+        image.set(2, 0, FColor::new_color(100.0, 100.0, 100.0));
+        image
+    }
+
+    #[test]
+    fn lookup_test()
+    {
+        let environment = ImageEnvironment::make_new(make_image(), 2.0, 0.0);
+        // Forward is the middle of the image, up the top row and down the bottom one
+        let forward_up = Vect::make_new(0.1, 1.0, -1.0);
+        assert_eq!(environment.radiance(&forward_up), FColor::new_color(200.0, 200.0, 200.0));
+        assert_eq!(environment.radiance(&Vect::make_new(-0.1, -1.0, -1.0)), FColor::new_color(1.0, 1.0, 1.0));
+        // Straight behind is the right edge
+        assert_eq!(environment.radiance(&Vect::make_new(0.0, -1.0, 1.0)), *make_image().get(3, 1) * 2.0);
+
+        for (u, v) in [(0.1, 0.2), (0.6, 0.7), (0.9, 0.45)]
+        {
+            let (u2, v2) = environment.direction_to_uv(&environment.uv_to_direction(u, v));
+            assert!((u - u2).abs() < 1e-9 && (v - v2).abs() < 1e-9);
+        }
+
+        // A quarter turn brings the bright pixel to the right of the camera
+        let rotated = ImageEnvironment::make_new(make_image(), 1.0, -90.0);
+        assert_eq!(rotated.radiance(&Vect::make_new(1.0, 1.0, 0.1)), FColor::new_color(100.0, 100.0, 100.0));
+    }
+
+    #[test]
+    fn sampling_test()
+    {
+        seed_thread_rand(21);
+        let environment = ImageEnvironment::make_new(make_image(), 1.0, 30.0);
+
+        // The density integrates to one over the sphere
+        let samples = 40000;
+        let total: f64 = (0..samples).map(|_| environment.pdf(&Vect::random_unit_vect())).sum();
+        assert!((total / samples as f64 * 4.0 * PI - 1.0).abs() < 0.05);
+
+        // Most samples go towards the bright pixel, and sample() agrees with pdf()
+        let mut bright = 0;
+        for _ in 0..1000
+        {
+            let (direction, pdf) = environment.sample().unwrap();
+            assert!((direction.length() - 1.0).abs() < 1e-9);
+            assert!((environment.pdf(&direction) - pdf).abs() < 1e-5 * pdf);
+            bright += (environment.radiance(&direction).r() == 100.0) as usize;
+        }
+        assert!(bright > 900);
+
+        assert!(ImageEnvironment::make_new(FrameBuffer::new(4, 2), 1.0, 0.0).sample().is_none());
+    }
+}
@@ -0,0 +1,669 @@
+use std::ops::{Add, AddAssign, Mul};
+use std::sync::Arc;
+
+use super::environment::Environment;
+use super::settings::RenderSettings;
+use super::super::math::core::*;
+use super::super::math::hittable::*;
+use super::super::math::light::Light;
+use super::super::math::material::Material;
+use super::super::math::medium::Fog;
+use super::super::math::point::Point;
+use super::super::math::random::thread_rand;
+use super::super::math::ray::Ray;
+use super::super::math::vect::Vect;
+use super::super::utils::color::FColor;
+use super::super::utils::spectrum::*;
+
+// Top level hittables of the scene that give off light, the targets of next event estimation
+pub fn make_emitter_list(world: &HittableList) -> HittableList
+{
+    let mut emitters = HittableList::default();
+    for hittable in world.hittables.iter().filter(|hittable| hittable.is_emissive())
+    {
+        emitters.add(Arc::clone(hittable));
+    }
+    emitters
+}
+
+// Weight of a sample taken with the strategy of density pdf when other_pdf could also have produced it
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64
+{
+    let (squared, other_squared) = (pdf * pdf, other_pdf * other_pdf);
+    if squared + other_squared <= 0.0
+    {
+        return 0.0;
+    }
+    squared / (squared + other_squared)
+}
+
+// Light coming from behind a surface can only go through it, while media take it from every side
+fn faces(hit_result: &HitResult, direction: &Vect) -> bool
+{
+    let material = hit_result.material();
+    material.is_volumetric() || material.is_transmissive(hit_result) || Vect::dot(direction, hit_result.normal()) > 0.0
+}
+
+// Shadow ray from a hit point, traced at the same moment as the ray that found it
+fn shadow_ray(ray: &Ray, hit_result: &HitResult, direction: Vect) -> Ray
+{
+    let mut shadow_ray = Ray::make_new(*hit_result.point(), direction);
+    shadow_ray.set_time(ray.time());
+    shadow_ray
+}
+
+// Path tracing: follows a ray through up to max_depth bounces, adding the light emitted by every
+// surface it hits, weighted by the attenuation gathered on the way there.
+// Non specular hits also sample the emitters and the punctual lights directly. The emission found by the
+// bounce that follows is then either skipped so it isn't counted twice, or with mis both are kept and
+// weighted with the power heuristic. Without emitters the surfaces are only found by the random walk.
+// In spectral mode every path carries a few wavelengths instead of RGB, the hero one rides on the ray for
+// the materials that disperse, and the result is brought back to RGB through the CIE matching functions.
+// Fog scatters the rays between surfaces and dims the light samples by its transmittance.
+pub struct PathTracer<'a>
+{
+    world: &'a dyn Hittable,
+    emitters: HittableList,
+    lights: &'a [Arc<dyn Light>],
+    environment: &'a dyn Environment,
+    fog: Option<&'a Fog>,
+    max_depth: u32,
+    mis: bool,
+    spectral: bool,
+}
+
+impl<'a> PathTracer<'a>
+{
+    pub fn make_new(world: &'a dyn Hittable, emitters: HittableList, lights: &'a [Arc<dyn Light>],
+                    environment: &'a dyn Environment, fog: Option<&'a Fog>, settings: &RenderSettings) -> Self
+    {
+        PathTracer
+        {
+            world,
+            emitters,
+            lights,
+            environment,
+            fog,
+            max_depth: settings.max_depth,
+            mis: settings.mis,
+            spectral: settings.spectral,
+        }
+    }
+
+    // Fraction of the light that gets through the objects and the fog along the first distance units of a shadow ray
+    fn visibility(&self, shadow_ray: &Ray, distance: f64) -> f64
+    {
+        let fog = self.fog.map_or(1.0, |fog| fog.transmittance(shadow_ray, distance));
+        if fog <= 0.0
+        {
+            return 0.0;
+        }
+        fog * self.world.transmittance(shadow_ray, 0.001, distance * (1.0 - FLOAT_MARGIN_OF_ERROR))
+    }
+
+    // Closest surface along the ray, or the point where the fog scatters it before getting there
+    fn intersect(&self, ray: &Ray) -> Option<HitResult>
+    {
+        let hit_result = self.world.hit(ray, 0.001, INFINITY);
+        let Some(fog) = self.fog else { return hit_result };
+        let distance = hit_result.as_ref().map_or(INFINITY, |hit_result| hit_result.t());
+        fog.sample(ray, distance).or(hit_result)
+    }
+
+    // Direct light reaching the hit point through one shadow ray towards a randomly picked emitter
+    fn sample_emitters<P: PathSpectrum>(&self, ray: &Ray, hit_result: &HitResult, path: &P) -> P::Value
+    {
+        let direction = self.emitters.random_direction(hit_result.point(), ray.time());
+        if direction.is_zero()
+        {
+            return P::black();
+        }
+        let shadow_ray = shadow_ray(ray, hit_result, direction);
+        let wi = shadow_ray.direction();
+        let wo = -ray.direction();
+        if !faces(hit_result, wi)
+        {
+            return P::black();
+        }
+        let light_pdf = self.emitters.pdf_value(hit_result.point(), wi, ray.time());
+        let scattering = path.eval(hit_result.material(), wi, &wo, hit_result);
+        if light_pdf <= 0.0 || P::is_black(&scattering)
+        {
+            return P::black();
+        }
+
+        let Some(light_hit) = self.emitters.hit(&shadow_ray, 0.001, INFINITY)
+        else
+        {
+            return P::black();
+        };
+        let visibility = self.visibility(&shadow_ray, light_hit.t());
+        if visibility <= 0.0
+        {
+            return P::black();
+        }
+        let weight = if self.mis { power_heuristic(light_pdf, hit_result.material().pdf(wi, &wo, hit_result)) } else { 1.0 };
+        path.light(&light_hit.material().emitted(&shadow_ray, &light_hit)) * scattering * (visibility * weight / light_pdf)
+    }
+
+    // Direct light from a direction picked by the environment, if it can be sampled
+    fn sample_environment<P: PathSpectrum>(&self, ray: &Ray, hit_result: &HitResult, path: &P) -> P::Value
+    {
+        let Some((wi, environment_pdf)) = self.environment.sample() else { return P::black() };
+        let wo = -ray.direction();
+        if !faces(hit_result, &wi)
+        {
+            return P::black();
+        }
+        let scattering = path.eval(hit_result.material(), &wi, &wo, hit_result);
+        let shadow_ray = shadow_ray(ray, hit_result, wi);
+        if P::is_black(&scattering)
+        {
+            return P::black();
+        }
+        let visibility = self.visibility(&shadow_ray, INFINITY);
+        if visibility <= 0.0
+        {
+            return P::black();
+        }
+        let weight = if self.mis { power_heuristic(environment_pdf, hit_result.material().pdf(&wi, &wo, hit_result)) } else { 1.0 };
+        path.light(&self.environment.radiance(&wi)) * scattering * (visibility * weight / environment_pdf)
+    }
+
+    // Punctual lights can't be found by the random walk, so every one of them is sampled at every bounce
+    fn sample_lights<P: PathSpectrum>(&self, ray: &Ray, hit_result: &HitResult, path: &P) -> P::Value
+    {
+        let mut radiance = P::black();
+        let wo = -ray.direction();
+        for light in self.lights
+        {
+            let Some(sample) = light.sample(hit_result.point()) else { continue };
+            if !faces(hit_result, &sample.direction)
+            {
+                continue;
+            }
+            let scattering = path.eval(hit_result.material(), &sample.direction, &wo, hit_result);
+            let shadow_ray = shadow_ray(ray, hit_result, sample.direction);
+            if P::is_black(&scattering)
+            {
+                continue;
+            }
+            let visibility = self.visibility(&shadow_ray, sample.distance);
+            if visibility > 0.0
+            {
+                radiance += scattering * path.light(&sample.irradiance) * visibility;
+            }
+        }
+        radiance
+    }
+
+    pub fn ray_color(&self, ray: &Ray) -> FColor
+    {
+        if !self.spectral
+        {
+            return self.trace(ray, &mut RgbPath);
+        }
+        let mut wavelengths = SampledWavelengths::sample(thread_rand());
+        let mut ray = *ray;
+        ray.set_wavelength(Some(wavelengths.hero()));
+        let radiance = self.trace(&ray, &mut wavelengths);
+        wavelengths.to_rgb(&radiance)
+    }
+
+    fn trace<P: PathSpectrum>(&self, ray: &Ray, path: &mut P) -> P::Value
+    {
+        let mut radiance = P::black();
+        let mut throughput = P::white();
+        let mut ray = *ray;
+        // Point and BSDF density of the previous bounce when it sampled the lights
+        let mut sampled_bounce: Option<(Point, f64)> = None;
+
+        for _depth in 0..self.max_depth
+        {
+            let Some(hit_result) = self.intersect(&ray)
+            else
+            {
+                // Directions the environment could have sampled were already counted, or get their share with mis
+                let mut weight = 1.0;
+                if let Some((_, bsdf_pdf)) = sampled_bounce
+                {
+                    let environment_pdf = self.environment.pdf(ray.direction());
+                    if environment_pdf > 0.0
+                    {
+                        weight = if self.mis { power_heuristic(bsdf_pdf, environment_pdf) } else { 0.0 };
+                    }
+                }
+                return radiance + throughput * path.light(&self.environment.radiance(ray.direction())) * weight;
+            };
+
+            let material = hit_result.material();
+            let emitted = material.emitted(&ray, &hit_result);
+            match sampled_bounce
+            {
+                // Glowing media aren't emitters, light sampling never finds them
+                Some((origin, bsdf_pdf)) if !self.emitters.is_empty() && !material.is_volumetric() =>
+                {
+                    if self.mis && !emitted.is_zero()
+                    {
+                        let light_pdf = self.emitters.pdf_value(&origin, ray.direction(), ray.time());
+                        radiance += throughput * path.light(&emitted) * power_heuristic(bsdf_pdf, light_pdf);
+                    }
+                }
+                _ => radiance += throughput * path.light(&emitted),
+            }
+
+            let light_sampling = !material.is_specular();
+            if light_sampling
+            {
+                if !self.emitters.is_empty()
+                {
+                    radiance += throughput * self.sample_emitters(&ray, &hit_result, path);
+                }
+                radiance += throughput * (self.sample_environment(&ray, &hit_result, path) + self.sample_lights(&ray, &hit_result, path));
+            }
+
+            match path.scatter(material, &ray, &hit_result)
+            {
+                Some((attenuation, scattered_ray)) =>
+                {
+                    sampled_bounce = if light_sampling
+                    {
+                        Some((*hit_result.point(), material.pdf(scattered_ray.direction(), &-ray.direction(), &hit_result)))
+                    }
+                    else
+                    {
+                        None
+                    };
+                    throughput = throughput * attenuation;
+                    // The whole path carries the wavelength and the time it started with
+                    let (wavelength, time) = (ray.wavelength(), ray.time());
+                    ray = scattered_ray;
+                    ray.set_wavelength(wavelength);
+                    ray.set_time(time);
+                }
+                None => return radiance,
+            }
+        }
+        radiance
+    }
+}
+
+// What a path carries: RGB colors, or the values at the wavelengths of a spectral path.
+// Lights are described in RGB and turned into the path's values when they are reached.
+trait PathSpectrum
+{
+    type Value: Copy + Add<Output = Self::Value> + AddAssign + Mul<Output = Self::Value> + Mul<f64, Output = Self::Value>;
+
+    fn black() -> Self::Value;
+    fn white() -> Self::Value;
+    fn is_black(value: &Self::Value) -> bool;
+    fn light(&self, radiance: &FColor) -> Self::Value;
+    fn scatter(&mut self, material: &dyn Material, ray: &Ray, hit_result: &HitResult) -> Option<(Self::Value, Ray)>;
+    fn eval(&self, material: &dyn Material, wi: &Vect, wo: &Vect, hit_result: &HitResult) -> Self::Value;
+}
+
+struct RgbPath;
+
+impl PathSpectrum for RgbPath
+{
+    type Value = FColor;
+
+    fn black() -> FColor
+    {
+        FColor::make_new(0.0, 0.0, 0.0)
+    }
+
+    fn white() -> FColor
+    {
+        FColor::make_new(1.0, 1.0, 1.0)
+    }
+
+    fn is_black(value: &FColor) -> bool
+    {
+        value.is_zero()
+    }
+
+    fn light(&self, radiance: &FColor) -> FColor
+    {
+        *radiance
+    }
+
+    fn scatter(&mut self, material: &dyn Material, ray: &Ray, hit_result: &HitResult) -> Option<(FColor, Ray)>
+    {
+        material.scatter(ray, hit_result)
+    }
+
+    fn eval(&self, material: &dyn Material, wi: &Vect, wo: &Vect, hit_result: &HitResult) -> FColor
+    {
+        material.eval(wi, wo, hit_result)
+    }
+}
+
+impl PathSpectrum for SampledWavelengths
+{
+    type Value = SampledSpectrum;
+
+    fn black() -> SampledSpectrum
+    {
+        SampledSpectrum{ data: [0.0; WAVELENGTH_COUNT] }
+    }
+
+    fn white() -> SampledSpectrum
+    {
+        SampledSpectrum{ data: [1.0; WAVELENGTH_COUNT] }
+    }
+
+    fn is_black(value: &SampledSpectrum) -> bool
+    {
+        value.data.iter().all(|component| *component == 0.0)
+    }
+
+    fn light(&self, radiance: &FColor) -> SampledSpectrum
+    {
+        self.upsample(radiance)
+    }
+
+    fn scatter(&mut self, material: &dyn Material, ray: &Ray, hit_result: &HitResult) -> Option<(SampledSpectrum, Ray)>
+    {
+        material.scatter_spectral(ray, hit_result, self)
+    }
+
+    fn eval(&self, material: &dyn Material, wi: &Vect, wo: &Vect, hit_result: &HitResult) -> SampledSpectrum
+    {
+        material.eval_spectral(wi, wo, hit_result, self)
+    }
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::environment::*;
+    use super::super::super::math::framebuffer::FrameBuffer;
+    use super::super::super::math::light::*;
+    use super::super::super::math::material::*;
+    use super::super::super::math::medium::*;
+    use super::super::super::math::principled::*;
+    use super::super::super::math::random::seed_thread_rand;
+    use super::super::super::math::sphere::Sphere;
+    use super::super::super::math::texture::SolidColor;
+    use super::super::super::math::triangle::Triangle;
+
+    fn make_tracer<'a>(world: &'a HittableList, emitters: HittableList, lights: &'a [Arc<dyn Light>], environment: &'a dyn Environment,
+                       max_depth: u32, mis: bool) -> PathTracer<'a>
+    {
+        PathTracer::make_new(world, emitters, lights, environment, None, &RenderSettings{ max_depth, mis, ..RenderSettings::default() })
+    }
+
+    // A large floor in the y = 0 plane
+    fn add_floor(world: &mut HittableList, material: Arc<dyn Material>)
+    {
+        let corners = [Point::make_new(-100.0, 0.0, -100.0), Point::make_new(100.0, 0.0, -100.0),
+                       Point::make_new(100.0, 0.0, 100.0), Point::make_new(-100.0, 0.0, 100.0)];
+        world.add(Arc::new(Triangle::new(&corners[0], &corners[1], &corners[2], material.clone())));
+        world.add(Arc::new(Triangle::new(&corners[0], &corners[2], &corners[3], material)));
+    }
+
+    #[test]
+    fn emission_test()
+    {
+        let ray = Ray::make_new(Point::make_new(0.0, 0.0, 0.0), Vect::make_new(0.0, 0.0, -1.0));
+        let black = ConstantEnvironment::make_new(FColor::new_color(0.0, 0.0, 0.0));
+
+        // Looking straight at a light
+        let mut world = HittableList::default();
+        world.add(Arc::new(Sphere::make_new(Point::make_new(0.0, 0.0, -2.0), 0.5, Arc::new(DiffuseLight::make_new(FColor::new_color(3.0, 2.0, 1.0))))));
+        assert_eq!(make_tracer(&world, HittableList::default(), &[], &black, 5, true).ray_color(&ray), FColor::new_color(3.0, 2.0, 1.0));
+        assert!(make_tracer(&world, HittableList::default(), &[], &black, 0, true).ray_color(&ray).is_zero());
+
+        // A diffuse surface inside a closed light sphere gets the light times its albedo on the first bounce
+        let mut world = HittableList::default();
+        world.add(Arc::new(Sphere::make_new(Point::make_new(0.0, 0.0, 0.0), 10.0, Arc::new(DiffuseLight::make_new(FColor::new_color(1.0, 1.0, 1.0))))));
+        world.add(Arc::new(Sphere::make_new(Point::make_new(0.0, 0.0, -2.0), 0.5, Arc::new(Lambertian::make_new(FColor::new_color(0.5, 0.25, 1.0))))));
+        assert_eq!(make_tracer(&world, HittableList::default(), &[], &black, 5, true).ray_color(&ray), FColor::new_color(0.5, 0.25, 1.0));
+        // Without a second bounce the light is never reached
+        assert!(make_tracer(&world, HittableList::default(), &[], &black, 1, true).ray_color(&ray).is_zero());
+
+        // Escaping rays pick up the background
+        let world = HittableList::default();
+        let background = ConstantEnvironment::make_new(FColor::new_color(0.2, 0.3, 0.4));
+        assert_eq!(make_tracer(&world, HittableList::default(), &[], &background, 5, true).ray_color(&ray), FColor::new_color(0.2, 0.3, 0.4));
+    }
+
+    #[test]
+    fn emitter_list_test()
+    {
+        let mut world = HittableList::default();
+        world.add(Arc::new(Sphere::default()));
+        world.add(Arc::new(Sphere::make_new(Point::make_new(0.0, 5.0, 0.0), 1.0, Arc::new(DiffuseLight::default()))));
+        let mut group = HittableList::default();
+        group.add(Arc::new(Sphere::make_new(Point::make_new(0.0, -5.0, 0.0), 1.0, Arc::new(DiffuseLight::default()))));
+        world.add(Arc::new(group));
+        assert_eq!(make_emitter_list(&world).len(), 2);
+    }
+
+    #[test]
+    fn next_event_estimation_test()
+    {
+        seed_thread_rand(11);
+        let black = ConstantEnvironment::make_new(FColor::new_color(0.0, 0.0, 0.0));
+
+        // A floor lit by a small sphere straight above the point the ray hits
+        let mut world = HittableList::default();
+        add_floor(&mut world, Arc::new(Lambertian::make_new(FColor::new_color(0.5, 0.5, 0.5))));
+        world.add(Arc::new(Sphere::make_new(Point::make_new(0.0, 5.0, 0.0), 0.5, Arc::new(DiffuseLight::make_new(FColor::new_color(10.0, 10.0, 10.0))))));
+        assert_eq!(make_emitter_list(&world).len(), 1);
+
+        // Irradiance from a sphere above a surface is PI * L * (r / d)^2
+        let expected = 0.5 * 10.0 * (0.5 * 0.5) / (5.0 * 5.0);
+        let ray = Ray::make_new(Point::make_new(0.0, 1.0, 1.0), Vect::make_new(0.0, -1.0, -1.0));
+        let samples = 2000;
+        for mis in [false, true]
+        {
+            // Two bounces: the light sample and the light found by the BSDF sample
+            let tracer = make_tracer(&world, make_emitter_list(&world), &[], &black, 2, mis);
+            let mut total = FColor::make_new(0.0, 0.0, 0.0);
+            for _ in 0..samples
+            {
+                total += tracer.ray_color(&ray);
+            }
+            assert!((total.r() / samples as f64 - expected).abs() < 0.02 * expected);
+            assert!(total.r() == total.g() && total.g() == total.b());
+        }
+    }
+
+    #[test]
+    fn glossy_mis_test()
+    {
+        seed_thread_rand(13);
+        let black = ConstantEnvironment::make_new(FColor::new_color(0.0, 0.0, 0.0));
+
+        // A rough metal floor reflecting a large light, both strategies have to agree
+        let mut world = HittableList::default();
+        add_floor(&mut world, Arc::new(Metal::make_new(FColor::new_color(0.8, 0.8, 0.8), 0.4)));
+        world.add(Arc::new(Sphere::make_new(Point::make_new(0.0, 3.0, -3.0), 1.5, Arc::new(DiffuseLight::make_new(FColor::new_color(2.0, 2.0, 2.0))))));
+
+        let ray = Ray::make_new(Point::make_new(0.0, 1.0, 1.0), Vect::make_new(0.0, -1.0, -1.0));
+        let samples = 4000;
+        let averages: Vec<f64> = [false, true].into_iter().map(|mis|
+            {
+                let tracer = make_tracer(&world, make_emitter_list(&world), &[], &black, 2, mis);
+                let total: f64 = (0..samples).map(|_| tracer.ray_color(&ray).r()).sum();
+                total / samples as f64
+            }).collect();
+        assert!(averages[1] > 0.0);
+        assert!((averages[0] - averages[1]).abs() < 0.05 * averages[1]);
+    }
+
+    // Average red value through a ray looking down at a floor with an emissive sphere below it, with next
+    // event estimation alone, with mis and with the BSDF samples only
+    fn light_below_floor(floor: Arc<dyn Material>, samples: usize) -> [f64; 3]
+    {
+        let black = ConstantEnvironment::make_new(FColor::new_color(0.0, 0.0, 0.0));
+        let mut world = HittableList::default();
+        add_floor(&mut world, floor);
+        world.add(Arc::new(Sphere::make_new(Point::make_new(0.0, -3.0, 0.0), 2.5, Arc::new(DiffuseLight::make_new(FColor::new_color(1.0, 1.0, 1.0))))));
+
+        let ray = Ray::make_new(Point::make_new(0.0, 1.0, 1.0), Vect::make_new(0.0, -1.0, -1.0));
+        let tracers = [make_tracer(&world, make_emitter_list(&world), &[], &black, 2, false),
+                       make_tracer(&world, make_emitter_list(&world), &[], &black, 2, true),
+                       make_tracer(&world, HittableList::default(), &[], &black, 2, true)];
+        tracers.map(|tracer| (0..samples).map(|_| tracer.ray_color(&ray).r()).sum::<f64>() / samples as f64)
+    }
+
+    #[test]
+    fn transmission_sampling_test()
+    {
+        // Light sampled through rough glass has to match what the refracted BSDF samples find,
+        // the latter being the noisiest
+        seed_thread_rand(23);
+        let [nee, mis, bsdf] = light_below_floor(Arc::new(RoughDielectric::make_new(1.5, 0.6)), 40000);
+        assert!(bsdf > 0.0);
+        assert!((nee - bsdf).abs() < 0.06 * bsdf, "{} {}", nee, bsdf);
+        assert!((mis - bsdf).abs() < 0.06 * bsdf, "{} {}", mis, bsdf);
+
+        // The same through the glass lobe of a principled material
+        let mut parameters = PrincipledParameters::default();
+        parameters.base_color = Arc::new(SolidColor::make_new(FColor::new_color(1.0, 1.0, 1.0)));
+        parameters.set_constant("transmission", 1.0);
+        parameters.set_constant("roughness", 0.6);
+        let [nee, mis, bsdf] = light_below_floor(Arc::new(Principled::make_new(parameters)), 40000);
+        assert!(bsdf > 0.0);
+        assert!((nee - bsdf).abs() < 0.06 * bsdf, "{} {}", nee, bsdf);
+        assert!((mis - bsdf).abs() < 0.06 * bsdf, "{} {}", mis, bsdf);
+    }
+
+    #[test]
+    fn punctual_light_test()
+    {
+        let black = ConstantEnvironment::make_new(FColor::new_color(0.0, 0.0, 0.0));
+        let mut world = HittableList::default();
+        add_floor(&mut world, Arc::new(Lambertian::make_new(FColor::new_color(0.5, 0.25, 1.0))));
+        let ray = Ray::make_new(Point::make_new(0.0, 1.0, 1.0), Vect::make_new(0.0, -1.0, -1.0));
+
+        // Irradiance of one straight above the floor, reflected with albedo / PI
+        let lights: Vec<Arc<dyn Light>> = vec![Arc::new(PointLight::make_new(Point::make_new(0.0, 2.0, 0.0), FColor::new_color(4.0, 4.0, 4.0)))];
+        let color = make_tracer(&world, HittableList::default(), &lights, &black, 1, true).ray_color(&ray);
+        assert!((color - FColor::new_color(0.5, 0.25, 1.0) / PI).length() < 1e-9);
+
+        // The sun at 60 degrees from the zenith
+        let lights: Vec<Arc<dyn Light>> = vec![Arc::new(DirectionalLight::make_new(Vect::make_new(3f64.sqrt(), 1.0, 0.0), FColor::new_color(2.0, 2.0, 2.0), 0.0))];
+        let color = make_tracer(&world, HittableList::default(), &lights, &black, 1, true).ray_color(&ray);
+        assert!((color - FColor::new_color(0.5, 0.25, 1.0) / PI).length() < 1e-9);
+
+        // A ball of smoke in the way only dims the light, by the transmittance across its diameter
+        let mut smoky = HittableList::default();
+        add_floor(&mut smoky, Arc::new(Lambertian::make_new(FColor::new_color(0.5, 0.25, 1.0))));
+        let ball = Arc::new(Sphere::make_new(Point::make_new(3f64.sqrt() * 0.5, 0.5, 0.0), 0.3, Arc::new(Lambertian::default())));
+        smoky.add(Arc::new(ConstantMedium::make_new(ball, 1.0, Arc::new(Isotropic::default()))));
+        let color = make_tracer(&smoky, HittableList::default(), &lights, &black, 1, true).ray_color(&ray);
+        assert!((color - FColor::new_color(0.5, 0.25, 1.0) / PI * (-0.6f64).exp()).length() < 1e-9);
+
+        // Blocked by a sphere in between
+        world.add(Arc::new(Sphere::make_new(Point::make_new(3f64.sqrt(), 1.0, 0.0), 0.5, Arc::new(Lambertian::default()))));
+        assert!(make_tracer(&world, HittableList::default(), &lights, &black, 1, true).ray_color(&ray).is_zero());
+        // A light below the floor doesn't reach it
+        let lights: Vec<Arc<dyn Light>> = vec![Arc::new(PointLight::make_new(Point::make_new(0.0, -2.0, 0.0), FColor::new_color(4.0, 4.0, 4.0)))];
+        assert!(make_tracer(&world, HittableList::default(), &lights, &black, 1, true).ray_color(&ray).is_zero());
+    }
+
+    #[test]
+    fn environment_sampling_test()
+    {
+        seed_thread_rand(17);
+        let mut world = HittableList::default();
+        add_floor(&mut world, Arc::new(Lambertian::make_new(FColor::new_color(0.5, 0.5, 0.5))));
+        let ray = Ray::make_new(Point::make_new(0.0, 1.0, 1.0), Vect::make_new(0.0, -1.0, -1.0));
+
+        // A uniform environment of radiance 2 reflected by the floor
+        let mut image = FrameBuffer::new(8, 4);
+        for (_, _, pixel) in image.rows_mut(0..4).iter_mut()
+        {
+            *pixel = FColor::new_color(2.0, 2.0, 2.0);
+        }
+        let environment = ImageEnvironment::make_new(image, 1.0, 0.0);
+        let samples = 4000;
+        for mis in [false, true]
+        {
+            let tracer = make_tracer(&world, HittableList::default(), &[], &environment, 2, mis);
+            let total: f64 = (0..samples).map(|_| tracer.ray_color(&ray).r()).sum();
+            assert!((total / samples as f64 - 1.0).abs() < 0.03);
+        }
+    }
+
+    #[test]
+    fn fog_test()
+    {
+        seed_thread_rand(37);
+        let black = ConstantEnvironment::make_new(FColor::new_color(0.0, 0.0, 0.0));
+        let mut world = HittableList::default();
+        add_floor(&mut world, Arc::new(Lambertian::make_new(FColor::new_color(0.5, 0.25, 1.0))));
+        let ray = Ray::make_new(Point::make_new(0.0, 1.0, 1.0), Vect::make_new(0.0, -1.0, -1.0));
+        let lights: Vec<Arc<dyn Light>> = vec![Arc::new(PointLight::make_new(Point::make_new(0.0, 2.0, 0.0), FColor::new_color(4.0, 4.0, 4.0)))];
+
+        // Black fog only absorbs: the light is dimmed over its 2 units down to the floor and the sqrt(2) units back up
+        let fog = Fog::make_new(0.2, Arc::new(Isotropic::make_new(FColor::new_color(0.0, 0.0, 0.0))), INFINITY);
+        let tracer = PathTracer::make_new(&world, HittableList::default(), &lights, &black, Some(&fog), &RenderSettings{ max_depth: 2, ..RenderSettings::default() });
+        let samples = 20000;
+        let total: f64 = (0..samples).map(|_| tracer.ray_color(&ray).r()).sum();
+        let expected = 0.5 / PI * (-0.2 * (2.0 + 2f64.sqrt())).exp();
+        assert!((total / samples as f64 - expected).abs() < 0.02 * expected);
+
+        // Fog lying below the point light doesn't dim the first unit under it
+        let fog = Fog::make_new(0.2, Arc::new(Isotropic::make_new(FColor::new_color(0.0, 0.0, 0.0))), 1.0);
+        let tracer = PathTracer::make_new(&world, HittableList::default(), &lights, &black, Some(&fog), &RenderSettings{ max_depth: 2, ..RenderSettings::default() });
+        let total: f64 = (0..samples).map(|_| tracer.ray_color(&ray).r()).sum();
+        let expected = 0.5 / PI * (-0.2 * (1.0 + 2f64.sqrt())).exp();
+        assert!((total / samples as f64 - expected).abs() < 0.02 * expected);
+
+        // White fog lights up where the beam of a light goes through it
+        let world = HittableList::default();
+        let fog = Fog::make_new(0.5, Arc::new(HenyeyGreenstein::make_new(FColor::new_color(1.0, 1.0, 1.0), 0.5)), INFINITY);
+        let tracer = PathTracer::make_new(&world, HittableList::default(), &lights, &black, Some(&fog), &RenderSettings{ max_depth: 3, ..RenderSettings::default() });
+        let total: f64 = (0..1000).map(|_| tracer.ray_color(&ray).r()).sum();
+        assert!(total > 0.0);
+    }
+
+    #[test]
+    fn spectral_test()
+    {
+        seed_thread_rand(19);
+        let black = ConstantEnvironment::make_new(FColor::new_color(0.0, 0.0, 0.0));
+        let ray = Ray::make_new(Point::make_new(0.0, 0.0, 0.0), Vect::make_new(0.0, 0.0, -1.0));
+        let settings = RenderSettings{ max_depth: 5, spectral: true, ..RenderSettings::default() };
+
+        // A colored surface inside a white light sphere averages to its albedo over the wavelengths
+        let albedo = FColor::new_color(0.7, 0.4, 0.2);
+        let mut world = HittableList::default();
+        world.add(Arc::new(Sphere::make_new(Point::make_new(0.0, 0.0, 0.0), 10.0, Arc::new(DiffuseLight::make_new(FColor::new_color(1.0, 1.0, 1.0))))));
+        world.add(Arc::new(Sphere::make_new(Point::make_new(0.0, 0.0, -2.0), 0.5, Arc::new(Lambertian::make_new(albedo)))));
+        let tracer = PathTracer::make_new(&world, HittableList::default(), &[], &black, None, &settings);
+        let samples = 4000;
+        let mut total = FColor::make_new(0.0, 0.0, 0.0);
+        for _ in 0..samples
+        {
+            total += tracer.ray_color(&ray);
+        }
+        assert!((total / samples as f64 - albedo).length() < 0.03);
+
+        // Dispersive glass in front of a white light still lets it through on average, with a single
+        // wavelength left on each path it takes more samples
+        let mut world = HittableList::default();
+        world.add(Arc::new(Sphere::make_new(Point::make_new(0.0, 0.0, 0.0), 10.0, Arc::new(DiffuseLight::make_new(FColor::new_color(1.0, 1.0, 1.0))))));
+        world.add(Arc::new(Sphere::make_new(Point::make_new(0.0, 0.0, -2.0), 0.5, Arc::new(Dielectric::make_absorbing(RefractiveIndex::from_preset("bk7").unwrap(), FColor::new_color(0.0, 0.0, 0.0))))));
+        let tracer = PathTracer::make_new(&world, HittableList::default(), &[], &black, None, &RenderSettings{ max_depth: 20, ..settings });
+        let samples = 20000;
+        let mut total = FColor::make_new(0.0, 0.0, 0.0);
+        for _ in 0..samples
+        {
+            total += tracer.ray_color(&ray);
+        }
+        assert!((total / samples as f64 - FColor::new_color(1.0, 1.0, 1.0)).length() < 0.05);
+    }
+}
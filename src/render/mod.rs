@@ -0,0 +1,7 @@
+pub mod settings;
+pub mod scene;
+pub mod builtin;
+pub mod scheduler;
+pub mod integrator;
+pub mod environment;
+pub mod sky;
@@ -0,0 +1,23 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use super::environment::Environment;
+use super::settings::RenderSettings;
+use super::super::math::camera::Camera;
+use super::super::math::hittable::HittableList;
+use super::super::math::light::Light;
+use super::super::math::medium::Fog;
+
+// Everything needed to render one image
+pub struct Scene
+{
+    pub camera: Camera,
+    pub world: HittableList,
+    // Punctual lights, emissive objects are part of the world
+    pub lights: Vec<Arc<dyn Light>>,
+    pub environment: Arc<dyn Environment>,
+    // Medium filling the space between the objects, if any
+    pub fog: Option<Fog>,
+    pub settings: RenderSettings,
+    pub output: PathBuf,
+}
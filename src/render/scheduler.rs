@@ -0,0 +1,80 @@
+use super::super::math::framebuffer::{FrameBuffer, FrameBufferTile};
+use super::super::utils::threadpool::ThreadPool;
+
+// Cuts the frame into small tiles and queues them on the thread pool.
+// Idle workers keep pulling the next tile from the pool's shared queue, so expensive parts of the
+// image get spread over every worker instead of stalling the one thread that owns them.
+
+pub const DEFAULT_TILE_SIZE: usize = 32;
+
+// Calls render_tile on every tile of fb. The tiles are disjoint views, so the jobs write straight into fb.
+pub fn render_tiles<F>(pool: &ThreadPool, fb: &mut FrameBuffer, tile_size: usize, render_tile: F) -> Result<(), String>
+where
+    F: Fn(&mut FrameBufferTile) + Sync,
+{
+    let render_tile = &render_tile;
+    let errors: Vec<String> = pool.scope(|scope|
+        {
+            let handles: Vec<_> = fb.tiles_mut(tile_size, tile_size).into_iter().map(|mut tile| scope.spawn(move || render_tile(&mut tile))).collect();
+            handles.into_iter().filter_map(|handle| handle.join().err()).map(|error| error.message).collect()
+        });
+
+    match errors.first()
+    {
+        Some(error) => Err(format!("{} tiles failed to render: {}", errors.len(), error)),
+        None => Ok(()),
+    }
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::super::utils::color::FColor;
+
+    #[test]
+    fn render_tiles_test()
+    {
+        let pool = ThreadPool::new(4).unwrap();
+        let mut fb = FrameBuffer::new(50, 37);
+        render_tiles(&pool, &mut fb, 8, |tile|
+            {
+                for (x, y, pixel) in tile.iter_mut()
+                {
+                    *pixel = FColor::new_color(x as f64, y as f64, 1.0);
+                }
+            }).unwrap();
+
+        for y in 0..fb.height()
+        {
+            for x in 0..fb.width()
+            {
+                assert_eq!(*fb.get(x, y), FColor::new_color(x as f64, y as f64, 1.0));
+            }
+        }
+    }
+
+    #[test]
+    fn failed_tile_test()
+    {
+        let pool = ThreadPool::new(2).unwrap();
+        let mut fb = FrameBuffer::new(64, 64);
+        let result = render_tiles(&pool, &mut fb, 32, |tile|
+            {
+                assert!(tile.x() != 32 || tile.y() != 32, "bad tile");
+                for (_, _, pixel) in tile.iter_mut()
+                {
+                    *pixel = FColor::new_color(1.0, 1.0, 1.0);
+                }
+            });
+        assert_eq!(result, Err("1 tiles failed to render: bad tile".to_string()));
+        // The other tiles still got rendered
+        assert_eq!(*fb.get(0, 63), FColor::new_color(1.0, 1.0, 1.0));
+    }
+}
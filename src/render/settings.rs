@@ -0,0 +1,52 @@
+use std::thread::available_parallelism;
+
+use super::scheduler::DEFAULT_TILE_SIZE;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RenderSettings
+{
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub max_depth: u32,
+    // Weights light samples against BSDF samples with the power heuristic instead of only sampling lights
+    pub mis: bool,
+    // Traces wavelengths instead of RGB, for dispersion and saturated colors
+    pub spectral: bool,
+    pub threads: usize,
+    // Side of the square blocks of pixels handed out to the render threads
+    pub tile_size: usize,
+    // Every pixel derives its random sequence from this, so equal seeds give equal images
+    pub seed: u64,
+}
+
+impl RenderSettings
+{
+    pub const DEFAULT_HEIGHT: usize = 720;
+    pub const DEFAULT_WIDTH: usize = 1280;
+    pub const DEFAULT_SAMPLES: usize = 500;
+    pub const DEFAULT_MAX_DEPTH: u32 = 50;
+    // Largest width or height accepted from scene files and the command line
+    pub const MAX_RESOLUTION: usize = 65536;
+
+    pub fn default() -> Self
+    {
+        RenderSettings
+        {
+            width: RenderSettings::DEFAULT_WIDTH,
+            height: RenderSettings::DEFAULT_HEIGHT,
+            samples: RenderSettings::DEFAULT_SAMPLES,
+            max_depth: RenderSettings::DEFAULT_MAX_DEPTH,
+            mis: true,
+            spectral: false,
+            threads: available_parallelism().map_or(1, |threads| threads.get()),
+            tile_size: DEFAULT_TILE_SIZE,
+            seed: 0,
+        }
+    }
+
+    pub fn aspect_ratio(&self) -> f64
+    {
+        self.width as f64 / self.height as f64
+    }
+}
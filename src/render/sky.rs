@@ -0,0 +1,260 @@
+use super::environment::Environment;
+use super::super::math::core::*;
+use super::super::math::light::DirectionalLight;
+use super::super::math::vect::Vect;
+use super::super::utils::color::FColor;
+
+// Daylight after Preetham, Shirley and Smits, "A Practical Analytic Model for Daylight" (1999).
+// The sky is the Perez luminance distribution fitted to the turbidity (haziness) of the atmosphere and the
+// position of the sun, and the sun itself is a directional light dimmed by the same atmosphere.
+// The scene's up axis is +y, north is -z and east is +x.
+
+// Radiance units per kcd/m² of luminance, so that a clear sky is around 0.5
+const LUMINANCE_SCALE: f64 = 0.05;
+// Illuminance of the sun above the atmosphere, in klx
+const SOLAR_ILLUMINANCE: f64 = 128.0;
+// Angle the sun covers in the sky, in degrees
+pub const SUN_ANGULAR_DIAMETER: f64 = 0.53;
+// Representative wavelengths of the red, green and blue channels, in micrometers
+const WAVELENGTHS: [f64; 3] = [0.65, 0.55, 0.45];
+
+// Direction towards the sun from its elevation above the horizon and its compass bearing
+// (0 is north, 90 east), both in degrees
+pub fn sun_direction(elevation: f64, azimuth: f64) -> Vect
+{
+    let (sin_elevation, cos_elevation) = degrees_to_radians(elevation).sin_cos();
+    let (sin_azimuth, cos_azimuth) = degrees_to_radians(azimuth).sin_cos();
+    Vect::make_new(cos_elevation * sin_azimuth, sin_elevation, -cos_elevation * cos_azimuth)
+}
+
+// Direction towards the sun for a place and a time, following the appendix of the paper.
+// latitude is positive north and longitude positive east (degrees), utc_offset is the time zone of the
+// local time in hours, day is the day of the year starting at 1 and hour the local time (e.g. 14.5).
+pub fn solar_direction(latitude: f64, longitude: f64, utc_offset: f64, day: f64, hour: f64) -> Vect
+{
+    let latitude = degrees_to_radians(latitude);
+    // Solar time corrects the clock time with the equation of time and the offset from the time zone meridian
+    let solar_time = hour + 0.170 * (4.0 * PI * (day - 80.0) / 373.0).sin() - 0.129 * (2.0 * PI * (day - 8.0) / 355.0).sin()
+                   + (longitude - 15.0 * utc_offset) / 15.0;
+    let declination = 0.4093 * (2.0 * PI * (day - 81.0) / 368.0).sin();
+    let hour_angle = PI * solar_time / 12.0;
+
+    let sin_elevation = latitude.sin() * declination.sin() - latitude.cos() * declination.cos() * hour_angle.cos();
+    // Measured from south towards west
+    let azimuth = (-declination.cos() * hour_angle.sin()).atan2(latitude.cos() * declination.sin() - latitude.sin() * declination.cos() * hour_angle.cos());
+    sun_direction(radians_to_degrees(clamp(sin_elevation, -1.0, 1.0).asin()), 180.0 + radians_to_degrees(azimuth))
+}
+
+// Perez coefficients A to E as linear functions of the turbidity, for the luminance and the two chromaticities
+const PEREZ_LUMINANCE: [[f64; 2]; 5] = [[0.1787, -1.4630], [-0.3554, 0.4275], [-0.0227, 5.3251], [0.1206, -2.5771], [-0.0670, 0.3703]];
+const PEREZ_X: [[f64; 2]; 5] = [[-0.0193, -0.2592], [-0.0665, 0.0008], [-0.0004, 0.2125], [-0.0641, -0.8989], [-0.0033, 0.0452]];
+const PEREZ_Y: [[f64; 2]; 5] = [[-0.0167, -0.2608], [-0.0950, 0.0092], [-0.0079, 0.2102], [-0.0441, -1.6537], [-0.0109, 0.0529]];
+
+// Zenith chromaticity polynomials in turbidity (rows) and sun zenith angle (columns)
+const ZENITH_X: [[f64; 4]; 3] = [[0.00166, -0.00375, 0.00209, 0.0], [-0.02903, 0.06377, -0.03202, 0.00394], [0.11693, -0.21196, 0.06052, 0.25886]];
+const ZENITH_Y: [[f64; 4]; 3] = [[0.00275, -0.00610, 0.00317, 0.0], [-0.04214, 0.08970, -0.04153, 0.00516], [0.15346, -0.26756, 0.06670, 0.26688]];
+
+fn perez_coefficients(table: &[[f64; 2]; 5], turbidity: f64) -> [f64; 5]
+{
+    table.map(|[slope, offset]| slope * turbidity + offset)
+}
+
+// Relative luminance of the sky at zenith angle theta and angle gamma from the sun
+fn perez(coefficients: &[f64; 5], cos_theta: f64, gamma: f64) -> f64
+{
+    let [a, b, c, d, e] = *coefficients;
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos() * gamma.cos())
+}
+
+fn zenith_chromaticity(table: &[[f64; 4]; 3], turbidity: f64, theta_sun: f64) -> f64
+{
+    let turbidity_terms = [turbidity * turbidity, turbidity, 1.0];
+    let angle_terms = [theta_sun * theta_sun * theta_sun, theta_sun * theta_sun, theta_sun, 1.0];
+    table.iter().zip(turbidity_terms).map(|(row, t)| t * row.iter().zip(angle_terms).map(|(value, a)| value * a).sum::<f64>()).sum()
+}
+
+// CIE xyY to linear sRGB
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> FColor
+{
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    FColor::new_color(( 3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z).max(0.0),
+                      (-0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z).max(0.0),
+                      ( 0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z).max(0.0))
+}
+
+pub struct PreethamSky
+{
+    sun_direction: Vect,
+    turbidity: f64,
+    intensity: f64,
+    coefficients: [[f64; 5]; 3],
+    // Zenith luminance and chromaticity divided by the Perez function at the zenith
+    zenith: [f64; 3],
+}
+
+impl PreethamSky
+{
+    // turbidity goes from about 2 (very clear) to 10 (hazy), intensity scales both the sky and the sun
+    pub fn make_new(sun_direction: Vect, turbidity: f64, intensity: f64) -> Self
+    {
+        let sun_direction = sun_direction.get_normalized();
+        let turbidity = clamp(turbidity, 1.7, 10.0);
+        // The model isn't defined for a sun below the horizon
+        let theta_sun = clamp(sun_direction.y(), 0.0, 1.0).acos().min(degrees_to_radians(89.5));
+
+        let chi = (4.0 / 9.0 - turbidity / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_luminance = ((4.0453 * turbidity - 4.9710) * chi.tan() - 0.2155 * turbidity + 2.4192).max(0.0);
+        let zenith_values = [zenith_luminance,
+                             zenith_chromaticity(&ZENITH_X, turbidity, theta_sun),
+                             zenith_chromaticity(&ZENITH_Y, turbidity, theta_sun)];
+
+        let coefficients = [perez_coefficients(&PEREZ_LUMINANCE, turbidity),
+                            perez_coefficients(&PEREZ_X, turbidity),
+                            perez_coefficients(&PEREZ_Y, turbidity)];
+        let mut zenith = [0.0; 3];
+        for ind in 0..3
+        {
+            zenith[ind] = zenith_values[ind] / perez(&coefficients[ind], 1.0, theta_sun);
+        }
+
+        PreethamSky
+        {
+            sun_direction,
+            turbidity,
+            intensity,
+            coefficients,
+            zenith,
+        }
+    }
+
+    pub fn sun_direction(&self) -> &Vect
+    {
+        &self.sun_direction
+    }
+
+    // Fraction of the sunlight that makes it through the atmosphere for each channel,
+    // from Rayleigh scattering by the air and Angstrom's formula for aerosols
+    fn sun_transmittance(&self) -> FColor
+    {
+        let theta = self.sun_direction.y().acos();
+        let zenith_degrees = radians_to_degrees(theta);
+        // Relative optical mass of the air along the way
+        let optical_mass = 1.0 / (theta.cos() + 0.15 * (93.885 - zenith_degrees).powf(-1.253));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        let [r, g, b] = WAVELENGTHS.map(|lambda|
+            {
+                let rayleigh = (-0.008735 * lambda.powf(-4.08) * optical_mass).exp();
+                let aerosol = (-beta * lambda.powf(-1.3) * optical_mass).exp();
+                rayleigh * aerosol
+            });
+        FColor::new_color(r, g, b)
+    }
+
+    // The sun disk matching this sky, or None once it has set
+    pub fn sun_light(&self) -> Option<DirectionalLight>
+    {
+        if self.sun_direction.y() <= 0.0
+        {
+            return None;
+        }
+        let irradiance = self.sun_transmittance() * (SOLAR_ILLUMINANCE * LUMINANCE_SCALE * self.intensity);
+        Some(DirectionalLight::make_new(self.sun_direction, irradiance, SUN_ANGULAR_DIAMETER))
+    }
+}
+
+impl Environment for PreethamSky
+{
+    // Without the sun disk, which is lit by sun_light(). Below the horizon the horizon's color continues.
+    fn radiance(&self, direction: &Vect) -> FColor
+    {
+        let mut direction = *direction;
+        *direction.get_y() = direction.y().max(0.0);
+        if direction.is_zero()
+        {
+            direction = Vect::make_new(0.0, 1.0, 0.0);
+        }
+        direction.normalize();
+
+        let gamma = clamp(Vect::dot(&direction, &self.sun_direction), -1.0, 1.0).acos();
+        // Grazing the horizon the Perez function blows up
+        let cos_theta = direction.y().max(0.01);
+        let [luminance, x, y] = [0, 1, 2].map(|ind| self.zenith[ind] * perez(&self.coefficients[ind], cos_theta, gamma));
+        xyy_to_rgb(x, y, luminance) * (LUMINANCE_SCALE * self.intensity)
+    }
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::super::math::light::Light;
+    use super::super::super::math::point::Point;
+
+    fn elevation(direction: &Vect) -> f64
+    {
+        radians_to_degrees(direction.y().asin())
+    }
+
+    #[test]
+    fn sun_position_test()
+    {
+        let east = sun_direction(0.0, 90.0);
+        assert!((east - Vect::make_new(1.0, 0.0, 0.0)).length() < 1e-12);
+        assert!((sun_direction(90.0, 0.0) - Vect::make_new(0.0, 1.0, 0.0)).length() < 1e-12);
+
+        // Close to overhead at noon on the equator around the equinox
+        assert!(elevation(&solar_direction(0.0, 0.0, 0.0, 80.0, 12.0)) > 85.0);
+        // About 73 degrees at 40 north on the summer solstice, in the south
+        let noon = solar_direction(40.0, 0.0, 0.0, 172.0, 12.0);
+        assert!((elevation(&noon) - 73.4).abs() < 1.0);
+        assert!(noon.z() > 0.0);
+        // Rising in the east and setting in the west, below the horizon at midnight
+        assert!(solar_direction(40.0, 0.0, 0.0, 172.0, 8.0).x() > 0.0);
+        assert!(solar_direction(40.0, 0.0, 0.0, 172.0, 16.0).x() < 0.0);
+        assert!(elevation(&solar_direction(40.0, 0.0, 0.0, 172.0, 0.0)) < 0.0);
+        // The time zone and the longitude cancel out
+        let shifted = solar_direction(40.0, 90.0, 6.0, 172.0, 12.0);
+        assert!((shifted - noon).length() < 1e-12);
+    }
+
+    #[test]
+    fn sky_test()
+    {
+        let sky = PreethamSky::make_new(sun_direction(30.0, 180.0), 3.0, 1.0);
+        let zenith = sky.radiance(&Vect::make_new(0.0, 1.0, 0.0));
+        // Blue overhead, in the plausible range of the chosen units
+        assert!(zenith.b() > zenith.r());
+        assert!(zenith.g() > 0.05 && zenith.g() < 2.0);
+        // Brighter around the sun than on the opposite side
+        let near_sun = sky.radiance(&sun_direction(35.0, 180.0));
+        let away = sky.radiance(&sun_direction(35.0, 0.0));
+        assert!(near_sun.g() > 2.0 * away.g());
+        // The horizon continues below
+        let below = sky.radiance(&Vect::make_new(1.0, -0.5, 0.0));
+        assert!((below - sky.radiance(&Vect::make_new(1.0, 0.0, 0.0))).length() < 1e-9);
+    }
+
+    #[test]
+    fn sun_light_test()
+    {
+        let point = Point::make_new(0.0, 0.0, 0.0);
+        let high = PreethamSky::make_new(sun_direction(60.0, 180.0), 3.0, 1.0).sun_light().unwrap().sample(&point).unwrap();
+        let low = PreethamSky::make_new(sun_direction(5.0, 180.0), 3.0, 1.0).sun_light().unwrap().sample(&point).unwrap();
+        let hazy = PreethamSky::make_new(sun_direction(60.0, 180.0), 8.0, 1.0).sun_light().unwrap().sample(&point).unwrap();
+
+        assert!(high.irradiance.g() > 3.0 && high.irradiance.g() < SOLAR_ILLUMINANCE * LUMINANCE_SCALE);
+        // Lower and hazier suns are dimmer, and the low one is redder
+        assert!(low.irradiance.g() < high.irradiance.g());
+        assert!(hazy.irradiance.g() < high.irradiance.g());
+        assert!(low.irradiance.r() / low.irradiance.b() > high.irradiance.r() / high.irradiance.b());
+
+        assert!(PreethamSky::make_new(sun_direction(-10.0, 0.0), 3.0, 1.0).sun_light().is_none());
+    }
+}
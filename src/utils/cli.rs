@@ -0,0 +1,262 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use super::super::formats::image::ImageFormat;
+use super::super::formats::exr::{ExrCompression, ExrPixelType};
+use super::super::render::settings::RenderSettings;
+
+// Command line parsing for the renderer binary.
+// Flags take their value either as the next argument (--spp 64) or inline (--spp=64).
+
+pub const USAGE: &str = "\
+Usage: rust_ray_tracer [OPTIONS] [SCENE]
+
+Renders a scene file or one of the built-in scenes (random, simple, cornell).
+
+Options:
+  --scene <file|name>   Scene file or built-in scene name [default: random]
+  --output <path>       Output image path, overrides the scene's output
+  --format <format>     Output format: ppm, png, pfm, hdr, exr [default: from the output extension]
+  --exr-compression <c> OpenEXR compression: none, zip [default: zip]
+  --exr-type <type>     OpenEXR channel type: half, float [default: half]
+  --width <pixels>      Image width, the height follows the scene's aspect ratio unless given
+  --height <pixels>     Image height, the width follows the scene's aspect ratio unless given
+  --spp <count>         Samples (rays) per pixel
+  --max-depth <count>   Maximum number of bounces per ray
+  --mis <on|off>        Multiple importance sampling of lights and materials [default: on]
+  --spectral <on|off>   Trace wavelengths instead of RGB colors [default: from the scene, off]
+  --threads <count>     Number of render threads [default: available parallelism]
+  --seed <number>       Seed for a reproducible render [default: time based]
+  -h, --help            Print this help
+
+Exit codes: 0 on success, 1 if the render failed, 2 on invalid arguments.
+";
+
+#[derive(Debug, PartialEq, Default)]
+pub struct CliOptions
+{
+    pub scene: Option<String>,
+    pub output: Option<PathBuf>,
+    pub format: Option<ImageFormat>,
+    pub exr_compression: Option<ExrCompression>,
+    pub exr_pixel_type: Option<ExrPixelType>,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub samples: Option<usize>,
+    pub max_depth: Option<u32>,
+    pub mis: Option<bool>,
+    pub spectral: Option<bool>,
+    pub threads: Option<usize>,
+    pub seed: Option<u64>,
+    pub help: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CliError
+{
+    pub message: String,
+}
+
+impl CliError
+{
+    fn make_new(message: String) -> Self
+    {
+        CliError{ message }
+    }
+}
+
+impl fmt::Display for CliError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CliError {}
+
+fn parse_switch(flag: &str, value: &str) -> Result<bool, CliError>
+{
+    match value.to_ascii_lowercase().as_str()
+    {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(CliError::make_new(format!("{} expects on or off, found '{}'", flag, value))),
+    }
+}
+
+fn parse_count(flag: &str, value: &str, max: usize) -> Result<usize, CliError>
+{
+    match value.parse::<usize>()
+    {
+        Ok(count) if count > 0 && count <= max => Ok(count),
+        _ => Err(CliError::make_new(format!("{} expects a whole number between 1 and {}, found '{}'", flag, max, value))),
+    }
+}
+
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<CliOptions, CliError>
+{
+    let mut options = CliOptions::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next()
+    {
+        if arg == "-h" || arg == "--help"
+        {
+            options.help = true;
+            continue;
+        }
+        if !arg.starts_with("--")
+        {
+            // A lone positional argument is the scene
+            if options.scene.is_some()
+            {
+                return Err(CliError::make_new(format!("unexpected argument '{}'", arg)));
+            }
+            options.scene = Some(arg);
+            continue;
+        }
+
+        let (flag, inline_value) = match arg.split_once('=')
+        {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg.clone(), None),
+        };
+        let known = ["--scene", "--output", "--format", "--exr-compression", "--exr-type", "--width", "--height", "--spp", "--max-depth", "--mis", "--spectral", "--threads", "--seed"];
+        if !known.contains(&flag.as_str())
+        {
+            return Err(CliError::make_new(format!("unknown option '{}'", flag)));
+        }
+        let value = match inline_value.or_else(|| args.next())
+        {
+            Some(value) if !value.is_empty() => value,
+            _ => return Err(CliError::make_new(format!("{} expects a value", flag))),
+        };
+
+        match flag.as_str()
+        {
+            "--scene" => options.scene = Some(value),
+            "--output" => options.output = Some(PathBuf::from(value)),
+            "--format" =>
+            {
+                let format = ImageFormat::from_name(&value).ok_or_else(||
+                    {
+                        let names: Vec<&str> = ImageFormat::ALL.iter().map(|format| format.name()).collect();
+                        CliError::make_new(format!("unsupported format '{}', expected one of: {}", value, names.join(", ")))
+                    })?;
+                options.format = Some(format);
+            }
+            "--exr-compression" =>
+            {
+                let compression = ExrCompression::from_name(&value).ok_or_else(||
+                    CliError::make_new(format!("unsupported OpenEXR compression '{}', expected none or zip", value)))?;
+                options.exr_compression = Some(compression);
+            }
+            "--exr-type" =>
+            {
+                let pixel_type = ExrPixelType::from_name(&value).ok_or_else(||
+                    CliError::make_new(format!("unsupported OpenEXR channel type '{}', expected half or float", value)))?;
+                options.exr_pixel_type = Some(pixel_type);
+            }
+            "--width" => options.width = Some(parse_count(&flag, &value, RenderSettings::MAX_RESOLUTION)?),
+            "--height" => options.height = Some(parse_count(&flag, &value, RenderSettings::MAX_RESOLUTION)?),
+            "--spp" => options.samples = Some(parse_count(&flag, &value, usize::MAX)?),
+            "--max-depth" => options.max_depth = Some(parse_count(&flag, &value, u32::MAX as usize)? as u32),
+            "--mis" => options.mis = Some(parse_switch(&flag, &value)?),
+            "--spectral" => options.spectral = Some(parse_switch(&flag, &value)?),
+            "--threads" => options.threads = Some(parse_count(&flag, &value, 4096)?),
+            "--seed" =>
+            {
+                let seed = value.parse::<u64>().map_err(|_| CliError::make_new(format!("--seed expects a non-negative integer, found '{}'", value)))?;
+                options.seed = Some(seed);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // An explicit output path has to agree with an explicit format
+    if let Some(output) = &options.output
+    {
+        match (ImageFormat::from_path(output), options.format)
+        {
+            (Some(from_path), Some(format)) if from_path != format =>
+            {
+                return Err(CliError::make_new(format!("the output extension doesn't match --format {}", format.name())));
+            }
+            (None, None) if output.extension().is_some() =>
+            {
+                return Err(CliError::make_new(format!("can't tell the image format of '{}', use --format", output.display())));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(options)
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliOptions, CliError>
+    {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_test()
+    {
+        let options = parse(&["--width", "640", "--height=360", "--spp", "16", "--max-depth", "8", "--mis", "off", "--spectral=on", "--threads", "4",
+                              "--seed", "42", "--scene", "simple", "--output", "out/a.ppm", "--exr-compression", "none", "--exr-type=float"]).unwrap();
+        assert_eq!(options, CliOptions
+        {
+            scene: Some("simple".to_string()),
+            output: Some(PathBuf::from("out/a.ppm")),
+            format: None,
+            exr_compression: Some(ExrCompression::None),
+            exr_pixel_type: Some(ExrPixelType::Float),
+            width: Some(640),
+            height: Some(360),
+            samples: Some(16),
+            max_depth: Some(8),
+            mis: Some(false),
+            spectral: Some(true),
+            threads: Some(4),
+            seed: Some(42),
+            help: false,
+        });
+
+        assert_eq!(parse(&[]).unwrap(), CliOptions::default());
+        assert!(parse(&["--help"]).unwrap().help);
+        assert_eq!(parse(&["scenes/simple.scene"]).unwrap().scene, Some("scenes/simple.scene".to_string()));
+        assert_eq!(parse(&["--format", "PPM"]).unwrap().format, Some(ImageFormat::Ppm));
+        assert_eq!(parse(&["--output", "a.png"]).unwrap().output, Some(PathBuf::from("a.png")));
+    }
+
+    #[test]
+    fn error_test()
+    {
+        assert!(parse(&["--width", "0"]).is_err());
+        assert!(parse(&["--width", "-5"]).is_err());
+        assert!(parse(&["--height", "100000"]).is_err());
+        assert!(parse(&["--spp"]).is_err());
+        assert!(parse(&["--spp="]).is_err());
+        assert!(parse(&["--seed", "abc"]).is_err());
+        assert!(parse(&["--mis", "yes"]).is_err());
+        assert!(parse(&["--spectral", "1"]).is_err());
+        assert!(parse(&["--format", "gif"]).is_err());
+        assert!(parse(&["--frobnicate"]).is_err());
+        assert!(parse(&["a.scene", "b.scene"]).is_err());
+        assert!(parse(&["--output", "render.gif"]).is_err());
+        assert!(parse(&["--output", "render.png", "--format", "ppm"]).is_err());
+        assert!(parse(&["--exr-compression", "piz"]).is_err());
+        assert!(parse(&["--exr-type", "uint"]).is_err());
+    }
+}
@@ -1,2 +1,4 @@
-pub mod color;
-pub mod threadpool;
\ No newline at end of file
+pub mod color;
+pub mod threadpool;
+pub mod cli;
+pub mod spectrum;
@@ -0,0 +1,391 @@
+use std::sync::OnceLock;
+
+use super::color::FColor;
+use super::super::math::vect::Vect;
+
+// Spectral rendering support: the CIE 1931 observer, wavelength sampling and conversions between
+// spectra and the renderer's linear RGB. Wavelengths are in nanometers.
+//
+// RGB values are turned into spectra by mixing three smooth basis spectra (blue, green and red bands with
+// soft logistic edges) with weights chosen so the spectrum projects back onto the same RGB. The bands add
+// up to one, so white stays a flat spectrum. Like the RGB renderer, white is the equal energy illuminant.
+
+pub const MIN_WAVELENGTH: f64 = 360.0;
+pub const MAX_WAVELENGTH: f64 = 830.0;
+
+// Wavelengths traced together by one spectral path
+pub const WAVELENGTH_COUNT: usize = 4;
+
+// Values at the wavelengths of a spectral path
+pub type SampledSpectrum = Vect<WAVELENGTH_COUNT>;
+
+// Piecewise Gaussian used by the analytic fit of the color matching functions
+fn lobe(wavelength: f64, center: f64, below: f64, above: f64) -> f64
+{
+    let width = if wavelength < center { below } else { above };
+    let t = (wavelength - center) / width;
+    (-0.5 * t * t).exp()
+}
+
+// CIE 1931 2° color matching functions, from the multi-lobe fit of Wyman, Sloan and Shirley,
+// "Simple Analytic Approximations to the CIE XYZ Color Matching Functions" (2013)
+pub fn color_matching(wavelength: f64) -> [f64; 3]
+{
+    [1.056 * lobe(wavelength, 599.8, 37.9, 31.0) + 0.362 * lobe(wavelength, 442.0, 16.0, 26.7) - 0.065 * lobe(wavelength, 501.1, 20.4, 26.2),
+     0.821 * lobe(wavelength, 568.8, 46.9, 40.5) + 0.286 * lobe(wavelength, 530.9, 16.3, 31.1),
+     1.217 * lobe(wavelength, 437.0, 11.8, 36.0) + 0.681 * lobe(wavelength, 459.0, 26.0, 13.8)]
+}
+
+// CIE XYZ to linear sRGB
+const XYZ_TO_RGB: [[f64; 3]; 3] = [[3.2404542, -1.5371385, -0.4985314],
+                                   [-0.9692660, 1.8760108, 0.0415560],
+                                   [0.0556434, -0.2040259, 1.0572252]];
+
+fn multiply(matrix: &[[f64; 3]; 3], vector: [f64; 3]) -> [f64; 3]
+{
+    matrix.map(|row| row[0] * vector[0] + row[1] * vector[1] + row[2] * vector[2])
+}
+
+fn invert(m: &[[f64; 3]; 3]) -> [[f64; 3]; 3]
+{
+    let cofactor = |row: usize, column: usize|
+    {
+        let (r0, r1) = ((row + 1) % 3, (row + 2) % 3);
+        let (c0, c1) = ((column + 1) % 3, (column + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let determinant = (0..3).map(|column| m[0][column] * cofactor(0, column)).sum::<f64>();
+    let mut inverse = [[0.0; 3]; 3];
+    for (row, values) in inverse.iter_mut().enumerate()
+    {
+        for (column, value) in values.iter_mut().enumerate()
+        {
+            *value = cofactor(column, row) / determinant;
+        }
+    }
+    inverse
+}
+
+// Smooth bands the RGB values are spread over, they sum to one at every wavelength
+fn basis(wavelength: f64) -> [f64; 3]
+{
+    let logistic = |x: f64| 1.0 / (1.0 + (-x).exp());
+    let blue = logistic((490.0 - wavelength) / 12.0);
+    let red = logistic((wavelength - 590.0) / 12.0);
+    [red, 1.0 - red - blue, blue]
+}
+
+// XYZ of a spectrum, summed on a 1nm grid
+fn spectrum_to_xyz(spectrum: &dyn Fn(f64) -> f64) -> [f64; 3]
+{
+    let mut xyz = [0.0; 3];
+    let mut wavelength = MIN_WAVELENGTH;
+    while wavelength <= MAX_WAVELENGTH
+    {
+        let cmf = color_matching(wavelength);
+        let value = spectrum(wavelength);
+        for channel in 0..3
+        {
+            xyz[channel] += cmf[channel] * value;
+        }
+        wavelength += 1.0;
+    }
+    xyz
+}
+
+struct Tables
+{
+    // Integral of the y color matching function, the luminance of a flat spectrum of 1
+    y_integral: f64,
+    // Per channel scale that maps the equal energy white to (1, 1, 1)
+    white_balance: [f64; 3],
+    // RGB to the weights of the basis bands
+    rgb_to_basis: [[f64; 3]; 3],
+}
+
+fn tables() -> &'static Tables
+{
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(||
+    {
+        let white = spectrum_to_xyz(&|_| 1.0);
+        let y_integral = white[1];
+        let white_rgb = multiply(&XYZ_TO_RGB, white.map(|value| value / y_integral));
+        let white_balance = white_rgb.map(|value| 1.0 / value);
+
+        // Balanced RGB color of each basis function, the columns of the matrix
+        let columns = [0, 1, 2].map(|band|
+            {
+                let xyz = spectrum_to_xyz(&|wavelength| basis(wavelength)[band]).map(|value| value / y_integral);
+                multiply(&XYZ_TO_RGB, xyz)
+            });
+        let mut basis_to_rgb = [[0.0; 3]; 3];
+        for (channel, row) in basis_to_rgb.iter_mut().enumerate()
+        {
+            for (band, value) in row.iter_mut().enumerate()
+            {
+                *value = columns[band][channel] * white_balance[channel];
+            }
+        }
+        Tables{ y_integral, white_balance, rgb_to_basis: invert(&basis_to_rgb) }
+    })
+}
+
+// Value at a wavelength of the spectrum standing for an RGB color. Saturated colors can call for
+// negative values, which are clipped.
+pub fn rgb_to_spectrum(rgb: &FColor, wavelength: f64) -> f64
+{
+    let weights = multiply(&tables().rgb_to_basis, [rgb.r(), rgb.g(), rgb.b()]);
+    let bands = basis(wavelength);
+    (weights[0] * bands[0] + weights[1] * bands[1] + weights[2] * bands[2]).max(0.0)
+}
+
+fn xyz_to_rgb(xyz: [f64; 3]) -> FColor
+{
+    let balance = tables().white_balance;
+    let rgb = multiply(&XYZ_TO_RGB, xyz);
+    FColor::make_new(rgb[0] * balance[0], rgb[1] * balance[1], rgb[2] * balance[2])
+}
+
+// Spectral radiance of a black body at a temperature in kelvins (Planck's law), in W / (m^2 sr nm)
+pub fn blackbody(wavelength: f64, kelvin: f64) -> f64
+{
+    const PLANCK: f64 = 6.62607015e-34;
+    const LIGHT_SPEED: f64 = 299792458.0;
+    const BOLTZMANN: f64 = 1.380649e-23;
+    if kelvin <= 0.0
+    {
+        return 0.0;
+    }
+    let meters = wavelength * 1e-9;
+    let exponent = PLANCK * LIGHT_SPEED / (meters * BOLTZMANN * kelvin);
+    2.0 * PLANCK * LIGHT_SPEED * LIGHT_SPEED / (meters.powi(5) * exponent.exp_m1()) * 1e-9
+}
+
+// Range and spacing of the temperatures in the blackbody color table
+const BLACKBODY_MIN: f64 = 500.0;
+const BLACKBODY_MAX: f64 = 40000.0;
+const BLACKBODY_STEP: f64 = 100.0;
+
+// Color of a black body at a temperature in kelvins, scaled to a luminance of one. Interpolated from a
+// table, temperatures outside of it get the color at its ends.
+pub fn blackbody_color(kelvin: f64) -> FColor
+{
+    static TABLE: OnceLock<Vec<FColor>> = OnceLock::new();
+    let table = TABLE.get_or_init(||
+    {
+        let count = ((BLACKBODY_MAX - BLACKBODY_MIN) / BLACKBODY_STEP) as usize + 1;
+        (0..count).map(|index|
+            {
+                let kelvin = BLACKBODY_MIN + index as f64 * BLACKBODY_STEP;
+                let xyz = spectrum_to_xyz(&|wavelength| blackbody(wavelength, kelvin));
+                xyz_to_rgb(xyz.map(|value| value / xyz[1]))
+            }).collect()
+    });
+    let position = (kelvin.clamp(BLACKBODY_MIN, BLACKBODY_MAX) - BLACKBODY_MIN) / BLACKBODY_STEP;
+    let index = (position as usize).min(table.len() - 2);
+    let fraction = position - index as f64;
+    table[index] * (1.0 - fraction) + table[index + 1] * fraction
+}
+
+// Density of sample_visible_wavelength(), roughly following the eye's sensitivity
+pub fn visible_wavelength_pdf(wavelength: f64) -> f64
+{
+    if !(MIN_WAVELENGTH..=MAX_WAVELENGTH).contains(&wavelength)
+    {
+        return 0.0;
+    }
+    let cosh = (0.0072 * (wavelength - 538.0)).cosh();
+    0.0039398042 / (cosh * cosh)
+}
+
+// Wavelength for a uniform u, see Radziszewski et al., "An Improved Technique for Full Spectral Rendering" (2009)
+pub fn sample_visible_wavelength(u: f64) -> f64
+{
+    538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh()
+}
+
+// Wavelengths of one spectral path, after Wilkie et al., "Hero Wavelength Spectral Sampling" (2014).
+// The first one, the hero, decides where the path goes and the others are evenly spread over the
+// sampling density, so together they cover the spectrum.
+#[derive(Debug, Clone)]
+pub struct SampledWavelengths
+{
+    wavelengths: [f64; WAVELENGTH_COUNT],
+    pdfs: [f64; WAVELENGTH_COUNT],
+}
+
+impl SampledWavelengths
+{
+    pub fn sample(u: f64) -> Self
+    {
+        let mut wavelengths = [0.0; WAVELENGTH_COUNT];
+        let mut pdfs = [0.0; WAVELENGTH_COUNT];
+        for ind in 0..WAVELENGTH_COUNT
+        {
+            let stratum = (u + ind as f64 / WAVELENGTH_COUNT as f64).fract();
+            wavelengths[ind] = sample_visible_wavelength(stratum);
+            pdfs[ind] = visible_wavelength_pdf(wavelengths[ind]);
+        }
+        SampledWavelengths{ wavelengths, pdfs }
+    }
+
+    pub fn hero(&self) -> f64
+    {
+        self.wavelengths[0]
+    }
+
+    pub fn wavelengths(&self) -> &[f64; WAVELENGTH_COUNT]
+    {
+        &self.wavelengths
+    }
+
+    // Called when the path takes a direction only right for the hero, such as a refraction through a
+    // dispersive material. The hero then stands for the whole spectrum.
+    pub fn terminate_secondary(&mut self)
+    {
+        if self.is_secondary_terminated()
+        {
+            return;
+        }
+        self.pdfs[0] /= WAVELENGTH_COUNT as f64;
+        for pdf in self.pdfs.iter_mut().skip(1)
+        {
+            *pdf = 0.0;
+        }
+    }
+
+    pub fn is_secondary_terminated(&self) -> bool
+    {
+        self.pdfs[1..].iter().all(|&pdf| pdf == 0.0)
+    }
+
+    // Spectrum of an RGB color at these wavelengths
+    pub fn upsample(&self, rgb: &FColor) -> SampledSpectrum
+    {
+        SampledSpectrum{ data: self.wavelengths.map(|wavelength| rgb_to_spectrum(rgb, wavelength)) }
+    }
+
+    // Estimate of the RGB color of a spectrum from its values at these wavelengths
+    pub fn to_rgb(&self, spectrum: &SampledSpectrum) -> FColor
+    {
+        let mut xyz = [0.0; 3];
+        for ind in 0..WAVELENGTH_COUNT
+        {
+            if self.pdfs[ind] <= 0.0
+            {
+                continue;
+            }
+            let cmf = color_matching(self.wavelengths[ind]);
+            for channel in 0..3
+            {
+                xyz[channel] += cmf[channel] * spectrum[ind] / self.pdfs[ind];
+            }
+        }
+        xyz_to_rgb(xyz.map(|value| value / (WAVELENGTH_COUNT as f64 * tables().y_integral)))
+    }
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::super::math::random::{seed_thread_rand, thread_rand};
+
+    #[test]
+    fn color_matching_test()
+    {
+        // Green light looks brightest, and the fit peaks close to the tabulated values
+        let peak = color_matching(555.0);
+        assert!((peak[1] - 1.0).abs() < 0.02);
+        assert!(color_matching(450.0)[2] > 1.5 && color_matching(600.0)[0] > 1.0);
+        assert!(color_matching(830.0).iter().all(|value| *value < 1e-3));
+
+        let inverse = invert(&XYZ_TO_RGB);
+        let round_trip = multiply(&XYZ_TO_RGB, multiply(&inverse, [0.3, 0.5, 0.9]));
+        assert!((round_trip[0] - 0.3).abs() < 1e-12 && (round_trip[2] - 0.9).abs() < 1e-12);
+    }
+
+    #[test]
+    fn upsampling_test()
+    {
+        // White is flat and colors come back out of the spectrum they were turned into
+        for wavelength in [380.0, 500.0, 700.0]
+        {
+            assert!((rgb_to_spectrum(&FColor::make_new(1.0, 1.0, 1.0), wavelength) - 1.0).abs() < 1e-9);
+        }
+        let color = FColor::make_new(0.6, 0.4, 0.2);
+        let mut xyz = [0.0; 3];
+        for ind in 0..=470
+        {
+            let wavelength = MIN_WAVELENGTH + ind as f64;
+            let cmf = color_matching(wavelength);
+            for channel in 0..3
+            {
+                xyz[channel] += cmf[channel] * rgb_to_spectrum(&color, wavelength) / tables().y_integral;
+            }
+        }
+        assert!((xyz_to_rgb(xyz) - color).length() < 1e-6);
+
+        // Red light is mostly made of long wavelengths
+        let red = FColor::make_new(1.0, 0.0, 0.0);
+        assert!(rgb_to_spectrum(&red, 650.0) > 5.0 * rgb_to_spectrum(&red, 450.0));
+    }
+
+    #[test]
+    fn sampling_test()
+    {
+        seed_thread_rand(3);
+        // The density integrates to one and matches the sampling
+        let total: f64 = (0..4700).map(|ind| visible_wavelength_pdf(MIN_WAVELENGTH + (ind as f64 + 0.5) * 0.1) * 0.1).sum();
+        assert!((total - 1.0).abs() < 1e-3);
+        assert!((sample_visible_wavelength(0.5) - 538.0).abs() < 10.0);
+
+        // A flat spectrum averages to white
+        let mut average = FColor::make_new(0.0, 0.0, 0.0);
+        let samples = 20000;
+        for _ in 0..samples
+        {
+            let wavelengths = SampledWavelengths::sample(thread_rand());
+            assert!(wavelengths.wavelengths().iter().all(|wavelength| (MIN_WAVELENGTH..=MAX_WAVELENGTH).contains(wavelength)));
+            average += wavelengths.to_rgb(&SampledSpectrum{ data: [1.0; WAVELENGTH_COUNT] });
+        }
+        average /= samples as f64;
+        assert!((average - FColor::make_new(1.0, 1.0, 1.0)).length() < 0.02, "{:?}", average);
+
+        // Only the hero is left after termination, and it carries the weight of all of them
+        let mut wavelengths = SampledWavelengths::sample(0.3);
+        let spectrum = SampledSpectrum{ data: [1.0; WAVELENGTH_COUNT] };
+        let before = wavelengths.to_rgb(&SampledSpectrum{ data: [1.0, 0.0, 0.0, 0.0] });
+        wavelengths.terminate_secondary();
+        assert!(wavelengths.is_secondary_terminated());
+        assert!((wavelengths.to_rgb(&spectrum) - before * WAVELENGTH_COUNT as f64).length() < 1e-9);
+        wavelengths.terminate_secondary();
+        assert!((wavelengths.to_rgb(&spectrum) - before * WAVELENGTH_COUNT as f64).length() < 1e-9);
+    }
+
+    #[test]
+    fn blackbody_test()
+    {
+        // Wien's displacement law puts the peak at 2.898e6 nm K / T
+        let peak = (300..1200).max_by(|a, b| blackbody(*a as f64, 5000.0).total_cmp(&blackbody(*b as f64, 5000.0))).unwrap();
+        assert!((peak as f64 - 2.898e6 / 5000.0).abs() < 2.0);
+        assert_eq!(blackbody(500.0, 0.0), 0.0);
+
+        // Embers are red, hot stars blue, and close to the equal energy white is white
+        let ember = blackbody_color(1500.0);
+        assert!(ember.r() > ember.g() && ember.g() > ember.b());
+        let star = blackbody_color(20000.0);
+        assert!(star.b() > star.r());
+        let white = blackbody_color(5450.0);
+        assert!((white - FColor::make_new(1.0, 1.0, 1.0)).length() < 0.1);
+        assert_eq!(blackbody_color(100.0), blackbody_color(BLACKBODY_MIN));
+        assert_eq!(blackbody_color(1e6), blackbody_color(BLACKBODY_MAX));
+    }
+}
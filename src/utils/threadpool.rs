@@ -1,75 +1,382 @@
-use std::thread;
-use std::thread::Builder;
-use std::io::Error;
-use std::sync::{mpsc, mpsc::SendError, Arc, Mutex};
-
-type Job = Box<dyn FnOnce() + Send + 'static>;
-
-struct Worker
-{
-    id: usize,
-    thread: thread::JoinHandle<()>
-}
-
-impl Worker
-{
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Result<Worker, Error>
-    {
-        let thread = Builder::new().spawn
-        (
-            move || loop
-            {
-                let job = receiver.lock().unwrap().recv().unwrap();
-
-                println!("Worker {id} got a job; executing.");
-
-                job();
-            }
-        )?;
-
-        Ok(
-            Worker
-            {
-                id,
-                thread
-            }
-        )
-    }
-}
-
-pub struct ThreadPool
-{
-    workers: Vec<Worker>,
-    sender: mpsc::Sender<Job>,
-}
-
-impl ThreadPool
-{
-    pub fn new(size: usize) -> Result<ThreadPool, Error>
-    {
-        assert!(size > 0);
-
-        let (sender, receiver) = mpsc::channel();
-
-        let receiver = Arc::new(Mutex::new(receiver));
-
-        let mut workers = Vec::with_capacity(size);
-
-        for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver))?);
-        }
-
-        Ok(ThreadPool {workers, sender})
-    }
-
-    pub fn execute<F>(&self, f: F) -> Result<(), SendError<Job>>
-    where
-        F: FnOnce() + Send + 'static,
-    {
-        let job = Box::new(f);
-
-        self.sender.send(job)?;
-        
-        Ok(())
-    }
-}
\ No newline at end of file
+use std::any::Any;
+use std::fmt;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+use std::thread::Builder;
+use std::io::Error;
+use std::sync::{mpsc, mpsc::SendError, Arc, Condvar, Mutex};
+
+// Fixed size pool of worker threads fed from one shared queue.
+// Jobs either run detached (execute), hand back their result through a JobHandle (spawn),
+// or borrow from the caller's stack inside scope(), which waits for all of them before returning.
+// A panicking job only fails its own handle, the worker that ran it carries on with the next job.
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+// Why a job didn't produce a result
+#[derive(Debug, PartialEq, Clone)]
+pub struct JobError
+{
+    pub message: String,
+}
+
+impl JobError
+{
+    fn from_panic(payload: Box<dyn Any + Send>) -> Self
+    {
+        let message = if let Some(message) = payload.downcast_ref::<&str>()
+        {
+            message.to_string()
+        }
+        else if let Some(message) = payload.downcast_ref::<String>()
+        {
+            message.clone()
+        }
+        else
+        {
+            "job panicked".to_string()
+        };
+        JobError{ message }
+    }
+}
+
+impl fmt::Display for JobError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for JobError {}
+
+type JobResult<T> = Result<T, JobError>;
+
+fn run_job<T, F: FnOnce() -> T>(f: F) -> JobResult<T>
+{
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(JobError::from_panic)
+}
+
+// Receives the result of a job submitted with ThreadPool::spawn
+pub struct JobHandle<T>
+{
+    receiver: mpsc::Receiver<JobResult<T>>,
+}
+
+impl<T> JobHandle<T>
+{
+    // Blocks until the job is done
+    pub fn join(self) -> JobResult<T>
+    {
+        self.receiver.recv().unwrap_or_else(|_| Err(JobError{ message: "job was dropped before it ran".to_string() }))
+    }
+}
+
+struct Worker
+{
+    id: usize,
+    thread: Option<thread::JoinHandle<()>>
+}
+
+impl Worker
+{
+    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Result<Worker, Error>
+    {
+        let thread = Builder::new().name(format!("worker-{id}")).spawn
+        (
+            move || loop
+            {
+                // The lock is released before the job runs so other workers can pick up jobs meanwhile
+                let message = receiver.lock().unwrap().recv();
+
+                match message
+                {
+                    // Jobs with a handle report their own panics, detached ones are simply dropped
+                    Ok(job) => { let _ = run_job(job); }
+                    // The pool was dropped, no more jobs will come
+                    Err(_) => break,
+                }
+            }
+        )?;
+
+        Ok(
+            Worker
+            {
+                id,
+                thread: Some(thread)
+            }
+        )
+    }
+}
+
+pub struct ThreadPool
+{
+    workers: Vec<Worker>,
+    sender: Option<mpsc::Sender<Job>>,
+}
+
+impl ThreadPool
+{
+    pub fn new(size: usize) -> Result<ThreadPool, Error>
+    {
+        assert!(size > 0);
+
+        let (sender, receiver) = mpsc::channel();
+
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let mut workers = Vec::with_capacity(size);
+
+        for id in 0..size {
+            workers.push(Worker::new(id, Arc::clone(&receiver))?);
+        }
+
+        Ok(ThreadPool {workers, sender: Some(sender)})
+    }
+
+    pub fn size(&self) -> usize
+    {
+        self.workers.len()
+    }
+
+    pub fn execute<F>(&self, f: F) -> Result<(), SendError<Job>>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let job = Box::new(f);
+
+        self.sender.as_ref().expect("The pool is shutting down").send(job)?;
+
+        Ok(())
+    }
+
+    pub fn spawn<F, T>(&self, f: F) -> JobHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        // A failed send drops the job, and with it the sender, which the handle reports
+        let _ = self.execute(move || { let _ = sender.send(run_job(f)); });
+        JobHandle{ receiver }
+    }
+
+    // Runs f with a Scope whose jobs may borrow anything that outlives the call.
+    // Returns once every job spawned in the scope has finished, even if f panics.
+    // Calling this from inside a pool job can deadlock if every worker ends up waiting on a scope.
+    pub fn scope<'env, F, T>(&self, f: F) -> T
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+    {
+        let scope = Scope
+        {
+            pool: self,
+            state: Arc::new(ScopeState{ pending: Mutex::new(0), finished: Condvar::new() }),
+            scope: PhantomData,
+            env: PhantomData,
+        };
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+
+        let mut pending = scope.state.pending.lock().unwrap();
+        while *pending > 0
+        {
+            pending = scope.state.finished.wait(pending).unwrap();
+        }
+        drop(pending);
+
+        match result
+        {
+            Ok(result) => result,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+impl Drop for ThreadPool
+{
+    // Closes the queue, lets the workers finish the jobs already queued and waits for them
+    fn drop(&mut self)
+    {
+        drop(self.sender.take());
+
+        for worker in &mut self.workers
+        {
+            if let Some(thread) = worker.thread.take()
+            {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+struct ScopeState
+{
+    pending: Mutex<usize>,
+    finished: Condvar,
+}
+
+pub struct Scope<'scope, 'env: 'scope>
+{
+    pool: &'scope ThreadPool,
+    state: Arc<ScopeState>,
+    // Same variance as std::thread::Scope
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'scope> Scope<'scope, '_>
+{
+    pub fn spawn<F, T>(&'scope self, f: F) -> ScopedJobHandle<'scope, T>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let state = Arc::clone(&self.state);
+        *state.pending.lock().unwrap() += 1;
+
+        let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(move ||
+            {
+                let _ = sender.send(run_job(f));
+                // Nothing borrowed may be touched after the count goes down
+                drop(sender);
+                let mut pending = state.pending.lock().unwrap();
+                *pending -= 1;
+                if *pending == 0
+                {
+                    state.finished.notify_all();
+                }
+            });
+        // SAFETY: ThreadPool::scope doesn't return before pending drops back to zero, which only happens
+        // once the job has run and everything it borrowed has been dropped, so the job never outlives 'scope.
+        // The pool itself is borrowed for 'scope, so it can't shut down and drop the job unrun in between.
+        let job: Job = unsafe { std::mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, Job>(job) };
+        if let Err(error) = self.pool.sender.as_ref().expect("The pool is shutting down").send(job)
+        {
+            // The job was never queued, so it won't bring the count back down itself
+            drop(error);
+            *self.state.pending.lock().unwrap() -= 1;
+            panic!("The pool's workers have stopped");
+        }
+
+        ScopedJobHandle{ receiver, scope: PhantomData }
+    }
+}
+
+// Receives the result of a job spawned in a Scope
+pub struct ScopedJobHandle<'scope, T>
+{
+    receiver: mpsc::Receiver<JobResult<T>>,
+    scope: PhantomData<&'scope ()>,
+}
+
+impl<T> ScopedJobHandle<'_, T>
+{
+    // Blocks until the job is done
+    pub fn join(self) -> JobResult<T>
+    {
+        self.receiver.recv().unwrap_or_else(|_| Err(JobError{ message: "job was dropped before it ran".to_string() }))
+    }
+}
+
+//============================================
+//============================================
+//===============Unit Tests===================
+//============================================
+//============================================
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn execute_test()
+    {
+        let (sender, receiver) = mpsc::channel();
+        let pool = ThreadPool::new(4).unwrap();
+        assert_eq!(pool.size(), 4);
+        for ind in 0..100
+        {
+            let sender = sender.clone();
+            pool.execute(move || sender.send(ind * ind).unwrap()).unwrap();
+        }
+        drop(sender);
+
+        let mut results: Vec<i32> = receiver.iter().collect();
+        results.sort();
+        assert_eq!(results, (0..100).map(|ind| ind * ind).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn drop_test()
+    {
+        // Dropping the pool runs every queued job before returning
+        let counter = Arc::new(AtomicUsize::new(0));
+        {
+            let pool = ThreadPool::new(3).unwrap();
+            for _ in 0..50
+            {
+                let counter = Arc::clone(&counter);
+                pool.execute(move || { counter.fetch_add(1, Ordering::SeqCst); }).unwrap();
+            }
+        }
+        assert_eq!(counter.load(Ordering::SeqCst), 50);
+    }
+
+    #[test]
+    fn spawn_test()
+    {
+        let pool = ThreadPool::new(2).unwrap();
+        let handles: Vec<JobHandle<usize>> = (0..10).map(|ind| pool.spawn(move || ind * 2)).collect();
+        let results: Vec<usize> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+        assert_eq!(results, (0..10).map(|ind| ind * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn panic_test()
+    {
+        // A single worker has to survive the panic to run the following jobs
+        let pool = ThreadPool::new(1).unwrap();
+        let failed = pool.spawn(|| -> usize { panic!("broken job") });
+        pool.execute(|| panic!("broken detached job")).unwrap();
+        let working = pool.spawn(|| 7);
+
+        assert_eq!(failed.join(), Err(JobError{ message: "broken job".to_string() }));
+        assert_eq!(working.join(), Ok(7));
+    }
+
+    #[test]
+    fn scope_test()
+    {
+        let pool = ThreadPool::new(3).unwrap();
+        let numbers: Vec<usize> = (1..=100).collect();
+        let mut squares = vec![0; numbers.len()];
+
+        let total = pool.scope(|scope|
+            {
+                for (chunk, output) in numbers.chunks(10).zip(squares.chunks_mut(10))
+                {
+                    scope.spawn(move ||
+                        {
+                            for (number, square) in chunk.iter().zip(output.iter_mut())
+                            {
+                                *square = number * number;
+                            }
+                        });
+                }
+                let handles: Vec<_> = numbers.chunks(25).map(|chunk| scope.spawn(move || chunk.iter().sum::<usize>())).collect();
+                handles.into_iter().map(|handle| handle.join().unwrap()).sum::<usize>()
+            });
+
+        // Un-joined jobs are done too once the scope returns
+        assert_eq!(total, 5050);
+        assert_eq!(squares[99], 10000);
+        assert!(squares.iter().zip(&numbers).all(|(square, number)| *square == number * number));
+
+        let failed = pool.scope(|scope| scope.spawn(|| -> usize { panic!("broken scoped job") }).join());
+        assert!(failed.is_err());
+    }
+}